@@ -0,0 +1,176 @@
+#[macro_use]
+extern crate criterion;
+extern crate consistent_rs;
+
+use criterion::{black_box, Criterion};
+use consistent_rs::{BTreeRing, Consistant};
+
+fn consistant(members: usize) -> Consistant {
+    let mut consistant = Consistant::default();
+    for i in 0..members {
+        consistant.add(format!("node-{}", i));
+    }
+    consistant
+}
+
+fn btree_ring(members: usize) -> BTreeRing {
+    let mut ring = BTreeRing::default();
+    for i in 0..members {
+        ring.add(format!("node-{}", i));
+    }
+    ring
+}
+
+fn bench_get(c: &mut Criterion) {
+    let keys: Vec<String> = (0..1024).map(|i| format!("key{}", i)).collect();
+
+    for &members in &[10, 100] {
+        let consistant = consistant(members);
+        let compiled = consistant.compile(1 << 16);
+        let frozen = consistant.clone().freeze();
+        let btree = btree_ring(members);
+        let mut i = 0;
+
+        c.bench_function(&format!("Consistant::get/{}", members), |b| {
+            b.iter(|| {
+                i = (i + 1) % keys.len();
+                consistant.get(black_box(keys[i].as_str()))
+            })
+        });
+        c.bench_function(&format!("Consistant::get_n/{}", members), |b| {
+            b.iter(|| {
+                i = (i + 1) % keys.len();
+                consistant.get_n(black_box(keys[i].as_str()), 3)
+            })
+        });
+        c.bench_function(&format!("Consistant::is_owner/{}", members), |b| {
+            b.iter(|| {
+                i = (i + 1) % keys.len();
+                consistant.is_owner("node-1", black_box(keys[i].as_str()), 3)
+            })
+        });
+        c.bench_function(&format!("BTreeRing::get/{}", members), |b| {
+            b.iter(|| {
+                i = (i + 1) % keys.len();
+                btree.get(black_box(keys[i].as_str()))
+            })
+        });
+        c.bench_function(&format!("BTreeRing::get_n/{}", members), |b| {
+            b.iter(|| {
+                i = (i + 1) % keys.len();
+                btree.get_n(black_box(keys[i].as_str()), 3)
+            })
+        });
+        c.bench_function(&format!("CompiledRing::get/{}", members), |b| {
+            b.iter(|| {
+                i = (i + 1) % keys.len();
+                compiled.get(black_box(&keys[i])).map(|element| element.len())
+            })
+        });
+        c.bench_function(&format!("CompiledRing::get_n/{}", members), |b| {
+            b.iter(|| {
+                i = (i + 1) % keys.len();
+                compiled.get_n(black_box(&keys[i]), 3).map(|elements| elements.len())
+            })
+        });
+        c.bench_function(&format!("FrozenRing::get/{}", members), |b| {
+            b.iter(|| {
+                i = (i + 1) % keys.len();
+                frozen.get(black_box(&keys[i])).map(|element| element.len())
+            })
+        });
+        c.bench_function(&format!("FrozenRing::get_n/{}", members), |b| {
+            b.iter(|| {
+                i = (i + 1) % keys.len();
+                frozen.get_n(black_box(&keys[i]), 3).map(|elements| elements.len())
+            })
+        });
+    }
+}
+
+fn bench_mutation(c: &mut Criterion) {
+    let mut consistant = Consistant::new(40);
+    for i in 0..10000 {
+        consistant.add(format!("node-{}", i));
+    }
+    // Sorted by a lookup, as the hash ring of a running service is.
+    consistant.get("david");
+
+    c.bench_function("Consistant::add+remove/10000 members", |b| {
+        b.iter(|| {
+            consistant.add("node-new");
+            consistant.remove("node-new");
+        })
+    });
+
+    let mut btree = BTreeRing::new(40);
+    for i in 0..10000 {
+        btree.add(format!("node-{}", i));
+    }
+    c.bench_function("BTreeRing::add+remove/10000 members", |b| {
+        b.iter(|| {
+            btree.add("node-new");
+            btree.remove("node-new");
+        })
+    });
+
+    let names: Vec<String> = (0..1000).map(|i| format!("node-{}", i)).collect();
+    c.bench_function("Consistant::add/1000 members", |b| {
+        b.iter(|| {
+            let mut consistant = Consistant::new(40);
+            for name in &names {
+                consistant.add(name.as_str());
+            }
+            consistant.get("david")
+        })
+    });
+    c.bench_function("BTreeRing::add/1000 members", |b| {
+        b.iter(|| {
+            let mut ring = BTreeRing::new(40);
+            for name in &names {
+                ring.add(name.as_str());
+            }
+            ring.get("david")
+        })
+    });
+}
+
+fn bench_bulk(c: &mut Criterion) {
+    // Parallel with the rayon feature, as a single sort without it.
+    let members: Vec<(String, usize)> =
+        (0..20000).map(|i| (format!("node-{}", i), 1 + i % 3)).collect();
+    let mut group = c.benchmark_group("bulk");
+    group.sample_size(10);
+    group.bench_function("Consistant::add_weighted/20000 members", |b| {
+        b.iter(|| {
+            let mut consistant = Consistant::new(40);
+            for &(ref name, weight) in &members {
+                consistant.add_weighted(name.as_str(), weight);
+            }
+            consistant.get("david")
+        })
+    });
+    group.bench_function("Consistant::from_members/20000 members", |b| {
+        b.iter(|| Consistant::from_members(40, members.iter().cloned()).get("david"))
+    });
+    group.finish();
+}
+
+#[cfg(feature = "rayon")]
+fn bench_assign(c: &mut Criterion) {
+    let keys: Vec<String> = (0..100000).map(|i| format!("key{}", i)).collect();
+    let consistant = consistant(100);
+
+    c.bench_function("Consistant::get/100000 keys", |b| {
+        b.iter(|| keys.iter().filter(|key| consistant.get(key.as_str()).is_some()).count())
+    });
+    c.bench_function("Consistant::par_assign/100000 keys", |b| {
+        b.iter(|| consistant.par_assign(&keys).len())
+    });
+}
+
+#[cfg(not(feature = "rayon"))]
+fn bench_assign(_: &mut Criterion) {}
+
+criterion_group!(benches, bench_get, bench_mutation, bench_bulk, bench_assign);
+criterion_main!(benches);
@@ -0,0 +1,42 @@
+// This file is @generated by prost-build.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Ring {
+    /// PROTO_FORMAT_VERSION of the writer.
+    #[prost(uint32, tag = "1")]
+    pub format_version: u32,
+    /// HASHER_ID.
+    #[prost(uint32, tag = "2")]
+    pub hasher_id: u32,
+    /// The id of the placement strategy, see `PlacementStrategy::id`.
+    #[prost(uint32, tag = "3")]
+    pub placement_id: u32,
+    #[prost(uint64, tag = "4")]
+    pub seed: u64,
+    /// See `Consistant::version`.
+    #[prost(uint64, tag = "5")]
+    pub ring_version: u64,
+    #[prost(uint32, tag = "6")]
+    pub replicas: u32,
+    /// Sorted by name.
+    #[prost(message, repeated, tag = "7")]
+    pub members: ::prost::alloc::vec::Vec<Member>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Member {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "2")]
+    pub weight: u32,
+    /// Carried for the control plane, the hash ring not placing by zone.
+    #[prost(string, tag = "3")]
+    pub zone: ::prost::alloc::string::String,
+    /// The hashes of the virtual nodes of the member, in replica order. When
+    /// present, they must be the ones the placement strategy derives, unless
+    /// explicit_tokens is set.
+    #[prost(fixed32, repeated, tag = "4")]
+    pub tokens: ::prost::alloc::vec::Vec<u32>,
+    /// Set for a member whose virtual nodes are not derived from its name, as
+    /// `Consistant::replace_node` leaves them, the tokens being then required.
+    #[prost(bool, tag = "5")]
+    pub explicit_tokens: bool,
+}
@@ -0,0 +1,410 @@
+use std::collections::hash_map::HashMap;
+use std::sync::Arc;
+use auto_replicas::moved_share;
+use consistant::Consistant;
+use error::Error;
+
+/// The largest factor every weight may be scaled by, for finer weights.
+const MAX_SCALE: usize = 8;
+/// The count of corrections of the weights at every scale.
+const ROUNDS: usize = 10;
+/// The largest factor a weight may be corrected by, up or down, in a round.
+const MAX_CORRECTION: f64 = 4.0;
+/// The most virtual nodes of the hash rings built to predict the shares.
+const MAX_VNODES: usize = 1 << 20;
+
+/// The shares of the keyspace owned by the elements.
+type Shares = HashMap<Arc<str>, f64>;
+
+/// Recommend the count of virtual nodes per element for the coefficient of
+/// variation of the shares of the keyspace of the given count of elements,
+/// their standard deviation relative to their mean, to stay within the given
+/// target, like 0.05 for 5%, with the given confidence, like 0.95. See
+/// `Consistant::current_cv` for the one of a hash ring.
+///
+/// The shares of `n` elements of `r` virtual nodes each are estimated as
+/// those of random virtual nodes, their coefficient of variation squared
+/// then following a chi-squared law of `n - 1` degrees of freedom divided
+/// by `n * r`, the quantile of which is approximated by Wilson and
+/// Hilferty's. An element alone needs a single virtual node.
+///
+/// The estimate holds for virtual nodes spread as randomly as those of
+/// `PlacementStrategy::SeededRandom`. Those of `PlacementStrategy::Classic`
+/// are the CRC32 checksums of names differing by a few characters, which
+/// are not: the shares of elements named alike may stop evening out past a
+/// few hundred virtual nodes, whatever the count recommended.
+///
+/// `Error::InvalidBalanceTarget` is returned for no element, a target which
+/// is not positive, a confidence which is not between 0 and 1, or if the
+/// virtual nodes needed outnumber the checksums.
+pub fn recommend_replicas(members: usize, target_cv: f64, confidence: f64) -> Result<usize, Error> {
+    if members == 0 || target_cv.is_nan() || target_cv <= 0.0 || confidence.is_nan() ||
+       confidence <= 0.0 || confidence >= 1.0 {
+        return Err(Error::InvalidBalanceTarget { members });
+    }
+    if members == 1 {
+        return Ok(1);
+    }
+
+    let freedom = (members - 1) as f64;
+    let z = normal_quantile(confidence);
+    let spread = 2.0 / (9.0 * freedom);
+    let quantile = freedom * (1.0 - spread + z * spread.sqrt()).max(0.0).powi(3);
+    let replicas = (quantile / (members as f64 * target_cv * target_cv)).ceil().max(1.0);
+    if replicas * members as f64 > u32::MAX as f64 {
+        return Err(Error::InvalidBalanceTarget { members });
+    }
+    Ok(replicas as usize)
+}
+
+/// WeightRecommendation is a weight change `recommend_weights` suggests.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeightRecommendation {
+    pub member: String,
+    pub current_weight: usize,
+    pub suggested_weight: usize,
+    /// The share of the keyspace the element would own once every
+    /// recommendation is applied.
+    pub predicted_share: f64,
+}
+
+impl Consistant {
+    /// Recommend the weights bringing the observed load of every element
+    /// within the given tolerance of the mean, like 0.1 for 10%, without
+    /// changing the hash ring. Nothing is recommended if the loads already
+    /// are within it.
+    ///
+    /// The load of an element is assumed to spread evenly over the keyspace
+    /// it owns, and its predicted share to be owned by building the hash
+    /// ring of the suggested weights. Weights being whole, they may all be
+    /// scaled up for finer steps, every element then being recommended. The
+    /// elements of no observed load keep their weight, scaled as well. If no
+    /// weights are found within the tolerance, the closest ones are, the
+    /// search stopping before the hash rings built outgrow a million virtual
+    /// nodes.
+    pub fn recommend_weights(&self,
+                             observed_load: &HashMap<String, f64>,
+                             tolerance: f64)
+                             -> Vec<WeightRecommendation> {
+        let members = self.weighted_members();
+        let shares = owned_shares(self);
+        // The load of every element per share of the keyspace it owns.
+        let densities: Vec<Option<f64>> = members.iter()
+            .map(|(element, _)| {
+                match (observed_load.get(&**element), shares.get(element)) {
+                    (Some(&load), Some(&share)) if load > 0.0 && share > 0.0 => {
+                        Some(load / share)
+                    }
+                    _ => None,
+                }
+            })
+            .collect();
+        if spread(&members, &densities, &shares) <= tolerance {
+            return Vec::new();
+        }
+
+        // The smallest spread found, with its weights and shares.
+        let mut best: Option<(f64, Vec<usize>, Shares)> = None;
+        'scales: for scale in 1..MAX_SCALE + 1 {
+            let mut weights: Vec<f64> = members.iter()
+                .map(|&(_, weight)| (weight * scale) as f64)
+                .collect();
+            for _ in 0..ROUNDS {
+                let rounded: Vec<usize> = weights.iter()
+                    .map(|weight| (weight.round() as usize).max(1))
+                    .collect();
+                let vnodes = rounded.iter()
+                    .try_fold(0usize, |sum, weight| sum.checked_add(*weight))
+                    .and_then(|sum| sum.checked_mul(self.replicas_num));
+                if best.is_some() && vnodes.is_none_or(|vnodes| vnodes > MAX_VNODES) {
+                    break 'scales;
+                }
+                let candidate = self.with_weights(&members, &rounded);
+                let predicted = owned_shares(&candidate);
+                let deviation = spread(&members, &densities, &predicted);
+                if best.as_ref().is_none_or(|best| deviation < best.0) {
+                    best = Some((deviation, rounded, predicted.clone()));
+                }
+                if deviation <= tolerance {
+                    break 'scales;
+                }
+
+                // Correct every weight by how far its predicted load is off.
+                let loads: Vec<Option<f64>> = members.iter()
+                    .zip(&densities)
+                    .map(|((element, _), density)| {
+                        density.map(|density| {
+                            density * predicted.get(element).cloned().unwrap_or(0.0)
+                        })
+                    })
+                    .collect();
+                let mean = mean(&loads);
+                for (weight, load) in weights.iter_mut().zip(loads) {
+                    if let Some(load) = load {
+                        let correction = if load > 0.0 { mean / load } else { 2.0 };
+                        *weight *= correction.clamp(1.0 / MAX_CORRECTION, MAX_CORRECTION);
+                    }
+                }
+            }
+        }
+
+        let (_, weights, predicted) = best.unwrap();
+        members.iter()
+            .zip(weights)
+            .filter(|&(&(_, current), suggested)| current != suggested)
+            .map(|(&(ref element, current_weight), suggested_weight)| {
+                WeightRecommendation {
+                    member: String::from(&**element),
+                    current_weight,
+                    suggested_weight,
+                    predicted_share: predicted.get(element).cloned().unwrap_or(0.0),
+                }
+            })
+            .collect()
+    }
+
+    /// Get the coefficient of variation of the shares of the keyspace the
+    /// elements own per unit of weight: their standard deviation relative
+    /// to their mean, 0 for an empty hash ring, see `recommend_replicas`.
+    pub fn current_cv(&self) -> f64 {
+        let members = self.weighted_members();
+        let shares = owned_shares(self);
+        let loads: Vec<f64> = members.iter()
+            .map(|(element, weight)| shares.get(element).cloned().unwrap_or(0.0) / *weight as f64)
+            .collect();
+        let mean = loads.iter().sum::<f64>() / loads.len().max(1) as f64;
+        if mean == 0.0 {
+            return 0.0;
+        }
+        let variance = loads.iter().map(|load| (load - mean).powi(2)).sum::<f64>() /
+                       loads.len() as f64;
+        variance.sqrt() / mean
+    }
+
+    /// Get the share of the keyspace which would change owner by applying
+    /// all the given recommendations, see `recommend_weights`.
+    pub fn weights_disruption(&self, recommendations: &[WeightRecommendation]) -> f64 {
+        let members = self.weighted_members();
+        let weights: Vec<usize> = members.iter()
+            .map(|&(ref element, weight)| {
+                recommendations.iter()
+                    .find(|recommendation| recommendation.member == **element)
+                    .map_or(weight, |recommendation| recommendation.suggested_weight)
+            })
+            .collect();
+        moved_share(self, &self.with_weights(&members, &weights))
+    }
+
+    /// Build a hash ring of the same configuration with the given weights.
+    fn with_weights(&self, members: &[(Arc<str>, usize)], weights: &[usize]) -> Consistant {
+        let mut consistant = Consistant::with_config(self.replicas_num,
+                                                     self.placement(),
+                                                     self.seed());
+        consistant.set_self_spacing(self.self_spacing());
+        for ((element, _), &weight) in members.iter().zip(weights) {
+            consistant.add_weighted(&**element, weight);
+        }
+        consistant
+    }
+}
+
+/// Get the shares of the keyspace owned by the elements of the hash ring.
+fn owned_shares(consistant: &Consistant) -> Shares {
+    let mut shares = HashMap::new();
+    for (start, end, element) in consistant.arcs() {
+        *shares.entry(element).or_insert(0.0) += (end - start) as f64 / (1u64 << 32) as f64;
+    }
+    shares
+}
+
+/// Get the quantile of the standard normal law of the given probability, by
+/// bisecting its distribution function.
+fn normal_quantile(probability: f64) -> f64 {
+    let (mut low, mut high) = (-10.0, 10.0);
+    for _ in 0..64 {
+        let mid = (low + high) / 2.0;
+        if normal_cdf(mid) < probability {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    (low + high) / 2.0
+}
+
+/// Get the distribution function of the standard normal law, its error
+/// function being approximated as by Abramowitz and Stegun's 7.1.26, within
+/// 1.5e-7.
+fn normal_cdf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.327_591_1 * x.abs() / 2f64.sqrt());
+    let polynomial = t * (0.254_829_592 +
+                          t * (-0.284_496_736 +
+                               t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    let erf = 1.0 - polynomial * (-x * x / 2.0).exp();
+    if x >= 0.0 { (1.0 + erf) / 2.0 } else { (1.0 - erf) / 2.0 }
+}
+
+/// Get the mean of the known loads.
+fn mean(loads: &[Option<f64>]) -> f64 {
+    let known: Vec<f64> = loads.iter().filter_map(|load| *load).collect();
+    known.iter().sum::<f64>() / known.len().max(1) as f64
+}
+
+/// Get the largest deviation from their mean of the loads of the elements
+/// owning the given shares, relative to the mean.
+fn spread(members: &[(Arc<str>, usize)],
+          densities: &[Option<f64>],
+          shares: &Shares)
+          -> f64 {
+    let loads: Vec<Option<f64>> = members.iter()
+        .zip(densities)
+        .map(|((element, _), density)| {
+            density.map(|density| density * shares.get(element).cloned().unwrap_or(0.0))
+        })
+        .collect();
+    let mean = mean(&loads);
+    if mean == 0.0 {
+        return 0.0;
+    }
+    loads.iter()
+        .filter_map(|load| *load)
+        .map(|load| (load - mean).abs() / mean)
+        .fold(0.0, f64::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use placement::PlacementStrategy;
+
+    #[test]
+    fn test_recommend_replicas() {
+        let replicas = recommend_replicas(10, 0.05, 0.95).unwrap();
+        assert_eq!(replicas, 677);
+        assert!(recommend_replicas(10, 0.05, 0.99).unwrap() > replicas);
+        assert!(recommend_replicas(10, 0.1, 0.95).unwrap() < replicas);
+        assert!(recommend_replicas(100, 0.05, 0.95).unwrap() < replicas);
+        assert_eq!(recommend_replicas(1, 0.01, 0.99), Ok(1));
+
+        // Built, the recommended rings meet the target as often as told.
+        let met = (0..20u64)
+            .filter(|&seed| {
+                let mut consistant =
+                    Consistant::with_config(replicas, PlacementStrategy::SeededRandom, seed);
+                for i in 0..10 {
+                    consistant.add(format!("cache{}", i));
+                }
+                consistant.current_cv() <= 0.05
+            })
+            .count();
+        assert!(met >= 17, "{}", met);
+
+        for &(members, target_cv, confidence) in &[(0, 0.05, 0.95),
+                                                   (10, 0.0, 0.95),
+                                                   (10, f64::NAN, 0.95),
+                                                   (10, 0.05, 1.0),
+                                                   (10, 0.05, 0.0),
+                                                   (1000, 1e-6, 0.95)] {
+            assert_eq!(recommend_replicas(members, target_cv, confidence),
+                       Err(Error::InvalidBalanceTarget { members }));
+        }
+    }
+
+    #[test]
+    fn test_current_cv() {
+        let mut consistant = Consistant::new(40);
+        assert_eq!(consistant.current_cv(), 0.0);
+        consistant.add("cacheA");
+        assert_eq!(consistant.current_cv(), 0.0);
+        for i in 0..9 {
+            consistant.add(format!("cache{}", i));
+        }
+        let cv = consistant.current_cv();
+        assert!(cv > 0.0 && cv < 0.5, "{}", cv);
+        let stretched = consistant.clone_with_replicas(400).current_cv();
+        assert!(stretched < cv, "{} {}", stretched, cv);
+
+        // The shares are per unit of weight.
+        consistant.add_weighted("cacheB", 4);
+        assert!(consistant.current_cv() < 0.5);
+    }
+
+    #[test]
+    fn test_recommend_weights() {
+        let mut consistant = Consistant::new(40);
+        for i in 0..5 {
+            consistant.add(format!("cache{}", i));
+        }
+        let fingerprint = consistant.fingerprint();
+        let shares = owned_shares(&consistant);
+
+        // The keys of cache0 are twice as hot.
+        let density = |element: &str| if element == "cache0" { 2.0 } else { 1.0 };
+        let observed: HashMap<String, f64> = shares.iter()
+            .map(|(element, share)| (String::from(&**element), 1000.0 * share * density(element)))
+            .collect();
+
+        let recommendations = consistant.recommend_weights(&observed, 0.1);
+        assert!(!recommendations.is_empty());
+        assert_eq!(consistant.fingerprint(), fingerprint);
+        let cache0 = recommendations.iter().find(|r| r.member == "cache0").unwrap();
+        assert_eq!(cache0.current_weight, 1);
+        let others = recommendations.iter().filter(|r| r.member != "cache0");
+        assert!(others.clone().all(|r| r.predicted_share > cache0.predicted_share));
+
+        // Applying them brings the simulated loads within the tolerance.
+        let mut applied = Consistant::new(40);
+        for member in consistant.members() {
+            let weight = recommendations.iter()
+                .find(|r| r.member == member)
+                .map_or(1, |r| r.suggested_weight);
+            applied.add_weighted(member, weight);
+        }
+        let applied_shares = owned_shares(&applied);
+        let loads: Vec<f64> = applied_shares.iter()
+            .map(|(element, share)| 1000.0 * share * density(element))
+            .collect();
+        let mean = loads.iter().sum::<f64>() / loads.len() as f64;
+        assert!(loads.iter().all(|load| (load - mean).abs() / mean <= 0.1), "{:?}", loads);
+        for recommendation in &recommendations {
+            let share = applied_shares[recommendation.member.as_str()];
+            assert!((recommendation.predicted_share - share).abs() < 1e-12);
+        }
+
+        let disruption = consistant.weights_disruption(&recommendations);
+        assert!(disruption > 0.0 && disruption < 1.0);
+        assert_eq!(consistant.weights_disruption(&[]), 0.0);
+
+        // Loads already within the tolerance are left alone.
+        let balanced: HashMap<String, f64> = shares.keys()
+            .map(|element| (String::from(&**element), 200.0))
+            .collect();
+        assert!(consistant.recommend_weights(&balanced, 0.1).is_empty());
+        assert!(Consistant::new(40).recommend_weights(&observed, 0.1).is_empty());
+    }
+
+    #[test]
+    fn test_recommend_weights_skewed() {
+        let mut consistant = Consistant::new(20);
+        for element in &["a", "b", "c"] {
+            consistant.add(*element);
+        }
+        let observed: HashMap<String, f64> =
+            [("a", 1000.0), ("b", 1000.0), ("c", 0.001)].iter()
+                .map(|&(element, load)| (String::from(element), load))
+                .collect();
+
+        let recommendations = consistant.recommend_weights(&observed, 0.1);
+        let vnodes: usize = consistant.members()
+            .iter()
+            .map(|member| {
+                recommendations.iter()
+                    .find(|r| r.member == *member)
+                    .map_or(1, |r| r.suggested_weight)
+            })
+            .sum::<usize>() * 20;
+        assert!(vnodes <= MAX_VNODES, "{}", vnodes);
+        let c = recommendations.iter().find(|r| r.member == "c").unwrap();
+        assert!(c.suggested_weight > c.current_weight);
+    }
+}
@@ -0,0 +1,458 @@
+use std::collections::hash_map::HashMap;
+use std::sync::Arc;
+use error::Error;
+use placement::checksum;
+
+/// AnchorHash is the consistent hash of Mendelson et al. (2020), mapping keys
+/// to the working buckets among a fixed capacity of them. A lookup takes
+/// O(1) expected time whatever the count of buckets, and adding or removing a
+/// bucket only moves the keys of that bucket.
+///
+/// Buckets are added back in the reverse order of their removal, the
+/// mappings then being restored exactly.
+#[derive(Debug, Clone)]
+pub struct AnchorHash {
+    /// The count of working buckets when each bucket was removed, 0 for the
+    /// working ones.
+    anchor: Vec<u32>,
+    /// The working buckets, followed by the removed ones.
+    working: Vec<u32>,
+    /// The position of each bucket in `working`.
+    location: Vec<u32>,
+    /// The successor of each removed bucket, which took its place.
+    successor: Vec<u32>,
+    removed: Vec<u32>,
+    size: u32,
+}
+
+impl AnchorHash {
+    /// Crete a new instance of AnchorHash of the given capacity, the buckets
+    /// `0..initial_buckets` working.
+    pub fn new(capacity: u32, initial_buckets: u32) -> Result<AnchorHash, Error> {
+        if capacity == 0 || initial_buckets > capacity {
+            return Err(Error::InvalidCapacity {
+                capacity,
+                buckets: initial_buckets,
+            });
+        }
+        let mut anchor = vec![0; capacity as usize];
+        let mut removed = Vec::with_capacity(capacity as usize);
+        for bucket in (initial_buckets..capacity).rev() {
+            anchor[bucket as usize] = bucket;
+            removed.push(bucket);
+        }
+
+        Ok(AnchorHash {
+            anchor,
+            working: (0..capacity).collect(),
+            location: (0..capacity).collect(),
+            successor: (0..capacity).collect(),
+            removed,
+            size: initial_buckets,
+        })
+    }
+
+    /// Get the count of buckets, working or not.
+    pub fn capacity(&self) -> u32 {
+        self.anchor.len() as u32
+    }
+
+    /// Get the count of working buckets.
+    pub fn len(&self) -> u32 {
+        self.size
+    }
+
+    /// Check whether no bucket is working.
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Check whether the given bucket is working.
+    pub fn contains(&self, bucket: u32) -> bool {
+        bucket < self.capacity() && {
+            let location = self.location[bucket as usize];
+            location < self.size && self.working[location as usize] == bucket
+        }
+    }
+
+    /// Add back the last removed bucket, returning it. `None` if every bucket
+    /// is working.
+    pub fn add_bucket(&mut self) -> Option<u32> {
+        let bucket = self.removed.pop()?;
+        let b = bucket as usize;
+        self.anchor[b] = 0;
+        let moved = self.working[self.size as usize];
+        self.location[moved as usize] = self.size;
+        self.working[self.location[b] as usize] = bucket;
+        self.successor[b] = bucket;
+        self.size += 1;
+        Some(bucket)
+    }
+
+    /// Remove the given bucket, returning whether it was working.
+    pub fn remove_bucket(&mut self, bucket: u32) -> bool {
+        if !self.contains(bucket) {
+            return false;
+        }
+        let b = bucket as usize;
+        self.removed.push(bucket);
+        self.size -= 1;
+        self.anchor[b] = self.size;
+        let last = self.working[self.size as usize];
+        self.working[self.location[b] as usize] = last;
+        self.location[last as usize] = self.location[b];
+        self.successor[b] = last;
+        true
+    }
+
+    /// Get the bucket of the given key, `None` if no bucket is working.
+    pub fn get<S: AsRef<str>>(&self, key: S) -> Option<u32> {
+        if self.size == 0 {
+            return None;
+        }
+        Some(find(key.as_ref().as_bytes(),
+                  self.capacity(),
+                  |bucket| self.anchor[bucket as usize],
+                  |bucket| self.successor[bucket as usize]))
+    }
+}
+
+/// Find the bucket of the given key among the given count of buckets, of the
+/// given anchors and successors, some bucket being working.
+#[inline]
+fn find<A, S>(key: &[u8], capacity: u32, anchor_of: A, successor_of: S) -> u32
+    where A: Fn(u32) -> u32,
+          S: Fn(u32) -> u32
+{
+    let mut bucket = checksum(0, key) % capacity;
+    while anchor_of(bucket) > 0 {
+        let anchor = anchor_of(bucket);
+        let mut next = checksum(u64::from(bucket) + 1, key) % anchor;
+        while anchor_of(next) >= anchor {
+            next = successor_of(next);
+        }
+        bucket = next;
+    }
+    bucket
+}
+
+/// The arrays of an `AnchorHash` an `Overlay` changes entries of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Array {
+    Anchor,
+    Working,
+    Location,
+    Successor,
+}
+
+/// Overlay is an `AnchorHash` with some more buckets removed, for walking
+/// the buckets a key goes to as they leave in turn. It keeps the few entries
+/// the removals change rather than a copy of the arrays of the capacity.
+struct Overlay<'a> {
+    base: &'a AnchorHash,
+    /// The changed entries, the last change of an entry coming last.
+    changed: Vec<(Array, u32, u32)>,
+    size: u32,
+}
+
+impl<'a> Overlay<'a> {
+    fn new(base: &'a AnchorHash, removals: usize) -> Overlay<'a> {
+        Overlay {
+            base,
+            changed: Vec::with_capacity(4 * removals),
+            size: base.size,
+        }
+    }
+
+    fn entry(&self, array: Array, index: u32) -> u32 {
+        match self.changed.iter().rev().find(|entry| entry.0 == array && entry.1 == index) {
+            Some(entry) => entry.2,
+            None => {
+                let base = match array {
+                    Array::Anchor => &self.base.anchor,
+                    Array::Working => &self.base.working,
+                    Array::Location => &self.base.location,
+                    Array::Successor => &self.base.successor,
+                };
+                base[index as usize]
+            }
+        }
+    }
+
+    /// Remove the given working bucket, as `AnchorHash::remove_bucket` does.
+    fn remove_bucket(&mut self, bucket: u32) {
+        self.size -= 1;
+        let location = self.entry(Array::Location, bucket);
+        let last = self.entry(Array::Working, self.size);
+        self.changed.push((Array::Anchor, bucket, self.size));
+        self.changed.push((Array::Working, location, last));
+        self.changed.push((Array::Location, last, location));
+        self.changed.push((Array::Successor, bucket, last));
+    }
+
+    /// Get the bucket of the given key, as `AnchorHash::get` does.
+    fn get(&self, key: &[u8]) -> Option<u32> {
+        if self.size == 0 {
+            return None;
+        }
+        Some(find(key,
+                  self.base.capacity(),
+                  |bucket| self.entry(Array::Anchor, bucket),
+                  |bucket| self.entry(Array::Successor, bucket)))
+    }
+}
+
+/// AnchorRing maps keys to named elements through an `AnchorHash`, for use
+/// in place of `Consistant` where the capacity is known.
+#[derive(Debug, Clone)]
+pub struct AnchorRing {
+    anchor: AnchorHash,
+    buckets: Vec<Option<Arc<str>>>,
+    members: HashMap<Arc<str>, u32>,
+}
+
+impl AnchorRing {
+    /// Crete a new instance of AnchorRing of up to `capacity` elements.
+    pub fn new(capacity: u32) -> Result<AnchorRing, Error> {
+        Ok(AnchorRing {
+            anchor: AnchorHash::new(capacity, 0)?,
+            buckets: vec![None; capacity as usize],
+            members: HashMap::new(),
+        })
+    }
+
+    /// Add an elment, returning whether it was added. It is not when it is
+    /// already in or when the capacity is reached.
+    pub fn add<S: Into<String>>(&mut self, element: S) -> bool {
+        let element = Arc::<str>::from(element.into());
+        if self.members.contains_key(&element) {
+            return false;
+        }
+        match self.anchor.add_bucket() {
+            Some(bucket) => {
+                self.buckets[bucket as usize] = Some(element.clone());
+                self.members.insert(element, bucket);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove the given element.
+    pub fn remove<S: AsRef<str>>(&mut self, name: S) {
+        if let Some(bucket) = self.members.remove(name.as_ref()) {
+            self.anchor.remove_bucket(bucket);
+            self.buckets[bucket as usize] = None;
+        }
+    }
+
+    /// Get the cloeset element's name to the given "name".
+    pub fn get<S: AsRef<str>>(&self, name: S) -> Option<String> {
+        self.anchor
+            .get(name)
+            .and_then(|bucket| self.buckets[bucket as usize].as_ref())
+            .map(|element| String::from(&**element))
+    }
+
+    /// Get the N cloeset elements' names to the given "name": the one of
+    /// `get`, then the ones it would go to as each of them is removed in
+    /// turn, or all of them if there are not as many. The removals are
+    /// walked without copying the buckets of the capacity.
+    pub fn get_n<S: AsRef<str>>(&self, name: S, n: usize) -> Option<Vec<String>> {
+        if n == 0 || self.members.is_empty() {
+            return None;
+        }
+        let count = n.min(self.members.len());
+        let mut anchor = Overlay::new(&self.anchor, count);
+        let mut owners = Vec::with_capacity(count);
+        while owners.len() < n {
+            let bucket = match anchor.get(name.as_ref().as_bytes()) {
+                Some(bucket) => bucket,
+                None => break,
+            };
+            anchor.remove_bucket(bucket);
+            if let Some(ref element) = self.buckets[bucket as usize] {
+                owners.push(String::from(&**element));
+            }
+        }
+
+        Some(owners)
+    }
+
+    /// Get the count of added elements.
+    pub fn count(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Get the names of the added elements, sorted.
+    pub fn members(&self) -> Vec<String> {
+        let mut members: Vec<String> =
+            self.members.keys().map(|element| String::from(&**element)).collect();
+        members.sort();
+        members
+    }
+
+    /// Check whether the given element is in the hash ring.
+    pub fn contains(&self, name: &str) -> bool {
+        self.members.contains_key(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xorshift::Xorshift;
+
+    fn assign(anchor: &AnchorHash, keys: &[String]) -> Vec<u32> {
+        keys.iter().map(|key| anchor.get(key).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_new() {
+        assert_eq!(AnchorHash::new(0, 0).unwrap_err(),
+                   Error::InvalidCapacity {
+                       capacity: 0,
+                       buckets: 0,
+                   });
+        assert!(AnchorHash::new(4, 5).is_err());
+
+        let anchor = AnchorHash::new(16, 10).unwrap();
+        assert_eq!((anchor.capacity(), anchor.len()), (16, 10));
+        assert!(anchor.contains(9) && !anchor.contains(10) && !anchor.contains(16));
+        for i in 0..1000 {
+            assert!(anchor.get(format!("key{}", i)).unwrap() < 10);
+        }
+        assert_eq!(AnchorHash::new(16, 0).unwrap().get("david"), None);
+    }
+
+    #[test]
+    fn test_minimal_disruption() {
+        let keys: Vec<String> = (0..5000).map(|i| format!("key{}", i)).collect();
+        let mut anchor = AnchorHash::new(64, 32).unwrap();
+        let mut state = Xorshift::new(42);
+        let mut history = Vec::new();
+
+        for _ in 0..200 {
+            let before = assign(&anchor, &keys);
+            let remove = anchor.len() > 1 && (anchor.len() == 64 || state.next_u64() & 1 == 0);
+            if remove {
+                let working: Vec<u32> = (0..64).filter(|b| anchor.contains(*b)).collect();
+                let bucket = working[state.next_u64() as usize % working.len()];
+                assert!(anchor.remove_bucket(bucket));
+                assert!(!anchor.remove_bucket(bucket));
+
+                // Only the keys of the removed bucket move.
+                for (old, new) in before.iter().zip(assign(&anchor, &keys)) {
+                    if *old == bucket {
+                        assert!(new != bucket && anchor.contains(new));
+                    } else {
+                        assert_eq!(*old, new);
+                    }
+                }
+                history.push((bucket, before));
+            } else {
+                let bucket = anchor.add_bucket().unwrap();
+                let after = assign(&anchor, &keys);
+
+                // Only keys moving to the added bucket move, back to where
+                // they were before its removal.
+                for (old, new) in before.iter().zip(&after) {
+                    assert!(old == new || *new == bucket);
+                }
+                if let Some((removed, restored)) = history.pop() {
+                    assert_eq!(bucket, removed);
+                    assert_eq!(after, restored);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_balance() {
+        let mut anchor = AnchorHash::new(100, 100).unwrap();
+        for bucket in (0..100).step_by(3) {
+            anchor.remove_bucket(bucket);
+        }
+        let mut counts = vec![0; 100];
+        for i in 0..66000 {
+            counts[anchor.get(format!("key{}", i)).unwrap() as usize] += 1;
+        }
+        for (bucket, count) in counts.iter().enumerate() {
+            if bucket % 3 == 0 {
+                assert_eq!(*count, 0);
+            } else {
+                assert!(*count > 500 && *count < 1500, "bucket {}: {}", bucket, count);
+            }
+        }
+    }
+
+    #[test]
+    fn test_anchor_ring() {
+        let mut ring = AnchorRing::new(3).unwrap();
+        assert_eq!(ring.get("david"), None);
+        assert!(ring.add("cacheA"));
+        assert!(!ring.add("cacheA"));
+        assert!(ring.add("cacheB"));
+        assert!(ring.add("cacheC"));
+        assert!(!ring.add("cacheD"));
+        assert_eq!(ring.count(), 3);
+        assert_eq!(ring.members(), ["cacheA", "cacheB", "cacheC"]);
+
+        let keys: Vec<String> = (0..1000).map(|i| format!("key{}", i)).collect();
+        let before: Vec<String> = keys.iter().map(|key| ring.get(key.as_str()).unwrap()).collect();
+        ring.remove("cacheB");
+        assert!(!ring.contains("cacheB"));
+        for (key, owner) in keys.iter().zip(&before) {
+            let new = ring.get(key.as_str()).unwrap();
+            assert!(*owner == "cacheB" && new != "cacheB" || new == *owner);
+        }
+
+        assert!(ring.add("cacheD"));
+        for (key, owner) in keys.iter().zip(&before) {
+            let new = ring.get(key.as_str()).unwrap();
+            assert_eq!(new, if owner == "cacheB" { "cacheD" } else { owner.as_str() });
+        }
+    }
+
+    #[test]
+    fn test_anchor_ring_get_n() {
+        let mut ring = AnchorRing::new(10).unwrap();
+        assert_eq!(ring.get_n("david", 2), None);
+        for i in 0..6 {
+            ring.add(format!("cache{}", i));
+        }
+        assert_eq!(ring.get_n("david", 0), None);
+        assert_eq!(ring.get_n("david", 10).unwrap().len(), 6);
+
+        // The next owners are the ones the key goes to as the first leave.
+        for i in 0..100 {
+            let key = format!("key{}", i);
+            let owners = ring.get_n(key.as_str(), 3).unwrap();
+            assert_eq!(owners[0], ring.get(key.as_str()).unwrap());
+            let mut failed = ring.clone();
+            for owner in &owners {
+                assert_eq!(failed.get(key.as_str()).as_ref(), Some(owner));
+                failed.remove(owner);
+            }
+        }
+
+        // Likewise past removals, of a capacity much larger than N.
+        let mut ring = AnchorRing::new(1000).unwrap();
+        for i in 0..200 {
+            ring.add(format!("cache{}", i));
+        }
+        for i in (0..200).step_by(3) {
+            ring.remove(format!("cache{}", i));
+        }
+        for i in 0..100 {
+            let key = format!("key{}", i);
+            let owners = ring.get_n(key.as_str(), 20).unwrap();
+            assert_eq!(owners.len(), 20);
+            let mut failed = ring.clone();
+            for owner in &owners {
+                assert_eq!(failed.get(key.as_str()).as_ref(), Some(owner));
+                failed.remove(owner);
+            }
+        }
+    }
+}
@@ -0,0 +1,388 @@
+//! Zero-copy archives of the hash ring, with the `rkyv` feature.
+//!
+//! `Consistant::archive` lays the hash ring out with rkyv: the fields of
+//! `RingState` followed by the virtual nodes sorted by hash, each one holding
+//! the index of its member. `ArchivedRing::access` checks the bytes, which
+//! may come from anywhere, and then maps keys right from them, the member
+//! names being borrowed rather than copied.
+
+use std::collections::hash_map::HashMap;
+use rkyv::rancor::Error as RkyvError;
+use rkyv::util::AlignedVec;
+use rkyv::{Archive, Serialize};
+use consistant::Consistant;
+use error::{ArchiveError, StateError};
+use fractional::{admits, next_admitting};
+use limits::ResourceLimits;
+use placement::checksum;
+use range_override::range_contains;
+use snapshot::HASHER_ID;
+use state::{MemberState, RingState, STATE_FORMAT_VERSION};
+
+/// The layout of an archived hash ring, see `ArchivedRing`.
+#[derive(Archive, Serialize)]
+#[rkyv(archived = ArchivedRing)]
+pub struct RingArchive {
+    format_version: u32,
+    hasher_id: u8,
+    placement_id: u8,
+    seed: u64,
+    ring_version: u64,
+    replicas: u64,
+    replication_factor: u64,
+    min_self_spacing: u32,
+    max_members: Option<u64>,
+    max_vnodes: Option<u64>,
+    /// Sorted by name.
+    members: Vec<MemberArchive>,
+    /// Sorted by prefix.
+    prefix_rules: Vec<RuleArchive>,
+    /// Sorted by start.
+    range_assignments: Vec<RangeArchive>,
+    /// Sorted by group.
+    colocation_groups: Vec<GroupArchive>,
+    /// Sorted by hash.
+    vnodes: Vec<VnodeArchive>,
+}
+
+#[derive(Archive, Serialize)]
+pub struct MemberArchive {
+    name: String,
+    weight: u64,
+    fraction: Option<u32>,
+    incarnation: u64,
+    tags: Vec<String>,
+    tokens: Option<Vec<u32>>,
+}
+
+#[derive(Archive, Serialize)]
+pub struct RuleArchive {
+    prefix: String,
+    member: String,
+}
+
+#[derive(Archive, Serialize)]
+pub struct RangeArchive {
+    start: u32,
+    end: u32,
+    member: String,
+}
+
+#[derive(Archive, Serialize)]
+pub struct GroupArchive {
+    group: String,
+    representative: String,
+}
+
+#[derive(Archive, Serialize)]
+pub struct VnodeArchive {
+    hash: u32,
+    /// The index of the member in `members`.
+    member: u32,
+}
+
+impl Consistant {
+    /// Archive the hash ring, for `ArchivedRing::access` to map keys right
+    /// from the bytes.
+    pub fn archive(&self) -> AlignedVec {
+        let state = self.export_state();
+        let indexes: HashMap<&str, u32> = state.members
+            .iter()
+            .enumerate()
+            .map(|(i, member)| (member.name.as_str(), i as u32))
+            .collect();
+        let vnodes = self.vnodes()
+            .into_iter()
+            .map(|(hash, element)| {
+                VnodeArchive {
+                    hash,
+                    member: indexes[&*element],
+                }
+            })
+            .collect();
+
+        let archive = RingArchive {
+            format_version: state.format_version,
+            hasher_id: state.hasher_id,
+            placement_id: state.placement_id,
+            seed: state.seed,
+            ring_version: state.ring_version,
+            replicas: state.replicas as u64,
+            replication_factor: state.replication_factor as u64,
+            min_self_spacing: state.min_self_spacing,
+            max_members: state.limits.max_members.map(|limit| limit as u64),
+            max_vnodes: state.limits.max_vnodes.map(|limit| limit as u64),
+            vnodes,
+            prefix_rules: state.prefix_rules
+                .iter()
+                .map(|(prefix, member)| {
+                    RuleArchive {
+                        prefix: prefix.clone(),
+                        member: member.clone(),
+                    }
+                })
+                .collect(),
+            range_assignments: state.range_assignments
+                .iter()
+                .map(|(start, end, member)| {
+                    RangeArchive {
+                        start: *start,
+                        end: *end,
+                        member: member.clone(),
+                    }
+                })
+                .collect(),
+            colocation_groups: state.colocation_groups
+                .into_iter()
+                .map(|(group, representative)| GroupArchive { group, representative })
+                .collect(),
+            members: state.members
+                .into_iter()
+                .map(|member| {
+                    MemberArchive {
+                        name: member.name,
+                        weight: member.weight as u64,
+                        fraction: member.fraction,
+                        incarnation: member.incarnation,
+                        tags: member.tags,
+                        tokens: member.tokens,
+                    }
+                })
+                .collect(),
+        };
+        rkyv::to_bytes::<RkyvError>(&archive).expect("archiving to memory does not fail")
+    }
+}
+
+impl ArchivedRing {
+    /// Get the archived hash ring of the given bytes, written by
+    /// `Consistant::archive` and aligned as rkyv wants. The bytes are checked
+    /// first, the virtual nodes included, but nothing is copied.
+    pub fn access(bytes: &[u8]) -> Result<&ArchivedRing, ArchiveError> {
+        let ring = rkyv::access::<ArchivedRing, RkyvError>(bytes)
+            .map_err(|err| ArchiveError::Invalid(err.to_string()))?;
+        let version = ring.format_version.to_native();
+        if version == 0 || version > STATE_FORMAT_VERSION {
+            return Err(ArchiveError::UnsupportedVersion(version));
+        }
+        if ring.hasher_id != HASHER_ID {
+            return Err(ArchiveError::UnknownHasher(ring.hasher_id));
+        }
+        let members = ring.members.len() as u32;
+        let mut previous = None;
+        for vnode in ring.vnodes.iter() {
+            let hash = vnode.hash.to_native();
+            if vnode.member.to_native() >= members || previous.is_some_and(|p| p >= hash) {
+                return Err(ArchiveError::Invalid(String::from("invalid virtual nodes")));
+            }
+            previous = Some(hash);
+        }
+
+        Ok(ring)
+    }
+
+    /// Get the count of archived elements.
+    pub fn count(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Get the cloeset element's name to the given "name", as the archived
+    /// `Consistant` would, borrowed from the archive.
+    pub fn get<S: AsRef<str>>(&self, name: S) -> Option<&str> {
+        self.get_n(name, 1).map(|mut owners| owners.remove(0))
+    }
+
+    /// Get the N cloeset elements' names to the given "name", as the archived
+    /// `Consistant` would, borrowed from the archive.
+    pub fn get_n<S: AsRef<str>>(&self, name: S, n: usize) -> Option<Vec<&str>> {
+        let key = name.as_ref();
+        if n == 0 || self.vnodes.is_empty() {
+            return None;
+        }
+        let count = n.min(self.members.len());
+        let mut owners = Vec::with_capacity(count);
+        let sum = checksum(self.seed.to_native(), key.as_bytes());
+        let start = self.vnodes.partition_point(|vnode| vnode.hash.to_native() <= sum);
+        owners.extend(self.prefix_rule(key)
+            .or_else(|| self.range_assignment(sum))
+            .or_else(|| self.reroute(start % self.vnodes.len(), sum)));
+
+        let (before, after) = self.vnodes.split_at(start);
+        for vnode in after.iter().chain(before) {
+            if owners.len() == count {
+                break;
+            }
+            let owner = vnode.member.to_native();
+            if !owners.contains(&owner) {
+                owners.push(owner);
+            }
+        }
+
+        Some(owners.into_iter().map(|owner| self.members[owner as usize].name.as_str()).collect())
+    }
+
+    /// Build the archived hash ring, which can then be changed.
+    pub fn deserialize(&self) -> Result<Consistant, StateError> {
+        Consistant::from_state(RingState {
+            format_version: self.format_version.to_native(),
+            hasher_id: self.hasher_id,
+            placement_id: self.placement_id,
+            seed: self.seed.to_native(),
+            ring_version: self.ring_version.to_native(),
+            replicas: self.replicas.to_native() as usize,
+            replication_factor: self.replication_factor.to_native() as usize,
+            min_self_spacing: self.min_self_spacing.to_native(),
+            limits: ResourceLimits {
+                max_members: self.max_members.as_ref().map(|limit| limit.to_native() as usize),
+                max_vnodes: self.max_vnodes.as_ref().map(|limit| limit.to_native() as usize),
+            },
+            members: self.members
+                .iter()
+                .map(|member| {
+                    MemberState {
+                        name: String::from(member.name.as_str()),
+                        weight: member.weight.to_native() as usize,
+                        incarnation: member.incarnation.to_native(),
+                        tags: member.tags.iter().map(|tag| String::from(tag.as_str())).collect(),
+                        tokens: member.tokens
+                            .as_ref()
+                            .map(|tokens| tokens.iter().map(|token| token.to_native()).collect()),
+                        fraction: member.fraction.as_ref().map(|fraction| fraction.to_native()),
+                    }
+                })
+                .collect(),
+            prefix_rules: self.prefix_rules
+                .iter()
+                .map(|rule| {
+                    (String::from(rule.prefix.as_str()), String::from(rule.member.as_str()))
+                })
+                .collect(),
+            range_assignments: self.range_assignments
+                .iter()
+                .map(|range| {
+                    (range.start.to_native(),
+                     range.end.to_native(),
+                     String::from(range.member.as_str()))
+                })
+                .collect(),
+            colocation_groups: self.colocation_groups
+                .iter()
+                .map(|group| {
+                    (String::from(group.group.as_str()),
+                     String::from(group.representative.as_str()))
+                })
+                .collect(),
+        })
+    }
+
+    /// Get the member index of the longest prefix rule of the given key, as
+    /// `Consistant::get` routes it, rules of unknown members skipped.
+    fn prefix_rule(&self, key: &str) -> Option<u32> {
+        self.prefix_rules
+            .iter()
+            .filter(|rule| key.starts_with(rule.prefix.as_str()))
+            .filter_map(|rule| {
+                self.members
+                    .binary_search_by(|member| member.name.as_str().cmp(rule.member.as_str()))
+                    .ok()
+                    .map(|index| (rule.prefix.len(), index as u32))
+            })
+            .max_by_key(|rule| rule.0)
+            .map(|rule| rule.1)
+    }
+
+    /// Get the member index the key of the given checksum goes to if the
+    /// member of the virtual node of the given index is of a fractional
+    /// weight and declines it, as `Consistant::get` routes it.
+    fn reroute(&self, start: usize, sum: u32) -> Option<u32> {
+        next_admitting(start, self.vnodes.len(), |index| {
+            let owner = self.vnodes[index].member.to_native();
+            let member = &self.members[owner as usize];
+            let admitted = member.fraction
+                .as_ref()
+                .is_none_or(|fraction| admits(member.name.as_str(), fraction.to_native(), sum));
+            (owner, admitted)
+        })
+    }
+
+    /// Get the member index of the range assignment of the given checksum,
+    /// assignments of unknown members skipped. The ranges are few, so they
+    /// are all matched.
+    fn range_assignment(&self, sum: u32) -> Option<u32> {
+        self.range_assignments
+            .iter()
+            .filter(|range| range_contains(range.start.to_native(), range.end.to_native(), sum))
+            .filter_map(|range| {
+                self.members
+                    .binary_search_by(|member| member.name.as_str().cmp(range.member.as_str()))
+                    .ok()
+                    .map(|index| index as u32)
+            })
+            .next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn consistant() -> Consistant {
+        let mut consistant = Consistant::with_seed(20, 7);
+        for i in 0..50 {
+            consistant.add_weighted(format!("node-{}", i), 1 + i % 3);
+        }
+        consistant.replace_node("node-42", "node-x").unwrap();
+        consistant.add_prefix_rule("tenant-42/", "node-7").unwrap();
+        consistant.assign_range(1 << 31, (1 << 31) + (1 << 28), "node-3").unwrap();
+        consistant.set_fractional_weight("node-11", 0.1).unwrap();
+        consistant.define_colocation_group("session-42", "tenant-42/session");
+        consistant
+    }
+
+    #[test]
+    fn test_archive() {
+        let consistant = consistant();
+        let bytes = consistant.archive();
+        let ring = ArchivedRing::access(&bytes).unwrap();
+        assert_eq!(ring.count(), 50);
+
+        let range = bytes.as_ptr_range();
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            let owner = ring.get(&key).unwrap();
+            // Borrowed from the archive rather than copied.
+            assert!(range.contains(&owner.as_ptr()));
+            assert_eq!(Some(String::from(owner)), consistant.get(&key));
+            let owners = ring.get_n(&key, 3).unwrap();
+            assert_eq!(owners, consistant.get_n(&key, 3).unwrap());
+        }
+        assert_eq!(ring.get("tenant-42/david"), Some("node-7"));
+        assert_eq!(ring.get_n("david", 0), None);
+        assert_eq!(ring.get_n("david", 60).unwrap().len(), 50);
+
+        let loaded = ring.deserialize().unwrap();
+        assert_eq!(loaded.export_state(), consistant.export_state());
+        assert_eq!(loaded.vnodes(), consistant.vnodes());
+
+        let empty = Consistant::new(20).archive();
+        assert_eq!(ArchivedRing::access(&empty).unwrap().get("david"), None);
+    }
+
+    #[test]
+    fn test_access_errors() {
+        let bytes = consistant().archive();
+        let mut truncated = AlignedVec::<16>::new();
+        truncated.extend_from_slice(&bytes[..bytes.len() - 8]);
+        assert!(matches!(ArchivedRing::access(&truncated), Err(ArchiveError::Invalid(_))));
+
+        // Every byte of the root flipped in turn is either caught or harmless.
+        for i in bytes.len() - 64..bytes.len() {
+            let mut corrupted = AlignedVec::<16>::new();
+            corrupted.extend_from_slice(&bytes);
+            corrupted[i] ^= 0xff;
+            if let Ok(ring) = ArchivedRing::access(&corrupted) {
+                let _ = ring.get_n("david", 3);
+            }
+        }
+    }
+}
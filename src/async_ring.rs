@@ -0,0 +1,158 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use consistant::Consistant;
+use tokio::sync::{OwnedRwLockReadGuard, OwnedRwLockWriteGuard, RwLock};
+
+/// AsyncRing shares a hash ring between tasks, with the `tokio` feature. It
+/// is cloned cheaply, every clone sharing the same ring. The lock is the
+/// fair one of tokio, so lookups waiting for it queue behind a pending
+/// mutation rather than starving it, and no task holds a lock of the
+/// standard library across an await.
+///
+/// The methods return futures rather than being `async fn`, the crate being
+/// of edition 2015. The futures own a handle of the ring, so they can be
+/// spawned.
+#[derive(Debug, Clone, Default)]
+pub struct AsyncRing {
+    ring: Arc<RwLock<Consistant>>,
+}
+
+impl AsyncRing {
+    /// Crete a new instance of AsyncRing sharing the given hash ring.
+    pub fn new(consistant: Consistant) -> Self {
+        AsyncRing { ring: Arc::new(RwLock::new(consistant)) }
+    }
+
+    /// Update the hash ring with the given function, once every earlier
+    /// lookup and update is done.
+    pub fn update<F, T>(&self, f: F) -> impl Future<Output = T> + Send + 'static
+        where F: FnOnce(&mut Consistant) -> T + Send + Unpin + 'static
+    {
+        Map::new(self.ring.clone().write_owned(),
+                 move |mut ring: OwnedRwLockWriteGuard<Consistant>| f(&mut ring))
+    }
+
+    /// Look the hash ring up with the given function, concurrently with the
+    /// other lookups.
+    pub fn read<F, T>(&self, f: F) -> impl Future<Output = T> + Send + 'static
+        where F: FnOnce(&Consistant) -> T + Send + Unpin + 'static
+    {
+        Map::new(self.ring.clone().read_owned(),
+                 move |ring: OwnedRwLockReadGuard<Consistant>| f(&ring))
+    }
+
+    /// Add an elment to the hash ring.
+    pub fn add<S: Into<String>>(&self, element: S) -> impl Future<Output = ()> + Send + 'static {
+        let element = element.into();
+        self.update(move |consistant| consistant.add(element))
+    }
+
+    /// Remove the given element.
+    pub fn remove<S: Into<String>>(&self, name: S) -> impl Future<Output = ()> + Send + 'static {
+        let name = name.into();
+        self.update(move |consistant| consistant.remove(name))
+    }
+
+    /// Get the cloeset element's name to the given "name".
+    pub fn get<S: Into<String>>(&self,
+                                name: S)
+                                -> impl Future<Output = Option<String>> + Send + 'static {
+        let name = name.into();
+        self.read(move |consistant| consistant.get(name))
+    }
+
+    /// Get the N cloeset distinct elements' names to the given "name".
+    pub fn get_n<S: Into<String>>(&self,
+                                  name: S,
+                                  n: usize)
+                                  -> impl Future<Output = Option<Vec<String>>> + Send + 'static {
+        let name = name.into();
+        self.read(move |consistant| consistant.get_n(name, n))
+    }
+
+    /// Get the names of the added elements, sorted.
+    pub fn members(&self) -> impl Future<Output = Vec<String>> + Send + 'static {
+        self.read(|consistant| consistant.members())
+    }
+}
+
+/// Map applies a function to the output of a future, standing for `.await`.
+struct Map<Fut, F> {
+    future: Pin<Box<Fut>>,
+    f: Option<F>,
+}
+
+impl<Fut, F> Map<Fut, F> {
+    fn new(future: Fut, f: F) -> Self {
+        Map {
+            future: Box::pin(future),
+            f: Some(f),
+        }
+    }
+}
+
+impl<Fut: Future, F: FnOnce(Fut::Output) -> T + Unpin, T> Future for Map<Fut, F> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        let this = self.get_mut();
+        match this.future.as_mut().poll(cx) {
+            Poll::Ready(output) => {
+                Poll::Ready((this.f.take().expect("future polled after completion"))(output))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use tokio::runtime::Builder;
+
+    #[test]
+    fn test_async_ring() {
+        let runtime = Builder::new_multi_thread().worker_threads(4).build().unwrap();
+        let ring = AsyncRing::new(Consistant::default());
+        runtime.block_on(ring.add("cacheA"));
+        assert_eq!(runtime.block_on(ring.get("david")), Some(String::from("cacheA")));
+
+        // Readers keep the lock busy while a writer adds elements one by one.
+        let writer = {
+            let (ring, handle) = (ring.clone(), runtime.handle().clone());
+            thread::spawn(move || {
+                for i in 0..50 {
+                    handle.block_on(ring.add(format!("cache{}", i)));
+                }
+                handle.block_on(ring.remove("cacheA"));
+            })
+        };
+        let readers: Vec<_> = (0..2000)
+            .map(|i| {
+                let key = format!("key{}", i % 100);
+                (runtime.spawn(ring.get(key.as_str())), runtime.spawn(ring.get_n(key, 2)))
+            })
+            .collect();
+        for (get, get_n) in readers {
+            assert!(runtime.block_on(get).unwrap().unwrap().starts_with("cache"));
+            let owners = runtime.block_on(get_n).unwrap().unwrap();
+            assert!(owners.len() == 2 && owners[0] != owners[1] || owners.len() == 1);
+        }
+        writer.join().unwrap();
+
+        let members = runtime.block_on(ring.members());
+        assert_eq!(members.len(), 50);
+        assert!(!members.contains(&String::from("cacheA")));
+
+        // Every lookup sees the ring as the last update left it.
+        let expected = runtime.block_on(ring.read(|consistant| consistant.clone()));
+        for i in 0..100 {
+            let key = format!("key{}", i);
+            assert_eq!(runtime.block_on(ring.get(key.as_str())), expected.get(key.as_str()));
+        }
+        assert_eq!(runtime.block_on(ring.update(|consistant| consistant.count())), 50);
+    }
+}
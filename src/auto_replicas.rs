@@ -0,0 +1,191 @@
+use consistant::Consistant;
+
+/// AutoReplicas derives the count of virtual nodes per weight unit from the
+/// membership of the hash ring, see `Consistant::set_auto_replicas`: as
+/// many as targeted while they fit under the cap on the total, fewer
+/// otherwise, but never fewer than 1.
+///
+/// The count is derived again only once it is 25% off, trading a little
+/// balance for no churn: shrinking leaves a fifth of the cap free, so that
+/// the next adds do not cross it again, and growing waits for 25% more.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AutoReplicas {
+    target: usize,
+    max_total: usize,
+}
+
+impl AutoReplicas {
+    /// Crete a new instance of AutoReplicas targeting the given count of
+    /// virtual nodes per weight unit, with at most the given total.
+    pub fn new(target_vnodes_per_member: usize, max_total_vnodes: usize) -> Self {
+        AutoReplicas {
+            target: target_vnodes_per_member.max(1),
+            max_total: max_total_vnodes,
+        }
+    }
+
+    /// Get the targeted count of virtual nodes per weight unit.
+    pub fn target_vnodes_per_member(&self) -> usize {
+        self.target
+    }
+
+    /// Get the cap on the total count of virtual nodes.
+    pub fn max_total_vnodes(&self) -> usize {
+        self.max_total
+    }
+
+    /// Get the count the hash ring should be rebuilt with, for the given one
+    /// and total weight, `None` to keep it.
+    pub(crate) fn rescale(&self, current: usize, total_weight: usize) -> Option<usize> {
+        if total_weight == 0 {
+            return if current == self.target { None } else { Some(self.target) };
+        }
+        let headroom = ((self.max_total - self.max_total / 5) / total_weight)
+            .max(1)
+            .min(self.target);
+        let rescaled = if current.saturating_mul(total_weight) > self.max_total ||
+                          current > self.target {
+            headroom.min(current)
+        } else if headroom >= current + (current / 4).max(1) {
+            headroom
+        } else {
+            current
+        };
+        if rescaled == current { None } else { Some(rescaled) }
+    }
+}
+
+/// Rescale is the rebuild of a hash ring with another count of virtual
+/// nodes, see `Consistant::last_rescale`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rescale {
+    pub from: usize,
+    pub to: usize,
+    /// The share of the keyspace which changed owner with the rebuild.
+    pub disruption: f64,
+}
+
+/// Get the share of the keyspace owned by other elements in the given hash
+/// rings.
+pub(crate) fn moved_share(before: &Consistant, after: &Consistant) -> f64 {
+    let (before, after) = (before.arcs(), after.arcs());
+    if before.is_empty() || after.is_empty() {
+        return if before.is_empty() && after.is_empty() { 0.0 } else { 1.0 };
+    }
+
+    let (mut i, mut j, mut start, mut moved) = (0, 0, 0, 0);
+    while i < before.len() && j < after.len() {
+        let end = before[i].1.min(after[j].1);
+        if before[i].2 != after[j].2 {
+            moved += end - start;
+        }
+        start = end;
+        if before[i].1 == end {
+            i += 1;
+        }
+        if after[j].1 == end {
+            j += 1;
+        }
+    }
+
+    moved as f64 / (1u64 << 32) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rescale() {
+        let auto = AutoReplicas::new(100, 10_000);
+        assert_eq!(auto.rescale(100, 0), None);
+        assert_eq!(auto.rescale(100, 50), None);
+        // Over the cap, shrinking to 80% of it.
+        assert_eq!(auto.rescale(100, 101), Some(79));
+        // But not growing back for a single element.
+        assert_eq!(auto.rescale(79, 100), None);
+        assert_eq!(auto.rescale(79, 63), Some(100));
+        assert_eq!(auto.rescale(10, 20_000), Some(1));
+        assert_eq!(auto.rescale(1, 20_000), None);
+        assert_eq!(AutoReplicas::new(20, 10_000).rescale(100, 10), Some(20));
+    }
+
+    #[test]
+    fn test_auto_replicas_cap() {
+        let mut consistant = Consistant::new(20);
+        consistant.set_auto_replicas(Some(AutoReplicas::new(100, 20_000)));
+        assert_eq!(consistant.replicas_num(), 100);
+
+        let mut rescales = 0;
+        for i in 0..2000 {
+            consistant.add(format!("cache{}", i));
+            assert!(consistant.replicas_num() * consistant.count() <= 20_000, "{}", i);
+            if let Some(rescale) = consistant.last_rescale() {
+                assert!(rescale.to < rescale.from);
+                assert!(rescale.disruption > 0.0 && rescale.disruption <= 1.0);
+                rescales += 1;
+            }
+        }
+        assert_eq!(consistant.replicas_num(), 8);
+        assert_eq!(consistant.vnodes().len(), 8 * 2000);
+        // The count is cut by a fifth or more at once.
+        assert!(rescales <= 12, "{}", rescales);
+
+        // Removing most elements grows it back.
+        consistant.retain(|element| element.len() <= 7);
+        assert_eq!(consistant.count(), 100);
+        assert_eq!(consistant.replicas_num(), 100);
+        let before = consistant.replicas_num();
+        consistant.remove("cache5");
+        assert_eq!((consistant.replicas_num(), consistant.last_rescale()), (before, None));
+
+        // A fixed count is kept.
+        consistant.set_auto_replicas(None);
+        for i in 0..300 {
+            consistant.add(format!("node{}", i));
+        }
+        assert_eq!(consistant.replicas_num(), 100);
+    }
+
+    /// Get the standard deviation of the shares of the keyspace of the
+    /// elements, relative to their mean.
+    fn relative_stddev(consistant: &Consistant) -> f64 {
+        let mut shares = vec![0u64; consistant.count()];
+        for (start, end, element) in consistant.arcs() {
+            shares[element[5..].parse::<usize>().unwrap()] += end - start;
+        }
+        let mean = (1u64 << 32) as f64 / shares.len() as f64;
+        let variance = shares.iter()
+            .map(|share| (*share as f64 - mean).powi(2))
+            .sum::<f64>() / shares.len() as f64;
+        variance.sqrt() / mean
+    }
+
+    #[test]
+    fn test_auto_replicas_balance() {
+        let mut consistant = Consistant::new(2);
+        for i in 0..10 {
+            consistant.add(format!("cache{}", i));
+        }
+        let fixed = relative_stddev(&consistant);
+        consistant.set_auto_replicas(Some(AutoReplicas::new(400, 2_000_000)));
+        assert_eq!(consistant.replicas_num(), 400);
+        assert_eq!(consistant.last_rescale().map(|rescale| rescale.from), Some(2));
+
+        let auto = relative_stddev(&consistant);
+        assert!(auto < 0.2 && auto < fixed / 2.0, "{} {}", auto, fixed);
+    }
+
+    #[test]
+    fn test_moved_share() {
+        let mut consistant = Consistant::new(20);
+        assert_eq!(moved_share(&consistant, &consistant.clone()), 0.0);
+        consistant.add("cacheA");
+        assert_eq!(moved_share(&Consistant::new(20), &consistant), 1.0);
+        consistant.add("cacheB");
+        let before = consistant.clone();
+        consistant.add("cacheC");
+        assert!((moved_share(&before, &consistant) - consistant.last_disruption().unwrap()).abs() <
+                1e-9);
+    }
+}
@@ -0,0 +1,238 @@
+use std::borrow::Cow;
+use std::collections::hash_map::HashMap;
+use std::collections::hash_set::HashSet;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower::Service;
+use consistant::Consistant;
+use error::BalanceError;
+
+/// ConsistentHashBalance dispatches requests to backend services by a hash
+/// ring of their names, with the `tower` feature. The key of a request is
+/// extracted by the given function and looked up in the ring, the request
+/// going to the first owner of `get_n` which is ready.
+///
+/// `poll_ready` polls the backends not ready yet, and is ready once any of
+/// them is. A backend which is pending, or whose `poll_ready` fails, is
+/// skipped by the requests until it is ready again, so that the keys it
+/// owns fall over to the next owners meanwhile. Backends are added and
+/// removed through the ring, so the other backends keep their keys.
+pub struct ConsistentHashBalance<S, F> {
+    ring: Consistant,
+    services: HashMap<String, S>,
+    /// The backends whose `poll_ready` returned ready since their last
+    /// request.
+    ready: HashSet<String>,
+    key: F,
+}
+
+impl<S, F> ConsistentHashBalance<S, F> {
+    /// Crete a new instance of ConsistentHashBalance without any backend,
+    /// extracting the keys of the requests with the given function.
+    pub fn new(replicas_num: usize, key: F) -> Self {
+        ConsistentHashBalance {
+            ring: Consistant::new(replicas_num),
+            services: HashMap::new(),
+            ready: HashSet::new(),
+            key,
+        }
+    }
+
+    /// Add a backend to the hash ring, replacing the service of the given
+    /// name if any.
+    pub fn add_service<N: Into<String>>(&mut self, name: N, service: S) {
+        let name = name.into();
+        if !self.ring.contains(&name) {
+            self.ring.add(name.as_str());
+        }
+        self.ready.remove(&name);
+        self.services.insert(name, service);
+    }
+
+    /// Remove the given backend, returning its service.
+    pub fn remove_service(&mut self, name: &str) -> Option<S> {
+        self.ring.remove(name);
+        self.ready.remove(name);
+        self.services.remove(name)
+    }
+
+    /// Get the hash ring of the backends.
+    pub fn ring(&self) -> &Consistant {
+        &self.ring
+    }
+}
+
+impl<S, F, Request> Service<Request> for ConsistentHashBalance<S, F>
+    where S: Service<Request>,
+          F: Fn(&Request) -> Cow<str>
+{
+    type Response = S::Response;
+    type Error = BalanceError<S::Error>;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        let mut pending = false;
+        for (name, service) in &mut self.services {
+            if self.ready.contains(name) {
+                continue;
+            }
+            match service.poll_ready(cx) {
+                Poll::Ready(Ok(())) => {
+                    self.ready.insert(name.clone());
+                }
+                Poll::Ready(Err(_)) => {}
+                Poll::Pending => pending = true,
+            }
+        }
+
+        if !self.ready.is_empty() {
+            Poll::Ready(Ok(()))
+        } else if pending {
+            Poll::Pending
+        } else {
+            Poll::Ready(Err(BalanceError::NoReadyBackend))
+        }
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let mut backend = None;
+        {
+            let key = (self.key)(&request);
+            let ready = &self.ready;
+            let _guard = self.ring.read();
+            self.ring.walk_members(&key, |element| {
+                if ready.contains(&**element) {
+                    backend = Some(String::from(&**element));
+                }
+                backend.is_some()
+            });
+        }
+
+        match backend {
+            Some(name) => {
+                self.ready.remove(&name);
+                let service = self.services.get_mut(&name).expect("a backend is in the ring");
+                ResponseFuture { future: Some(Box::pin(service.call(request))) }
+            }
+            None => ResponseFuture { future: None },
+        }
+    }
+}
+
+/// ResponseFuture is the response of a backend of `ConsistentHashBalance`.
+pub struct ResponseFuture<Fut> {
+    /// None if no backend was ready.
+    future: Option<Pin<Box<Fut>>>,
+}
+
+impl<Fut: Future<Output = Result<T, E>>, T, E> Future for ResponseFuture<Fut> {
+    type Output = Result<T, BalanceError<E>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        match self.get_mut().future {
+            Some(ref mut future) => {
+                future.as_mut().poll(cx).map(|res| res.map_err(BalanceError::Service))
+            }
+            None => Poll::Ready(Err(BalanceError::NoReadyBackend)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::task::Waker;
+    use tower_test::mock::{self, Handle, Mock, Spawn};
+
+    type Balance = Spawn<ConsistentHashBalance<Mock<String, String>, fn(&String) -> Cow<str>>>;
+    type Backends = Vec<Handle<String, String>>;
+
+    fn balance() -> (Balance, Backends) {
+        let key: fn(&String) -> Cow<str> = |request| Cow::Borrowed(request.as_str());
+        let mut balance = ConsistentHashBalance::new(20, key);
+        let mut handles = Vec::new();
+        for i in 0..5 {
+            let (service, mut handle) = mock::pair();
+            handle.allow(1000);
+            balance.add_service(format!("node-{}", i), service);
+            handles.push(handle);
+        }
+        (Spawn::new(balance), handles)
+    }
+
+    /// Send the given request, getting the backend it went to.
+    fn send(balance: &mut Balance, backends: &mut Backends, request: &str) -> String {
+        assert!(balance.poll_ready().is_ready());
+        let mut response = balance.call(String::from(request));
+        let mut backend = None;
+        for (i, handle) in backends.iter_mut().enumerate() {
+            if let Poll::Ready(Some((received, reply))) = handle.poll_request() {
+                assert_eq!((received.as_str(), backend), (request, None));
+                reply.send_response(format!("node-{}:{}", i, received));
+                backend = Some(format!("node-{}", i));
+            }
+        }
+        let backend = backend.expect("a backend received the request");
+        // Replied to already, the response is ready.
+        let mut cx = Context::from_waker(Waker::noop());
+        match Pin::new(&mut response).poll(&mut cx) {
+            Poll::Ready(res) => assert_eq!(res.unwrap(), format!("{}:{}", backend, request)),
+            Poll::Pending => panic!("the response is pending"),
+        }
+        backend
+    }
+
+    #[test]
+    fn test_consistent_hash_balance() {
+        let (mut balance, mut backends) = balance();
+        let keys: Vec<String> = (0..100).map(|i| format!("key{}", i)).collect();
+        for key in &keys {
+            let backend = send(&mut balance, &mut backends, key);
+            assert_eq!(Some(backend.as_str()), balance.get_ref().ring().get(key).as_deref());
+            assert_eq!(send(&mut balance, &mut backends, key), backend);
+        }
+
+        // Removing a backend only moves its own keys.
+        let ring = balance.get_ref().ring();
+        let owners: Vec<String> = keys.iter().map(|key| ring.get(key).unwrap()).collect();
+        balance.get_mut().remove_service("node-2").unwrap();
+        for (key, owner) in keys.iter().zip(&owners) {
+            let backend = send(&mut balance, &mut backends, key);
+            if owner != "node-2" {
+                assert_eq!(&backend, owner);
+            }
+            assert!(backend != "node-2");
+        }
+    }
+
+    #[test]
+    fn test_consistent_hash_balance_failover() {
+        let (mut balance, mut backends) = balance();
+        let owners = balance.get_ref().ring().get_n("david", 5).unwrap();
+        let index = |name: &str| name["node-".len()..].parse::<usize>().unwrap();
+
+        // The keys of a pending backend, then of a failed one, fall over to
+        // the next owners in the order of `get_n`.
+        backends[index(&owners[0])].allow(0);
+        assert_eq!(send(&mut balance, &mut backends, "david"), owners[1]);
+        backends[index(&owners[1])].send_error("unavailable");
+        assert_eq!(send(&mut balance, &mut backends, "david"), owners[2]);
+        backends[index(&owners[0])].allow(1000);
+        assert_eq!(send(&mut balance, &mut backends, "david"), owners[0]);
+
+        // Pending and failed backends leave none for the requests.
+        let (mut balance, mut backends) = self::balance();
+        for handle in &mut backends {
+            handle.allow(0);
+        }
+        assert!(balance.poll_ready().is_pending());
+        for handle in &mut backends {
+            handle.send_error("unavailable");
+        }
+        match balance.poll_ready() {
+            Poll::Ready(Err(BalanceError::NoReadyBackend)) => {}
+            _ => panic!("expected no ready backend"),
+        }
+    }
+}
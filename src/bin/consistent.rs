@@ -0,0 +1,134 @@
+//! Command line tool computing the mappings of a hash ring.
+
+extern crate consistent_rs;
+
+use std::env;
+use std::fs;
+use std::process;
+use consistent_rs::Consistant;
+
+const USAGE: &str = "usage:
+    consistent get    (--members LIST | --members-file FILE) [--replicas N] KEY
+    consistent get-n  (--members LIST | --members-file FILE) [--replicas N] -n N KEY
+    consistent map    (--members LIST | --members-file FILE) [--replicas N] --keys-file FILE
+    consistent stats  (--members LIST | --members-file FILE) [--replicas N]
+
+Members are given as a comma separated list or one per line in a file, each
+optionally weighted as name=weight, so that names such as host:port are
+kept whole.";
+
+struct Options {
+    command: String,
+    members: Vec<(String, usize)>,
+    replicas: usize,
+    n: usize,
+    keys_file: Option<String>,
+    key: Option<String>,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(err) = run(&args) {
+        eprintln!("consistent: {}", err);
+        process::exit(2);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let options = parse_args(args)?;
+
+    let mut consistant = Consistant::new(options.replicas);
+    for &(ref name, weight) in &options.members {
+        consistant.add_weighted(name.as_str(), weight);
+    }
+
+    match options.command.as_str() {
+        "get" => {
+            let key = options.key.ok_or("missing KEY")?;
+            println!("{}", consistant.get(key).unwrap_or_default());
+        }
+        "get-n" => {
+            let key = options.key.ok_or("missing KEY")?;
+            for element in consistant.get_n(key, options.n).unwrap_or_default() {
+                println!("{}", element);
+            }
+        }
+        "map" => {
+            let path = options.keys_file.ok_or("missing --keys-file")?;
+            let keys = read_file(&path)?;
+            for key in keys.lines().filter(|key| !key.is_empty()) {
+                println!("{}\t{}", key, consistant.get(key).unwrap_or_default());
+            }
+        }
+        "stats" => {
+            let bucket = &consistant.histogram(1).map_err(|err| err.to_string())?[0];
+            let mut shares: Vec<(&String, &f64)> = bucket.shares.iter().collect();
+            shares.sort_by(|a, b| a.0.cmp(b.0));
+            for (element, share) in shares {
+                println!("{}\t{:.4}", element, share);
+            }
+        }
+        command => return Err(format!("unknown command {:?}\n{}", command, USAGE)),
+    }
+
+    Ok(())
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        command: String::new(),
+        members: Vec::new(),
+        replicas: 20,
+        n: 1,
+        keys_file: None,
+        key: None,
+    };
+    let mut members = Vec::new();
+
+    let mut args = args.iter();
+    options.command = args.next().ok_or(USAGE)?.clone();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("missing value for {}", arg));
+        match arg.as_str() {
+            "--members" => members.extend(value()?.split(',').map(String::from)),
+            "--members-file" => members.extend(read_file(value()?)?.lines().map(String::from)),
+            "--replicas" => options.replicas = parse_number(arg, value()?)?,
+            "-n" => options.n = parse_number(arg, value()?)?,
+            "--keys-file" => options.keys_file = Some(value()?.clone()),
+            "-h" | "--help" => return Err(String::from(USAGE)),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}\n{}", arg, USAGE)),
+            _ if options.key.is_none() => options.key = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument {:?}", arg)),
+        }
+    }
+
+    for member in members.iter().map(|member| member.trim()).filter(|member| !member.is_empty()) {
+        options.members.push(parse_member(member)?);
+    }
+    if options.members.is_empty() {
+        return Err(String::from("no members given"));
+    }
+
+    Ok(options)
+}
+
+fn parse_member(member: &str) -> Result<(String, usize), String> {
+    match member.rfind('=') {
+        Some(i) => {
+            let weight = parse_number("weight", &member[i + 1..])?;
+            if weight == 0 {
+                return Err(format!("member {:?} has a zero weight", member));
+            }
+            Ok((String::from(&member[..i]), weight))
+        }
+        None => Ok((String::from(member), 1)),
+    }
+}
+
+fn parse_number(name: &str, value: &str) -> Result<usize, String> {
+    value.parse().map_err(|_| format!("invalid {} {:?}", name, value))
+}
+
+fn read_file(path: &str) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|err| format!("can not read {}: {}", path, err))
+}
@@ -0,0 +1,463 @@
+use std::collections::hash_map::HashMap;
+use std::fmt;
+use std::sync::{Arc, PoisonError};
+use std::time::{Duration, Instant};
+use consistant::Consistant;
+use error::Error;
+use fractional::{admits, MILLIONTHS};
+use sync::{RwLock, RwLockReadGuard};
+
+/// Clock tells the time to the circuit breakers, see
+/// `CircuitBreaker::clock`, so that tests can move it rather than sleep.
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Get the current time.
+    fn now(&self) -> Instant;
+}
+
+/// SystemClock is the clock of `Instant::now`, the default one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// CircuitBreaker configures the circuit breakers of the elements, see
+/// `Consistant::set_circuit_breaker`.
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cool_down: Duration,
+    /// The keys a half-open element admits, in millionths.
+    half_open: u32,
+    clock: Arc<dyn Clock>,
+}
+
+impl CircuitBreaker {
+    /// Crete a new instance of CircuitBreaker tripping after the given count
+    /// of failures in a row, at least 1, and half-open after the given
+    /// cool-down. A half-open element admits a tenth of its keys.
+    pub fn new(failure_threshold: u32, cool_down: Duration) -> CircuitBreaker {
+        CircuitBreaker {
+            failure_threshold: failure_threshold.max(1),
+            cool_down,
+            half_open: MILLIONTHS / 10,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Set the share of its keys a half-open element admits, between 0 and
+    /// 1, the same keys whenever it is half-open.
+    pub fn half_open_share(mut self, share: f64) -> CircuitBreaker {
+        let share = if share.is_nan() { 0.0 } else { share.clamp(0.0, 1.0) };
+        self.half_open = (share * f64::from(MILLIONTHS)).round() as u32;
+        self
+    }
+
+    /// Set the clock telling the time to the breakers.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> CircuitBreaker {
+        self.clock = clock;
+        self
+    }
+
+    /// Get the count of failures in a row tripping a breaker.
+    pub fn failure_threshold(&self) -> u32 {
+        self.failure_threshold
+    }
+
+    /// Get the time a tripped breaker waits before being half-open.
+    pub fn cool_down(&self) -> Duration {
+        self.cool_down
+    }
+}
+
+/// BreakerState is the state of the circuit breaker of an element, see
+/// `Consistant::breaker_state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    /// The element gets its keys.
+    Closed,
+    /// The element failed too many times in a row, and gets none of its
+    /// keys until the cool-down is over.
+    Open,
+    /// The cool-down is over, and the element gets a share of its keys
+    /// until the next report closes or trips the breaker again.
+    HalfOpen,
+}
+
+/// Breakers holds the circuit breakers of the elements. A breaker closed
+/// without any failure is not held.
+#[derive(Debug, Default)]
+pub(crate) struct Breakers {
+    config: Option<CircuitBreaker>,
+    states: RwLock<HashMap<Arc<str>, Breaker>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Breaker {
+    Closed { failures: u32 },
+    /// Half-open once the cool-down from the given time is over.
+    Open { since: Instant },
+}
+
+impl Clone for Breakers {
+    fn clone(&self) -> Breakers {
+        Breakers {
+            config: self.config.clone(),
+            states: RwLock::new(self.read().clone()),
+        }
+    }
+}
+
+impl Breakers {
+    pub(crate) fn remove(&mut self, element: &str) {
+        self.states_mut().remove(element);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.states_mut().clear();
+    }
+
+    fn read<'a>(&'a self) -> RwLockReadGuard<'a, HashMap<Arc<str>, Breaker>> {
+        self.states.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn states_mut(&mut self) -> &mut HashMap<Arc<str>, Breaker> {
+        self.states.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Call the given function with a check of whether the circuit breaker
+    /// of an element lets the key of the given checksum through, every
+    /// element passing without circuit breakers.
+    pub(crate) fn with_admission<R, F>(&self, sum: u32, f: F) -> R
+        where F: FnOnce(&dyn Fn(&str) -> bool) -> R
+    {
+        let config = match self.config {
+            Some(ref config) => config,
+            None => return f(&|_| true),
+        };
+        let now = config.clock.now();
+        let states = self.read();
+        f(&|element| Breakers::admitted(config, states.get(element), now, element, sum))
+    }
+
+    /// Check whether the given element of the given breaker lets the key of
+    /// the given checksum through at the given time, a half-open element
+    /// admitting its share of keys.
+    fn admitted(config: &CircuitBreaker,
+                breaker: Option<&Breaker>,
+                now: Instant,
+                element: &str,
+                sum: u32)
+                -> bool {
+        match Breakers::state(config, breaker, now) {
+            BreakerState::Closed => true,
+            BreakerState::Open => false,
+            BreakerState::HalfOpen => admits(element, config.half_open, sum),
+        }
+    }
+
+    /// Get the state of the given element at the given time.
+    fn state(config: &CircuitBreaker, breaker: Option<&Breaker>, now: Instant) -> BreakerState {
+        match breaker {
+            Some(&Breaker::Open { since }) if now < since + config.cool_down => {
+                BreakerState::Open
+            }
+            Some(&Breaker::Open { .. }) => BreakerState::HalfOpen,
+            _ => BreakerState::Closed,
+        }
+    }
+
+    /// Record a report of the given element.
+    fn report(&self, element: &Arc<str>, success: bool) {
+        let config = match self.config {
+            Some(ref config) => config,
+            None => return,
+        };
+        let now = config.clock.now();
+        let mut states = self.states.write().unwrap_or_else(PoisonError::into_inner);
+        let breaker = states.get(element).cloned();
+        let next = match (Breakers::state(config, breaker.as_ref(), now), success) {
+            (_, true) => None,
+            // Failures of an open element, as of requests sent before it
+            // tripped, do not delay its recovery.
+            (BreakerState::Open, false) => breaker,
+            (BreakerState::HalfOpen, false) => Some(Breaker::Open { since: now }),
+            (BreakerState::Closed, false) => {
+                let failures = match breaker {
+                    Some(Breaker::Closed { failures }) => failures + 1,
+                    _ => 1,
+                };
+                if failures >= config.failure_threshold {
+                    Some(Breaker::Open { since: now })
+                } else {
+                    Some(Breaker::Closed { failures })
+                }
+            }
+        };
+        match next {
+            Some(next) => {
+                states.insert(element.clone(), next);
+            }
+            None => {
+                states.remove(element);
+            }
+        }
+    }
+}
+
+impl Consistant {
+    /// Set the circuit breakers of the elements, or drop them for `None`,
+    /// which forgets every report. An element failing the given count of
+    /// times in a row is tripped: `get_healthy` skips it for the next owners
+    /// until the cool-down is over, then admits a share of its keys, the
+    /// next success closing the breaker and the next failure tripping it
+    /// again. The virtual nodes never change, so once the breakers are
+    /// closed the keys go back exactly where they were. The breakers are
+    /// dropped along with their element, and are not serialized.
+    pub fn set_circuit_breaker(&mut self, breaker: Option<CircuitBreaker>) {
+        let breakers = self.breakers_mut();
+        breakers.config = breaker;
+        breakers.clear();
+    }
+
+    /// Get the configuration of the circuit breakers, see
+    /// `set_circuit_breaker`.
+    pub fn circuit_breaker(&self) -> Option<&CircuitBreaker> {
+        self.breakers().config.as_ref()
+    }
+
+    /// Report a failure of the given element to its circuit breaker. Reports
+    /// take no write lock of the hash ring, and do nothing without circuit
+    /// breakers.
+    pub fn report_failure(&self, member: &str) -> Result<(), Error> {
+        self.report_health(member, false)
+    }
+
+    /// Report a success of the given element to its circuit breaker, which
+    /// closes it.
+    pub fn report_success(&self, member: &str) -> Result<(), Error> {
+        self.report_health(member, true)
+    }
+
+    fn report_health(&self, member: &str, success: bool) -> Result<(), Error> {
+        let _guard = self.read();
+        let slots = self.slots();
+        match slots.index(member) {
+            Some(owner) => {
+                self.breakers().report(slots.name(owner), success);
+                Ok(())
+            }
+            None => Err(Error::UnknownMember(String::from(member))),
+        }
+    }
+
+    /// Get the state of the circuit breaker of the given element, closed
+    /// without circuit breakers.
+    pub fn breaker_state(&self, name: &str) -> Option<BreakerState> {
+        if !self.contains(name) {
+            return None;
+        }
+        let breakers = self.breakers();
+        Some(match breakers.config {
+            Some(ref config) => {
+                Breakers::state(config, breakers.read().get(name), config.clock.now())
+            }
+            None => BreakerState::Closed,
+        })
+    }
+
+    /// Get the cloeset element's name to the given "name" whose circuit
+    /// breaker lets it through, the elements being walked in the order of
+    /// `get_n`. A half-open element only gets its share of keys, always the
+    /// same ones. If no element lets the key through, the fallback is
+    /// returned, see `set_fallback`, or without one the element of `get`, so
+    /// that keys still go somewhere. The empty hash ring returns the
+    /// fallback, as `get` does.
+    pub fn get_healthy<S: AsRef<str>>(&self, name: S) -> Option<String> {
+        let mut owners = self.get_n_healthy(name.as_ref(), 1)?;
+        match owners.pop() {
+            Some(owner) => Some(owner),
+            None => self.get(name),
+        }
+    }
+
+    /// Get the N cloeset elements' names to the given "name" whose circuit
+    /// breakers let them through, as `get_healthy`, or all of them if there
+    /// are not as many. If none lets the key through, the fallback is
+    /// returned alone, or nothing without one.
+    pub fn get_n_healthy<S: AsRef<str>>(&self, name: S, n: usize) -> Option<Vec<String>> {
+        let breakers = self.breakers();
+        let members = self.count();
+        if breakers.config.is_none() || members == 0 {
+            return self.get_n(name, n);
+        }
+        let name = name.as_ref();
+        let _guard = self.read();
+        let sum = self.pin(name).0;
+        let mut owners = Vec::with_capacity(n.min(members));
+        if n > 0 {
+            breakers.with_admission(sum, |admitted| {
+                self.walk_members(name, |element| {
+                    if admitted(element) {
+                        owners.push(String::from(&**element));
+                    }
+                    owners.len() == n
+                })
+            });
+            if owners.is_empty() {
+                owners.extend(self.fallback().map(String::from));
+            }
+        }
+
+        Some(owners)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// ManualClock only moves when told.
+    #[derive(Debug)]
+    struct ManualClock(Mutex<Instant>);
+
+    impl ManualClock {
+        fn advance(&self, by: Duration) {
+            *self.0.lock().unwrap() += by;
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> Instant {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    fn owners(consistant: &Consistant, keys: &[String]) -> Vec<String> {
+        keys.iter().map(|key| consistant.get_healthy(key).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        let clock = Arc::new(ManualClock(Mutex::new(Instant::now())));
+        let cool_down = Duration::from_secs(30);
+        let mut consistant = Consistant::new(20);
+        for i in 0..5 {
+            consistant.add(format!("node-{}", i));
+        }
+        let keys: Vec<String> = (0..2000).map(|i| format!("key{}", i)).collect();
+        let initial: Vec<String> = keys.iter().map(|key| consistant.get(key).unwrap()).collect();
+        consistant.report_failure("node-2").unwrap();
+        assert_eq!(consistant.breaker_state("node-2"), Some(BreakerState::Closed));
+        consistant.set_circuit_breaker(Some(CircuitBreaker::new(3, cool_down)
+            .half_open_share(0.5)
+            .clock(clock.clone())));
+        assert_eq!(consistant.circuit_breaker().unwrap().failure_threshold(), 3);
+        assert_eq!(owners(&consistant, &keys), initial);
+        let vnodes = consistant.vnodes();
+
+        // Closed: failures below the threshold, or broken by a success, do
+        // not trip the breaker.
+        consistant.report_failure("node-2").unwrap();
+        consistant.report_failure("node-2").unwrap();
+        consistant.report_success("node-2").unwrap();
+        consistant.report_failure("node-2").unwrap();
+        consistant.report_failure("node-2").unwrap();
+        assert_eq!(consistant.breaker_state("node-2"), Some(BreakerState::Closed));
+        assert_eq!(owners(&consistant, &keys), initial);
+
+        // Open: the keys of the element go to their next owners.
+        consistant.report_failure("node-2").unwrap();
+        assert_eq!(consistant.breaker_state("node-2"), Some(BreakerState::Open));
+        for (key, owner) in keys.iter().zip(&initial) {
+            let healthy = consistant.get_healthy(key).unwrap();
+            if owner == "node-2" {
+                assert_eq!(healthy, consistant.get_n(key, 2).unwrap()[1]);
+            } else {
+                assert_eq!(&healthy, owner);
+            }
+        }
+        clock.advance(cool_down / 2);
+        consistant.report_failure("node-2").unwrap();
+        clock.advance(cool_down / 2);
+
+        // Half-open: a share of its keys, always the same, comes back.
+        assert_eq!(consistant.breaker_state("node-2"), Some(BreakerState::HalfOpen));
+        let half_open = owners(&consistant, &keys);
+        assert_eq!(owners(&consistant, &keys), half_open);
+        let (back, total) = keys.iter()
+            .zip(&initial)
+            .filter(|&(_, owner)| owner == "node-2")
+            .fold((0, 0), |(back, total), (key, _)| {
+                let back = back + (consistant.get_healthy(key).unwrap() == "node-2") as usize;
+                (back, total + 1)
+            });
+        assert!(back * 3 > total && back * 3 < total * 2, "{} of {}", back, total);
+
+        // A failure trips it again, and a success after the cool-down
+        // closes it, the keys going back exactly where they were.
+        consistant.report_failure("node-2").unwrap();
+        assert_eq!(consistant.breaker_state("node-2"), Some(BreakerState::Open));
+        assert!(owners(&consistant, &keys).iter().all(|owner| owner != "node-2"));
+        clock.advance(cool_down);
+        consistant.report_success("node-2").unwrap();
+        assert_eq!(consistant.breaker_state("node-2"), Some(BreakerState::Closed));
+        assert_eq!(owners(&consistant, &keys), initial);
+        assert_eq!(consistant.vnodes(), vnodes);
+    }
+
+    #[test]
+    fn test_circuit_breaker_members() {
+        let mut consistant = Consistant::new(20);
+        assert_eq!(consistant.get_healthy("david"), None);
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+        consistant.set_circuit_breaker(Some(CircuitBreaker::new(0, Duration::from_secs(60))));
+        assert_eq!(consistant.report_failure("cacheX"),
+                   Err(Error::UnknownMember(String::from("cacheX"))));
+        assert_eq!(consistant.report_success("cacheX"),
+                   Err(Error::UnknownMember(String::from("cacheX"))));
+        assert_eq!(consistant.breaker_state("cacheX"), None);
+
+        // Every element tripped, the keys go where `get` sends them.
+        consistant.report_failure("cacheA").unwrap();
+        consistant.report_failure("cacheB").unwrap();
+        assert_eq!(consistant.get_n_healthy("david", 2), Some(vec![]));
+        assert_eq!(consistant.get_healthy("david"), consistant.get("david"));
+        assert_eq!(consistant.clone().breaker_state("cacheA"), Some(BreakerState::Open));
+
+        // The breaker goes along with its element.
+        consistant.remove("cacheA");
+        consistant.add("cacheA");
+        assert_eq!(consistant.breaker_state("cacheA"), Some(BreakerState::Closed));
+        assert_eq!(consistant.get_n_healthy("david", 2), Some(vec![String::from("cacheA")]));
+        consistant.set_circuit_breaker(None);
+        assert_eq!(consistant.get_n_healthy("david", 2), consistant.get_n("david", 2));
+    }
+
+    #[test]
+    fn test_circuit_breaker_fallback() {
+        let mut consistant = Consistant::new(20);
+        consistant.set_circuit_breaker(Some(CircuitBreaker::new(0, Duration::from_secs(60))));
+        consistant.set_fallback(Some(String::from("local-cache")));
+        let fallback = || Some(String::from("local-cache"));
+        assert_eq!(consistant.get_healthy("david"), fallback());
+        assert_eq!(consistant.get_n_healthy("david", 2), Some(vec![String::from("local-cache")]));
+        assert_eq!(consistant.get_n_healthy("david", 0), None);
+
+        // Every element tripped, the keys go to the fallback.
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+        assert_eq!(consistant.get_healthy("david"), consistant.get("david"));
+        consistant.report_failure("cacheA").unwrap();
+        consistant.report_failure("cacheB").unwrap();
+        assert_eq!(consistant.get_healthy("david"), fallback());
+        assert_eq!(consistant.get_n_healthy("david", 2), Some(vec![String::from("local-cache")]));
+        consistant.report_success("cacheB").unwrap();
+        assert_eq!(consistant.get_healthy("david"), Some(String::from("cacheB")));
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::btree_map::BTreeMap;
+use std::collections::hash_map::HashMap;
+use std::ops::Bound;
+use std::sync::Arc;
+use handle::Slots;
+use placement::{checksum, PlacementStrategy};
+
+/// BTreeRing is a hash ring keeping its virtual nodes in a single
+/// `BTreeMap` from their hashes to the slots of their elements, mapping keys
+/// as a `Consistant` of the same configuration and history does.
+///
+/// There is nothing to sort nor to keep in step: adds and removes take
+/// O(log n) per virtual node, and every lookup walks the tree, which is
+/// slower than the binary search of the sorted segments of `Consistant` on
+/// rings read far more often than changed. See the `BTreeRing` benchmarks of
+/// `benches/lookup.rs`.
+///
+/// As in `Consistant`, a virtual node colliding with one of another element
+/// is taken over by the element added last, and is kept by it when the other
+/// one is removed.
+#[derive(Debug, Clone)]
+pub struct BTreeRing {
+    replicas_num: usize,
+    members: HashMap<Arc<str>, usize>,
+    ring: BTreeMap<u32, u32>,
+    slots: Slots,
+    placement: PlacementStrategy,
+    seed: u64,
+}
+
+impl Default for BTreeRing {
+    fn default() -> BTreeRing {
+        BTreeRing::new(20)
+    }
+}
+
+impl BTreeRing {
+    /// Crete a new instance of BTreeRing.
+    pub fn new(replicas_num: usize) -> Self {
+        Self::with_config(replicas_num, PlacementStrategy::default(), 0)
+    }
+
+    /// Crete a new instance of BTreeRing placing virtual nodes with the
+    /// given strategy, see `Consistant::with_placement`.
+    pub fn with_placement(replicas_num: usize, placement: PlacementStrategy) -> Self {
+        Self::with_config(replicas_num, placement, 0)
+    }
+
+    /// Crete a new instance of BTreeRing of the given seed, see
+    /// `Consistant::with_seed`.
+    pub fn with_seed(replicas_num: usize, seed: u64) -> Self {
+        Self::with_config(replicas_num, PlacementStrategy::default(), seed)
+    }
+
+    fn with_config(replicas_num: usize, placement: PlacementStrategy, seed: u64) -> Self {
+        BTreeRing {
+            replicas_num,
+            members: HashMap::new(),
+            ring: BTreeMap::new(),
+            slots: Slots::default(),
+            placement,
+            seed,
+        }
+    }
+
+    /// Get the count of virtual nodes per weight unit.
+    pub fn replicas_num(&self) -> usize {
+        self.replicas_num
+    }
+
+    /// Get the count of added elements.
+    pub fn count(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Get the names of the added elements, sorted.
+    pub fn members(&self) -> Vec<String> {
+        let mut members: Vec<String> =
+            self.members.keys().map(|element| String::from(&**element)).collect();
+        members.sort();
+        members
+    }
+
+    /// Check whether the given element is in the hash ring.
+    pub fn contains(&self, name: &str) -> bool {
+        self.members.contains_key(name)
+    }
+
+    /// Get the count of virtual nodes, those taken over by other elements
+    /// counted once.
+    pub fn vnodes(&self) -> usize {
+        self.ring.len()
+    }
+
+    /// Add an elment to the hash ring.
+    pub fn add<S: Into<String>>(&mut self, element: S) {
+        self.add_weighted(element, 1)
+    }
+
+    /// Add an elment to the hash ring with the given weight, see
+    /// `Consistant::add_weighted`. A weight of 0 adds nothing.
+    pub fn add_weighted<S: Into<String>>(&mut self, element: S, weight: usize) {
+        let element = element.into();
+        if weight == 0 || self.members.contains_key(element.as_str()) {
+            return;
+        }
+        let points = self.placement.points(self.seed, &element, self.replicas_num * weight);
+        let element = Arc::<str>::from(element);
+        let owner = self.slots.insert(element.clone());
+        for sum in points {
+            self.ring.insert(sum, owner);
+        }
+        self.members.insert(element, weight);
+    }
+
+    /// Remove the given element.
+    pub fn remove<S: AsRef<str>>(&mut self, name: S) {
+        let name = name.as_ref();
+        let weight = match self.members.remove(name) {
+            Some(weight) => weight,
+            None => return,
+        };
+        let owner = self.slots.index(name).unwrap();
+        for sum in self.placement.points(self.seed, name, self.replicas_num * weight) {
+            // Virtual nodes taken over by other elements are kept.
+            if self.ring.get(&sum) == Some(&owner) {
+                self.ring.remove(&sum);
+            }
+        }
+        self.slots.remove(name);
+    }
+
+    /// Get the cloeset element's name to the given "name".
+    pub fn get<S: AsRef<str>>(&self, name: S) -> Option<String> {
+        self.successors(name.as_ref())
+            .next()
+            .map(|owner| String::from(&**self.slots.name(owner)))
+    }
+
+    /// Get the N cloeset elements' names to the given "name".
+    pub fn get_n<S: AsRef<str>>(&self, name: S, n: usize) -> Option<Vec<String>> {
+        if n == 0 || self.ring.is_empty() {
+            return None;
+        }
+        let count = n.min(self.members.len());
+        let mut owners = Vec::with_capacity(count);
+        for owner in self.successors(name.as_ref()) {
+            if owners.len() == count {
+                break;
+            }
+            if !owners.contains(&owner) {
+                owners.push(owner);
+            }
+        }
+
+        Some(owners.into_iter().map(|owner| String::from(&**self.slots.name(owner))).collect())
+    }
+
+    /// Iterate over the owners of the virtual nodes following the checksum
+    /// of the given key, once around the hash ring. The first virtual node
+    /// of a hash greater than the checksum owns the key, as in `Consistant`.
+    fn successors<'a>(&'a self, key: &str) -> impl Iterator<Item = u32> + 'a {
+        let sum = checksum(self.seed, key.as_bytes());
+        self.ring
+            .range((Bound::Excluded(sum), Bound::Unbounded))
+            .chain(self.ring.range(..=sum))
+            .map(|(_, owner)| *owner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consistant::Consistant;
+
+    #[test]
+    fn test_btree_ring() {
+        let mut ring = BTreeRing::default();
+        assert_eq!(ring.get("david"), None);
+        assert_eq!(ring.get_n("david", 2), None);
+
+        let mut consistant = Consistant::default();
+        for (element, weight) in &[("cacheA", 1), ("cacheB", 2), ("cacheC", 0), ("cacheD", 1)] {
+            ring.add_weighted(*element, *weight);
+            consistant.add_weighted(*element, *weight);
+        }
+        ring.add_weighted("cacheA", 3);
+        assert_eq!(ring.members(), ["cacheA", "cacheB", "cacheD"]);
+        assert_eq!(ring.vnodes(), 80);
+        assert_eq!(ring.get_n("david", 0), None);
+        assert_eq!(ring.get_n("david", 5).unwrap().len(), 3);
+
+        ring.remove("cacheA");
+        ring.remove("cacheA");
+        consistant.remove("cacheA");
+        assert!(!ring.contains("cacheA"));
+        assert_eq!(ring.vnodes(), 60);
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            assert_eq!(ring.get(&key), consistant.get(&key));
+            assert_eq!(ring.get_n(&key, 2), consistant.get_n(&key, 2));
+        }
+    }
+
+    #[test]
+    fn test_btree_ring_config() {
+        let mut ring = BTreeRing::with_seed(10, 42);
+        let mut consistant = Consistant::with_seed(10, 42);
+        let mut hashed = BTreeRing::with_placement(10, PlacementStrategy::DoubleHash);
+        let mut double = Consistant::with_placement(10, PlacementStrategy::DoubleHash);
+        for i in 0..8 {
+            ring.add(format!("node-{}", i));
+            consistant.add(format!("node-{}", i));
+            hashed.add(format!("node-{}", i));
+            double.add(format!("node-{}", i));
+        }
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            assert_eq!(ring.get(&key), consistant.get(&key));
+            assert_eq!(hashed.get_n(&key, 3), double.get_n(&key, 3));
+        }
+    }
+
+    #[test]
+    fn test_taken_over_vnodes() {
+        // The virtual nodes of both names hash to 2099223602.
+        let mut ring = BTreeRing::new(1);
+        let mut consistant = Consistant::new(1);
+        for element in &["qvmyidpfal", "vkhlkcscuh", "cacheA"] {
+            ring.add(*element);
+            consistant.add(*element);
+        }
+        assert_eq!(ring.vnodes(), 2);
+        let keys: Vec<String> = (0..100).map(|i| format!("key{}", i)).collect();
+        assert!(keys.iter().any(|key| ring.get(key) == Some(String::from("vkhlkcscuh"))));
+        for key in &keys {
+            assert_eq!(ring.get(key), consistant.get(key));
+        }
+
+        ring.remove("qvmyidpfal");
+        consistant.remove("qvmyidpfal");
+        assert_eq!(ring.vnodes(), 2);
+        ring.remove("vkhlkcscuh");
+        consistant.remove("vkhlkcscuh");
+        for key in &keys {
+            assert_eq!(ring.get(key), Some(String::from("cacheA")));
+            assert_eq!(ring.get(key), consistant.get(key));
+        }
+    }
+}
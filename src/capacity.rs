@@ -0,0 +1,161 @@
+use std::collections::hash_map::HashMap;
+use consistant::Consistant;
+
+/// WeightDerivationOpts configures how `derive_weights` turns capacities
+/// into weights. By default, the smallest capacity is of weight 1, and no
+/// weight is capped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WeightDerivationOpts {
+    /// The weight of the smallest capacity, the others being scaled from
+    /// it: the higher, the closer the weights follow the capacities.
+    pub granularity: usize,
+    /// The smallest weight, for the smallest elements not to be starved.
+    pub min_weight: usize,
+    /// The largest weight, if any.
+    pub max_weight: Option<usize>,
+}
+
+impl Default for WeightDerivationOpts {
+    fn default() -> WeightDerivationOpts {
+        WeightDerivationOpts {
+            granularity: 1,
+            min_weight: 1,
+            max_weight: None,
+        }
+    }
+}
+
+impl WeightDerivationOpts {
+    /// Crete a new instance of WeightDerivationOpts, of the default options.
+    pub fn new() -> WeightDerivationOpts {
+        WeightDerivationOpts::default()
+    }
+
+    /// Set the weight of the smallest capacity.
+    pub fn granularity(mut self, granularity: usize) -> WeightDerivationOpts {
+        self.granularity = granularity;
+        self
+    }
+
+    /// Set the smallest weight.
+    pub fn min_weight(mut self, weight: usize) -> WeightDerivationOpts {
+        self.min_weight = weight;
+        self
+    }
+
+    /// Set the largest weight.
+    pub fn max_weight(mut self, weight: usize) -> WeightDerivationOpts {
+        self.max_weight = Some(weight);
+        self
+    }
+}
+
+/// Derive the weights of the elements from their capacities, in any unit,
+/// so that services given the same capacities derive the same weights. The
+/// weight of an element is, in 64-bit floating point:
+///
+/// ```text
+/// round(capacity / smallest capacity * granularity)
+/// ```
+///
+/// rounded half away from zero, then raised to `min_weight` and lowered to
+/// `max_weight`, no weight being below 1. The elements of a capacity which
+/// is not a positive finite number are left out.
+pub fn derive_weights(capacities: &HashMap<String, f64>,
+                      opts: WeightDerivationOpts)
+                      -> HashMap<String, usize> {
+    let valid = |capacity: f64| capacity.is_finite() && capacity > 0.0;
+    let smallest = capacities.values()
+        .cloned()
+        .filter(|&capacity| valid(capacity))
+        .fold(f64::INFINITY, f64::min);
+    capacities.iter()
+        .filter(|&(_, &capacity)| valid(capacity))
+        .map(|(element, &capacity)| {
+            let weight = (capacity / smallest * opts.granularity as f64).round();
+            let weight = (weight.min(usize::MAX as f64) as usize).max(opts.min_weight).max(1);
+            (element.clone(), opts.max_weight.map_or(weight, |max| weight.min(max.max(1))))
+        })
+        .collect()
+}
+
+impl Consistant {
+    /// Build a hash ring of the given count of virtual nodes per unit of
+    /// weight, of the elements of the given capacities weighted by
+    /// `derive_weights`. The elements are added sorted by name, so that
+    /// the same capacities build the same hash ring.
+    pub fn from_capacities(capacities: &HashMap<String, f64>,
+                           replicas: usize,
+                           opts: WeightDerivationOpts)
+                           -> Consistant {
+        let mut weights: Vec<(String, usize)> = derive_weights(capacities, opts)
+            .into_iter()
+            .collect();
+        weights.sort();
+        let mut consistant = Consistant::new(replicas);
+        for (element, weight) in weights {
+            consistant.add_weighted(element, weight);
+        }
+        consistant
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capacities(capacities: &[(&str, f64)]) -> HashMap<String, f64> {
+        capacities.iter().map(|&(element, capacity)| (String::from(element), capacity)).collect()
+    }
+
+    fn sorted(weights: HashMap<String, usize>) -> Vec<(String, usize)> {
+        let mut weights: Vec<(String, usize)> = weights.into_iter().collect();
+        weights.sort();
+        weights
+    }
+
+    #[test]
+    fn test_derive_weights() {
+        let trio = capacities(&[("cacheA", 100.0), ("cacheB", 200.0), ("cacheC", 400.0)]);
+        assert_eq!(sorted(derive_weights(&trio, WeightDerivationOpts::new())),
+                   [(String::from("cacheA"), 1), (String::from("cacheB"), 2),
+                    (String::from("cacheC"), 4)]);
+
+        // Halves round up, under them down.
+        let edges = capacities(&[("a", 100.0), ("b", 149.0), ("c", 150.0), ("d", 249.9),
+                                 ("e", 250.0), ("f", 0.0), ("g", f64::NAN), ("h", -1.0)]);
+        let weights = sorted(derive_weights(&edges, WeightDerivationOpts::new()));
+        assert_eq!(weights.iter().map(|weight| weight.1).collect::<Vec<_>>(), [1, 1, 2, 2, 3]);
+        let weights = sorted(derive_weights(&edges, WeightDerivationOpts::new().granularity(4)));
+        assert_eq!(weights.iter().map(|weight| weight.1).collect::<Vec<_>>(),
+                   [4, 6, 6, 10, 10]);
+        let opts = WeightDerivationOpts::new().granularity(4).min_weight(5).max_weight(8);
+        let weights = sorted(derive_weights(&edges, opts));
+        assert_eq!(weights.iter().map(|weight| weight.1).collect::<Vec<_>>(), [5, 6, 6, 8, 8]);
+        let opts = WeightDerivationOpts::new().granularity(0).min_weight(0).max_weight(0);
+        assert!(derive_weights(&edges, opts).values().all(|&weight| weight == 1));
+        assert!(derive_weights(&HashMap::new(), WeightDerivationOpts::new()).is_empty());
+    }
+
+    #[test]
+    fn test_from_capacities() {
+        let inventory = capacities(&[("cacheA", 100.0), ("cacheB", 200.0), ("cacheC", 400.0),
+                                     ("cacheD", 300.0)]);
+        let opts = WeightDerivationOpts::new();
+        let consistant = Consistant::from_capacities(&inventory, 200, opts);
+        assert_eq!(consistant.weight("cacheC"), Some(4));
+        assert_eq!(consistant.vnodes(),
+                   Consistant::from_capacities(&inventory, 200, opts).vnodes());
+
+        // The shares of the keyspace follow the capacities.
+        let mut shares = HashMap::new();
+        for (start, end, element) in consistant.arcs() {
+            *shares.entry(String::from(&*element)).or_insert(0.0) +=
+                (end - start) as f64 / (1u64 << 32) as f64;
+        }
+        for (element, capacity) in &inventory {
+            let share = shares[element] / (capacity / 1000.0);
+            assert!((share - 1.0).abs() < 0.2, "{} {}", element, share);
+        }
+    }
+}
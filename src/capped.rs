@@ -0,0 +1,114 @@
+use std::collections::hash_map::HashMap;
+use std::sync::Arc;
+use consistant::Consistant;
+
+/// CappedAssignment is the assignment of items to elements of
+/// `Consistant::assign_capped`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CappedAssignment<S> {
+    /// The items of every element assigned any, in their input order.
+    pub assigned: HashMap<String, Vec<S>>,
+    /// The items no element had room for, in their input order.
+    pub unassigned: Vec<S>,
+}
+
+impl Consistant {
+    /// Assign each of the given items to its cloeset element unless it is
+    /// assigned `cap` items already, in which case it spills to the next
+    /// element with room in the order of `get_n`. The items are assigned in
+    /// their input order, so the assignment only depends on it. Once every
+    /// element is at the cap, or if the hash ring is empty, the items left
+    /// are unassigned.
+    pub fn assign_capped<I, S>(&self, items: I, cap: usize) -> CappedAssignment<S>
+        where I: IntoIterator<Item = S>,
+              S: AsRef<str>
+    {
+        let members = self.count();
+        let _guard = self.read();
+        let mut assignment = CappedAssignment {
+            assigned: HashMap::new(),
+            unassigned: Vec::new(),
+        };
+        let mut full = 0;
+        for item in items {
+            let mut owner: Option<Arc<str>> = None;
+            if full < members && cap > 0 {
+                let assigned = &assignment.assigned;
+                self.walk_members(item.as_ref(), |element| {
+                    if assigned.get(&**element).map_or(0, Vec::len) < cap {
+                        owner = Some(element.clone());
+                    }
+                    owner.is_some()
+                });
+            }
+            match owner {
+                Some(element) => {
+                    let items = assignment.assigned.entry(String::from(&*element)).or_default();
+                    items.push(item);
+                    if items.len() == cap {
+                        full += 1;
+                    }
+                }
+                None => assignment.unassigned.push(item),
+            }
+        }
+
+        assignment
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assign_capped() {
+        let mut consistant = Consistant::new(20);
+        for i in 0..10 {
+            consistant.add_weighted(format!("node-{}", i), 1 + i % 3);
+        }
+        let items: Vec<String> = (0..1000).map(|i| format!("job-{}", i)).collect();
+
+        for &cap in &[120, 200, 50] {
+            let assignment = consistant.assign_capped(&items, cap);
+            assert_eq!(assignment, consistant.assign_capped(&items, cap));
+            let mut owners = HashMap::new();
+            for (element, assigned) in &assignment.assigned {
+                assert!(assigned.len() <= cap, "{} has {}", element, assigned.len());
+                for item in assigned {
+                    owners.insert(item.as_str(), element.as_str());
+                }
+            }
+
+            // Replayed in order, every item goes to the first with room.
+            let mut counts: HashMap<String, usize> = HashMap::new();
+            for item in &items {
+                let room = consistant.get_n(item, 10)
+                    .unwrap()
+                    .into_iter()
+                    .find(|element| counts.get(element).cloned().unwrap_or(0) < cap);
+                let primary = consistant.get(item).unwrap();
+                if counts.get(&primary).cloned().unwrap_or(0) < cap {
+                    assert_eq!(owners[item.as_str()], primary);
+                }
+                assert_eq!(owners.get(item.as_str()).cloned(), room.as_deref());
+                if let Some(element) = room {
+                    *counts.entry(element).or_insert(0) += 1;
+                }
+            }
+
+            let capacity = 10 * cap;
+            assert_eq!(owners.len(), capacity.min(items.len()));
+            let unassigned: Vec<&String> = items[capacity.min(items.len())..].iter().collect();
+            assert_eq!(assignment.unassigned, unassigned);
+        }
+
+        let plain = consistant.assign_capped(&items, items.len());
+        assert!(plain.unassigned.is_empty());
+        for (element, group) in consistant.group_keys_by_node(&items) {
+            assert_eq!(plain.assigned[&element], group);
+        }
+        assert_eq!(consistant.assign_capped(&items, 0).unassigned.len(), 1000);
+        assert_eq!(Consistant::default().assign_capped(&items, 10).unassigned.len(), 1000);
+    }
+}
@@ -0,0 +1,297 @@
+use std::cmp::Ordering;
+
+/// Carp maps URLs to members with the Cache Array Routing Protocol
+/// (draft-vinod-carp-v1), as Squid's `carp` parents do. Each member is
+/// registered with a relative load factor, and a URL goes to the member of
+/// the highest score, combining its hash with the URL's and scaled by the
+/// member's load multiplier. URLs are spread only roughly in proportion to
+/// the load factors, the hashes of the draft mixing poorly.
+///
+/// Like `AnchorRing`, it is a hashing scheme of its own beside `Consistant`,
+/// sharing neither its virtual nodes nor its mappings: a lookup scores every
+/// member, which suits the few members of a cache array.
+#[derive(Debug, Clone, Default)]
+pub struct Carp {
+    /// Sorted by load factor, then in the order they were added, as Squid
+    /// sorts its parents.
+    members: Vec<CarpMember>,
+}
+
+#[derive(Debug, Clone)]
+struct CarpMember {
+    name: String,
+    load_factor: usize,
+    hash: u32,
+    multiplier: f64,
+}
+
+impl Carp {
+    /// Crete a new instance of Carp.
+    pub fn new() -> Carp {
+        Carp::default()
+    }
+
+    /// Add an elment with the given relative load factor, an element of
+    /// factor 2 getting twice as many URLs as one of factor 1. A load factor
+    /// of 0, or an element already in, adds nothing.
+    pub fn add<S: Into<String>>(&mut self, element: S, load_factor: usize) {
+        let element = element.into();
+        if load_factor == 0 || self.contains(&element) {
+            return;
+        }
+        let index = self.members.partition_point(|member| member.load_factor <= load_factor);
+        self.members.insert(index,
+                            CarpMember {
+                                hash: member_hash(&element),
+                                name: element,
+                                load_factor,
+                                multiplier: 0.0,
+                            });
+        self.update_multipliers();
+    }
+
+    /// Remove the given element. Only its URLs move when every load factor
+    /// is the same, else the multipliers of the others change too and some
+    /// of their URLs move between them.
+    pub fn remove<S: AsRef<str>>(&mut self, name: S) {
+        let count = self.members.len();
+        self.members.retain(|member| member.name != name.as_ref());
+        if self.members.len() != count {
+            self.update_multipliers();
+        }
+    }
+
+    /// Get the member of the given URL.
+    pub fn get<S: AsRef<str>>(&self, url: S) -> Option<String> {
+        let url = url_hash(url.as_ref());
+        let mut best: Option<(&CarpMember, f64)> = None;
+        for member in &self.members {
+            let score = f64::from(combine(url, member.hash)) * member.multiplier;
+            if best.is_none_or(|(_, high)| score > high) {
+                best = Some((member, score));
+            }
+        }
+
+        best.map(|(member, _)| member.name.clone())
+    }
+
+    /// Get the N members of the highest scores for the given URL, the first
+    /// one being the one of `get`, or all of them if there are not as many.
+    pub fn get_n<S: AsRef<str>>(&self, url: S, n: usize) -> Option<Vec<String>> {
+        if n == 0 || self.members.is_empty() {
+            return None;
+        }
+        let url = url_hash(url.as_ref());
+        let mut scores: Vec<(f64, &CarpMember)> = self.members
+            .iter()
+            .map(|member| (f64::from(combine(url, member.hash)) * member.multiplier, member))
+            .collect();
+        // Stable, so equal scores keep the first member as `get` does.
+        scores.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+
+        Some(scores.into_iter().take(n).map(|(_, member)| member.name.clone()).collect())
+    }
+
+    /// Get the load factor of the given element.
+    pub fn load_factor(&self, name: &str) -> Option<usize> {
+        self.find(name).map(|member| member.load_factor)
+    }
+
+    /// Get the load multiplier of the given element, which its scores are
+    /// scaled by.
+    pub fn multiplier(&self, name: &str) -> Option<f64> {
+        self.find(name).map(|member| member.multiplier)
+    }
+
+    /// Get the count of added elements.
+    pub fn count(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Get the names of the added elements, sorted.
+    pub fn members(&self) -> Vec<String> {
+        let mut members: Vec<String> =
+            self.members.iter().map(|member| member.name.clone()).collect();
+        members.sort();
+        members
+    }
+
+    /// Check whether the given element is added.
+    pub fn contains(&self, name: &str) -> bool {
+        self.find(name).is_some()
+    }
+
+    fn find(&self, name: &str) -> Option<&CarpMember> {
+        self.members.iter().find(|member| member.name == name)
+    }
+
+    /// Compute the load multipliers of section 3.3 of the draft from the
+    /// load factors, normalized to sum to 1, in increasing order.
+    fn update_multipliers(&mut self) {
+        let total: usize = self.members.iter().map(|member| member.load_factor).sum();
+        let k = self.members.len();
+        let (mut p_last, mut x_last, mut product) = (0.0, 0.0, 1.0);
+
+        for (i, member) in self.members.iter_mut().enumerate() {
+            let p = member.load_factor as f64 / total as f64;
+            let remaining = (k - i) as f64;
+            let x = (remaining * (p - p_last) / product + f64::powf(x_last, remaining))
+                .powf(1.0 / remaining);
+            member.multiplier = x;
+            product *= x;
+            x_last = x;
+            p_last = p;
+        }
+    }
+}
+
+/// Hash a URL. The bytes are added as the signed `char` of C, for the URLs
+/// out of ASCII to route as Squid does.
+fn url_hash(url: &str) -> u32 {
+    url.bytes().fold(0u32, |hash, byte| {
+        hash.wrapping_add(hash.rotate_left(19).wrapping_add(byte as i8 as u32))
+    })
+}
+
+fn member_hash(name: &str) -> u32 {
+    let hash = url_hash(name);
+    hash.wrapping_add(hash.wrapping_mul(0x6253_1965)).rotate_left(21)
+}
+
+fn combine(url_hash: u32, member_hash: u32) -> u32 {
+    let hash = url_hash ^ member_hash;
+    hash.wrapping_add(hash.wrapping_mul(0x6253_1965)).rotate_left(21)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MEMBERS: &[(&str, usize)] = &[("proxy1.example.com", 1),
+                                        ("proxy2.example.com", 2),
+                                        ("proxy3.example.com", 4),
+                                        ("proxy4.example.com", 3),
+                                        ("caché.example.com", 2)];
+
+    #[test]
+    fn test_squid_vectors() {
+        let mut equal = Carp::new();
+        for i in 1..4 {
+            equal.add(format!("proxy{}.example.com", i), 1);
+        }
+        let mut weighted = Carp::new();
+        for &(member, load_factor) in MEMBERS {
+            weighted.add(member, load_factor);
+        }
+        let mut removed = false;
+        let mut checked = 0;
+
+        let fixture = include_str!("../tests/fixtures/carp-squid.tsv");
+        for line in fixture.lines().filter(|line| !line.starts_with('#')) {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields[1] == "removed" && !removed {
+                weighted.remove("proxy3.example.com");
+                removed = true;
+            }
+            let carp = if fields[1] == "equal" { &equal } else { &weighted };
+            match fields[0] {
+                "member" => {
+                    assert_eq!(format!("{:08x}", member_hash(fields[2])), fields[4]);
+                    assert_eq!(carp.load_factor(fields[2]), fields[3].parse().ok());
+                    let expected: f64 = fields[5].parse().unwrap();
+                    assert!((carp.multiplier(fields[2]).unwrap() - expected).abs() < 1e-12,
+                            "{}",
+                            line);
+                }
+                "get" => {
+                    assert_eq!(carp.get(fields[2]).as_deref(), Some(fields[3]), "{}", line);
+                    checked += 1;
+                }
+                record => panic!("unknown record {:?}", record),
+            }
+        }
+        assert!(removed);
+        assert_eq!(checked, 600);
+    }
+
+    #[test]
+    fn test_add_remove() {
+        let mut carp = Carp::new();
+        assert_eq!(carp.get("http://www.example.com/"), None);
+        carp.add("cacheA", 1);
+        carp.add("cacheA", 2);
+        carp.add("cacheB", 0);
+        assert_eq!(carp.members(), ["cacheA"]);
+        assert_eq!(carp.load_factor("cacheA"), Some(1));
+        assert_eq!(carp.multiplier("cacheA"), Some(1.0));
+        carp.add("cacheB", 1);
+        carp.add("cacheC", 1);
+        assert_eq!(carp.count(), 3);
+
+        // Of equal load factors, only the URLs of a removed member move, and
+        // they move back when it is added back.
+        let urls: Vec<String> = (0..2000).map(|i| format!("http://example.com/{}", i)).collect();
+        let before: Vec<String> = urls.iter().map(|url| carp.get(url).unwrap()).collect();
+        carp.remove("cacheA");
+        assert!(!carp.contains("cacheA"));
+        let mut moved = 0;
+        for (url, owner) in urls.iter().zip(&before) {
+            let new = carp.get(url).unwrap();
+            if owner == "cacheA" {
+                assert!(new != "cacheA");
+                moved += 1;
+            } else {
+                assert_eq!(new, *owner);
+            }
+        }
+        assert!(moved > 500 && moved < 850, "{}", moved);
+
+        carp.add("cacheA", 1);
+        for (url, owner) in urls.iter().zip(&before) {
+            assert_eq!(carp.get(url).as_ref(), Some(owner));
+        }
+    }
+
+    #[test]
+    fn test_load_factors() {
+        let mut carp = Carp::new();
+        for &(member, load_factor) in MEMBERS {
+            carp.add(member, load_factor);
+        }
+
+        let mut counts = vec![0; MEMBERS.len()];
+        for i in 0..120000 {
+            let owner = carp.get(format!("http://example.com/{}", i)).unwrap();
+            counts[MEMBERS.iter().position(|member| member.0 == owner).unwrap()] += 1;
+        }
+        // The hashes of the draft only roughly follow the load factors, but
+        // a greater one always gets more URLs.
+        for (a, count_a) in MEMBERS.iter().zip(&counts) {
+            for (b, count_b) in MEMBERS.iter().zip(&counts) {
+                assert!(a.1 <= b.1 || count_a > count_b, "{:?}", counts);
+            }
+            let expected = 10000 * a.1;
+            assert!(*count_a > expected / 2 && *count_a < expected * 3 / 2, "{:?}", counts);
+        }
+    }
+
+    #[test]
+    fn test_get_n() {
+        let mut carp = Carp::new();
+        assert_eq!(carp.get_n("http://example.com/", 2), None);
+        for &(member, load_factor) in MEMBERS {
+            carp.add(member, load_factor);
+        }
+        assert_eq!(carp.get_n("http://example.com/", 0), None);
+
+        for i in 0..100 {
+            let url = format!("http://example.com/{}", i);
+            let owners = carp.get_n(url.as_str(), MEMBERS.len() + 1).unwrap();
+            assert_eq!(owners.len(), MEMBERS.len());
+            assert_eq!(owners[0], carp.get(url.as_str()).unwrap());
+            let mut sorted = owners.clone();
+            sorted.sort();
+            assert_eq!(sorted, carp.members());
+        }
+    }
+}
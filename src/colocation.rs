@@ -0,0 +1,173 @@
+use consistant::Consistant;
+
+/// ColocationChange is a colocation group whose representative key moved to
+/// another element since it was last checked, see
+/// `Consistant::check_colocations`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColocationChange {
+    pub group: String,
+    pub representative: String,
+    /// The element the keys of the group were routed to, `None` if the hash
+    /// ring was empty.
+    pub from: Option<String>,
+    /// The element the keys of the group are routed to now.
+    pub to: Option<String>,
+}
+
+/// Colocation is a colocation group of the hash ring.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Colocation {
+    pub(crate) representative: String,
+    /// The owner of the representative when the group was last checked.
+    pub(crate) owner: Option<String>,
+}
+
+impl Consistant {
+    /// Define a colocation group: every key looked up through `get_grouped`
+    /// with the given group is routed as its representative key is, so that
+    /// they all share one element. A group of the same id is replaced. The
+    /// current owner of the representative is recorded for
+    /// `check_colocations`.
+    pub fn define_colocation_group<S: Into<String>>(&mut self, group: S, representative: &str) {
+        self.insert_colocation_group(group.into(), String::from(representative));
+    }
+
+    /// Remove the given colocation group, returning its representative key.
+    pub fn remove_colocation_group(&mut self, group: &str) -> Option<String> {
+        self.colocations_mut().remove(group).map(|colocation| colocation.representative)
+    }
+
+    /// Get the colocation groups as `(group, representative)`, sorted by
+    /// group.
+    pub fn colocation_groups(&self) -> Vec<(String, String)> {
+        self.colocations()
+            .iter()
+            .map(|(group, colocation)| (group.clone(), colocation.representative.clone()))
+            .collect()
+    }
+
+    /// Get the cloeset element's name to the representative key of the given
+    /// group, whatever the key. The key of a group not defined is looked up
+    /// by itself, as `get` does.
+    pub fn get_grouped<S: AsRef<str>>(&self, group: &str, key: S) -> Option<String> {
+        match self.colocations().get(group) {
+            Some(colocation) => self.get(colocation.representative.as_str()),
+            None => self.get(key),
+        }
+    }
+
+    /// Get the colocation groups whose representative key moved to another
+    /// element since they were defined or last checked, sorted by group,
+    /// recording their new owners. Call it after mutating the hash ring to
+    /// migrate the keys of the groups listed.
+    pub fn check_colocations(&mut self) -> Vec<ColocationChange> {
+        let owners: Vec<Option<String>> = self.colocations()
+            .values()
+            .map(|colocation| self.get(colocation.representative.as_str()))
+            .collect();
+        let mut changes = Vec::new();
+        for ((group, colocation), owner) in self.colocations_mut().iter_mut().zip(owners) {
+            if colocation.owner != owner {
+                changes.push(ColocationChange {
+                    group: group.clone(),
+                    representative: colocation.representative.clone(),
+                    from: colocation.owner.take(),
+                    to: owner.clone(),
+                });
+                colocation.owner = owner;
+            }
+        }
+        changes
+    }
+
+    /// Set the given colocation group, recording the current owner of its
+    /// representative.
+    pub(crate) fn insert_colocation_group(&mut self, group: String, representative: String) {
+        let owner = self.get(representative.as_str());
+        self.colocations_mut().insert(group,
+                                      Colocation {
+                                          representative,
+                                          owner,
+                                      });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn consistant() -> Consistant {
+        let mut consistant = Consistant::new(20);
+        for i in 0..8 {
+            consistant.add(format!("cache{}", i));
+        }
+        for i in 0..50 {
+            consistant.define_colocation_group(format!("session{}", i),
+                                               &format!("session{}", i));
+        }
+        consistant
+    }
+
+    fn owners(consistant: &Consistant) -> Vec<Option<String>> {
+        (0..50).map(|i| consistant.get_grouped(&format!("session{}", i), "")).collect()
+    }
+
+    #[test]
+    fn test_get_grouped() {
+        let consistant = consistant();
+        for i in 0..50 {
+            let group = format!("session{}", i);
+            let owner = consistant.get(group.as_str());
+            assert!(owner.is_some());
+            for key in &["", "lock", "david", "session-data"] {
+                assert_eq!(consistant.get_grouped(&group, key), owner);
+            }
+        }
+        assert_eq!(consistant.get_grouped("unknown", "david"), consistant.get("david"));
+        assert_eq!(consistant.colocation_groups().len(), 50);
+        assert_eq!(consistant.colocation_groups()[0],
+                   (String::from("session0"), String::from("session0")));
+    }
+
+    #[test]
+    fn test_check_colocations() {
+        let mut consistant = consistant();
+        assert!(consistant.check_colocations().is_empty());
+
+        // Only the groups whose representative moved are listed, once.
+        for mutation in 0..3 {
+            let before = owners(&consistant);
+            match mutation {
+                0 => consistant.remove("cache3"),
+                1 => consistant.add("cache8"),
+                _ => consistant.add_weighted("cache9", 3),
+            }
+            let after = owners(&consistant);
+            let mut moved: Vec<String> = (0..50)
+                .filter(|&i| before[i] != after[i])
+                .map(|i| format!("session{}", i))
+                .collect();
+            moved.sort();
+            assert!(!moved.is_empty());
+            let changes = consistant.check_colocations();
+            assert_eq!(changes.iter().map(|change| change.group.clone()).collect::<Vec<_>>(),
+                       moved);
+            for change in &changes {
+                assert_eq!(change.to, consistant.get(change.representative.as_str()));
+                assert!(change.from.is_some() && change.from != change.to);
+            }
+            assert!(consistant.check_colocations().is_empty());
+        }
+
+        for i in (1..50).step_by(5) {
+            assert_eq!(consistant.remove_colocation_group(&format!("session{}", i)),
+                       Some(format!("session{}", i)));
+        }
+        assert_eq!(consistant.remove_colocation_group("session1"), None);
+        assert_eq!(consistant.colocation_groups().len(), 40);
+        consistant.clear();
+        let changes = consistant.check_colocations();
+        assert_eq!(changes.len(), 40);
+        assert!(changes.iter().all(|change| change.to.is_none()));
+    }
+}
@@ -0,0 +1,206 @@
+use std::sync::Arc;
+use consistant::{distinct_owners, Consistant};
+use routing::Routing;
+
+/// CompiledRing is an immutable copy of a hash ring with a precomputed
+/// table of slots, each one splitting evenly the u32 keyspace, for lookups
+/// without a search of the whole ring.
+///
+/// A slot records the first virtual node at or after its start, the lookup
+/// then scanning only the virtual nodes of the key's own slot. So the mappings
+/// are exactly those of the compiled `Consistant`, whatever the count of slots,
+/// the fallback of the empty hash ring included.
+///
+/// Cloning a CompiledRing only bumps reference counts.
+#[derive(Debug, Clone)]
+pub struct CompiledRing {
+    routing: Arc<Routing>,
+    shift: u32,
+    table: Arc<[u32]>,
+    keys: Arc<[u32]>,
+    owners: Arc<[u32]>,
+    members: Arc<[Arc<str>]>,
+    fallback: Option<Arc<str>>,
+}
+
+impl Consistant {
+    /// Compile the hash ring into a `CompiledRing` of the given count of
+    /// slots, rounded up to a power of two, 2^16 being a sensible default.
+    /// Later changes to the hash ring are not reflected. The key normalizer,
+    /// the prefix rules, the range assignments of the elements in, the
+    /// fractional weights and the fallback are kept.
+    pub fn compile(&self, slots: usize) -> CompiledRing {
+        let bits = slots.max(1).next_power_of_two().trailing_zeros().min(32);
+        let members = self.sorted_members();
+        let vnodes = self.vnodes();
+
+        let keys: Vec<u32> = vnodes.iter().map(|vnode| vnode.0).collect();
+        let owners = vnodes.iter()
+            .map(|vnode| members.binary_search(&vnode.1).unwrap() as u32)
+            .collect::<Vec<u32>>();
+        let table = (0..1u64 << bits)
+            .map(|slot| {
+                let start = slot << (32 - bits);
+                keys.partition_point(|key| u64::from(*key) < start) as u32
+            })
+            .collect::<Vec<u32>>();
+
+        CompiledRing {
+            routing: Arc::new(Routing::new(self, &members)),
+            shift: 32 - bits,
+            table: table.into(),
+            keys: keys.into(),
+            owners: owners.into(),
+            members: members.into(),
+            fallback: self.fallback().map(Arc::from),
+        }
+    }
+}
+
+impl CompiledRing {
+    /// Get the cloeset element's name to the given key.
+    pub fn get<S: AsRef<str>>(&self, key: S) -> Option<&str> {
+        match self.locate(key.as_ref()) {
+            Some((index, routed)) => {
+                Some(&*self.members[routed.unwrap_or(self.owners[index]) as usize])
+            }
+            None => self.fallback.as_deref(),
+        }
+    }
+
+    /// Get the N cloeset elements' names to the given key.
+    pub fn get_n<S: AsRef<str>>(&self, key: S, n: usize) -> Option<Vec<&str>> {
+        if n == 0 {
+            return None;
+        }
+        let (index, routed) = match self.locate(key.as_ref()) {
+            Some(located) => located,
+            None => return self.fallback.as_deref().map(|fallback| vec![fallback]),
+        };
+        let count = n.min(self.members.len());
+
+        let owners = distinct_owners(self.keys.len(), |i| self.owners[i], index, routed, count);
+
+        Some(owners.into_iter().map(|owner| &*self.members[owner as usize]).collect())
+    }
+
+    /// Get the names of the elements, sorted.
+    pub fn members(&self) -> Vec<&str> {
+        self.members.iter().map(|element| &**element).collect()
+    }
+
+    /// Get the count of slots of the table.
+    pub fn slots(&self) -> usize {
+        self.table.len()
+    }
+
+    /// Get the index of the virtual node of the given key, and the element
+    /// its longest prefix rule or range assignment routes it to, or the one
+    /// past a fractional element declining it, as `Consistant::get` does.
+    #[inline]
+    fn locate(&self, key: &str) -> Option<(usize, Option<u32>)> {
+        let name = |owner: u32| &*self.members[owner as usize];
+        self.routing.locate(key, &self.owners, |sum| self.index_of(sum), name)
+    }
+
+    #[inline]
+    fn index_of(&self, sum: u32) -> Option<usize> {
+        if self.keys.is_empty() {
+            return None;
+        }
+        let mut index = self.table[(u64::from(sum) >> self.shift) as usize] as usize;
+        while index < self.keys.len() && self.keys[index] <= sum {
+            index += 1;
+        }
+
+        Some(if index == self.keys.len() { 0 } else { index })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn consistant() -> Consistant {
+        let mut consistant = Consistant::with_seed(20, 7);
+        consistant.add("cacheA");
+        consistant.add_weighted("cacheB", 3);
+        consistant.add("cacheC");
+        consistant.add("cacheD");
+        consistant
+    }
+
+    #[test]
+    fn test_compile() {
+        let consistant = consistant();
+
+        for &slots in &[0, 1, 3, 64, 1 << 16] {
+            let compiled = consistant.compile(slots);
+            assert_eq!(compiled.slots(), slots.max(1).next_power_of_two());
+            assert_eq!(compiled.members(), ["cacheA", "cacheB", "cacheC", "cacheD"]);
+
+            for i in 0..1000 {
+                let key = format!("key{}", i);
+                assert_eq!(compiled.get(&key).map(String::from),
+                           consistant.get(key.as_str()),
+                           "{} slots, {}",
+                           slots,
+                           key);
+            }
+
+            // Around every virtual node, where an approximation would diverge.
+            for (hash, _) in consistant.vnodes() {
+                for sum in [hash.wrapping_sub(1), hash, hash.wrapping_add(1)].iter() {
+                    let owner = compiled.index_of(*sum)
+                        .map(|index| compiled.members[compiled.owners[index] as usize].clone());
+                    assert_eq!(owner, consistant.owner_of(*sum), "{} slots, {}", slots, sum);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_compile_get_n() {
+        let mut consistant = consistant();
+        consistant.assign_range(1 << 30, 1 << 31, "cacheA").unwrap();
+        consistant.set_fractional_weight("cacheC", 0.3).unwrap();
+        let compiled = consistant.compile(256);
+
+        assert_eq!(compiled.get_n("david", 0), None);
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            for n in 1..6 {
+                let expected = consistant.get_n(key.as_str(), n).unwrap();
+                assert_eq!(compiled.get_n(&key, n).unwrap(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_compile_empty() {
+        let compiled = Consistant::default().compile(16);
+
+        assert_eq!(compiled.get("david"), None);
+        assert_eq!(compiled.get_n("david", 2), None);
+        assert!(compiled.members().is_empty());
+
+        let mut consistant = Consistant::default();
+        consistant.set_fallback(Some(String::from("local-cache")));
+        let compiled = consistant.compile(16);
+        assert_eq!(compiled.get("david"), Some("local-cache"));
+        assert_eq!(compiled.get_n("david", 2), Some(vec!["local-cache"]));
+        assert_eq!(compiled.get_n("david", 0), None);
+        consistant.add("cacheA");
+        assert_eq!(consistant.compile(16).get("david"), Some("cacheA"));
+    }
+
+    #[test]
+    fn test_compile_send_sync() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+        let compiled = consistant().compile(16);
+        assert_send_sync(&compiled);
+        let cloned = compiled.clone();
+        assert!(Arc::ptr_eq(&cloned.table, &compiled.table));
+    }
+}
@@ -0,0 +1,307 @@
+//! Hash ring configuration files, with the `config` feature.
+//!
+//! The schema, in TOML:
+//!
+//! ```text
+//! replicas = 20            # optional, 20 by default
+//!
+//! [[member]]
+//! name = "cacheA"
+//! weight = 2               # optional, 1 by default
+//! zone = "eu-west-1a"      # optional
+//!
+//! [[member]]
+//! name = "cacheB"
+//! ```
+//!
+//! and in YAML:
+//!
+//! ```text
+//! replicas: 20
+//! member:
+//!   - name: cacheA
+//!     weight: 2
+//!     zone: eu-west-1a
+//!   - name: cacheB
+//! ```
+//!
+//! A member may also list `tokens`, the hashes of its virtual nodes, which
+//! `to_config_string` writes for the members not deriving them from their
+//! name, see `Consistant::replace_node`.
+//!
+//! Unknown keys are rejected, catching misspelled ones. The zones are kept in
+//! `RingConfig` for the callers placing by zone, the hash ring itself not
+//! knowing them. The placement strategy, seed and spacing of the virtual
+//! nodes are not part of the schema: a configured hash ring always uses the
+//! default ones, the members of a spaced hash ring listing their tokens.
+
+use consistant::Consistant;
+use error::ConfigError;
+use std::collections::hash_set::HashSet;
+
+/// The format of a configuration document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml,
+}
+
+/// RingConfig is a parsed configuration document, see the `config` module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RingConfig {
+    pub replicas: usize,
+    pub members: Vec<MemberConfig>,
+}
+
+/// MemberConfig is a `[[member]]` entry of a configuration document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemberConfig {
+    pub name: String,
+    pub weight: usize,
+    pub zone: Option<String>,
+    /// The hashes of the virtual nodes, `None` for the derived ones.
+    pub tokens: Option<Vec<u32>>,
+}
+
+/// The document as written, before validation.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    #[serde(default = "default_replicas")]
+    replicas: i64,
+    #[serde(default)]
+    member: Vec<RawMember>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawMember {
+    name: String,
+    #[serde(default = "default_weight")]
+    weight: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    zone: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tokens: Option<Vec<u32>>,
+}
+
+fn default_replicas() -> i64 {
+    20
+}
+
+fn default_weight() -> i64 {
+    1
+}
+
+impl RingConfig {
+    /// Parse and validate a configuration document.
+    pub fn parse(s: &str, format: ConfigFormat) -> Result<RingConfig, ConfigError> {
+        let raw: RawConfig = match format {
+            ConfigFormat::Toml => {
+                ::toml::from_str(s).map_err(|err| ConfigError::Parse(err.message().to_string()))?
+            }
+            ConfigFormat::Yaml => {
+                ::serde_yaml::from_str(s).map_err(|err| ConfigError::Parse(err.to_string()))?
+            }
+        };
+        if raw.replicas <= 0 {
+            return Err(ConfigError::InvalidReplicas(raw.replicas));
+        }
+
+        let mut names = HashSet::new();
+        let mut members = Vec::with_capacity(raw.member.len());
+        for member in raw.member {
+            if member.weight <= 0 {
+                return Err(ConfigError::InvalidWeight {
+                    member: member.name,
+                    weight: member.weight,
+                });
+            }
+            if !names.insert(member.name.clone()) {
+                return Err(ConfigError::DuplicateMember(member.name));
+            }
+            let weight = member.weight as usize;
+            if let Some(ref tokens) = member.tokens {
+                if tokens.len() as i64 != raw.replicas * member.weight {
+                    return Err(ConfigError::InvalidTokens {
+                        member: member.name,
+                        count: tokens.len(),
+                    });
+                }
+            }
+            members.push(MemberConfig {
+                name: member.name,
+                weight,
+                zone: member.zone,
+                tokens: member.tokens,
+            });
+        }
+
+        Ok(RingConfig {
+            replicas: raw.replicas as usize,
+            members,
+        })
+    }
+
+    /// Build the configured hash ring, adding the members in order.
+    pub fn build(&self) -> Consistant {
+        let mut consistant = Consistant::new(self.replicas);
+        for member in &self.members {
+            match member.tokens {
+                Some(ref tokens) => {
+                    consistant.add_with_tokens(member.name.clone(), member.weight, tokens.clone())
+                }
+                None => consistant.add_weighted(member.name.as_str(), member.weight),
+            }
+        }
+        consistant
+    }
+
+    /// Get the configuration document, in the given format.
+    pub fn to_string(&self, format: ConfigFormat) -> String {
+        let raw = RawConfig {
+            replicas: self.replicas as i64,
+            member: self.members
+                .iter()
+                .map(|member| {
+                    RawMember {
+                        name: member.name.clone(),
+                        weight: member.weight as i64,
+                        zone: member.zone.clone(),
+                        tokens: member.tokens.clone(),
+                    }
+                })
+                .collect(),
+        };
+        match format {
+            ConfigFormat::Toml => ::toml::to_string(&raw).unwrap(),
+            ConfigFormat::Yaml => ::serde_yaml::to_string(&raw).unwrap(),
+        }
+    }
+}
+
+impl Consistant {
+    /// Load a hash ring from a configuration document, see the `config`
+    /// module.
+    pub fn from_config_str(s: &str, format: ConfigFormat) -> Result<Consistant, ConfigError> {
+        RingConfig::parse(s, format).map(|config| config.build())
+    }
+
+    /// Get the configuration document of the hash ring, members sorted by
+    /// name and without zones.
+    pub fn to_config_string(&self, format: ConfigFormat) -> String {
+        RingConfig {
+                replicas: self.replicas_num,
+                members: self.weighted_members()
+                    .into_iter()
+                    .map(|(element, weight)| {
+                        MemberConfig {
+                            tokens: self.explicit_tokens(&element).or_else(|| {
+                                if self.self_spacing() == 0 {
+                                    None
+                                } else {
+                                    Some(self.derived_points(&element, weight))
+                                }
+                            }),
+                            name: String::from(&*element),
+                            weight,
+                            zone: None,
+                        }
+                    })
+                    .collect(),
+            }
+            .to_string(format)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use placement::checksum;
+
+    const FIXTURE: &str = include_str!("../tests/fixtures/ring.toml");
+
+    /// A checksum of the virtual nodes, which any change of the hash ring
+    /// changes.
+    fn fingerprint(consistant: &Consistant) -> u32 {
+        let vnodes: Vec<String> = consistant.vnodes()
+            .into_iter()
+            .map(|(hash, element)| format!("{:08x}={}", hash, element))
+            .collect();
+        checksum(0, vnodes.join(",").as_bytes())
+    }
+
+    #[test]
+    fn test_from_config_str() {
+        let config = RingConfig::parse(FIXTURE, ConfigFormat::Toml).unwrap();
+        assert_eq!(config.replicas, 40);
+        assert_eq!(config.members[1],
+                   MemberConfig {
+                       name: String::from("cacheB"),
+                       weight: 1,
+                       zone: Some(String::from("eu-west-1b")),
+                       tokens: None,
+                   });
+
+        let consistant = Consistant::from_config_str(FIXTURE, ConfigFormat::Toml).unwrap();
+        let mut expected = Consistant::new(40);
+        expected.add_weighted("cacheA", 2);
+        expected.add("cacheB");
+        expected.add_weighted("cacheC", 3);
+        assert_eq!(consistant.to_json(), expected.to_json());
+        assert_eq!(fingerprint(&consistant), 0x7e6b_b926);
+
+        let yaml = config.to_string(ConfigFormat::Yaml);
+        assert_eq!(RingConfig::parse(&yaml, ConfigFormat::Yaml).unwrap(), config);
+    }
+
+    #[test]
+    fn test_to_config_string() {
+        let consistant = Consistant::from_config_str(FIXTURE, ConfigFormat::Toml).unwrap();
+        for format in [ConfigFormat::Toml, ConfigFormat::Yaml].iter() {
+            let loaded = Consistant::from_config_str(&consistant.to_config_string(*format),
+                                                     *format)
+                .unwrap();
+            assert_eq!(fingerprint(&loaded), fingerprint(&consistant));
+        }
+        assert_eq!(Consistant::new(3).to_config_string(ConfigFormat::Toml),
+                   "replicas = 3\nmember = []\n");
+
+        let mut replaced = Consistant::new(2);
+        replaced.add("cacheA");
+        replaced.replace_node("cacheA", "cacheB").unwrap();
+        let toml = replaced.to_config_string(ConfigFormat::Toml);
+        assert_eq!(toml,
+                   "replicas = 2\n\n[[member]]\nname = \"cacheB\"\nweight = 1\n\
+                    tokens = [1161674923, 842723389]\n");
+        let loaded = Consistant::from_config_str(&toml, ConfigFormat::Toml).unwrap();
+        assert_eq!(loaded.vnodes(), replaced.vnodes());
+    }
+
+    #[test]
+    fn test_config_errors() {
+        let parse = |s: &str| Consistant::from_config_str(s, ConfigFormat::Toml).unwrap_err();
+        assert_eq!(parse("replicas = 0"), ConfigError::InvalidReplicas(0));
+        assert_eq!(parse("[[member]]\nname = \"cacheA\"\nweight = -1\n"),
+                   ConfigError::InvalidWeight {
+                       member: String::from("cacheA"),
+                       weight: -1,
+                   });
+        assert_eq!(parse("[[member]]\nname = \"cacheA\"\n[[member]]\nname = \"cacheA\"\n"),
+                   ConfigError::DuplicateMember(String::from("cacheA")));
+        assert_eq!(parse("[[member]]\nname = \"cacheA\"\nwieght = 2\n").to_string(),
+                   "invalid configuration: unknown field `wieght`, expected one of `name`, \
+                    `weight`, `zone`, `tokens`");
+        assert!(matches!(parse("replicas = "), ConfigError::Parse(_)));
+        assert_eq!(parse("replicas = 2\n[[member]]\nname = \"cacheA\"\ntokens = [1]\n"),
+                   ConfigError::InvalidTokens {
+                       member: String::from("cacheA"),
+                       count: 1,
+                   });
+
+        let err = Consistant::from_config_str("member:\n  - name: cacheA\n    weight: 0\n",
+                                              ConfigFormat::Yaml)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "member \"cacheA\" has an invalid weight 0");
+    }
+}
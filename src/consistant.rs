@@ -1,28 +1,143 @@
+use std::borrow::Cow;
+use std::cmp;
 use std::default::Default;
-use std::rc::Rc;
+use std::mem;
+use std::sync::Arc;
 use std::iter::Iterator;
+use std::collections::btree_map::BTreeMap;
 use std::collections::hash_map::HashMap;
-use std::sync::RwLock;
-use crc::crc32::checksum_ieee;
+use std::collections::hash_set::HashSet;
+use std::sync::PoisonError;
+use auto_replicas::{moved_share, AutoReplicas, Rescale};
+use breaker::Breakers;
+use colocation::Colocation;
+use error::{DisruptionExceeded, Error, LimitExceeded, ReplicationError};
+use explain::{LookupExplanation, SkipReason, SkippedCandidate};
+use fractional::{admits, next_admitting, to_millionths, MILLIONTHS};
+use handle::Slots;
+use history::{History, VersionSnapshot};
+use latency::Latencies;
+use limits::ResourceLimits;
+use normalize::KeyNormalizer;
+#[cfg(feature = "rayon")]
+use parallel::PARALLEL_VNODES;
+use placement::PlacementStrategy;
+use range_override::RangeOverrides;
+use rebalance::MutationPreview;
+use registry::Registered;
+use segments::SortedKeys;
+use selection::SelectionPolicy;
+use siphash::{KeyHasher, SipKey};
+use sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// The version of the mappings of keys to elements. Hash rings of equal
+/// configuration map every key to the same elements, in the same order for
+/// `get_n`, in every release of the same `MAPPING_VERSION`. It is only bumped
+/// along with a change of hashing or virtual node placement, which
+/// `tests/fixtures/mapping-v1.tsv` catches.
+pub const MAPPING_VERSION: u32 = 1;
 
 /// Consistant holds the information of the hash ring.
 #[derive(Debug)]
 pub struct Consistant {
     pub replicas_num: usize,
 
-    circle: HashMap<u32, Rc<String>>,
-    members: HashMap<Rc<String>, ()>,
-    sorted_keys: Vec<u32>,
+    members: HashMap<Arc<str>, usize>,
+    /// The virtual node hashes of the elements not deriving them from their
+    /// name, as `replace_node` leaves them.
+    tokens: HashMap<Arc<str>, Vec<u32>>,
+    /// The incarnations of the elements, kept after their removal for the
+    /// next add to bump.
+    incarnations: HashMap<Arc<str>, u64>,
+    /// The tags of the elements having any, sorted.
+    tags: HashMap<Arc<str>, Vec<String>>,
+    /// The fractions of the elements of a fractional weight, in millionths,
+    /// see `set_fractional_weight`.
+    fractions: HashMap<Arc<str>, u32>,
+    /// The moving averages of the latencies of the elements, see
+    /// `report_latency`.
+    latencies: Latencies,
+    /// The circuit breakers of the elements, see `set_circuit_breaker`.
+    breakers: Breakers,
+    /// The registry the elements are added from, see `with_registry`.
+    registered: Registered,
+    /// Sorted by the first lookup after a mutation, hence its own lock.
+    sorted_keys: RwLock<SortedKeys>,
+    placement: PlacementStrategy,
+    seed: u64,
+    /// The secret the lookup keys are hashed with, see `with_key`.
+    secret: Option<SipKey>,
+    slots: Slots,
+    version: u64,
+    disruption: Disruption,
+    /// The share of the keyspace the checked mutations may move, see
+    /// `set_max_disruption`.
+    max_disruption: Option<f64>,
+    /// The most elements and virtual nodes the adds may leave, see
+    /// `set_limits`.
+    limits: ResourceLimits,
+    normalizer: Option<KeyNormalizer>,
+    /// The elements the keys of the given prefixes are routed to, see
+    /// `add_prefix_rule`.
+    prefix_rules: BTreeMap<String, String>,
+    /// The elements the given ranges of checksums are routed to, see
+    /// `assign_range`.
+    range_overrides: RangeOverrides<String>,
+    /// The colocation groups by id, see `define_colocation_group`.
+    colocations: BTreeMap<String, Colocation>,
+    /// The count of elements `owners` returns, see `set_replication_factor`.
+    replication_factor: usize,
+    /// The policy deriving `replicas_num` from the membership, see
+    /// `set_auto_replicas`.
+    auto_replicas: Option<AutoReplicas>,
+    /// The smallest distance between two virtual nodes of the same element,
+    /// 0 for none, see `set_min_self_spacing`.
+    min_self_spacing: u32,
+    /// The snapshots of the last versions, see `set_keep_versions`.
+    history: History,
+    /// The name the lookups return while the hash ring is empty, see
+    /// `set_fallback`.
+    fallback: Option<String>,
     lock: RwLock<()>,
 }
 
 impl Default for Consistant {
     fn default() -> Consistant {
+        Consistant::new(20)
+    }
+}
+
+impl Clone for Consistant {
+    fn clone(&self) -> Consistant {
+        let _guard = read_lock(&self.lock);
         Consistant {
-            replicas_num: 20,
-            circle: HashMap::new(),
-            members: HashMap::new(),
-            sorted_keys: Vec::new(),
+            replicas_num: self.replicas_num,
+            members: self.members.clone(),
+            tokens: self.tokens.clone(),
+            incarnations: self.incarnations.clone(),
+            tags: self.tags.clone(),
+            fractions: self.fractions.clone(),
+            latencies: self.latencies.clone(),
+            breakers: self.breakers.clone(),
+            registered: self.registered.clone(),
+            sorted_keys: RwLock::new(read_lock(&self.sorted_keys).clone()),
+            placement: self.placement,
+            seed: self.seed,
+            secret: self.secret,
+            slots: self.slots.clone(),
+            version: self.version,
+            disruption: self.disruption,
+            max_disruption: self.max_disruption,
+            limits: self.limits,
+            normalizer: self.normalizer.clone(),
+            prefix_rules: self.prefix_rules.clone(),
+            range_overrides: self.range_overrides.clone(),
+            colocations: self.colocations.clone(),
+            replication_factor: self.replication_factor,
+            auto_replicas: self.auto_replicas,
+            min_self_spacing: self.min_self_spacing,
+            history: self.history.clone(),
+            fallback: self.fallback.clone(),
             lock: RwLock::new(()),
         }
     }
@@ -31,230 +146,3850 @@ impl Default for Consistant {
 impl Consistant {
     /// Crete a new instance of Consistant.
     pub fn new(replicas_num: usize) -> Self {
+        Self::with_placement(replicas_num, PlacementStrategy::default())
+    }
+
+    /// Crete a new instance of Consistant placing virtual nodes with the given
+    /// strategy.
+    pub fn with_placement(replicas_num: usize, placement: PlacementStrategy) -> Self {
+        Self::with_config(replicas_num, placement, 0)
+    }
+
+    /// Crete a new instance of Consistant whose virtual nodes and keys are
+    /// hashed with the given seed, see `seed`. Rings of different seeds map
+    /// the same keys and elements differently, the seed 0 being the one of
+    /// `new`.
+    pub fn with_seed(replicas_num: usize, seed: u64) -> Self {
+        Self::with_config(replicas_num, PlacementStrategy::default(), seed)
+    }
+
+    /// Crete a new instance of Consistant whose lookup keys are hashed with
+    /// SipHash-2-4 keyed by the given secret, so that keys crafted to land
+    /// on a single element cannot be found without it. The virtual nodes are
+    /// still placed as by `new`. Rings of different secrets map the same
+    /// keys differently, so the mappings are only shared by the processes
+    /// sharing the secret. The secret is neither printed by `Debug` nor
+    /// persisted, see `set_hash_key`.
+    pub fn with_key(replicas_num: usize, key: [u8; 16]) -> Self {
+        let mut consistant = Self::new(replicas_num);
+        consistant.secret = Some(SipKey::new(key));
+        consistant
+    }
+
+    pub(crate) fn with_config(replicas_num: usize,
+                              placement: PlacementStrategy,
+                              seed: u64)
+                              -> Self {
         Consistant {
-            replicas_num: replicas_num,
-            circle: HashMap::new(),
+            replicas_num,
             members: HashMap::new(),
-            sorted_keys: Vec::new(),
+            tokens: HashMap::new(),
+            incarnations: HashMap::new(),
+            tags: HashMap::new(),
+            fractions: HashMap::new(),
+            latencies: Latencies::default(),
+            breakers: Breakers::default(),
+            registered: Registered::default(),
+            sorted_keys: RwLock::new(SortedKeys::default()),
+            placement,
+            seed,
+            secret: None,
+            slots: Slots::default(),
+            version: 0,
+            disruption: Disruption::default(),
+            max_disruption: None,
+            limits: ResourceLimits::default(),
+            normalizer: None,
+            prefix_rules: BTreeMap::new(),
+            range_overrides: RangeOverrides::default(),
+            colocations: BTreeMap::new(),
+            replication_factor: 1,
+            auto_replicas: None,
+            min_self_spacing: 0,
+            history: History::default(),
+            fallback: None,
             lock: RwLock::new(()),
         }
     }
 
+    /// Get the strategy placing the virtual nodes.
+    pub fn placement(&self) -> PlacementStrategy {
+        self.placement
+    }
+
+    /// Get the seed of the hash ring. Unless it is 0, the 8 little endian
+    /// bytes of the seed are prepended to every virtual node name and key
+    /// before computing their checksums.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Set the secret the lookup keys are hashed with, see `with_key`, or
+    /// hash them by `seed` again for `None`. The persisted forms of the hash
+    /// ring leave the secret out, so it is set again after loading them. The
+    /// snapshots of `save` and the documents of `to_json` record that the
+    /// hash ring is keyed, so that they are only loaded along with the
+    /// secret, see `load_keyed` and `from_json_keyed`.
+    pub fn set_hash_key(&mut self, key: Option<[u8; 16]>) {
+        self.set_secret(key.map(SipKey::new));
+    }
+
+    pub(crate) fn set_secret(&mut self, secret: Option<SipKey>) {
+        let _guard = write_lock(&self.lock);
+        self.secret = secret;
+    }
+
+    /// Check whether the lookup keys are hashed with a secret, see
+    /// `with_key`.
+    pub fn is_keyed(&self) -> bool {
+        self.secret.is_some()
+    }
+
+    /// Get the hasher of the lookup keys.
+    pub(crate) fn key_hasher(&self) -> KeyHasher {
+        KeyHasher {
+            seed: self.seed,
+            secret: self.secret,
+        }
+    }
+
+    /// Get the version of the hash ring, starting at 0 and bumped by every
+    /// call actually adding or removing elements. Equal rings may have
+    /// different versions, which tell their history rather than content.
+    pub fn version(&self) -> u64 {
+        let _guard = read_lock(&self.lock);
+        self.version
+    }
+
+    /// Get the share of the keyspace which changed owner with the last add,
+    /// remove or clear, 0 if it did not change the hash ring, the rebuild
+    /// along with it included, see `last_rescale`. `None` before any of them.
+    pub fn last_disruption(&self) -> Option<f64> {
+        let _guard = read_lock(&self.lock);
+        self.disruption.last
+    }
+
+    /// Get the sum of the shares of the keyspace which changed owner with
+    /// every add, remove or clear, see `last_disruption`.
+    pub fn total_disruption(&self) -> f64 {
+        let _guard = read_lock(&self.lock);
+        self.disruption.total
+    }
+
+    /// Get the share of the keyspace the checked mutations may move, `None`
+    /// for no limit.
+    pub fn max_disruption(&self) -> Option<f64> {
+        self.max_disruption
+    }
+
+    /// Limit the share of the keyspace, between 0 and 1, which `checked_add`,
+    /// `checked_add_weighted` and `checked_remove` may move at once. The other
+    /// mutations are never limited, forcing any change through. There is no
+    /// limit by default.
+    pub fn set_max_disruption(&mut self, budget: Option<f64>) {
+        self.max_disruption = budget;
+    }
+
+    /// Get the limits of the size of the hash ring.
+    pub fn limits(&self) -> ResourceLimits {
+        self.limits
+    }
+
+    /// Limit the count of elements and of virtual nodes of the hash ring,
+    /// which the adds and raised weights may not go over: `try_add_weighted`,
+    /// `try_add_all` and `MemberEntry::apply` fail with the limit, while
+    /// `add`, `add_weighted` and `add_all` add nothing. A hash ring over
+    /// lowered limits is left as it is, as are changes of `replicas_num`.
+    /// There is no limit by default.
+    pub fn set_limits(&mut self, limits: ResourceLimits) {
+        self.limits = limits;
+    }
+
+    /// Check whether adding the given counts of elements and virtual nodes
+    /// keeps the hash ring within its limits.
+    pub(crate) fn check_limits(&self, members: usize, vnodes: usize) -> Result<(), LimitExceeded> {
+        let held = read_lock(&self.sorted_keys).len();
+        self.limits.check(self.members.len().saturating_add(members),
+                          held.saturating_add(vnodes))
+    }
+
+    /// Get the normalizer of the lookup keys, `None` for the keys to be
+    /// hashed as they are.
+    pub fn key_normalizer(&self) -> Option<&KeyNormalizer> {
+        self.normalizer.as_ref()
+    }
+
+    /// Set the normalizer applied to every lookup key before it is hashed,
+    /// `None` for the keys to be hashed as they are, the default. Element
+    /// names are not normalized, so no virtual node moves, but the keys may
+    /// map to other elements.
+    pub fn set_key_normalizer(&mut self, normalizer: Option<KeyNormalizer>) {
+        self.normalizer = normalizer;
+    }
+
+    /// Route every key starting with the given prefix to the given element,
+    /// whatever its hash, replacing the rule of the same prefix if any. The
+    /// longest prefix matching a key wins, the key being normalized first.
+    /// `get_n` and the like return the element first, then the others in
+    /// the order of the hash ring.
+    ///
+    /// The element must be in the hash ring. A rule whose element is removed
+    /// afterwards is kept but skipped, the keys falling back to the next
+    /// longest rule or to hashing, until the element is added again. No
+    /// virtual node moves, nor is the version bumped.
+    pub fn add_prefix_rule<S: Into<String>>(&mut self,
+                                            prefix: S,
+                                            member: &str)
+                                            -> Result<(), Error> {
+        let _guard = write_lock(&self.lock);
+        if !self.members.contains_key(member) {
+            return Err(Error::UnknownMember(String::from(member)));
+        }
+        self.prefix_rules.insert(prefix.into(), String::from(member));
+        Ok(())
+    }
+
+    /// Remove the rule of the given prefix, returning its element.
+    pub fn remove_prefix_rule(&mut self, prefix: &str) -> Option<String> {
+        let _guard = write_lock(&self.lock);
+        self.prefix_rules.remove(prefix)
+    }
+
+    /// Get the prefix rules as `(prefix, element)`, sorted by prefix, those
+    /// of removed elements included.
+    pub fn prefix_rules(&self) -> Vec<(String, String)> {
+        let _guard = read_lock(&self.lock);
+        self.prefix_rules.iter().map(|(prefix, member)| (prefix.clone(), member.clone())).collect()
+    }
+
+    pub(crate) fn prefix_rule_map(&self) -> &BTreeMap<String, String> {
+        &self.prefix_rules
+    }
+
+    /// Set the rule of the given prefix, its element not being checked.
+    pub(crate) fn insert_prefix_rule(&mut self, prefix: String, member: String) {
+        let _guard = write_lock(&self.lock);
+        self.prefix_rules.insert(prefix, member);
+    }
+
+    /// Route the keys whose checksum is in the range `(start, end]` to the
+    /// given element, whatever the virtual nodes. The range is the one of
+    /// `nodes_for_range`, wrapping past `u32::MAX` if `end` is lower than
+    /// `start`, `start == end` being the whole keyspace. `get_n` and the like
+    /// return the element first, then the others in the order of the hash
+    /// ring, and `get_by_hash` returns it for the checksums of the range. A
+    /// prefix rule matching a key takes precedence over the ranges.
+    ///
+    /// The ranges never overlap: a range overlapping one already assigned
+    /// is refused with it, but assigning the very same range replaces its
+    /// element. The element must be in the hash ring. An assignment whose
+    /// element is removed afterwards is kept but skipped, the keys falling
+    /// back to hashing, until the element is added again. No virtual node
+    /// moves, nor is the version bumped.
+    pub fn assign_range(&mut self, start: u32, end: u32, member: &str) -> Result<(), Error> {
+        let _guard = write_lock(&self.lock);
+        if !self.members.contains_key(member) {
+            return Err(Error::UnknownMember(String::from(member)));
+        }
+        self.range_overrides.insert(start, end, String::from(member))
+    }
+
+    /// Remove the assignment of the range `(start, end]`, returning its
+    /// element. Only the very range assigned is removed, the keys in it
+    /// going back to the elements they hash to.
+    pub fn clear_range_assignment(&mut self, start: u32, end: u32) -> Option<String> {
+        let _guard = write_lock(&self.lock);
+        self.range_overrides.remove(start, end)
+    }
+
+    /// Get the range assignments as `(start, end, element)`, sorted by
+    /// start, those of removed elements included.
+    pub fn range_assignments(&self) -> Vec<(u32, u32, String)> {
+        let _guard = read_lock(&self.lock);
+        self.range_overrides
+            .iter()
+            .map(|(start, end, member)| (start, end, member.clone()))
+            .collect()
+    }
+
+    pub(crate) fn range_overrides(&self) -> &RangeOverrides<String> {
+        &self.range_overrides
+    }
+
+    /// Assign the given range, its element not being checked.
+    pub(crate) fn insert_range_assignment(&mut self,
+                                          start: u32,
+                                          end: u32,
+                                          member: String)
+                                          -> Result<(), Error> {
+        let _guard = write_lock(&self.lock);
+        self.range_overrides.insert(start, end, member)
+    }
+
+    /// Get the count of elements `owners` returns for every key, 1 by
+    /// default.
+    pub fn replication_factor(&self) -> usize {
+        self.replication_factor
+    }
+
+    /// Set the count of elements `owners` returns for every key, for the
+    /// call sites not to pass it to `get_n` each. The primary element of
+    /// every key is left alone, only the count of the following ones
+    /// changing.
+    pub fn set_replication_factor(&mut self, factor: usize) {
+        self.replication_factor = factor;
+    }
+
+    /// Get the name the lookups return while the hash ring is empty, `None`
+    /// for none.
+    pub fn fallback(&self) -> Option<&str> {
+        self.fallback.as_deref()
+    }
+
+    /// Set the name `get`, `get_n` and the lookups built on them, such as
+    /// `owners`, `get_composite`, `get_by_hash`, `get_primary_and_backup`,
+    /// `get_nth_owner` and `get_healthy`, return while the hash ring is
+    /// empty, as the only owner of every key, `None` for them to return
+    /// `None`, the default. The lookups filtering the elements, such as
+    /// `select`, never return it. The fallback is not an element: it has no
+    /// virtual node, is not counted by `count` and never returned while an
+    /// element is in. `get_with_source` tells its answers apart. The strict
+    /// lookups, `get_n_strict` and `owners_strict`, still fail on the empty
+    /// hash ring. The frozen and compiled hash rings keep it but, like the
+    /// key normalizer, it is not serialized.
+    pub fn set_fallback(&mut self, fallback: Option<String>) {
+        self.fallback = fallback;
+    }
+
+    /// Get the count of virtual nodes per weight unit, the one derived from
+    /// the membership with `set_auto_replicas`.
+    pub fn replicas_num(&self) -> usize {
+        self.replicas_num
+    }
+
+    /// Get the policy deriving the count of virtual nodes from the
+    /// membership, `None` for a fixed count.
+    pub fn auto_replicas(&self) -> Option<AutoReplicas> {
+        self.auto_replicas
+    }
+
+    /// Derive the count of virtual nodes per weight unit from the membership
+    /// with the given policy, `None` for a fixed count, the default. The hash
+    /// ring is rebuilt whenever the policy changes the count, right away and
+    /// after the adds and removes, which `last_rescale` reports. A rebuild
+    /// derives the virtual nodes recorded by `replace_node` from the names
+    /// again.
+    pub fn set_auto_replicas(&mut self, auto_replicas: Option<AutoReplicas>) {
+        self.auto_replicas = auto_replicas;
+        self.disruption.rescale = None;
+        self.auto_rescale();
+    }
+
+    /// Get the rebuild of the hash ring along with the last add, remove or
+    /// clear, `None` if the count of virtual nodes was kept, see
+    /// `set_auto_replicas`. Its disruption is counted in `last_disruption`.
+    pub fn last_rescale(&self) -> Option<Rescale> {
+        let _guard = read_lock(&self.lock);
+        self.disruption.rescale
+    }
+
+    /// Get the smallest distance between two virtual nodes of the same
+    /// element, as a fraction of the keyspace, 0 for none.
+    pub fn min_self_spacing(&self) -> f64 {
+        self.min_self_spacing as f64 / (1u64 << 32) as f64
+    }
+
+    /// Keep the virtual nodes of every element at least the given fraction
+    /// of the keyspace apart, like 0.001, 0 for no spacing, the default. A
+    /// virtual node too close to a previous one of its element is derived
+    /// again from the name of the element, its index and an attempt count,
+    /// so the placement stays deterministic. After 16 attempts the last one
+    /// is kept, so the spacing times the count of virtual nodes of every
+    /// element should stay well under half the keyspace. The virtual
+    /// nodes of the elements are derived again right away, but for the ones
+    /// recorded by `replace_node`, without bumping the version nor counting
+    /// the disruption.
+    pub fn set_min_self_spacing(&mut self, fraction: f64) {
+        let spacing = (fraction.clamp(0.0, 1.0) * (1u64 << 32) as f64) as u64;
+        self.set_self_spacing(spacing.min(u64::from(u32::MAX)) as u32);
+    }
+
+    /// Get the smallest distance between two virtual nodes of the same
+    /// element, in checksums.
+    pub(crate) fn self_spacing(&self) -> u32 {
+        self.min_self_spacing
+    }
+
+    /// Set the smallest distance between two virtual nodes of the same
+    /// element, in checksums, deriving the virtual nodes again.
+    pub(crate) fn set_self_spacing(&mut self, spacing: u32) {
+        let _guard = write_lock(&self.lock);
+        if spacing == self.min_self_spacing {
+            return;
+        }
+        self.min_self_spacing = spacing;
+
+        let mut members: Vec<(&Arc<str>, &usize)> = self.members.iter().collect();
+        members.sort();
+        let points: Vec<(u32, Vec<u32>)> = members.into_iter()
+            .map(|(element, &weight)| {
+                (self.slots.index(element).unwrap(), self.points(element, weight))
+            })
+            .collect();
+        let keys = lock_mut(&mut self.sorted_keys);
+        keys.clear();
+        for (owner, points) in points {
+            keys.extend(points.into_iter().map(|sum| (sum, owner)));
+        }
+    }
+
     /// Get the count of added elements.
     pub fn count(&self) -> usize {
-        let _ = self.lock.read().expect("rLock");
+        let _guard = read_lock(&self.lock);
         self.members.len()
     }
 
+    /// Get the names of the added elements, sorted.
+    pub fn members(&self) -> Vec<String> {
+        self.sorted_members().iter().map(|element| String::from(&**element)).collect()
+    }
+
     /// Add an elment to the hash ring.
     pub fn add<S: Into<String>>(&mut self, element: S) {
-        let _ = self.lock.write().expect("wLock");
-        let s = &Rc::new(element.into());
-        if self.contains(s) {
-            return;
-        }
+        self.add_weighted(element, 1)
+    }
+
+    /// Add an elment to the hash ring with the given weight, the element
+    /// getting `weight` times as many virtual nodes as an element added by
+    /// `add`. A weight of 0, or an element over the limits, adds nothing,
+    /// see `set_limits`.
+    pub fn add_weighted<S: Into<String>>(&mut self, element: S, weight: usize) {
+        let _ = self.try_add_weighted(element, weight);
+    }
+
+    /// Same as `add`, but the element is refused with the limit it would go
+    /// over, see `set_limits`.
+    pub fn try_add<S: Into<String>>(&mut self, element: S) -> Result<(), LimitExceeded> {
+        self.try_add_weighted(element, 1)
+    }
+
+    /// Same as `add_weighted`, but the element is refused with the limit it
+    /// would go over, see `set_limits`.
+    pub fn try_add_weighted<S: Into<String>>(&mut self,
+                                             element: S,
+                                             weight: usize)
+                                             -> Result<(), LimitExceeded> {
+        self.try_add_interned(Arc::from(element.into()), weight)
+    }
+
+    /// Same as `try_add_weighted`, the element being named by the given
+    /// shared name rather than a copy of it.
+    pub(crate) fn try_add_interned(&mut self,
+                                   element: Arc<str>,
+                                   weight: usize)
+                                   -> Result<(), LimitExceeded> {
+        let points = {
+            let _guard = write_lock(&self.lock);
+            if weight == 0 || self.members.contains_key(&*element) {
+                self.disruption.record(0.0);
+                return Ok(());
+            }
+            if let Err(err) = self.check_limits(1, self.replicas_num.saturating_mul(weight)) {
+                self.disruption.record(0.0);
+                return Err(err);
+            }
+            self.derived_points(&element, weight)
+        };
+        self.insert(element, weight, points);
+        Ok(())
+    }
+
+    /// Same as `add`, but nothing is added if it would move more of the
+    /// keyspace than allowed, see `set_max_disruption`.
+    pub fn checked_add<S: Into<String>>(&mut self, element: S) -> Result<(), DisruptionExceeded> {
+        self.checked_add_weighted(element, 1)
+    }
 
-        for i in 0..self.replicas_num {
-            let sum = checksum_ieee(Self::generate_element_name(s, i).as_bytes());
-            self.circle.insert(sum, s.clone());
-            self.sorted_keys.push(sum)
+    /// Same as `add_weighted`, but nothing is added if it would move more of
+    /// the keyspace than allowed, see `set_max_disruption`.
+    pub fn checked_add_weighted<S: Into<String>>(&mut self,
+                                                 element: S,
+                                                 weight: usize)
+                                                 -> Result<(), DisruptionExceeded> {
+        let element = element.into();
+        if let Some(budget) = self.max_disruption {
+            let would_move = self.preview_add_weighted(&element, weight).disruption;
+            if would_move > budget {
+                return Err(DisruptionExceeded { would_move, budget });
+            }
         }
+        self.add_weighted(element, weight);
+        Ok(())
+    }
 
-        self.members.insert(s.clone(), ());
-        self.sorted_keys.sort();
+    /// Add the given elements with their weights, leaving the same virtual
+    /// nodes as `add_weighted` does for each in turn, but hashing them all
+    /// before sorting them once, and bumping the version once. Elements of a
+    /// weight of 0 or already in are skipped, as are repeated ones after
+    /// their first occurrence. With the `rayon` feature, large loads are
+    /// hashed and sorted across the threads of the rayon pool. If they would
+    /// take the hash ring over its limits, none is added, see `set_limits`.
+    pub fn add_all<I, S>(&mut self, members: I)
+        where I: IntoIterator<Item = (S, usize)>,
+              S: Into<String>
+    {
+        let _ = self.try_add_all(members);
     }
 
-    /// Get the cloeset element's name to the given "name".
-    pub fn get<S: Into<String>>(&self, name: S) -> Option<String> {
-        let _ = self.lock.read().expect("rLock");
-        if self.circle.len() == 0 {
-            return None;
+    /// Same as `add_all`, but the elements are refused with the limit they
+    /// would go over, see `set_limits`.
+    pub fn try_add_all<I, S>(&mut self, members: I) -> Result<(), LimitExceeded>
+        where I: IntoIterator<Item = (S, usize)>,
+              S: Into<String>
+    {
+        let guard = write_lock(&self.lock);
+        let mut seen = HashSet::new();
+        let added: Vec<(Arc<str>, usize)> = members.into_iter()
+            .map(|(element, weight)| (Arc::<str>::from(element.into()), weight))
+            .filter(|&(ref element, weight)| {
+                weight > 0 && !self.members.contains_key(element) && seen.insert(element.clone())
+            })
+            .collect();
+        if added.is_empty() {
+            self.disruption.record(0.0);
+            return Ok(());
+        }
+        let vnodes = added.iter()
+            .fold(0usize, |vnodes, member| {
+                vnodes.saturating_add(self.replicas_num.saturating_mul(member.1))
+            });
+        if let Err(err) = self.check_limits(added.len(), vnodes) {
+            self.disruption.record(0.0);
+            return Err(err);
+        }
+        self.history.record(self.history_snapshot());
+
+        let slots = &mut self.slots;
+        let owners: Vec<u32> = added.iter().map(|member| slots.insert(member.0.clone())).collect();
+        let vnodes = self.bulk_vnodes(&added, &owners);
+        lock_mut(&mut self.sorted_keys).extend_sorted(vnodes);
+        for (element, weight) in added {
+            reincarnate(&mut self.incarnations, &element);
+            if self.records_tokens() {
+                let points = self.derived_points(&element, weight);
+                self.tokens.insert(element.clone(), points);
+            }
+            self.members.insert(element, weight);
         }
-        let key = self.sorted_keys[self.get_key_index(checksum_ieee(name.into().as_bytes()))];
+        self.version += 1;
+        let owners: HashSet<u32> = owners.into_iter().collect();
+        let disruption = self.share_of_owners(&owners);
+        self.disruption.record(disruption);
+        drop(guard);
+        self.auto_rescale();
+        Ok(())
+    }
 
-        Some(self.get_i_from_circle(key))
+    /// Crete a new instance of Consistant holding the given elements with
+    /// their weights, see `add_all`.
+    pub fn from_members<I, S>(replicas_num: usize, members: I) -> Self
+        where I: IntoIterator<Item = (S, usize)>,
+              S: Into<String>
+    {
+        let mut consistant = Self::new(replicas_num);
+        consistant.add_all(members);
+        consistant
     }
 
-    /// Get the N cloeset elements' names to the given "name".
-    pub fn get_n<S: Into<String>>(&self, name: S, n: usize) -> Option<Vec<String>> {
-        let _ = self.lock.read().expect("rLock");
-        if n == 0 || self.circle.len() == 0 {
-            return None;
+    /// Add an elment to the hash ring with the given tags, see `set_tags`. The
+    /// tags of an element already in are replaced.
+    pub fn add_with_tags<S: Into<String>>(&mut self, element: S, tags: &[&str]) {
+        let element = element.into();
+        self.add(element.as_str());
+        let tags = tags.iter().map(|tag| String::from(*tag)).collect();
+        self.insert_tags(&element, tags);
+    }
+
+    /// Replace the tags of the given element, which `get_with_tag` and
+    /// `get_n_with_tag` restrict lookups by. No virtual node moves, nor is
+    /// the version bumped.
+    pub fn set_tags(&mut self, name: &str, tags: &[&str]) -> Result<(), Error> {
+        if !self.contains(name) {
+            return Err(Error::UnknownMember(String::from(name)));
         }
-        let count = if self.count() > n { n } else { self.count() };
-        let mut start = self.get_key_index(checksum_ieee(name.into().as_bytes()));
-        let mut element = self.get_i_from_circle(self.sorted_keys[start]);
+        self.insert_tags(name, tags.iter().map(|tag| String::from(*tag)).collect());
+        Ok(())
+    }
 
-        let mut res = Vec::with_capacity(count);
-        res.push(element);
+    /// Get the tags of the given element, sorted.
+    pub fn tags_of(&self, name: &str) -> Option<Vec<String>> {
+        let _guard = read_lock(&self.lock);
+        if !self.members.contains_key(name) {
+            return None;
+        }
+        Some(self.tags.get(name).cloned().unwrap_or_default())
+    }
 
-        loop {
-            start = start + 1;
-            if start >= self.sorted_keys.len() {
-                start = 0;
+    /// Set the tags of the given element, which must be in the hash ring.
+    pub(crate) fn insert_tags(&mut self, name: &str, mut tags: Vec<String>) {
+        let _guard = write_lock(&self.lock);
+        tags.sort();
+        tags.dedup();
+        match self.members.get_key_value(name) {
+            Some((element, _)) if !tags.is_empty() => {
+                let element = element.clone();
+                self.tags.insert(element, tags);
             }
-            element = self.get_i_from_circle(self.sorted_keys[start]);
-            if !res.contains(&element) {
-                res.push(element)
-            }
-
-            if res.len() == count {
-                break;
+            _ => {
+                self.tags.remove(name);
             }
         }
+    }
 
-        Some(res)
+    /// Add an elment with the given weight and virtual node hashes, which are
+    /// recorded unless they are the ones derived from its name. The element
+    /// must not be in the hash ring yet.
+    pub(crate) fn add_with_tokens(&mut self, element: String, weight: usize, tokens: Vec<u32>) {
+        let s = Arc::<str>::from(element);
+        if self.records_tokens() || tokens != self.derived_points(&s, weight) {
+            let _guard = write_lock(&self.lock);
+            self.tokens.insert(s.clone(), tokens.clone());
+        }
+        self.insert(s, weight, tokens);
     }
 
-    /// Remove the given element.
-    pub fn remove<S: Into<String>>(&mut self, name: S) {
-        let _ = self.lock.write().expect("wLock");
-        let s = &Rc::new(name.into());
-        if !self.contains(s) {
-            return;
+    /// Change the weight of the given element, which must be in the hash
+    /// ring and derive its virtual nodes from its name, in a single pass.
+    /// Only the virtual nodes of one weight but not the other are added or
+    /// removed, so those taken over by other elements stay so.
+    pub(crate) fn reweight(&mut self, name: &str, weight: usize) {
+        let guard = write_lock(&self.lock);
+        self.history.record(self.history_snapshot());
+        let owner = self.slots.index(name).unwrap();
+        let before = self.derived_points(name, self.members[name]);
+        let after = self.derived_points(name, weight);
+        let common = before.iter().zip(&after).take_while(|(a, b)| a == b).count();
+        let share = self.share_of(owner, &before);
+
+        let keys = lock_mut(&mut self.sorted_keys);
+        for sum in &before[common..] {
+            keys.remove(*sum, owner);
         }
+        keys.extend(after[common..].iter().map(|sum| (*sum, owner)));
 
-        for i in 0..self.replicas_num {
-            let sum = &checksum_ieee(Self::generate_element_name(s, i).as_bytes());
-            self.circle.remove(sum);
+        *self.members.get_mut(name).unwrap() = weight;
+        self.fractions.remove(name);
+        self.version += 1;
+        let disruption = (self.share_of(owner, &after) - share).abs();
+        self.disruption.record(disruption);
+        drop(guard);
+        self.auto_rescale();
+    }
 
-            match self.sorted_keys.iter().position(|key| key.eq(sum)) {
-                Some(index) => self.sorted_keys.remove(index),
-                None => unreachable!(),
-            };
+    fn insert(&mut self, s: Arc<str>, weight: usize, points: Vec<u32>) {
+        let guard = write_lock(&self.lock);
+        self.history.record(self.history_snapshot());
+        let owner = self.slots.insert(s.clone());
+
+        // A colliding virtual node of another element is taken over.
+        lock_mut(&mut self.sorted_keys).extend(points.iter().map(|sum| (*sum, owner)));
+        if self.records_tokens() && !self.tokens.contains_key(&s) {
+            self.tokens.insert(s.clone(), points.clone());
         }
 
-        self.members.remove(s);
+        reincarnate(&mut self.incarnations, &s);
+        self.members.insert(s.clone(), weight);
+        self.version += 1;
+        let disruption = self.share_of(owner, &points) * self.fraction_of(&s);
+        self.disruption.record(disruption);
+        drop(guard);
+        self.auto_rescale();
     }
 
-    #[inline]
-    fn get_i_from_circle(&self, i: u32) -> String {
-        match self.circle.get(&i) {
-            Some(rc) => (**rc).clone(),
-            None => unreachable!(),
+    /// Get the incarnation of the given element, 0 when it is first added and
+    /// bumped every time it is added again after being removed. Adding an
+    /// element already in leaves it unchanged. The virtual nodes do not
+    /// depend on it, so that a new incarnation owns the keys of the previous
+    /// ones.
+    pub fn incarnation(&self, name: &str) -> Option<u64> {
+        let _guard = read_lock(&self.lock);
+        if !self.members.contains_key(name) {
+            return None;
         }
+        self.incarnations.get(name).cloned()
     }
 
-    #[inline]
-    fn contains(&self, name: &Rc<String>) -> bool {
-        self.members.contains_key(name)
+    /// Set the incarnation of the given element, for loading it.
+    pub(crate) fn set_incarnation(&mut self, name: &str, incarnation: u64) {
+        if let Some(current) = self.incarnations.get_mut(name) {
+            *current = incarnation;
+        }
     }
 
-    #[inline]
-    fn get_key_index(&self, sum: u32) -> usize {
-        let iter = (&self.sorted_keys).into_iter();
+    /// Get the weight of the given element, 1 for an element of a fractional
+    /// weight, see `fractional_weight`.
+    pub fn weight(&self, name: &str) -> Option<usize> {
+        let _guard = read_lock(&self.lock);
+        self.members.get(name).cloned()
+    }
+
+    /// Add an elment to the hash ring with the given fractional weight, in
+    /// `(0, 1]`, for it to get about that share of the keys of an element
+    /// added by `add`, see `set_fractional_weight`. A weight out of range, or
+    /// an element already in, adds nothing.
+    pub fn add_fractional<S: Into<String>>(&mut self, element: S, weight: f64) {
+        let element = element.into();
+        let millionths = match to_millionths(weight) {
+            Some(millionths) if !self.contains(&element) &&
+                                self.check_limits(1, self.replicas_num).is_ok() => millionths,
+            _ => {
+                let _guard = write_lock(&self.lock);
+                self.disruption.record(0.0);
+                return;
+            }
+        };
+        let s = Arc::<str>::from(element);
+        let points = {
+            let _guard = write_lock(&self.lock);
+            if millionths < MILLIONTHS {
+                self.fractions.insert(s.clone(), millionths);
+            }
+            self.derived_points(&s, 1)
+        };
+        self.insert(s, 1, points);
+    }
 
-        for (i, key) in iter.enumerate() {
-            if sum < *key {
-                return i;
+    /// Set the fractional weight of the given element, in `(0, 1]`, as for a
+    /// canary taking a small share of the keys before being ramped up. The
+    /// element keeps the virtual nodes of weight 1, but only admits the
+    /// given fraction of the keys of its virtual nodes, those it declines
+    /// going to the next element clockwise admitting them, as if the element
+    /// were not in. The keys it admits are picked by hashing them with its
+    /// name, so `get` stays the same for the same hash ring, and raising the
+    /// weight only moves keys onto the element, never among the others.
+    /// A weight of 1 makes it a plain element of weight 1 again.
+    ///
+    /// The element must be in the hash ring. One of another weight is first
+    /// reweighted to 1, as a mutation of its own, unless it has the virtual
+    /// nodes recorded by `replace_node`, which are refused. Only the owner
+    /// of a key is fractional: `get_n` and the like return the element
+    /// among the following owners of the keys it declines, and
+    /// `nodes_for_range` and the distributions count its virtual nodes
+    /// whole. The admitted keys change with the name, as with
+    /// `replace_node`.
+    pub fn set_fractional_weight(&mut self, name: &str, weight: f64) -> Result<(), Error> {
+        let millionths = match to_millionths(weight) {
+            Some(millionths) => millionths,
+            None => return Err(Error::InvalidWeight(String::from(name))),
+        };
+        match self.weight(name) {
+            None => return Err(Error::UnknownMember(String::from(name))),
+            Some(1) => {}
+            Some(_) if self.explicit_tokens(name).is_some() => {
+                return Err(Error::ExplicitTokens(String::from(name)));
             }
+            Some(_) => self.reweight(name, 1),
         }
 
-        0
+        let _guard = write_lock(&self.lock);
+        if self.fractions.get(name).cloned().unwrap_or(MILLIONTHS) == millionths {
+            return Ok(());
+        }
+        let before = self.fraction_of(name);
+        self.history.record(self.history_snapshot());
+        let s = self.slots.name(self.slots.index(name).unwrap()).clone();
+        if millionths < MILLIONTHS {
+            self.fractions.insert(s, millionths);
+        } else {
+            self.fractions.remove(name);
+        }
+        self.version += 1;
+        let owner = self.slots.index(name).unwrap();
+        let share = self.share_of(owner, &self.points(name, 1));
+        let disruption = share * (self.fraction_of(name) - before).abs();
+        self.disruption.record(disruption);
+        Ok(())
     }
 
-    #[inline]
-    fn generate_element_name(element: &str, i: usize) -> String {
-        String::from(element) + &i.to_string()
+    /// Get the weight of the given element, its fraction for an element of
+    /// a fractional weight, see `set_fractional_weight`.
+    pub fn fractional_weight(&self, name: &str) -> Option<f64> {
+        let _guard = read_lock(&self.lock);
+        let weight = *self.members.get(name)?;
+        Some(weight as f64 * self.fraction_of(name))
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Get the share of its keys the given element admits, 1 unless it is
+    /// of a fractional weight.
+    fn fraction_of(&self, name: &str) -> f64 {
+        match self.fractions.get(name) {
+            Some(millionths) => f64::from(*millionths) / f64::from(MILLIONTHS),
+            None => 1.0,
+        }
+    }
 
-    #[test]
-    fn test_default() {
-        let consistant = Consistant::default();
+    /// Get the cloeset element's name to the given "name".
+    pub fn get<S: AsRef<str>>(&self, name: S) -> Option<String> {
+        let _guard = read_lock(&self.lock);
+        let keys = self.keys();
+        let (sum, routed) = self.route(&keys, name.as_ref());
+        self.get_at(&keys, sum, routed)
+    }
 
-        assert_eq!(consistant.replicas_num, 20);
-        assert_eq!(consistant.circle.len(), 0);
-        assert_eq!(consistant.sorted_keys.len(), 0);
+    /// Get the N cloeset elements' names to the given "name".
+    pub fn get_n<S: AsRef<str>>(&self, name: S, n: usize) -> Option<Vec<String>> {
+        let _guard = read_lock(&self.lock);
+        let keys = self.keys();
+        let (sum, routed) = self.route(&keys, name.as_ref());
+        self.get_n_at(&keys, sum, routed, n)
     }
 
-    #[test]
-    fn test_new() {
-        let consistant = Consistant::new(30);
+    /// Same as `get`, along with where the name comes from: the hash ring,
+    /// a prefix rule or range assignment, or the fallback.
+    pub fn get_with_source<S: AsRef<str>>(&self, name: S) -> Option<(String, RouteSource)> {
+        let _guard = read_lock(&self.lock);
+        let keys = self.keys();
+        let (sum, pinned) = self.pin(name.as_ref());
+        let source = match pinned {
+            _ if keys.is_empty() => RouteSource::Fallback,
+            Some(_) => RouteSource::Pinned,
+            None => RouteSource::Ring,
+        };
+        let owner = self.get_at(&keys, sum, pinned.or_else(|| self.reroute(&keys, sum)));
+        owner.map(|element| (element, source))
+    }
 
-        assert_eq!(consistant.replicas_num, 30);
-        assert_eq!(consistant.circle.len(), 0);
-        assert_eq!(consistant.sorted_keys.len(), 0);
+    /// Get the elements owning the given key, the `replication_factor`
+    /// cloeset ones as `get_n` does, or fewer if there are not as many.
+    pub fn owners<S: AsRef<str>>(&self, key: S) -> Option<Vec<String>> {
+        self.get_n(key, self.replication_factor)
     }
 
-    #[test]
-    fn test_count() {
-        let mut consistant = Consistant::default();
-        consistant.add("cacheA");
-        consistant.add("cacheB");
-        consistant.add("cacheC");
-        assert_eq!(consistant.count(), 3);
+    /// Same as `owners`, but returns an error instead of fewer elements than
+    /// the replication factor, as `get_n_strict` does.
+    pub fn owners_strict<S: AsRef<str>>(&self, key: S) -> Result<Vec<String>, ReplicationError> {
+        self.get_n_strict(key, self.replication_factor)
     }
 
-    #[test]
-    fn test_add() {
-        let mut consistant = Consistant::default();
-        consistant.add("cacheA");
-        consistant.add("cacheB");
-        consistant.add("cacheC");
+    /// Get the cloeset element's name to the key made of the given parts,
+    /// without formatting them into a single key. Parts told apart only by
+    /// where they split, as `["ab", "c"]` and `["a", "bc"]`, make distinct
+    /// keys.
+    ///
+    /// The parts are encoded each as its length in 4 little endian bytes
+    /// followed by its bytes, the encoding being hashed as the bytes of a
+    /// single key would. So `get_composite(&[b"ab", b"c"])` is
+    /// `get("\u{2}\0\0\0ab\u{1}\0\0\0c")`, whenever the encoding is valid
+    /// UTF-8. Neither the key normalizer nor the prefix rules apply, being
+    /// defined on single keys.
+    pub fn get_composite(&self, parts: &[&[u8]]) -> Option<String> {
+        let _guard = read_lock(&self.lock);
+        let keys = self.keys();
+        let sum = self.key_hasher().checksum_parts(parts);
+        self.get_at(&keys, sum, self.reroute(&keys, sum))
+    }
 
-        assert_eq!(consistant.circle.len(), 3 * consistant.replicas_num);
-        assert_eq!(consistant.sorted_keys.len(), 3 * consistant.replicas_num);
+    /// Get the N cloeset elements' names to the key made of the given parts,
+    /// encoded as by `get_composite`.
+    pub fn get_n_composite(&self, parts: &[&[u8]], n: usize) -> Option<Vec<String>> {
+        let _guard = read_lock(&self.lock);
+        let keys = self.keys();
+        let sum = self.key_hasher().checksum_parts(parts);
+        self.get_n_at(&keys, sum, self.reroute(&keys, sum), n)
     }
 
-    #[test]
-    fn test_contains() {
-        let mut consistant = Consistant::default();
-        consistant.add("cacheA");
+    /// Same as `get`, for the given checksum and routed element, the read
+    /// lock being held.
+    fn get_at(&self, keys: &SortedKeys, sum: u32, routed: Option<u32>) -> Option<String> {
+        if keys.is_empty() {
+            return self.fallback.clone();
+        }
+        let owner = routed.unwrap_or_else(|| keys[keys.successor(sum)].1);
 
-        assert_eq!(consistant.contains(&Rc::new(String::from("cacheA"))), true);
-        assert_eq!(consistant.contains(&Rc::new(String::from("cacheB"))), false);
-        assert_eq!(consistant.contains(&Rc::new(String::from("CachEa"))), false);
+        Some(String::from(&**self.slots.name(owner)))
     }
 
-    #[test]
-    fn test_get() {
-        let mut consistant = Consistant::default();
-        consistant.add("cacheA");
-        consistant.add("cacheB");
-        consistant.add("cacheC");
+    /// Same as `get_n`, for the given checksum and routed element, the read
+    /// lock being held.
+    fn get_n_at(&self,
+                keys: &SortedKeys,
+                sum: u32,
+                routed: Option<u32>,
+                n: usize)
+                -> Option<Vec<String>> {
+        if n == 0 {
+            return None;
+        }
+        if keys.is_empty() {
+            return self.fallback.clone().map(|fallback| vec![fallback]);
+        }
+        let count = if self.members.len() > n { n } else { self.members.len() };
+        let start = keys.successor(sum);
+        let owners = distinct_owners(keys.len(), |index| keys[index].1, start, routed, count);
 
-        assert_eq!(consistant.get("david").unwrap(),
-                   consistant.get("david").unwrap());
-        assert_eq!(consistant.get("kally").unwrap(),
-                   consistant.get("kally").unwrap());
-        assert_eq!(consistant.get("jason").unwrap(),
-                   consistant.get("jason").unwrap());
+        Some(owners.into_iter().map(|owner| String::from(&**self.slots.name(owner))).collect())
     }
 
-    #[test]
-    fn test_get_n() {
-        let mut consistant = Consistant::default();
-        consistant.add("cacheA");
-        consistant.add("cacheB");
-        consistant.add("cacheC");
+    /// Same as `get_n`, but fills the given buffer with the names, cleared
+    /// first, rather than allocating them. Returns the count of names written,
+    /// 0 where `get_n` returns `None`.
+    pub fn get_n_into<'a, S: AsRef<str>>(&'a self,
+                                         key: S,
+                                         n: usize,
+                                         out: &mut Vec<&'a str>)
+                                         -> usize {
+        let _guard = read_lock(&self.lock);
+        out.clear();
+        let keys = self.keys();
+        if n == 0 || keys.is_empty() {
+            return 0;
+        }
+        let count = n.min(self.members.len());
+        let (sum, routed) = self.route(&keys, key.as_ref());
+        let mut index = keys.successor(sum);
 
-        let res = consistant.get_n("david", 3).unwrap();
-        assert_eq!(res.len(), 3);
+        out.extend(routed.map(|owner| &**self.slots.name(owner)));
+        // A lap at most, see `distinct_owners`.
+        for _ in 0..keys.len() {
+            if out.len() == count {
+                break;
+            }
+            let element = &**self.slots.name(keys[index].1);
+            if !out.contains(&element) {
+                out.push(element);
+            }
+            index += 1;
+            if index >= keys.len() {
+                index = 0;
+            }
+        }
 
-        consistant.remove("cacheA");
+        out.len()
+    }
 
-        let res2 = consistant.get_n("david", 3).unwrap();
-        assert_eq!(res2.len(), 2);
+    /// Check whether the given element is one of the N cloeset elements to
+    /// the given key, as `get_n(key, n)` containing it, without allocating.
+    pub fn is_owner<S: AsRef<str>>(&self, member: &str, key: S, n: usize) -> bool {
+        let _guard = read_lock(&self.lock);
+        let target = match self.slots.index(member) {
+            Some(target) => target,
+            None => return false,
+        };
+        let keys = self.keys();
+        if n == 0 || keys.is_empty() {
+            return false;
+        }
+        let (sum, routed) = self.route(&keys, key.as_ref());
+        if routed == Some(target) {
+            return true;
+        }
+        let start = keys.successor(sum);
+
+        // The routed element is skipped, leaving one owner less to walk.
+        let count = n.min(self.members.len());
+        let (mut found, mut others) = (false, count - routed.iter().count());
+        if others == 0 {
+            return false;
+        }
+        walk_owners(keys.len(), |index| keys[index].1, start, count, |owner| {
+            if Some(owner) == routed {
+                return false;
+            }
+            found = owner == target;
+            others -= 1;
+            found || others == 0
+        });
+        found
     }
 
-    #[test]
-    fn test_remove() {
-        let mut consistant = Consistant::default();
-        consistant.add("cacheA");
-        consistant.add("cacheB");
-        consistant.add("cacheC");
+    /// Get the i-th distinct element clockwise from the given key, 0 being
+    /// the closest, as `get_n(name, i + 1).last()` does but without
+    /// collecting the others. `None` if there are not more than i elements.
+    pub fn get_nth_owner<S: AsRef<str>>(&self, key: S, i: usize) -> Option<String> {
+        let _guard = read_lock(&self.lock);
+        let keys = self.keys();
+        if keys.is_empty() {
+            return if i == 0 { self.fallback.clone() } else { None };
+        }
+        if i >= self.members.len() {
+            return None;
+        }
+        let (sum, routed) = self.route(&keys, key.as_ref());
+        let start = keys.successor(sum);
+        let i = match routed {
+            Some(owner) if i == 0 => return Some(String::from(&**self.slots.name(owner))),
+            Some(_) => i - 1,
+            None => i,
+        };
 
-        consistant.remove("cacheC");
-        assert_eq!(consistant.count(), 2);
+        let (mut nth, mut seen) = (None, 0);
+        walk_owners(keys.len(), |index| keys[index].1, start, self.members.len(), |owner| {
+            if Some(owner) == routed {
+                return false;
+            }
+            seen += 1;
+            if seen == i + 1 {
+                nth = Some(owner);
+            }
+            nth.is_some()
+        });
+        nth.map(|owner| String::from(&**self.slots.name(owner)))
+    }
 
-        assert!(consistant.get("david").unwrap() != String::from("cacheC"));
-        assert!(consistant.get("kally").unwrap() != String::from("cacheC"));
-        assert!(consistant.get("jason").unwrap() != String::from("cacheC"));
+    /// Get the cloeset element having the given tag to the given "name",
+    /// skipping the others clockwise. `None` if no element has the tag.
+    pub fn get_with_tag<S: AsRef<str>>(&self, name: S, tag: &str) -> Option<String> {
+        self.get_n_with_tag(name, 1, tag).and_then(|mut owners| owners.pop())
+    }
+
+    /// Get the N cloeset distinct elements having the given tag to the given
+    /// "name", as `get_n` does among them only. `None` if no element has the
+    /// tag.
+    pub fn get_n_with_tag<S: AsRef<str>>(&self,
+                                         name: S,
+                                         n: usize,
+                                         tag: &str)
+                                         -> Option<Vec<String>> {
+        self.select(name, n, &SelectionPolicy::new().require_tag(tag))
     }
-}
 
+    /// Walk the distinct elements clockwise from the given "name", the routed
+    /// one first, as `get_n` does, calling `visit` with every one until it
+    /// returns true. The read lock must be held.
+    pub(crate) fn walk_members<F: FnMut(&Arc<str>) -> bool>(&self, name: &str, mut visit: F) {
+        let keys = self.keys();
+        if keys.is_empty() {
+            return;
+        }
+        let (sum, routed) = self.route(&keys, name);
+        let start = keys.successor(sum);
+
+        let mut walked = HashSet::new();
+        let ring = (0..keys.len()).map(|i| keys[(start + i) % keys.len()].1);
+        for owner in routed.into_iter().chain(ring) {
+            if walked.insert(owner) &&
+               (visit(self.slots.name(owner)) || walked.len() == self.members.len()) {
+                return;
+            }
+        }
+    }
+
+    /// Get the tags of the given element, sorted, the read lock being held.
+    pub(crate) fn tags_ref(&self, name: &str) -> &[String] {
+        self.tags.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    /// Get the cloeset element's name to the given "name", along with the next
+    /// distinct element clockwise if any, as `get_n(name, 2)` does, the
+    /// fallback without a backup on the empty hash ring.
+    pub fn get_primary_and_backup<S: AsRef<str>>(&self,
+                                                   name: S)
+                                                   -> Option<(String, Option<String>)> {
+        let _guard = read_lock(&self.lock);
+        let keys = self.keys();
+        if keys.is_empty() {
+            return self.fallback.clone().map(|fallback| (fallback, None));
+        }
+        let (sum, routed) = self.route(&keys, name.as_ref());
+        let start = keys.successor(sum);
+        let primary = routed.unwrap_or(keys[start].1);
+        let name = |owner| String::from(&**self.slots.name(owner));
+        if self.members.len() < 2 {
+            return Some((name(primary), None));
+        }
+
+        let backup = (0..keys.len())
+            .map(|i| keys[(start + i) % keys.len()].1)
+            .find(|owner| *owner != primary)
+            .map(name);
+
+        Some((name(primary), backup))
+    }
+
+    /// Get the element owning the given checksum, as if it were the one of a
+    /// key passed to `get`.
+    pub fn get_by_hash(&self, sum: u32) -> Option<String> {
+        let _guard = read_lock(&self.lock);
+        let keys = self.keys();
+        let routed = self.range_assignment(sum).map(|range| range.2);
+        self.get_at(&keys, sum, routed.or_else(|| self.reroute(&keys, sum)))
+    }
+
+    /// Explain how the given key is resolved to its cloeset element.
+    pub fn explain<S: AsRef<str>>(&self, key: S) -> Option<LookupExplanation> {
+        let _guard = read_lock(&self.lock);
+        let keys = self.keys();
+        if keys.is_empty() {
+            return None;
+        }
+        let key = key.as_ref();
+        let normalized = self.normalize(key);
+        let checksum = self.key_hasher().checksum(normalized.as_bytes());
+        let index = keys.successor(checksum);
+        let (vnode_hash, mut owner) = keys[index];
+        let rule = self.prefix_rule(&normalized);
+        let range = match rule {
+            Some(_) => None,
+            None => self.range_assignment(checksum),
+        };
+        let routed = rule.map(|rule| rule.1).or(range.map(|range| range.2));
+        let mut skipped = Vec::new();
+        if let Some(routed) = routed {
+            owner = routed;
+        } else if let Some(rerouted) = self.reroute(&keys, checksum) {
+            skipped = self.declining(&keys, index, checksum);
+            owner = rerouted;
+        }
+
+        Some(LookupExplanation {
+            key: String::from(key),
+            checksum,
+            index,
+            vnode_hash,
+            owner: String::from(&**self.slots.name(owner)),
+            wrapped: checksum >= keys[keys.len() - 1].0,
+            prefix_rule: rule.map(|(prefix, _)| String::from(prefix)),
+            range_assignment: range.map(|(start, end, _)| (start, end)),
+            skipped,
+        })
+    }
+
+    /// Get the distinct fractional elements declining the key of the given
+    /// checksum clockwise from the virtual node of the given index, up to
+    /// the first one admitting it.
+    fn declining(&self, keys: &SortedKeys, index: usize, sum: u32) -> Vec<SkippedCandidate> {
+        let mut skipped: Vec<SkippedCandidate> = Vec::new();
+        for i in 0..keys.len() {
+            let name = self.slots.name(keys[(index + i) % keys.len()].1);
+            let millionths = match self.fractions.get(name) {
+                Some(millionths) if !admits(name, *millionths, sum) => *millionths,
+                _ => break,
+            };
+            if skipped.iter().all(|candidate| *candidate.element != **name) {
+                skipped.push(SkippedCandidate {
+                    element: String::from(&**name),
+                    reason: SkipReason::FractionalWeight { millionths },
+                });
+            }
+        }
+        skipped
+    }
+
+    /// Get the clockwise distance from the checksum of the given key to the
+    /// virtual node the hash ring matches it to, whatever the prefix rules,
+    /// range assignments and fractional weights route it to. `None` for an
+    /// empty hash ring.
+    pub fn distance_to_owner<S: AsRef<str>>(&self, key: S) -> Option<u32> {
+        let _guard = read_lock(&self.lock);
+        let keys = self.keys();
+        if keys.is_empty() {
+            return None;
+        }
+        let sum = self.key_hasher().checksum(self.normalize(key.as_ref()).as_bytes());
+        Some(keys[keys.successor(sum)].0.wrapping_sub(sum))
+    }
+
+    /// Get every element with the clockwise distance from the checksum of
+    /// the given key to its nearest virtual node, the nearest first, which
+    /// is the order of `get_n` for a key no prefix rule, range assignment nor
+    /// fractional weight routes. Distances wrap around the hash ring, a
+    /// virtual node on the checksum itself being a full turn away, which is
+    /// counted as 0 but comes last as `get_n` skips it.
+    pub fn distances<S: AsRef<str>>(&self, key: S) -> Vec<(String, u32)> {
+        let _guard = read_lock(&self.lock);
+        let keys = self.keys();
+        if keys.is_empty() {
+            return Vec::new();
+        }
+        let sum = self.key_hasher().checksum(self.normalize(key.as_ref()).as_bytes());
+        let start = keys.successor(sum);
+
+        let mut distances = Vec::with_capacity(self.members.len());
+        let mut walked = HashSet::new();
+        for i in 0..keys.len() {
+            let (hash, owner) = keys[(start + i) % keys.len()];
+            if walked.insert(owner) {
+                distances.push((String::from(&**self.slots.name(owner)), hash.wrapping_sub(sum)));
+                if distances.len() == self.members.len() {
+                    break;
+                }
+            }
+        }
+        distances
+    }
+
+    /// Same as `get_n`, but returns an error instead of fewer elements when
+    /// the hash ring can not supply N distinct elements. The fallback is
+    /// not an element, so the empty hash ring never supplies any.
+    pub fn get_n_strict<S: AsRef<str>>(&self,
+                                         name: S,
+                                         n: usize)
+                                         -> Result<Vec<String>, ReplicationError> {
+        if n == 0 {
+            return Ok(Vec::new());
+        }
+
+        let res = {
+            let _guard = read_lock(&self.lock);
+            if self.members.is_empty() {
+                Vec::new()
+            } else {
+                let keys = self.keys();
+                let (sum, routed) = self.route(&keys, name.as_ref());
+                self.get_n_at(&keys, sum, routed, n).unwrap_or_default()
+            }
+        };
+        if res.len() < n {
+            return Err(ReplicationError::NotEnoughMembers {
+                wanted: n,
+                available: res.len(),
+            });
+        }
+
+        Ok(res)
+    }
+
+    /// Remove the given element.
+    pub fn remove<S: AsRef<str>>(&mut self, name: S) {
+        let guard = write_lock(&self.lock);
+        let s = name.as_ref();
+        let weight = match self.members.get(s) {
+            Some(weight) => *weight,
+            None => {
+                self.disruption.record(0.0);
+                return;
+            }
+        };
+        self.history.record(self.history_snapshot());
+        let points = self.points(s, weight);
+        let owner = self.slots.index(s).unwrap();
+        let disruption = self.share_of(owner, &points) * self.fraction_of(s);
+
+        let keys = lock_mut(&mut self.sorted_keys);
+        for sum in points {
+            // Virtual nodes taken over by other elements are kept.
+            keys.remove(sum, owner);
+        }
+
+        self.members.remove(s);
+        self.tokens.remove(s);
+        self.tags.remove(s);
+        self.fractions.remove(s);
+        self.latencies.remove(s);
+        self.breakers.remove(s);
+        self.registered.remove(s);
+        self.slots.remove(s);
+        self.version += 1;
+        self.disruption.record(disruption);
+        drop(guard);
+        self.auto_rescale();
+    }
+
+    /// Same as `remove`, but nothing is removed if it would move more of the
+    /// keyspace than allowed, see `set_max_disruption`.
+    pub fn checked_remove<S: AsRef<str>>(&mut self, name: S) -> Result<(), DisruptionExceeded> {
+        if let Some(budget) = self.max_disruption {
+            let would_move = self.preview_remove(name.as_ref()).disruption;
+            if would_move > budget {
+                return Err(DisruptionExceeded { would_move, budget });
+            }
+        }
+        self.remove(name);
+        Ok(())
+    }
+
+    /// Keep only the elements for which the given function returns true,
+    /// returning the count of the removed ones. Their virtual nodes are
+    /// removed in a single pass, the hash ring ending as after removing them
+    /// one by one, but with its version bumped once.
+    pub fn retain<F: FnMut(&str) -> bool>(&mut self, mut f: F) -> usize {
+        let guard = write_lock(&self.lock);
+        let removed: Vec<Arc<str>> =
+            self.members.keys().filter(|element| !f(element)).cloned().collect();
+        if removed.is_empty() {
+            self.disruption.record(0.0);
+            return 0;
+        }
+        self.history.record(self.history_snapshot());
+
+        let mut owners = HashSet::with_capacity(removed.len());
+        let mut disruption = 0.0;
+        for element in &removed {
+            let owner = self.slots.index(element).unwrap();
+            disruption += self.share_of(owner, &self.points(element, self.members[element])) *
+                          self.fraction_of(element);
+            owners.insert(owner);
+        }
+        lock_mut(&mut self.sorted_keys).retain(|key| !owners.contains(&key.1));
+
+        for element in &removed {
+            self.members.remove(element);
+            self.tokens.remove(element);
+            self.tags.remove(element);
+            self.fractions.remove(element);
+            self.latencies.remove(element);
+            self.breakers.remove(element);
+            self.registered.remove(element);
+            self.slots.remove(element);
+        }
+        self.version += 1;
+        self.disruption.record(disruption.min(1.0));
+        drop(guard);
+        self.auto_rescale();
+
+        removed.len()
+    }
+
+    /// Preview adding the given element, without changing the hash ring.
+    /// Only the neighbours of its would-be virtual nodes are looked at. An
+    /// element already in changes nothing.
+    pub fn preview_add(&self, name: &str) -> MutationPreview {
+        self.preview_add_weighted(name, 1)
+    }
+
+    /// Same as `preview_add`, for an element of the given weight, see
+    /// `add_weighted`.
+    pub fn preview_add_weighted(&self, name: &str, weight: usize) -> MutationPreview {
+        let _guard = read_lock(&self.lock);
+        let keys = self.keys();
+        let mut preview = MutationPreview {
+            disruption: 0.0,
+            moves: Vec::new(),
+            count: self.members.len(),
+            vnodes: keys.len(),
+        };
+        if weight == 0 || self.members.contains_key(name) {
+            return preview;
+        }
+        preview.count += 1;
+        let mut points = self.derived_points(name, weight);
+        points.sort();
+        points.dedup();
+        if points.is_empty() {
+            return preview;
+        }
+        let to = Some(String::from(name));
+        if keys.is_empty() {
+            preview.disruption = 1.0;
+            preview.moves.push((None, to));
+            preview.vnodes = points.len();
+            return preview;
+        }
+
+        let arc = |sum: u32, start: u32| {
+            match u64::from(sum.wrapping_sub(start)) {
+                0 => 1 << 32,
+                arc => arc,
+            }
+        };
+        let mut length = 0;
+        for (i, &sum) in points.iter().enumerate() {
+            // A colliding virtual node is taken over, else the arc is split.
+            let from = match keys.get(sum) {
+                Some(owner) => owner,
+                None => {
+                    preview.vnodes += 1;
+                    keys[keys.successor(sum)].1
+                }
+            };
+            // The moved arc starts at the closest virtual node before, of
+            // either element.
+            let before = points[if i == 0 { points.len() - 1 } else { i - 1 }];
+            length += cmp::min(arc(sum, before), arc(sum, keys.predecessor(sum)));
+            preview.moves.push((Some(String::from(&**self.slots.name(from))), to.clone()));
+        }
+        preview.disruption = length as f64 / (1u64 << 32) as f64;
+        preview.moves.sort();
+        preview.moves.dedup();
+
+        preview
+    }
+
+    /// Preview removing the given element, without changing the hash ring,
+    /// as `preview_add` does. An element not in changes nothing.
+    pub fn preview_remove(&self, name: &str) -> MutationPreview {
+        let _guard = read_lock(&self.lock);
+        let (owner, weight) = match (self.slots.index(name), self.members.get(name)) {
+            (Some(owner), Some(weight)) => (owner, *weight),
+            _ => {
+                return MutationPreview {
+                    disruption: 0.0,
+                    moves: Vec::new(),
+                    count: self.members.len(),
+                    vnodes: self.keys().len(),
+                }
+            }
+        };
+        let points = self.points(name, weight);
+        let disruption = self.share_of(owner, &points);
+
+        let keys = self.keys();
+        let mut owned: Vec<u32> =
+            points.into_iter().filter(|sum| keys.get(*sum) == Some(owner)).collect();
+        owned.sort();
+        owned.dedup();
+        let mut moves = Vec::with_capacity(owned.len());
+        for sum in &owned {
+            // The arc goes to the next virtual node of another element.
+            let mut index = keys.successor(*sum);
+            let to = (0..keys.len())
+                .map(|_| {
+                    let next = keys[index].1;
+                    index = if index + 1 == keys.len() { 0 } else { index + 1 };
+                    next
+                })
+                .find(|next| *next != owner)
+                .map(|next| String::from(&**self.slots.name(next)));
+            moves.push((Some(String::from(name)), to));
+        }
+        moves.sort();
+        moves.dedup();
+
+        MutationPreview {
+            disruption,
+            moves,
+            count: self.members.len() - 1,
+            vnodes: keys.len() - owned.len(),
+        }
+    }
+
+    /// Rename the element `old` to `new`, which takes over its virtual nodes
+    /// so that no key moves, as when a host is replaced in place. Handles of
+    /// `old` resolve to `new`, which also takes its tags. The virtual nodes of
+    /// `new` are then recorded as its tokens by every serialization, not being
+    /// derived from its name.
+    pub fn replace_node(&mut self, old: &str, new: &str) -> Result<(), Error> {
+        let _guard = write_lock(&self.lock);
+        if self.members.contains_key(new) {
+            return Err(Error::DuplicateMember(String::from(new)));
+        }
+        let weight = match self.members.get(old) {
+            Some(weight) => *weight,
+            None => return Err(Error::UnknownMember(String::from(old))),
+        };
+        self.history.record(self.history_snapshot());
+        self.members.remove(old);
+        let points = self.points(old, weight);
+        self.tokens.remove(old);
+
+        let s = Arc::<str>::from(new);
+        self.slots.rename(old, s.clone());
+        if self.records_tokens() || points != self.derived_points(&s, weight) {
+            self.tokens.insert(s.clone(), points);
+        }
+        if let Some(tags) = self.tags.remove(old) {
+            self.tags.insert(s.clone(), tags);
+        }
+        if let Some(fraction) = self.fractions.remove(old) {
+            self.fractions.insert(s.clone(), fraction);
+        }
+        self.latencies.remove(old);
+        self.breakers.remove(old);
+        self.registered.remove(old);
+        reincarnate(&mut self.incarnations, &s);
+        self.members.insert(s, weight);
+        self.version += 1;
+        self.disruption.record(0.0);
+
+        Ok(())
+    }
+
+    /// Remove all the elements.
+    pub fn clear(&mut self) {
+        let guard = write_lock(&self.lock);
+        if self.members.is_empty() {
+            self.disruption.record(0.0);
+            return;
+        }
+        self.history.record(self.history_snapshot());
+        self.members.clear();
+        self.tokens.clear();
+        self.tags.clear();
+        self.fractions.clear();
+        self.latencies.clear();
+        self.breakers.clear();
+        self.registered.clear();
+        lock_mut(&mut self.sorted_keys).clear();
+        self.slots.clear();
+        self.version += 1;
+        self.disruption.record(1.0);
+        drop(guard);
+        self.auto_rescale();
+    }
+
+    /// Release the memory held by the hash ring beyond what its elements need,
+    /// such as after removing most of them. The mappings are unchanged.
+    pub fn compact(&mut self) {
+        let _guard = write_lock(&self.lock);
+        self.members.shrink_to_fit();
+        self.tokens.shrink_to_fit();
+        self.incarnations.shrink_to_fit();
+        self.tags.shrink_to_fit();
+        self.fractions.shrink_to_fit();
+        lock_mut(&mut self.sorted_keys).shrink_to_fit();
+        self.slots.shrink_to_fit();
+    }
+
+    /// Get an estimate of the heap bytes held by the hash ring.
+    pub fn memory_usage(&self) -> usize {
+        let _guard = read_lock(&self.lock);
+        let names: usize = self.members.keys().map(|element| element.len()).sum();
+
+        self.members.capacity() * (mem::size_of::<(Arc<str>, usize)>() + 1) +
+        self.tokens.values().map(|tokens| tokens.capacity() * 4).sum::<usize>() +
+        self.incarnations.capacity() * (mem::size_of::<(Arc<str>, u64)>() + 1) +
+        self.tags.values().flatten().map(|tag| tag.capacity()).sum::<usize>() +
+        self.fractions.capacity() * (mem::size_of::<(Arc<str>, u32)>() + 1) +
+        read_lock(&self.sorted_keys).memory_usage() + names +
+        self.slots.memory_usage()
+    }
+
+    /// Group the given keys by their closest element. Keys keep their input
+    /// order within each group. An empty hash ring yields an empty map.
+    pub fn group_keys_by_node<I, S>(&self, keys: I) -> HashMap<String, Vec<S>>
+        where I: IntoIterator<Item = S>,
+              S: AsRef<str>
+    {
+        let _guard = read_lock(&self.lock);
+        let mut groups: HashMap<String, Vec<S>> = HashMap::new();
+        let sorted_keys = self.keys();
+        if sorted_keys.is_empty() {
+            return groups;
+        }
+
+        for key in keys {
+            let (sum, routed) = self.route(&sorted_keys, key.as_ref());
+            let owner = routed.unwrap_or_else(|| sorted_keys[sorted_keys.successor(sum)].1);
+            let element = String::from(&**self.slots.name(owner));
+            groups.entry(element).or_default().push(key);
+        }
+
+        groups
+    }
+
+    /// Build a copy of the hash ring with the given count of virtual nodes
+    /// per weight unit, leaving the original untouched, as for comparing
+    /// replica counts. The elements keep their weights, but not the virtual
+    /// nodes recorded by `replace_node`, which are derived from their names
+    /// again, and their handles, see `get_handle`. The copy keeps the past
+    /// versions of `set_keep_versions`, which still map keys as this hash ring
+    /// did, only the current one being of the new count. With the same count,
+    /// the copy is a clone, otherwise its count is fixed, see
+    /// `set_auto_replicas`.
+    pub fn clone_with_replicas(&self, replicas: usize) -> Consistant {
+        if replicas == self.replicas_num {
+            return self.clone();
+        }
+        let mut consistant = Consistant::with_config(replicas, self.placement, self.seed);
+        consistant.secret = self.secret;
+        consistant.max_disruption = self.max_disruption;
+        consistant.normalizer = self.normalizer.clone();
+        consistant.prefix_rules = self.prefix_rules.clone();
+        consistant.range_overrides = self.range_overrides.clone();
+        consistant.colocations = self.colocations.clone();
+        consistant.replication_factor = self.replication_factor;
+        consistant.min_self_spacing = self.min_self_spacing;
+        consistant.fallback = self.fallback.clone();
+        for (element, weight) in self.weighted_members() {
+            let _ = consistant.try_add_interned(element, weight);
+        }
+        // The elements keep their slots, for the handles of this hash ring
+        // to resolve to the same elements in the copy.
+        let slots: HashMap<u32, u32> = consistant.members
+            .keys()
+            .filter_map(|element| consistant.slots.index(element).zip(self.slots.index(element)))
+            .collect();
+        lock_mut(&mut consistant.sorted_keys).map_owners(|owner| slots[&owner]);
+        consistant.slots = self.slots.clone();
+        // Set after the elements are added, for the copy to hold them all.
+        consistant.limits = self.limits;
+        consistant.incarnations = self.incarnations.clone();
+        consistant.tags = self.tags.clone();
+        consistant.fractions = self.fractions.clone();
+        consistant.latencies = self.latencies.clone();
+        consistant.breakers = self.breakers.clone();
+        consistant.registered = self.registered.clone();
+        consistant.history = self.history.clone();
+        consistant.set_version(self.version());
+        consistant
+    }
+
+    /// Build a new hash ring which only contains the elements passing the
+    /// given predicate. The kept elements keep their original positions, so
+    /// keys owned by them are not moved.
+    pub fn subset<F: Fn(&str) -> bool>(&self, pred: F) -> Consistant {
+        let _guard = read_lock(&self.lock);
+        let mut subset = Consistant::with_config(self.replicas_num, self.placement, self.seed);
+        subset.secret = self.secret;
+        subset.max_disruption = self.max_disruption;
+        subset.limits = self.limits;
+        subset.normalizer = self.normalizer.clone();
+        subset.prefix_rules = self.prefix_rules.clone();
+        subset.range_overrides = self.range_overrides.clone();
+        subset.colocations = self.colocations.clone();
+        subset.replication_factor = self.replication_factor;
+        subset.auto_replicas = self.auto_replicas;
+        subset.min_self_spacing = self.min_self_spacing;
+        subset.fallback = self.fallback.clone();
+
+        let mut owners = HashMap::new();
+        for (element, weight) in &self.members {
+            if pred(element) {
+                subset.members.insert(element.clone(), *weight);
+                if let Some(tokens) = self.tokens.get(element) {
+                    subset.tokens.insert(element.clone(), tokens.clone());
+                }
+                if let Some(incarnation) = self.incarnations.get(element) {
+                    subset.incarnations.insert(element.clone(), *incarnation);
+                }
+                if let Some(tags) = self.tags.get(element) {
+                    subset.tags.insert(element.clone(), tags.clone());
+                }
+                if let Some(fraction) = self.fractions.get(element) {
+                    subset.fractions.insert(element.clone(), *fraction);
+                }
+                let owner = subset.slots.insert(element.clone());
+                owners.insert(self.slots.index(element).unwrap(), owner);
+            }
+        }
+        let mut sorted_keys = self.keys().clone();
+        sorted_keys.retain(|key| owners.contains_key(&key.1));
+        sorted_keys.map_owners(|owner| owners[&owner]);
+        subset.sorted_keys = RwLock::new(sorted_keys);
+        subset.registered = self.registered.kept(&subset.members);
+
+        subset
+    }
+
+    /// Check whether the given element is in the hash ring.
+    pub fn contains(&self, name: &str) -> bool {
+        let _guard = read_lock(&self.lock);
+        self.members.contains_key(name)
+    }
+
+    /// Get the arcs of the hash ring as `(start, end, element)`, where the
+    /// element owns the checksums in `[start, end)`. The arcs are sorted and
+    /// cover the whole u32 keyspace, the wrap-around arc being split in two.
+    pub(crate) fn arcs(&self) -> Vec<(u64, u64, Arc<str>)> {
+        let _guard = read_lock(&self.lock);
+        let keys = self.keys();
+        let mut arcs = Vec::with_capacity(keys.len() + 1);
+        if keys.is_empty() {
+            return arcs;
+        }
+
+        let mut start = 0;
+        for &(key, owner) in keys.iter() {
+            let end = u64::from(key);
+            if end > start {
+                arcs.push((start, end, self.slots.name(owner).clone()));
+            }
+            start = end;
+        }
+        arcs.push((start, 1 << 32, self.slots.name(keys[0].1).clone()));
+
+        arcs
+    }
+
+    /// Get the virtual nodes of the hash ring as `(hash, element)`, sorted by
+    /// hash.
+    pub(crate) fn vnodes(&self) -> Vec<(u32, Arc<str>)> {
+        let _guard = read_lock(&self.lock);
+        self.keys().iter().map(|&(key, owner)| (key, self.slots.name(owner).clone())).collect()
+    }
+
+    /// Get the count of virtual nodes of the hash ring.
+    pub(crate) fn vnode_count(&self) -> usize {
+        let _guard = read_lock(&self.lock);
+        self.keys().len()
+    }
+
+    /// Get the elements of the hash ring, sorted by name.
+    pub(crate) fn sorted_members(&self) -> Vec<Arc<str>> {
+        let _guard = read_lock(&self.lock);
+        let mut members: Vec<Arc<str>> = self.members.keys().cloned().collect();
+        members.sort();
+        members
+    }
+
+    /// Get the elements of the hash ring with their weights, sorted by name.
+    pub(crate) fn weighted_members(&self) -> Vec<(Arc<str>, usize)> {
+        let _guard = read_lock(&self.lock);
+        let mut members: Vec<(Arc<str>, usize)> = self.members
+            .iter()
+            .map(|(element, weight)| (element.clone(), *weight))
+            .collect();
+        members.sort();
+        members
+    }
+
+    /// Get the virtual node hashes of the given element if they are not the
+    /// ones derived from its name, for serializing them.
+    pub(crate) fn explicit_tokens(&self, name: &str) -> Option<Vec<u32>> {
+        let _guard = read_lock(&self.lock);
+        self.tokens.get(name).cloned()
+    }
+
+    /// Normalize the given lookup key if a normalizer is set.
+    #[inline]
+    fn normalize<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        match self.normalizer {
+            Some(ref normalizer) => normalizer.normalize(key),
+            None => Cow::Borrowed(key),
+        }
+    }
+
+    /// Get the checksum of the given lookup key, normalized first, and the
+    /// element its longest prefix rule, or else the range assignment of the
+    /// checksum, routes it to, if any, or else the one past a fractional
+    /// element declining it, see `set_fractional_weight`. The keys are the
+    /// ones of `keys`, whose guard the caller holds rather than taking the
+    /// lock again.
+    #[inline]
+    pub(crate) fn route(&self, keys: &SortedKeys, key: &str) -> (u32, Option<u32>) {
+        let (sum, pinned) = self.pin(key);
+        (sum, pinned.or_else(|| self.reroute(keys, sum)))
+    }
+
+    /// Same as `route`, fractional elements aside.
+    #[inline]
+    pub(crate) fn pin(&self, key: &str) -> (u32, Option<u32>) {
+        let key = self.normalize(key);
+        let sum = self.key_hasher().checksum(key.as_bytes());
+        let routed = match self.prefix_rule(&key) {
+            Some(rule) => Some(rule.1),
+            None => self.range_assignment(sum).map(|range| range.2),
+        };
+        (sum, routed)
+    }
+
+    /// Get the element the key of the given checksum goes to if the owner
+    /// of its virtual node is a fractional element declining it, the next
+    /// element clockwise admitting it.
+    #[inline]
+    fn reroute(&self, keys: &SortedKeys, sum: u32) -> Option<u32> {
+        if self.fractions.is_empty() || keys.is_empty() {
+            return None;
+        }
+        next_admitting(keys.successor(sum), keys.len(), |index| {
+            let owner = keys[index].1;
+            let name = self.slots.name(owner);
+            let admitted = self.fractions
+                .get(name)
+                .is_none_or(|millionths| admits(name, *millionths, sum));
+            (owner, admitted)
+        })
+    }
+
+    pub(crate) fn fractions(&self) -> &HashMap<Arc<str>, u32> {
+        &self.fractions
+    }
+
+    /// Get the fraction of the given element in millionths, `None` unless it
+    /// is of a fractional weight.
+    pub(crate) fn fraction(&self, name: &str) -> Option<u32> {
+        let _guard = read_lock(&self.lock);
+        self.fractions.get(name).cloned()
+    }
+
+    /// Set the fraction of the given element in millionths, which must be in
+    /// the hash ring, for loading it.
+    pub(crate) fn insert_fraction(&mut self, name: &str, millionths: u32) {
+        let _guard = write_lock(&self.lock);
+        let element = self.slots.name(self.slots.index(name).unwrap()).clone();
+        self.fractions.insert(element, millionths);
+    }
+
+    /// Get the assigned range of the given checksum and its element, the
+    /// assignments of removed elements skipped.
+    #[inline]
+    fn range_assignment(&self, sum: u32) -> Option<(u32, u32, u32)> {
+        if self.range_overrides.is_empty() {
+            return None;
+        }
+        self.range_overrides
+            .find(sum)
+            .and_then(|(start, end, member)| {
+                self.slots.index(member).map(|owner| (start, end, owner))
+            })
+    }
+
+    /// Get the longest prefix of the given normalized key having a rule,
+    /// and the element of the rule, the rules of removed elements skipped.
+    /// The rules are few, so they are all matched.
+    fn prefix_rule(&self, key: &str) -> Option<(&str, u32)> {
+        if self.prefix_rules.is_empty() {
+            return None;
+        }
+        self.prefix_rules
+            .iter()
+            .filter(|&(prefix, _)| key.starts_with(prefix.as_str()))
+            .filter_map(|(prefix, member)| {
+                self.slots.index(member).map(|owner| (prefix.as_str(), owner))
+            })
+            .max_by_key(|rule| rule.0.len())
+    }
+
+    /// Get the element the given lookup key resolves to, as `get` does.
+    pub(crate) fn key_owner(&self, key: &str) -> Option<Arc<str>> {
+        let _guard = read_lock(&self.lock);
+        self.key_owner_ref(key).cloned()
+    }
+
+    /// Same as `key_owner`, but the read lock must be held.
+    #[inline]
+    pub(crate) fn key_owner_ref(&self, key: &str) -> Option<&Arc<str>> {
+        let keys = self.keys();
+        if keys.is_empty() {
+            return None;
+        }
+        let (sum, routed) = self.route(&keys, key);
+        Some(self.slots.name(routed.unwrap_or_else(|| keys[keys.successor(sum)].1)))
+    }
+
+    pub(crate) fn latencies(&self) -> &Latencies {
+        &self.latencies
+    }
+
+    pub(crate) fn breakers(&self) -> &Breakers {
+        &self.breakers
+    }
+
+    pub(crate) fn breakers_mut(&mut self) -> &mut Breakers {
+        &mut self.breakers
+    }
+
+    pub(crate) fn registered(&self) -> &Registered {
+        &self.registered
+    }
+
+    pub(crate) fn registered_mut(&mut self) -> &mut Registered {
+        &mut self.registered
+    }
+
+    pub(crate) fn colocations(&self) -> &BTreeMap<String, Colocation> {
+        &self.colocations
+    }
+
+    pub(crate) fn colocations_mut(&mut self) -> &mut BTreeMap<String, Colocation> {
+        &mut self.colocations
+    }
+
+    pub(crate) fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub(crate) fn history_mut(&mut self) -> &mut History {
+        &mut self.history
+    }
+
+    /// Take the snapshot of the current version for the history, if kept.
+    /// The lock must be held.
+    fn history_snapshot(&self) -> Option<VersionSnapshot> {
+        if !self.history.is_enabled() {
+            return None;
+        }
+        Some(VersionSnapshot::new(self, self.version, &self.keys()))
+    }
+
+    /// Set the version of the hash ring, for loading it.
+    pub(crate) fn set_version(&mut self, version: u64) {
+        self.version = version;
+    }
+
+    /// Get the handle slots of the elements.
+    pub(crate) fn slots(&self) -> &Slots {
+        &self.slots
+    }
+
+    /// Get the element owning the given checksum.
+    pub(crate) fn owner_of(&self, sum: u32) -> Option<Arc<str>> {
+        let _guard = read_lock(&self.lock);
+        self.owner_ref(sum).cloned()
+    }
+
+    /// Acquire the read lock, for lookups through `owner_ref` or `walk_members`.
+    pub(crate) fn read<'a>(&'a self) -> RwLockReadGuard<'a, ()> {
+        read_lock(&self.lock)
+    }
+
+    /// Same as `owner_of`, but the read lock must be held.
+    #[inline]
+    pub(crate) fn owner_ref(&self, sum: u32) -> Option<&Arc<str>> {
+        let keys = self.keys();
+        if keys.is_empty() {
+            return None;
+        }
+        Some(self.slots.name(keys[keys.successor(sum)].1))
+    }
+
+    /// Rebuild the hash ring if the policy of `set_auto_replicas` changes
+    /// the count of virtual nodes for the current membership.
+    fn auto_rescale(&mut self) {
+        let to = match self.auto_replicas {
+            Some(auto_replicas) => {
+                let total_weight = self.weighted_members().iter().map(|member| member.1).sum();
+                match auto_replicas.rescale(self.replicas_num, total_weight) {
+                    Some(to) => to,
+                    None => return,
+                }
+            }
+            None => return,
+        };
+
+        let mut rebuilt = self.clone_with_replicas(to);
+        let disruption = moved_share(self, &rebuilt);
+        rebuilt.auto_replicas = self.auto_replicas;
+        rebuilt.disruption = self.disruption;
+        rebuilt.disruption.last = Some((self.disruption.last.unwrap_or(0.0) + disruption).min(1.0));
+        rebuilt.disruption.total += disruption;
+        rebuilt.disruption.rescale = Some(Rescale {
+            from: self.replicas_num,
+            to,
+            disruption,
+        });
+        *self = rebuilt;
+    }
+
+    /// Get the virtual node hashes derived from the name of the given
+    /// element, of the given weight.
+    pub(crate) fn derived_points(&self, element: &str, weight: usize) -> Vec<u32> {
+        self.placement.spaced_points(self.seed,
+                                     element,
+                                     self.replicas_num * weight,
+                                     self.min_self_spacing)
+    }
+
+    /// Check whether the virtual nodes of every element are recorded, as the
+    /// draws of `PlacementStrategy::SeededRandom` are.
+    fn records_tokens(&self) -> bool {
+        self.placement == PlacementStrategy::SeededRandom
+    }
+
+    /// Get the virtual node hashes of the given element, of the given weight.
+    fn points(&self, element: &str, weight: usize) -> Vec<u32> {
+        match self.tokens.get(element) {
+            Some(tokens) => tokens.clone(),
+            None => self.derived_points(element, weight),
+        }
+    }
+
+    /// Get the share of the keyspace owned by the element of the given slot,
+    /// whose virtual nodes are among the given points. Only the arcs ending at
+    /// them are measured, not the whole hash ring.
+    fn share_of(&self, owner: u32, points: &[u32]) -> f64 {
+        let keys = read_lock(&self.sorted_keys);
+        let mut owned: Vec<u32> = points.iter()
+            .filter(|sum| keys.get(**sum) == Some(owner))
+            .cloned()
+            .collect();
+        owned.sort();
+        owned.dedup();
+
+        let mut length = 0;
+        for sum in owned {
+            let start = keys.predecessor(sum);
+            length += match u64::from(sum.wrapping_sub(start)) {
+                0 => 1 << 32,
+                arc => arc,
+            };
+        }
+
+        length as f64 / (1u64 << 32) as f64
+    }
+
+    /// Get the share of the keyspace owned by the elements of the given
+    /// slots, from the arcs ending at every virtual node.
+    fn share_of_owners(&self, owners: &HashSet<u32>) -> f64 {
+        let keys = self.keys();
+        if keys.is_empty() {
+            return 0.0;
+        }
+        let mut start = keys[keys.len() - 1].0;
+        let mut length = 0;
+        for &(sum, owner) in keys.iter() {
+            if owners.contains(&owner) {
+                length += match u64::from(sum.wrapping_sub(start)) {
+                    0 => 1 << 32,
+                    arc => arc,
+                };
+            }
+            start = sum;
+        }
+
+        length as f64 / (1u64 << 32) as f64
+    }
+
+    /// Get the virtual nodes of the given elements, of the given slots,
+    /// sorted by hash, the element coming last keeping a hash they share as
+    /// adding them in turn does.
+    fn bulk_vnodes(&self, added: &[(Arc<str>, usize)], owners: &[u32]) -> Vec<(u32, u32)> {
+        #[cfg(feature = "rayon")]
+        {
+            let count = added.iter().map(|member| member.1).sum::<usize>() * self.replicas_num;
+            if count >= PARALLEL_VNODES {
+                return self.par_bulk_vnodes(added, owners);
+            }
+        }
+        let mut vnodes: Vec<(u32, u32)> = added.iter()
+            .enumerate()
+            .flat_map(|(index, &(ref element, weight))| {
+                self.derived_points(element, weight).into_iter().map(move |sum| (sum, index as u32))
+            })
+            .collect();
+        vnodes.sort_unstable();
+        merge_vnodes(vnodes, owners)
+    }
+
+    /// Get the virtual nodes, sorting them first if a mutation left them
+    /// unsorted. Concurrent lookups sort them only once, under the write lock
+    /// of the virtual nodes.
+    #[inline]
+    fn keys<'a>(&'a self) -> RwLockReadGuard<'a, SortedKeys> {
+        {
+            let keys = read_lock(&self.sorted_keys);
+            if keys.is_sorted() {
+                return keys;
+            }
+        }
+        write_lock(&self.sorted_keys).sort();
+        read_lock(&self.sorted_keys)
+    }
+}
+
+/// RouteSource tells where the name of a lookup comes from, see
+/// `Consistant::get_with_source`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteSource {
+    /// The virtual node following the key on the hash ring.
+    Ring,
+    /// A prefix rule or range assignment, see `Consistant::add_prefix_rule`
+    /// and `Consistant::assign_range`.
+    Pinned,
+    /// The fallback of the empty hash ring, see `Consistant::set_fallback`.
+    Fallback,
+}
+
+/// Disruption accumulates the shares of the keyspace changing owner with
+/// the mutations of a hash ring.
+#[derive(Debug, Clone, Copy, Default)]
+struct Disruption {
+    last: Option<f64>,
+    total: f64,
+    /// The rebuild along with the last mutation, see `last_rescale`.
+    rescale: Option<Rescale>,
+}
+
+impl Disruption {
+    fn record(&mut self, disruption: f64) {
+        self.last = Some(disruption);
+        self.total += disruption;
+        self.rescale = None;
+    }
+}
+
+/// Walk the `len` virtual nodes clockwise from the given index, calling
+/// `visit` with the owner of every one not met before, until it returns true
+/// or `count` owners are met. The owners met so far are kept in a set, so
+/// that every virtual node is looked at once. A lap at most is walked, see
+/// `distinct_owners`.
+fn walk_owners<O, F>(len: usize, owner_at: O, start: usize, count: usize, mut visit: F)
+    where O: Fn(usize) -> u32,
+          F: FnMut(u32) -> bool
+{
+    let (mut walked, mut distinct) = (OwnerSet::default(), 0);
+    let mut index = start;
+    for _ in 0..len {
+        if distinct == count {
+            return;
+        }
+        let owner = owner_at(index);
+        if walked.insert(owner) {
+            if visit(owner) {
+                return;
+            }
+            distinct += 1;
+        }
+        index = if index + 1 == len { 0 } else { index + 1 };
+    }
+}
+
+/// OwnerSet is a set of owners, the first 256 in a bitset which allocates
+/// nothing, the others in a hash set.
+#[derive(Default)]
+struct OwnerSet {
+    bits: [u64; 4],
+    others: HashSet<u32>,
+}
+
+impl OwnerSet {
+    /// Add the given owner, returning whether it was not in yet.
+    #[inline]
+    fn insert(&mut self, owner: u32) -> bool {
+        if owner >= 256 {
+            return self.others.insert(owner);
+        }
+        let (word, bit) = ((owner / 64) as usize, 1 << (owner % 64));
+        let added = self.bits[word] & bit == 0;
+        self.bits[word] |= bit;
+        added
+    }
+}
+
+/// Get the distinct owners of the `len` virtual nodes clockwise from the
+/// given index, the `first` one before them if any, until `count` of them
+/// are met. A lap at most is walked, for an element whose virtual nodes were
+/// all taken over by colliding ones of others owns none to be met, so that
+/// fewer owners than `count` may be found.
+pub(crate) fn distinct_owners<O>(len: usize,
+                                 owner_at: O,
+                                 start: usize,
+                                 first: Option<u32>,
+                                 count: usize)
+                                 -> Vec<u32>
+    where O: Fn(usize) -> u32
+{
+    let mut owners = Vec::with_capacity(count);
+    owners.extend(first);
+    let mut index = start;
+    for _ in 0..len {
+        if owners.len() >= count {
+            break;
+        }
+        let owner = owner_at(index);
+        if !owners.contains(&owner) {
+            owners.push(owner);
+        }
+        index = if index + 1 == len { 0 } else { index + 1 };
+    }
+    owners
+}
+
+/// Turn the given virtual nodes, sorted by hash and then index of their
+/// element, into the ones of the slots of these indexes, keeping the last
+/// element of every hash.
+pub(crate) fn merge_vnodes(mut vnodes: Vec<(u32, u32)>, owners: &[u32]) -> Vec<(u32, u32)> {
+    vnodes.dedup_by(|next, last| {
+        if next.0 != last.0 {
+            return false;
+        }
+        *last = *next;
+        true
+    });
+    for vnode in &mut vnodes {
+        vnode.1 = owners[vnode.1 as usize];
+    }
+    vnodes
+}
+
+/// Bump the incarnation of the given element being added, if it was in the
+/// hash ring before.
+fn reincarnate(incarnations: &mut HashMap<Arc<str>, u64>, s: &Arc<str>) {
+    match incarnations.get_mut(s) {
+        Some(incarnation) => *incarnation += 1,
+        None => {
+            incarnations.insert(s.clone(), 0);
+        }
+    }
+}
+
+/// Acquire the read lock. A poisoned lock is recovered instead of panicking,
+/// since every mutation leaves the hash ring consistent before it can panic.
+#[inline]
+fn read_lock<'a, T>(lock: &'a RwLock<T>) -> RwLockReadGuard<'a, T> {
+    lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Acquire the write lock, recovering it from poisoning as `read_lock` does.
+#[inline]
+fn write_lock<'a, T>(lock: &'a RwLock<T>) -> RwLockWriteGuard<'a, T> {
+    lock.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Get the value of a lock through a mutable borrow, recovering it from
+/// poisoning as `read_lock` does.
+#[inline]
+fn lock_mut<T>(lock: &mut RwLock<T>) -> &mut T {
+    lock.get_mut().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::ops::Bound;
+    use std::panic;
+    use std::thread;
+    use crc::crc32::checksum_ieee;
+    use placement::{checksum, checksum_parts};
+    use xorshift::Xorshift;
+
+    #[test]
+    fn test_default() {
+        let consistant = Consistant::default();
+
+        assert_eq!(consistant.replicas_num, 20);
+        assert_eq!(consistant.keys().len(), 0);
+    }
+
+    #[test]
+    fn test_new() {
+        let consistant = Consistant::new(30);
+
+        assert_eq!(consistant.replicas_num, 30);
+        assert_eq!(consistant.keys().len(), 0);
+    }
+
+    #[test]
+    fn test_count() {
+        let mut consistant = Consistant::default();
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+        consistant.add("cacheC");
+        assert_eq!(consistant.count(), 3);
+    }
+
+    #[test]
+    fn test_members() {
+        let mut consistant = Consistant::default();
+        consistant.add("cacheB");
+        consistant.add("cacheA");
+        consistant.add("cacheA");
+        assert_eq!(consistant.members(), vec!["cacheA", "cacheB"]);
+    }
+
+    #[test]
+    fn test_add() {
+        let mut consistant = Consistant::default();
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+        consistant.add("cacheC");
+
+        assert_eq!(consistant.keys().len(), 3 * consistant.replicas_num);
+    }
+
+    #[test]
+    fn test_contains() {
+        let mut consistant = Consistant::default();
+        consistant.add("cacheA");
+
+        assert!(consistant.contains("cacheA"));
+        assert!(!consistant.contains("cacheB"));
+        assert!(!consistant.contains("CachEa"));
+
+        consistant.add(String::from("cacheA"));
+        assert_eq!(consistant.count(), 1);
+        assert_eq!(consistant.keys().len(), consistant.replicas_num);
+    }
+
+    #[test]
+    fn test_get() {
+        let mut consistant = Consistant::default();
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+        consistant.add("cacheC");
+
+        assert_eq!(consistant.get("david").unwrap(),
+                   consistant.get("david").unwrap());
+        assert_eq!(consistant.get("kally").unwrap(),
+                   consistant.get("kally").unwrap());
+        assert_eq!(consistant.get("jason").unwrap(),
+                   consistant.get("jason").unwrap());
+    }
+
+    #[test]
+    fn test_get_n() {
+        let mut consistant = Consistant::default();
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+        consistant.add("cacheC");
+
+        let res = consistant.get_n("david", 3).unwrap();
+        assert_eq!(res.len(), 3);
+
+        consistant.remove("cacheA");
+
+        let res2 = consistant.get_n("david", 3).unwrap();
+        assert_eq!(res2.len(), 2);
+
+        for i in 0..100 {
+            let key = format!("key{}", i);
+            assert_eq!(consistant.get_n(key.as_str(), 1),
+                       consistant.get(key.as_str()).map(|element| vec![element]));
+        }
+    }
+
+    #[test]
+    fn test_get_by_hash() {
+        let mut consistant = Consistant::with_seed(20, 5);
+        assert_eq!(consistant.get_by_hash(0), None);
+
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+        for i in 0..100 {
+            let key = format!("key{}", i);
+            assert_eq!(consistant.get_by_hash(checksum(5, key.as_bytes())),
+                       consistant.get(key.as_str()));
+        }
+    }
+
+    #[test]
+    fn test_get_n_into() {
+        let empty = Consistant::default();
+        let mut out = vec!["stale"];
+        assert_eq!(empty.get_n_into("david", 2, &mut out), 0);
+        assert!(out.is_empty());
+
+        let mut consistant = Consistant::default();
+        consistant.add("cacheA");
+        consistant.add_weighted("cacheB", 2);
+        consistant.add("cacheC");
+        consistant.add("cacheD");
+        let mut out = Vec::new();
+        assert_eq!(consistant.get_n_into("david", 0, &mut out), 0);
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            for n in 1..6 {
+                let expected = consistant.get_n(key.as_str(), n).unwrap();
+                assert_eq!(consistant.get_n_into(&key, n, &mut out), expected.len());
+                assert_eq!(out, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_is_owner() {
+        let mut rng = Xorshift::new(42);
+        for round in 0..50 {
+            let mut consistant = Consistant::new(1 + round % 7);
+            let members = 1 + rng.next_u64() as usize % 8;
+            for i in 0..members {
+                consistant.add_weighted(format!("node-{}", i), 1 + rng.next_u64() as usize % 3);
+            }
+
+            for i in 0..200 {
+                let key = format!("key{}", rng.next_u64());
+                let n = i % (members + 2);
+                let owners = consistant.get_n(key.as_str(), n).unwrap_or_default();
+                for j in 0..members + 1 {
+                    let member = format!("node-{}", j);
+                    assert_eq!(consistant.is_owner(&member, &key, n),
+                               owners.contains(&member),
+                               "{} {} {}",
+                               member,
+                               key,
+                               n);
+                }
+            }
+        }
+        assert!(!Consistant::default().is_owner("node-0", "david", 3));
+    }
+
+    #[test]
+    fn test_get_nth_owner() {
+        let mut rng = Xorshift::new(7);
+
+        let mut consistant = Consistant::new(10);
+        assert_eq!(consistant.get_nth_owner("david", 0), None);
+        for i in 0..8 {
+            consistant.add_weighted(format!("cache{}", i), 1 + i % 3);
+        }
+        for _ in 0..2000 {
+            let key = format!("key{}", rng.next_u64());
+            let i = (rng.next_u64() % 10) as usize;
+            let expected = consistant.get_n(key.as_str(), i + 1)
+                .filter(|owners| owners.len() == i + 1)
+                .and_then(|owners| owners.last().cloned());
+            assert_eq!(consistant.get_nth_owner(key.as_str(), i), expected, "{} {}", key, i);
+        }
+        assert_eq!(consistant.get_nth_owner("david", 0), consistant.get("david"));
+        assert_eq!(consistant.get_nth_owner("david", 8), None);
+
+        // The last owner of a large hash ring, met past most virtual nodes.
+        let mut large = Consistant::new(10);
+        for i in 0..300 {
+            large.add(format!("cache{}", i));
+        }
+        let owners = large.get_n("david", 300).unwrap();
+        assert_eq!(owners.len(), 300);
+        assert_eq!(large.get_nth_owner("david", 299).as_ref(), owners.last());
+    }
+
+    #[test]
+    fn test_tags() {
+        let mut consistant = Consistant::new(10);
+        assert_eq!(consistant.get_with_tag("david", "ssd"), None);
+        consistant.add_with_tags("cacheA", &["ssd", "eu", "ssd"]);
+        consistant.add_with_tags("cacheB", &["hdd"]);
+        consistant.add_with_tags("cacheC", &["ssd"]);
+        consistant.add("cacheD");
+        assert_eq!(consistant.tags_of("cacheA"),
+                   Some(vec![String::from("eu"), String::from("ssd")]));
+        assert_eq!(consistant.tags_of("cacheD"), Some(Vec::new()));
+        assert_eq!(consistant.tags_of("cacheE"), None);
+        assert!(consistant.set_tags("cacheE", &["ssd"]).is_err());
+
+        // A key whose primary lacks the tag goes to the nearest tagged one.
+        let ssd = [String::from("cacheA"), String::from("cacheC")];
+        let mut skipped = 0;
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            let owners = consistant.get_n(key.as_str(), 4).unwrap();
+            let expected: Vec<String> = owners.into_iter().filter(|o| ssd.contains(o)).collect();
+            let tagged = consistant.get_with_tag(key.as_str(), "ssd").unwrap();
+            if Some(&tagged) != consistant.get(key.as_str()).as_ref() {
+                skipped += 1;
+            }
+            assert_eq!(tagged, expected[0]);
+            assert_eq!(consistant.get_n_with_tag(key.as_str(), 3, "ssd"), Some(expected));
+        }
+        assert!(skipped > 0);
+        assert_eq!(consistant.get_n_with_tag("david", 0, "ssd"), None);
+        assert_eq!(consistant.get_with_tag("david", "nvme"), None);
+
+        // Tags survive clones and never move a virtual node.
+        let (vnodes, version) = (consistant.vnodes(), consistant.version());
+        consistant.set_tags("cacheD", &["ssd"]).unwrap();
+        consistant.set_tags("cacheA", &[]).unwrap();
+        assert_eq!((consistant.vnodes(), consistant.version()), (vnodes, version));
+        assert_eq!(consistant.clone().tags_of("cacheD"), Some(vec![String::from("ssd")]));
+
+        // Removing the last tagged element leaves none to look up.
+        consistant.remove("cacheC");
+        consistant.remove("cacheD");
+        assert_eq!(consistant.get_with_tag("david", "ssd"), None);
+        assert_eq!(consistant.get_n_with_tag("david", 2, "ssd"), None);
+        consistant.add("cacheC");
+        assert_eq!(consistant.tags_of("cacheC"), Some(Vec::new()));
+    }
+
+    #[test]
+    fn test_prefix_rules() {
+        let mut consistant = Consistant::new(10);
+        for i in 0..6 {
+            consistant.add(format!("cache{}", i));
+        }
+        assert!(consistant.add_prefix_rule("tenant-", "cache9").is_err());
+        consistant.add_prefix_rule("tenant-", "cache1").unwrap();
+        consistant.add_prefix_rule("tenant-42/", "cache4").unwrap();
+        assert_eq!(consistant.prefix_rules(),
+                   [(String::from("tenant-"), String::from("cache1")),
+                    (String::from("tenant-42/"), String::from("cache4"))]);
+
+        // The longest prefix wins, the others following in ring order.
+        let compiled = consistant.compile(1 << 8);
+        let mut out = Vec::new();
+        for i in 0..200 {
+            for &(ref key, owner) in &[(format!("tenant-42/{}", i), "cache4"),
+                                       (format!("tenant-7/{}", i), "cache1")] {
+                let key = key.as_str();
+                let owners = consistant.get_n(key, 3).unwrap();
+                assert_eq!(owners[0], owner);
+                assert_eq!(consistant.get(key).unwrap(), owner);
+                assert_eq!(compiled.get(key), Some(owner));
+                assert_eq!(compiled.get_n(key, 3).unwrap(), owners);
+                consistant.get_n_into(key, 3, &mut out);
+                assert_eq!(out, owners);
+                assert_eq!(consistant.get_primary_and_backup(key).unwrap(),
+                           (owners[0].clone(), Some(owners[1].clone())));
+                for (j, member) in owners.iter().enumerate() {
+                    assert_eq!(consistant.get_nth_owner(key, j).as_ref(), Some(member));
+                    assert!(consistant.is_owner(member, key, j + 1));
+                    assert!(!consistant.is_owner(member, key, j));
+                }
+            }
+        }
+        let explanation = consistant.explain("tenant-42/david").unwrap();
+        assert_eq!(explanation.owner, "cache4");
+        assert_eq!(explanation.prefix_rule, Some(String::from("tenant-42/")));
+        assert_eq!(consistant.explain("david").unwrap().prefix_rule, None);
+
+        // The rule of a removed element is skipped until it is added again.
+        let kept = consistant.subset(|member| member != "cache0");
+        assert_eq!(kept.get("tenant-42/david"), Some(String::from("cache4")));
+        consistant.remove("cache4");
+        assert_eq!(consistant.get("tenant-42/david"), Some(String::from("cache1")));
+        consistant.remove("cache1");
+        let mut hashed = consistant.clone();
+        assert_eq!(hashed.remove_prefix_rule("tenant-"), Some(String::from("cache1")));
+        assert_eq!(hashed.remove_prefix_rule("tenant-"), None);
+        hashed.remove_prefix_rule("tenant-42/");
+        for i in 0..100 {
+            let key = format!("tenant-42/{}", i);
+            assert_eq!(consistant.get_n(key.as_str(), 3), hashed.get_n(key.as_str(), 3));
+        }
+        assert_eq!(consistant.prefix_rules().len(), 2);
+        consistant.add("cache4");
+        assert_eq!(consistant.get("tenant-42/david"), Some(String::from("cache4")));
+    }
+
+    #[test]
+    fn test_assign_range() {
+        let mut consistant = Consistant::new(10);
+        for i in 0..6 {
+            consistant.add(format!("cache{}", i));
+        }
+        let original = consistant.clone();
+        let (start, end) = (u32::MAX - (1 << 29), 1 << 29);
+        assert_eq!(consistant.assign_range(start, end, "cache9"),
+                   Err(Error::UnknownMember(String::from("cache9"))));
+        consistant.assign_range(start, end, "cache2").unwrap();
+        consistant.assign_range(1 << 31, 3 << 30, "cache4").unwrap();
+        assert_eq!(consistant.assign_range(1 << 30, (1 << 31) + 1, "cache1"),
+                   Err(Error::OverlappingRange {
+                       start: 1 << 31,
+                       end: 3 << 30,
+                   }));
+        consistant.add_prefix_rule("tenant-42/", "cache5").unwrap();
+        assert_eq!(consistant.range_assignments(),
+                   [(1 << 31, 3 << 30, String::from("cache4")),
+                    (start, end, String::from("cache2"))]);
+        assert_eq!(consistant.version(), original.version());
+
+        // The keys of the ranges move, the wrapping one included, the others
+        // are left alone.
+        let inside = |sum: u32| sum > start || sum <= end || (sum > 1 << 31 && sum <= 3 << 30);
+        let (mut moved, mut kept) = (0, 0);
+        for i in 0..2000 {
+            let key = format!("key{}", i);
+            let sum = checksum_ieee(key.as_bytes());
+            let owners = consistant.get_n(&key, 3).unwrap();
+            if inside(sum) {
+                let owner = if sum > 1 << 31 && sum <= 3 << 30 { "cache4" } else { "cache2" };
+                assert_eq!(owners[0], owner);
+                assert_eq!(consistant.get_by_hash(sum), Some(String::from(owner)));
+                let explanation = consistant.explain(&key).unwrap();
+                assert_eq!(explanation.owner, owner);
+                assert!(explanation.range_assignment.is_some());
+                moved += 1;
+            } else {
+                assert_eq!(owners, original.get_n(&key, 3).unwrap());
+                assert_eq!(consistant.explain(&key).unwrap().range_assignment, None);
+                kept += 1;
+            }
+            assert!(owners[1..].iter().all(|member| *member != owners[0]));
+        }
+        assert!(moved > 0 && kept > 0);
+        assert_eq!(consistant.get_by_hash(start), original.get_by_hash(start));
+        assert_eq!(consistant.get_by_hash(end), Some(String::from("cache2")));
+
+        // Prefix rules come first.
+        let explanation = consistant.explain("tenant-42/david").unwrap();
+        assert_eq!((explanation.owner.as_str(), explanation.range_assignment), ("cache5", None));
+
+        // The assignment of a removed element is skipped until it is added
+        // again, and clearing both restores every mapping.
+        let mut removed = consistant.clone();
+        removed.remove("cache4");
+        let mut without = original.clone();
+        without.remove("cache4");
+        assert_eq!(removed.get_by_hash(3 << 30), without.get_by_hash(3 << 30));
+        removed.add("cache4");
+        assert_eq!(removed.get_by_hash(3 << 30), Some(String::from("cache4")));
+
+        assert_eq!(consistant.clear_range_assignment(start, end - 1), None);
+        assert_eq!(consistant.clear_range_assignment(start, end), Some(String::from("cache2")));
+        assert_eq!(consistant.clear_range_assignment(1 << 31, 3 << 30),
+                   Some(String::from("cache4")));
+        consistant.remove_prefix_rule("tenant-42/");
+        assert!(consistant.range_assignments().is_empty());
+        for i in 0..2000 {
+            let key = format!("key{}", i);
+            assert_eq!(consistant.get_n(&key, 3), original.get_n(&key, 3));
+        }
+    }
+
+    #[test]
+    fn test_fallback() {
+        let mut consistant = Consistant::new(10);
+        assert_eq!(consistant.get_with_source("david"), None);
+        consistant.set_fallback(Some(String::from("local-cache")));
+        assert_eq!(consistant.fallback(), Some("local-cache"));
+        assert_eq!(consistant.get("david"), Some(String::from("local-cache")));
+        assert_eq!(consistant.get_n("david", 3), Some(vec![String::from("local-cache")]));
+        assert_eq!(consistant.get_n("david", 0), None);
+        assert_eq!(consistant.owners("david"), Some(vec![String::from("local-cache")]));
+        assert_eq!(consistant.get_by_hash(7), Some(String::from("local-cache")));
+        assert_eq!(consistant.get_primary_and_backup("david"),
+                   Some((String::from("local-cache"), None)));
+        assert_eq!(consistant.get_nth_owner("david", 0), Some(String::from("local-cache")));
+        assert_eq!(consistant.get_nth_owner("david", 1), None);
+        assert_eq!(consistant.select("david", 1, &SelectionPolicy::new()), None);
+        assert_eq!(consistant.get_n_strict("david", 1),
+                   Err(ReplicationError::NotEnoughMembers {
+                       wanted: 1,
+                       available: 0,
+                   }));
+        assert!(consistant.owners_strict("david").is_err());
+        assert_eq!(consistant.get_with_source("david"),
+                   Some((String::from("local-cache"), RouteSource::Fallback)));
+        assert_eq!(consistant.count(), 0);
+
+        // Never while an element is in.
+        consistant.add("cacheA");
+        assert_eq!(consistant.count(), 1);
+        assert!(!consistant.contains("local-cache"));
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            assert_eq!(consistant.get(&key), Some(String::from("cacheA")));
+            assert_eq!(consistant.get_n(&key, 2), Some(vec![String::from("cacheA")]));
+            assert_eq!(consistant.get_with_source(&key),
+                       Some((String::from("cacheA"), RouteSource::Ring)));
+        }
+        consistant.add("cacheB");
+        consistant.add_prefix_rule("tenant-42/", "cacheB").unwrap();
+        assert_eq!(consistant.get_with_source("tenant-42/david"),
+                   Some((String::from("cacheB"), RouteSource::Pinned)));
+
+        // Removing every element falls back again, the rules skipped.
+        consistant.retain(|_| false);
+        assert_eq!(consistant.get_with_source("tenant-42/david"),
+                   Some((String::from("local-cache"), RouteSource::Fallback)));
+        assert_eq!(consistant.clone().get("david"), Some(String::from("local-cache")));
+        consistant.set_fallback(None);
+        assert_eq!(consistant.get("david"), None);
+    }
+
+    #[test]
+    fn test_fractional_weight() {
+        let mut full = Consistant::new(50);
+        for i in 0..10 {
+            full.add(format!("cache{}", i));
+        }
+        let mut consistant = full.clone();
+        full.add("canary");
+        assert_eq!(consistant.set_fractional_weight("canary", 0.5),
+                   Err(Error::UnknownMember(String::from("canary"))));
+        consistant.add_fractional("canary", 1.5);
+        assert!(!consistant.contains("canary"));
+        consistant.add_fractional("canary", 0.05);
+        assert_eq!(consistant.weight("canary"), Some(1));
+        assert_eq!(consistant.fractional_weight("canary"), Some(0.05));
+        assert_eq!(consistant.fractional_weight("cache0"), Some(1.0));
+        assert_eq!(consistant.set_fractional_weight("canary", 0.0),
+                   Err(Error::InvalidWeight(String::from("canary"))));
+        let disruption = consistant.last_disruption().unwrap();
+        assert!(disruption > 0.0 && disruption < 0.01, "{}", disruption);
+
+        let keys: Vec<String> = (0..100000).map(|i| format!("key{}", i)).collect();
+        let owned = |consistant: &Consistant| {
+            keys.iter().filter(|key| consistant.get(key.as_str()).unwrap() == "canary").count()
+        };
+        let whole = owned(&full);
+        let owners = |consistant: &Consistant| -> Vec<String> {
+            keys.iter().map(|key| consistant.get(key).unwrap()).collect()
+        };
+        let mut before = owners(&consistant);
+        for &weight in &[0.05, 0.5, 1.0] {
+            let version = consistant.version();
+            consistant.set_fractional_weight("canary", weight).unwrap();
+            assert_eq!(consistant.version(), version + (weight != 0.05) as u64);
+            let canary = owned(&consistant) as f64;
+            assert!((canary / whole as f64 - weight).abs() < 0.02 + weight * 0.1,
+                    "{} of {} at {}",
+                    canary,
+                    whole,
+                    weight);
+
+            // Keys only ever move onto the canary, the same way every time.
+            let after = owners(&consistant);
+            for (before, after) in before.iter().zip(&after) {
+                assert!(before == after || after == "canary", "{} to {}", before, after);
+            }
+            let cloned = consistant.clone();
+            assert!(keys.iter().zip(&after).all(|(key, owner)| cloned.get(key).unwrap() == *owner));
+            before = after;
+        }
+        assert_eq!(consistant.fractional_weight("canary"), Some(1.0));
+        for key in &keys[..1000] {
+            assert_eq!(consistant.get_n(key, 3), full.get_n(key, 3));
+        }
+
+        // The keys declined go to the next element, the canary following.
+        consistant.set_fractional_weight("canary", 0.05).unwrap();
+        let mut skips = 0;
+        for key in &keys[..1000] {
+            let (owner, source) = consistant.get_with_source(key).unwrap();
+            assert_eq!(source, RouteSource::Ring);
+            let owners = consistant.get_n(key, 2).unwrap();
+            assert_eq!(owners[0], owner);
+            if full.get(key).unwrap() == "canary" && owner != "canary" {
+                assert_eq!(owners[1], "canary");
+                assert_eq!(owner, full.get_n(key, 2).unwrap()[1]);
+            }
+            assert_eq!(consistant.get_primary_and_backup(key).unwrap().0, owner);
+            let explanation = consistant.explain(key).unwrap();
+            assert_eq!(explanation.owner, owners[0]);
+            if full.get(key).unwrap() == "canary" && owner != "canary" {
+                let skipped = SkippedCandidate {
+                    element: String::from("canary"),
+                    reason: SkipReason::FractionalWeight { millionths: 50_000 },
+                };
+                assert_eq!(explanation.skipped, vec![skipped]);
+                assert!(explanation.to_string()
+                            .contains("skipped:    canary (fractional weight 0.05 not admitting \
+                                       the key)"),
+                        "{}",
+                        explanation);
+                skips += 1;
+            } else {
+                assert!(explanation.skipped.is_empty());
+            }
+        }
+        assert!(skips > 0);
+
+        // Reweighting to an integer weight makes it whole.
+        consistant.member("canary").weight(2).apply().unwrap();
+        assert_eq!(consistant.fractional_weight("canary"), Some(2.0));
+        consistant.set_fractional_weight("canary", 0.5).unwrap();
+        assert_eq!(consistant.weight("canary"), Some(1));
+        consistant.remove("canary");
+        assert_eq!(consistant.fractional_weight("canary"), None);
+        consistant.add("canary");
+        assert_eq!(consistant.fractional_weight("canary"), Some(1.0));
+    }
+
+    #[test]
+    fn test_replication_factor() {
+        let mut consistant = Consistant::new(10);
+        assert_eq!(consistant.replication_factor(), 1);
+        assert_eq!(consistant.owners("david"), None);
+        for i in 0..4 {
+            consistant.add(format!("cache{}", i));
+        }
+        assert_eq!(consistant.owners("david"), consistant.get("david").map(|owner| vec![owner]));
+
+        // The factor only changes the count of owners, not the primary.
+        let primaries: Vec<Option<String>> =
+            (0..100).map(|i| consistant.get(format!("key{}", i))).collect();
+        for factor in &[3, 5] {
+            consistant.set_replication_factor(*factor);
+            assert_eq!(consistant.clone().replication_factor(), *factor);
+            for (i, primary) in primaries.iter().enumerate() {
+                let key = format!("key{}", i);
+                let owners = consistant.owners(key.as_str()).unwrap();
+                assert_eq!(owners.len(), cmp::min(*factor, 4));
+                assert_eq!(Some(&owners[0]), primary.as_ref());
+                assert_eq!(Some(owners), consistant.get_n(key.as_str(), *factor));
+            }
+        }
+        assert_eq!(consistant.owners_strict("david"),
+                   Err(ReplicationError::NotEnoughMembers {
+                       wanted: 5,
+                       available: 4,
+                   }));
+        consistant.add("cache4");
+        assert_eq!(consistant.owners_strict("david").unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_get_primary_and_backup() {
+        let mut consistant = Consistant::default();
+        assert_eq!(consistant.get_primary_and_backup("david"), None);
+
+        consistant.add("cacheA");
+        assert_eq!(consistant.get_primary_and_backup("david"),
+                   Some((String::from("cacheA"), None)));
+
+        consistant.add("cacheB");
+        consistant.add("cacheC");
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            let (primary, backup) = consistant.get_primary_and_backup(key.as_str()).unwrap();
+            let mut expected = consistant.get_n(key.as_str(), 2).unwrap().into_iter();
+            assert_eq!(Some(primary), expected.next());
+            assert_eq!(backup, expected.next());
+        }
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut consistant = Consistant::default();
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+        consistant.add("cacheC");
+
+        consistant.remove("cacheC");
+        assert_eq!(consistant.count(), 2);
+
+        assert!(consistant.get("david").unwrap() != "cacheC");
+        assert!(consistant.get("kally").unwrap() != "cacheC");
+        assert!(consistant.get("jason").unwrap() != "cacheC");
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut rng = Xorshift::new(42);
+
+        for _ in 0..20 {
+            let mut consistant = Consistant::new(1 + (rng.next_u64() % 20) as usize);
+            for i in 0..(rng.next_u64() % 30) {
+                consistant.add_weighted(format!("rack{}-node{}", rng.next_u64() % 4, i),
+                                        1 + (rng.next_u64() % 3) as usize);
+            }
+            let rack = format!("rack{}-", rng.next_u64() % 4);
+            let mut expected = consistant.clone();
+            for element in consistant.members() {
+                if element.starts_with(&rack) {
+                    expected.remove(element);
+                }
+            }
+
+            let count = consistant.count();
+            let version = consistant.version();
+            let removed = consistant.retain(|element| !element.starts_with(&rack));
+            assert_eq!(removed, count - expected.count());
+            assert_eq!(consistant.version(), version + (removed > 0) as u64);
+            assert_eq!(consistant.members(), expected.members());
+            assert_eq!(consistant.vnodes(), expected.vnodes());
+            for i in 0..100 {
+                let key = format!("key{}", i);
+                assert_eq!(consistant.get_n(key.as_str(), 3), expected.get_n(key.as_str(), 3));
+            }
+        }
+    }
+
+    #[test]
+    fn test_retain_all_or_none() {
+        let mut consistant = Consistant::default();
+        consistant.add("cacheA");
+        consistant.add_weighted("cacheB", 2);
+        consistant.replace_node("cacheB", "cacheC").unwrap();
+        let vnodes = consistant.vnodes();
+
+        assert_eq!(consistant.retain(|_| true), 0);
+        assert_eq!(consistant.version(), 3);
+        assert_eq!(consistant.last_disruption(), Some(0.0));
+        assert_eq!(consistant.vnodes(), vnodes);
+
+        assert_eq!(consistant.retain(|_| false), 2);
+        assert_eq!(consistant.version(), 4);
+        assert!((consistant.last_disruption().unwrap() - 1.0).abs() < 1e-9);
+        assert_eq!(consistant.count(), 0);
+        assert_eq!(consistant.get("david"), None);
+        assert_eq!(consistant.explicit_tokens("cacheC"), None);
+
+        consistant.add("cacheA");
+        assert_eq!(consistant.get("david"), Some(String::from("cacheA")));
+    }
+
+    /// Get the preview of the mutation from one hash ring to the other, by
+    /// sweeping their arcs.
+    fn moved(before: &Consistant, after: &Consistant) -> MutationPreview {
+        let arcs = |consistant: &Consistant| {
+            let arcs: Vec<(u64, u64, Option<String>)> = consistant.arcs()
+                .into_iter()
+                .map(|(start, end, element)| (start, end, Some(String::from(&*element))))
+                .collect();
+            if arcs.is_empty() { vec![(0, 1 << 32, None)] } else { arcs }
+        };
+        let (a, b) = (arcs(before), arcs(after));
+        let (mut i, mut j, mut start, mut length) = (0, 0, 0, 0);
+        let mut pairs = Vec::new();
+        while start < 1 << 32 {
+            let end = cmp::min(a[i].1, b[j].1);
+            if a[i].2 != b[j].2 {
+                length += end - start;
+                pairs.push((a[i].2.clone(), b[j].2.clone()));
+            }
+            start = end;
+            if a[i].1 == end {
+                i += 1;
+            }
+            if b[j].1 == end {
+                j += 1;
+            }
+        }
+        pairs.sort();
+        pairs.dedup();
+
+        MutationPreview {
+            disruption: length as f64 / (1u64 << 32) as f64,
+            moves: pairs,
+            count: after.count(),
+            vnodes: after.vnodes().len(),
+        }
+    }
+
+    #[test]
+    fn test_preview() {
+        let mut rng = Xorshift::new(42);
+
+        for _ in 0..30 {
+            let mut consistant = Consistant::new(1 + (rng.next_u64() % 20) as usize);
+            for i in 0..(rng.next_u64() % 10) {
+                consistant.add_weighted(format!("node{}", i), 1 + (rng.next_u64() % 3) as usize);
+            }
+            let version = consistant.version();
+
+            let name = format!("node{}", rng.next_u64() % 12);
+            let mut expected = consistant.clone();
+            let preview = if consistant.contains(&name) {
+                expected.remove(name.as_str());
+                consistant.preview_remove(&name)
+            } else {
+                let weight = 1 + (rng.next_u64() % 3) as usize;
+                expected.add_weighted(name.as_str(), weight);
+                consistant.preview_add_weighted(&name, weight)
+            };
+            let mut moved = moved(&consistant, &expected);
+            assert!((preview.disruption - moved.disruption).abs() < 1e-9,
+                    "{} {}",
+                    preview.disruption,
+                    moved.disruption);
+            moved.disruption = preview.disruption;
+            assert_eq!(preview, moved);
+            assert_eq!(consistant.version(), version);
+        }
+    }
+
+    #[test]
+    fn test_preview_no_op() {
+        let mut consistant = Consistant::default();
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+
+        let unchanged = MutationPreview {
+            disruption: 0.0,
+            moves: Vec::new(),
+            count: 2,
+            vnodes: 40,
+        };
+        assert_eq!(consistant.preview_add("cacheA"), unchanged);
+        assert_eq!(consistant.preview_remove("cacheC"), unchanged);
+
+        consistant.remove("cacheB");
+        let preview = consistant.preview_remove("cacheA");
+        assert_eq!(preview.disruption, 1.0);
+        assert_eq!(preview.moves, [(Some(String::from("cacheA")), None)]);
+        assert_eq!((preview.count, preview.vnodes), (0, 0));
+        assert_eq!(Consistant::default().preview_add("cacheA").moves,
+                   [(None, Some(String::from("cacheA")))]);
+    }
+
+    #[test]
+    fn test_checked_mutations() {
+        let mut consistant = Consistant::new(10);
+        for i in 0..20 {
+            consistant.checked_add(format!("node{}", i)).unwrap();
+        }
+        // Unlimited by default.
+        consistant.checked_add_weighted("huge", 100).unwrap();
+        consistant.checked_remove("huge").unwrap();
+
+        consistant.set_max_disruption(Some(0.2));
+        assert_eq!(consistant.max_disruption(), Some(0.2));
+        consistant.checked_add("node20").unwrap();
+        assert!(consistant.last_disruption().unwrap() <= 0.2);
+
+        let (vnodes, version) = (consistant.vnodes(), consistant.version());
+        let err = consistant.checked_add_weighted("huge", 100).unwrap_err();
+        assert!(err.would_move > 0.7 && err.budget == 0.2, "{:?}", err);
+        assert_eq!(consistant.vnodes(), vnodes);
+        assert_eq!(consistant.version(), version);
+        assert!(!consistant.contains("huge"));
+
+        // The unchecked mutations force the change through.
+        consistant.add_weighted("huge", 100);
+        assert!(consistant.checked_remove("huge").is_err());
+        assert!(consistant.contains("huge"));
+        consistant.remove("huge");
+        assert_eq!(consistant.vnodes(), vnodes);
+        consistant.checked_remove("node20").unwrap();
+        assert_eq!(consistant.clone().max_disruption(), Some(0.2));
+    }
+
+    #[test]
+    fn test_incarnation() {
+        let mut consistant = Consistant::default();
+        assert_eq!(consistant.incarnation("cacheA"), None);
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+        assert_eq!(consistant.incarnation("cacheA"), Some(0));
+        consistant.add("cacheA");
+        assert_eq!(consistant.incarnation("cacheA"), Some(0));
+        let vnodes = consistant.vnodes();
+
+        // A new incarnation takes the virtual nodes of the previous ones.
+        for incarnation in 1..4 {
+            consistant.remove("cacheA");
+            assert_eq!(consistant.incarnation("cacheA"), None);
+            consistant.add("cacheA");
+            assert_eq!(consistant.incarnation("cacheA"), Some(incarnation));
+            assert_eq!(consistant.vnodes(), vnodes);
+        }
+        assert_eq!(consistant.incarnation("cacheB"), Some(0));
+
+        consistant.retain(|element| element != "cacheB");
+        consistant.add("cacheB");
+        assert_eq!(consistant.incarnation("cacheB"), Some(1));
+        consistant.clear();
+        consistant.add("cacheA");
+        assert_eq!(consistant.clone().incarnation("cacheA"), Some(4));
+        assert_eq!(consistant.clone_with_replicas(5).incarnation("cacheA"), Some(4));
+        assert_eq!(consistant.subset(|_| true).incarnation("cacheA"), Some(4));
+
+        let loaded = Consistant::from_json(&consistant.to_json()).unwrap();
+        assert_eq!(loaded.incarnation("cacheA"), Some(4));
+    }
+
+    #[test]
+    fn test_replace_node() {
+        let mut consistant = Consistant::default();
+        consistant.add("cacheA");
+        consistant.add_weighted("cacheB", 2);
+        consistant.add("cacheC");
+        let handle = consistant.get_handle("david").unwrap();
+        let owner = consistant.get("david").unwrap();
+        let keys: Vec<String> = (0..10000).map(|i| format!("key{}", i)).collect();
+        let before: Vec<String> = keys.iter().map(|key| consistant.get(key.as_str()).unwrap())
+            .collect();
+
+        assert_eq!(consistant.replace_node("cacheD", "cacheE"),
+                   Err(Error::UnknownMember(String::from("cacheD"))));
+        assert_eq!(consistant.replace_node("cacheB", "cacheC"),
+                   Err(Error::DuplicateMember(String::from("cacheC"))));
+        consistant.replace_node("cacheB", "cacheB-new").unwrap();
+
+        assert_eq!(consistant.members(), ["cacheA", "cacheB-new", "cacheC"]);
+        assert_eq!(consistant.weight("cacheB-new"), Some(2));
+        assert_eq!(consistant.last_disruption(), Some(0.0));
+        for (key, old) in keys.iter().zip(&before) {
+            let new = consistant.get(key.as_str()).unwrap();
+            assert_eq!(new, if old == "cacheB" { "cacheB-new" } else { old.as_str() });
+        }
+        let resolved = consistant.resolve(handle).unwrap();
+        assert_eq!(resolved, if owner == "cacheB" { "cacheB-new" } else { owner.as_str() });
+
+        // Its virtual nodes are recorded, and removed along with it.
+        assert_eq!(consistant.clone().subset(|_| true).vnodes(), consistant.vnodes());
+        consistant.remove("cacheB-new");
+        assert_eq!(consistant.keys().len(), 2 * consistant.replicas_num);
+        assert!(consistant.tokens.is_empty());
+
+        // Renamed back, the virtual nodes are derived from the name again.
+        consistant.replace_node("cacheA", "cacheF").unwrap();
+        consistant.replace_node("cacheF", "cacheA").unwrap();
+        assert!(consistant.tokens.is_empty());
+    }
+
+    #[test]
+    fn test_clone_with_replicas() {
+        let mut consistant = Consistant::with_seed(20, 42);
+        consistant.add("cacheA");
+        consistant.add_weighted("cacheB", 3);
+        consistant.add("cacheC");
+        consistant.replace_node("cacheC", "cacheD").unwrap();
+
+        let derived = consistant.clone_with_replicas(100);
+        assert_eq!(derived.replicas_num, 100);
+        assert_eq!(consistant.replicas_num, 20);
+        assert_eq!(derived.weighted_members(), consistant.weighted_members());
+        assert_eq!((derived.seed(), derived.version()), (42, consistant.version()));
+        assert_eq!(derived.keys().len(), 500);
+        assert_eq!(consistant.keys().len(), 100);
+        let mut expected = Consistant::with_seed(100, 42);
+        expected.add("cacheA");
+        expected.add_weighted("cacheB", 3);
+        expected.add("cacheD");
+        assert_eq!(derived.vnodes(), expected.vnodes());
+
+        assert_eq!(consistant.clone_with_replicas(20).vnodes(), consistant.vnodes());
+        assert_eq!(derived.clone_with_replicas(20).vnodes(),
+                   expected.clone_with_replicas(20).vnodes());
+
+        // The past versions are carried, as this hash ring mapped them.
+        let mut consistant = Consistant::new(20);
+        consistant.set_keep_versions(4);
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+        let before = consistant.clone();
+        consistant.add("cacheC");
+        let derived = consistant.clone_with_replicas(100);
+        assert_eq!(derived.keep_versions(), 4);
+        assert_eq!(derived.available_versions(), consistant.available_versions());
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            assert_eq!(derived.get_at_version(2, &key), Ok(before.get(&key)));
+            assert_eq!(derived.get_at_version(3, &key), Ok(derived.get(&key)));
+        }
+    }
+
+    #[test]
+    fn test_subset() {
+        let mut consistant = Consistant::default();
+        consistant.add("ssd-cacheA");
+        consistant.add("hdd-cacheB");
+        consistant.add("ssd-cacheC");
+
+        let subset = consistant.subset(|element| element.starts_with("ssd-"));
+        assert_eq!(subset.count(), 2);
+        assert_eq!(subset.replicas_num, consistant.replicas_num);
+        assert_eq!(subset.keys().len(), 2 * consistant.replicas_num);
+
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            let owner = consistant.get(key.as_str()).unwrap();
+            if owner.starts_with("ssd-") {
+                assert_eq!(subset.get(key.as_str()).unwrap(), owner);
+            }
+        }
+
+        let empty = consistant.subset(|_| false);
+        assert_eq!(empty.count(), 0);
+        assert_eq!(empty.get("david"), None);
+    }
+
+    #[test]
+    fn test_group_keys_by_node() {
+        let mut consistant = Consistant::default();
+        assert!(consistant.group_keys_by_node(vec!["david", "kally"]).is_empty());
+
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+        consistant.add("cacheC");
+
+        let keys = vec!["david", "kally", "jason", "david", "james", "kelly"];
+        let groups = consistant.group_keys_by_node(keys.clone());
+
+        let mut total = 0;
+        for (element, group) in &groups {
+            for key in group {
+                assert_eq!(&consistant.get(*key).unwrap(), element);
+            }
+            let expected: Vec<&str> = keys.iter()
+                .filter(|key| &consistant.get(**key).unwrap() == element)
+                .cloned()
+                .collect();
+            assert_eq!(group, &expected);
+            total += group.len();
+        }
+        assert_eq!(total, keys.len());
+    }
+
+    #[test]
+    fn test_get_n_strict() {
+        let mut consistant = Consistant::default();
+        assert_eq!(consistant.get_n_strict("david", 3),
+                   Err(ReplicationError::NotEnoughMembers {
+                       wanted: 3,
+                       available: 0,
+                   }));
+
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+        consistant.add("cacheC");
+
+        let res = consistant.get_n_strict("david", 3).unwrap();
+        assert_eq!(res, consistant.get_n("david", 3).unwrap());
+
+        assert_eq!(consistant.get_n_strict("david", 5),
+                   Err(ReplicationError::NotEnoughMembers {
+                       wanted: 5,
+                       available: 3,
+                   }));
+        assert_eq!(consistant.get_n("david", 5).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_remove_borrowed() {
+        let mut consistant = Consistant::default();
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+        consistant.add("cacheC");
+
+        consistant.remove("cacheA");
+        assert!(!consistant.contains("cacheA"));
+
+        consistant.remove(String::from("cacheB"));
+        assert!(!consistant.contains("cacheB"));
+
+        let name = String::from("cacheC");
+        consistant.remove(&name);
+        assert!(!consistant.contains("cacheC"));
+
+        assert_eq!(consistant.count(), 0);
+        assert_eq!(consistant.keys().len(), 0);
+    }
+
+    #[cfg(not(loom))]
+    #[test]
+    fn test_poisoned_lock() {
+        let mut consistant = Consistant::default();
+        consistant.add("cacheA");
+
+        let res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            let _guard = consistant.lock.write().unwrap();
+            panic!("poison the lock");
+        }));
+        assert!(res.is_err());
+        assert!(consistant.lock.is_poisoned());
+
+        assert_eq!(consistant.get("david").unwrap(), "cacheA");
+        consistant.add("cacheB");
+        assert_eq!(consistant.count(), 2);
+        assert_eq!(consistant.get_n("david", 2).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_explain() {
+        let mut consistant = Consistant::default();
+        assert_eq!(consistant.explain("david"), None);
+
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+        consistant.add("cacheC");
+
+        let max = consistant.keys()[consistant.keys().len() - 1].0;
+        let mut wrapped = false;
+        for i in 0..10000 {
+            let key = format!("key{}", i);
+            let explanation = consistant.explain(&key).unwrap();
+            assert_eq!(explanation.owner, consistant.get(key.as_str()).unwrap());
+            assert_eq!(explanation.checksum, checksum_ieee(key.as_bytes()));
+            assert_eq!(explanation.vnode_hash, consistant.keys()[explanation.index].0);
+            assert_eq!(explanation.wrapped, explanation.checksum >= max);
+            if explanation.wrapped {
+                assert_eq!(explanation.index, 0);
+                wrapped = true;
+            }
+        }
+        assert!(wrapped);
+
+        let text = consistant.explain("david").unwrap().to_string();
+        assert!(text.contains("owner:"));
+        assert!(!text.contains("range:"));
+        consistant.assign_range(0, 0, "cacheB").unwrap();
+        let text = consistant.explain("david").unwrap().to_string();
+        assert!(text.ends_with("range:      (0x00000000, 0x00000000]"), "{}", text);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_explain_serialize() {
+        let mut consistant = Consistant::new(50);
+        for i in 0..4 {
+            consistant.add(format!("cache{}", i));
+        }
+        consistant.set_fractional_weight("cache2", 0.1).unwrap();
+        let explanation = (0..1000)
+            .filter_map(|i| consistant.explain(format!("key{}", i)))
+            .find(|explanation| !explanation.skipped.is_empty())
+            .unwrap();
+        let json = serde_json::to_value(&explanation).unwrap();
+        assert_eq!(json["skipped"],
+                   serde_json::json!([{
+                       "element": "cache2",
+                       "reason": { "FractionalWeight": { "millionths": 100000 } },
+                   }]));
+    }
+
+    #[test]
+    fn test_distances() {
+        let mut consistant = Consistant::default();
+        assert_eq!(consistant.distance_to_owner("david"), None);
+        assert!(consistant.distances("david").is_empty());
+        for i in 0..8 {
+            consistant.add_weighted(format!("node-{}", i), 1 + i % 2);
+        }
+
+        let (min, max) = (consistant.keys()[0].0, consistant.keys()[consistant.keys().len() - 1].0);
+        let mut wrapped = false;
+        for i in 0..10000 {
+            let key = format!("key{}", i);
+            let sum = checksum_ieee(key.as_bytes());
+            let distance = consistant.distance_to_owner(&key).unwrap();
+            let explanation = consistant.explain(&key).unwrap();
+            assert_eq!(sum.wrapping_add(distance), explanation.vnode_hash);
+            if sum >= max {
+                // Past the last virtual node, the distance wraps to the first.
+                assert_eq!(u64::from(distance), (1u64 << 32) - u64::from(sum) + u64::from(min));
+                wrapped = true;
+            }
+
+            let distances = consistant.distances(&key);
+            assert_eq!(distances.len(), 8);
+            assert_eq!(distances[0], (consistant.get(&key).unwrap(), distance));
+            assert!(distances.windows(2).all(|pair| pair[0].1 < pair[1].1));
+            let names: Vec<String> = distances.into_iter().map(|entry| entry.0).collect();
+            assert_eq!(names, consistant.get_n(&key, 8).unwrap());
+        }
+        assert!(wrapped);
+
+        let mut single = Consistant::new(1);
+        single.add("cacheA");
+        let hash = single.keys()[0].0;
+        assert_eq!(single.distances("david"),
+                   [(String::from("cacheA"), hash.wrapping_sub(checksum_ieee(b"david")))]);
+    }
+
+    #[test]
+    fn test_add_weighted() {
+        let mut consistant = Consistant::default();
+        consistant.add("cacheA");
+        consistant.add_weighted("cacheB", 3);
+        consistant.add_weighted("cacheC", 0);
+
+        assert_eq!(consistant.count(), 2);
+        assert_eq!(consistant.weight("cacheA"), Some(1));
+        assert_eq!(consistant.weight("cacheB"), Some(3));
+        assert_eq!(consistant.weight("cacheC"), None);
+        assert_eq!(consistant.keys().len(), 4 * consistant.replicas_num);
+
+        consistant.remove("cacheB");
+        assert_eq!(consistant.keys().len(), consistant.replicas_num);
+    }
+
+    #[test]
+    fn test_add_all() {
+        let mut members: Vec<(String, usize)> =
+            (0..200).map(|i| (format!("node-{}", i), 1 + i % 3)).collect();
+        let mut sequential = Consistant::new(20);
+        for &(ref element, weight) in &members {
+            sequential.add_weighted(element.as_str(), weight);
+        }
+        members.push((String::from("node-7"), 5));
+        members.push((String::from("node-x"), 0));
+
+        let consistant = Consistant::from_members(20, members.clone());
+        assert_eq!(consistant.vnodes(), sequential.vnodes());
+        assert_eq!(consistant.weighted_members(), sequential.weighted_members());
+        assert_eq!(consistant.version(), 1);
+        assert_eq!(consistant.last_disruption(), Some(1.0));
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            assert_eq!(consistant.get(&key), sequential.get(&key));
+        }
+
+        // Adding back node-3 after node-31 gives it their colliding hashes.
+        let mut consistant = Consistant::new(20);
+        consistant.add_all(members[..100].iter().cloned());
+        consistant.add("node-500");
+        consistant.remove("node-3");
+        consistant.add_all(members.iter().cloned());
+        let mut sequential = Consistant::new(20);
+        for &(ref element, weight) in &members[..100] {
+            sequential.add_weighted(element.as_str(), weight);
+        }
+        sequential.add("node-500");
+        sequential.remove("node-3");
+        for &(ref element, weight) in &members {
+            sequential.add_weighted(element.as_str(), weight);
+        }
+        assert_eq!(consistant.vnodes(), sequential.vnodes());
+        assert_eq!(consistant.version(), 4);
+        assert_eq!(consistant.incarnation("node-3"), Some(1));
+        let share = consistant.last_disruption().unwrap();
+        assert!(share > 0.4 && share < 0.7, "{}", share);
+
+        consistant.add_all(members);
+        assert_eq!(consistant.version(), 4);
+        assert_eq!(consistant.last_disruption(), Some(0.0));
+    }
+
+    #[test]
+    fn test_double_hash_placement() {
+        let stddev = |placement| {
+            let mut consistant = Consistant::with_placement(20, placement);
+            for name in &["cacheA", "cacheB", "cacheC", "cacheD", "cacheE"] {
+                consistant.add(*name);
+            }
+            let shares = consistant.histogram(1).unwrap().remove(0).shares;
+            let mean = 1.0 / shares.len() as f64;
+            (shares.values().map(|share| (share - mean).powi(2)).sum::<f64>() /
+             shares.len() as f64)
+                .sqrt()
+        };
+
+        let classic = stddev(PlacementStrategy::Classic);
+        let double_hash = stddev(PlacementStrategy::DoubleHash);
+        assert!(double_hash < classic, "{} >= {}", double_hash, classic);
+
+        let mut consistant = Consistant::with_placement(20, PlacementStrategy::DoubleHash);
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+        assert_eq!(consistant.keys().len(), 40);
+        consistant.remove("cacheA");
+        assert_eq!(consistant.keys().len(), 20);
+        assert_eq!(consistant.subset(|_| true).placement(),
+                   PlacementStrategy::DoubleHash);
+    }
+
+    #[test]
+    fn test_seeded_random_placement() {
+        let mut consistant = Consistant::with_placement(20, PlacementStrategy::SeededRandom);
+        consistant.add("cacheA");
+        consistant.add_all(vec![("cacheB", 2), ("cacheC", 1)]);
+        let tokens = consistant.explicit_tokens("cacheA").unwrap();
+        // The same in every process, for the same seed only.
+        assert_eq!(tokens[..3], [0x3e9a_21d0, 0xb355_61ee, 0xe255_078a]);
+        assert_eq!(tokens, PlacementStrategy::SeededRandom.points(0, "cacheA", 20));
+        assert_eq!(consistant.explicit_tokens("cacheB").unwrap().len(), 40);
+        let seeded = Consistant::with_config(20, PlacementStrategy::SeededRandom, 42);
+        assert!(PlacementStrategy::SeededRandom.points(42, "cacheA", 20) != tokens);
+        assert_eq!(seeded.placement(), PlacementStrategy::SeededRandom);
+
+        // The recorded virtual nodes are carried, and removed with no orphan.
+        let loaded = Consistant::from_json(&consistant.to_json()).unwrap();
+        assert_eq!(loaded.vnodes(), consistant.vnodes());
+        assert_eq!(loaded.explicit_tokens("cacheB"), consistant.explicit_tokens("cacheB"));
+        assert_eq!(consistant.set_fractional_weight("cacheB", 0.5),
+                   Err(Error::ExplicitTokens(String::from("cacheB"))));
+        consistant.remove("cacheB");
+        assert_eq!(consistant.keys().len(), 40);
+        assert!(consistant.vnodes().iter().all(|vnode| &*vnode.1 != "cacheB"));
+        assert_eq!(consistant.explicit_tokens("cacheB"), None);
+        consistant.replace_node("cacheC", "cacheD").unwrap();
+        consistant.remove("cacheA");
+        consistant.remove("cacheD");
+        assert!(consistant.keys().is_empty());
+        assert!(consistant.tokens.is_empty());
+
+        // Over small rings, the draws balance as well as the classic names.
+        let stddev = |placement| {
+            let mut sum = 0.0;
+            for ring in 0..20 {
+                let mut consistant = Consistant::with_placement(20, placement);
+                for member in 0..5 {
+                    consistant.add(format!("ring{}-cache{}", ring, member));
+                }
+                let shares = consistant.histogram(1).unwrap().remove(0).shares;
+                let mean = 1.0 / shares.len() as f64;
+                sum += (shares.values().map(|share| (share - mean).powi(2)).sum::<f64>() /
+                        shares.len() as f64)
+                    .sqrt();
+            }
+            sum / 20.0
+        };
+        let classic = stddev(PlacementStrategy::Classic);
+        let seeded_random = stddev(PlacementStrategy::SeededRandom);
+        assert!(seeded_random <= classic, "{} > {}", seeded_random, classic);
+    }
+
+    #[test]
+    fn test_with_seed() {
+        let members: Vec<String> = (1..10).map(|i| format!("node-{}", i)).collect();
+        let ring = |seed| {
+            let mut consistant = Consistant::with_seed(20, seed);
+            for member in &members {
+                consistant.add(member.as_str());
+            }
+            consistant
+        };
+        let mut unseeded = Consistant::new(20);
+        for member in &members {
+            unseeded.add(member.as_str());
+        }
+        let (seeded, other) = (ring(42), ring(43));
+
+        assert_eq!(seeded.seed(), 42);
+        assert_eq!(ring(0).vnodes(), unseeded.vnodes());
+        assert!(seeded.vnodes() != unseeded.vnodes());
+        let differing = (0..1000)
+            .filter(|i| {
+                let key = format!("key{}", i);
+                seeded.get(key.as_str()) != other.get(key.as_str())
+            })
+            .count();
+        assert!(differing > 500, "{}", differing);
+
+        // Pinned so that a seeded ring maps keys the same in every process.
+        assert_eq!(checksum(42, b"key"),
+                   checksum_ieee(&[42, 0, 0, 0, 0, 0, 0, 0, b'k', b'e', b'y']));
+        assert_eq!(seeded.explain("key").unwrap().checksum, checksum(42, b"key"));
+        assert_eq!(seeded.subset(|_| true).seed(), 42);
+    }
+
+    #[test]
+    fn test_get_composite() {
+        // Pinned so that other languages can encode the parts alike.
+        assert_eq!(checksum_parts(0, &[b"ab", b"c"]), 2999842943);
+        assert_eq!(checksum_parts(0, &[b"a", b"bc"]), 1232122289);
+        assert_eq!(checksum_parts(0, &[b"users", b"orders", b"42"]), 2965966011);
+        assert_eq!(checksum_parts(0, &[b""]), 558161692);
+        assert_eq!(checksum_parts(0, &[]), 0);
+        assert_eq!(checksum_parts(42, &[b"ab", b"c"]), 1490283932);
+        assert_eq!(checksum_parts(42, &[b"ab", b"c"]),
+                   checksum(42, b"\x02\x00\x00\x00ab\x01\x00\x00\x00c"));
+
+        let mut consistant = Consistant::with_seed(20, 42);
+        assert_eq!(consistant.get_composite(&[b"users", b"42"]), None);
+        for i in 0..10 {
+            consistant.add(format!("cache{}", i));
+        }
+        let mut differing = 0;
+        for i in 0..1000 {
+            let (table, id) = (format!("table{}", i % 7), i.to_string());
+            let parts: [&[u8]; 3] = [b"users", table.as_bytes(), id.as_bytes()];
+            let key = format!("\u{5}\0\0\0users{}\0\0\0{}{}\0\0\0{}",
+                              table.len() as u8 as char,
+                              table,
+                              id.len() as u8 as char,
+                              id);
+            assert_eq!(consistant.get_composite(&parts), consistant.get(key.as_str()));
+            assert_eq!(consistant.get_n_composite(&parts, 3), consistant.get_n(key.as_str(), 3));
+            let joined = format!("users{}", table);
+            let split: [&[u8]; 2] = [joined.as_bytes(), id.as_bytes()];
+            if consistant.get_composite(&split) != consistant.get_composite(&parts) {
+                differing += 1;
+            }
+        }
+        assert!(differing > 500, "{}", differing);
+        assert_eq!(consistant.get_n_composite(&[b"users"], 0), None);
+    }
+
+    #[test]
+    fn test_colliding_vnodes() {
+        // "node-1" followed by 1x is also "node-11" followed by x.
+        let mut consistant = Consistant::default();
+        consistant.add("node-1");
+        consistant.add("node-11");
+        assert_eq!(consistant.keys().len(), 30);
+
+        consistant.remove("node-1");
+        assert_eq!(consistant.keys().len(), 20);
+        assert_eq!(consistant.get("david"), Some(String::from("node-11")));
+        consistant.remove("node-11");
+        assert!(consistant.keys().is_empty());
+        assert!(consistant.slots.index("node-11").is_none());
+    }
+
+    #[test]
+    fn test_taken_over_element() {
+        // "node-" followed by 1x and "node-11" followed by x are all the
+        // virtual nodes of "node-1", which owns none once they are added.
+        let mut consistant = Consistant::default();
+        consistant.add("node-1");
+        consistant.add("node-11");
+        consistant.add("node-");
+        assert!(consistant.vnodes().iter().all(|vnode| &*vnode.1 != "node-1"));
+
+        assert_eq!(consistant.get_n("david", 3).unwrap().len(), 2);
+        let mut out = Vec::new();
+        assert_eq!(consistant.get_n_into("david", 3, &mut out), 2);
+        assert_eq!(consistant.get_nth_owner("david", 2), None);
+        assert!(!consistant.is_owner("node-1", "david", 3));
+        assert_eq!(consistant.compile(64).get_n("david", 3).unwrap().len(), 2);
+        assert_eq!(consistant.clone().freeze().get_n("david", 3).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_lazy_sort() {
+        // Mutations leave the virtual nodes unsorted, so every lookup must see
+        // them as an eagerly sorted hash ring would.
+        let mut consistant = Consistant::default();
+        let mut model: BTreeMap<u32, String> = BTreeMap::new();
+        let owner = |model: &BTreeMap<u32, String>, key: &str| {
+            let sum = checksum_ieee(key.as_bytes());
+            let after = model.range((Bound::Excluded(sum), Bound::Unbounded));
+            after.chain(model.iter()).next().map(|vnode| vnode.1.clone())
+        };
+
+        for i in 0..300 {
+            let element = format!("node-{}", i);
+            let weight = 1 + i % 3;
+            consistant.add_weighted(element.as_str(), weight);
+            for sum in consistant.placement().points(0, &element, 20 * weight) {
+                model.insert(sum, element.clone());
+            }
+            if i % 7 == 6 {
+                let removed = format!("node-{}", i / 2);
+                consistant.remove(&removed);
+                model.retain(|_, element| *element != removed);
+            }
+            assert!(!read_lock(&consistant.sorted_keys).is_sorted());
+
+            let key = format!("key{}", i);
+            let expected = owner(&model, &key);
+            match i % 4 {
+                0 => assert_eq!(consistant.get(key.as_str()), expected),
+                1 => assert_eq!(consistant.get_n(key.as_str(), 2).unwrap()[0], expected.unwrap()),
+                2 => assert_eq!(consistant.explain(&key).map(|e| e.owner), expected),
+                _ => continue,
+            }
+            assert!(read_lock(&consistant.sorted_keys).is_sorted());
+        }
+
+        assert_eq!(consistant.keys().len(), model.len());
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            assert_eq!(consistant.get(key.as_str()), owner(&model, &key));
+        }
+    }
+
+    #[test]
+    fn test_vnodes_model() {
+        // The virtual nodes of the original storage, a map of hashes to
+        // elements searched along with the sorted hashes.
+        let mut model: BTreeMap<u32, String> = BTreeMap::new();
+        let model_get_n = |model: &BTreeMap<u32, String>, key: &str, n: usize| {
+            let sum = checksum_ieee(key.as_bytes());
+            let after = model.range((Bound::Excluded(sum), Bound::Unbounded));
+            let mut res: Vec<String> = Vec::new();
+            for element in after.chain(model.iter()).map(|vnode| vnode.1) {
+                if res.len() == n {
+                    break;
+                }
+                if !res.contains(element) {
+                    res.push(element.clone());
+                }
+            }
+            res
+        };
+
+        let mut consistant = Consistant::new(10);
+        let mut rng = Xorshift::new(7);
+        for round in 0..2000 {
+            let element = format!("node-{}", rng.next_u64() % 50);
+            if rng.next_u64().is_multiple_of(3) {
+                consistant.remove(&element);
+                model.retain(|_, owner| *owner != element);
+            } else if !consistant.contains(&element) {
+                let weight = (rng.next_u64() % 4) as usize;
+                consistant.add_weighted(element.as_str(), weight);
+                for sum in consistant.placement().points(0, &element, 10 * weight) {
+                    model.insert(sum, element.clone());
+                }
+            }
+
+            let key = format!("key{}", round);
+            let n = (rng.next_u64() % 4) as usize + 1;
+            let expected = model_get_n(&model, &key, n);
+            assert_eq!(consistant.get_n(key.as_str(), n).unwrap_or_default(), expected);
+            assert_eq!(consistant.get(key.as_str()), expected.first().cloned());
+        }
+        assert_eq!(consistant.vnodes().len(), model.len());
+        for ((sum, element), (model_sum, model_element)) in consistant.vnodes().iter().zip(&model) {
+            assert_eq!((sum, &**element), (model_sum, model_element.as_str()));
+        }
+    }
+
+    #[test]
+    fn test_lazy_sort_threads() {
+        let mut consistant = Consistant::default();
+        for i in 0..100 {
+            consistant.add(format!("node-{}", i));
+        }
+        let expected = consistant.clone().get("david");
+        consistant.add("node-100");
+        consistant.remove("node-100");
+
+        thread::scope(|scope| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| scope.spawn(|| consistant.get("david")))
+                .collect();
+            for handle in handles {
+                assert_eq!(handle.join().unwrap(), expected);
+            }
+        });
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+        let mut consistant = Consistant::default();
+        for i in 0..10 {
+            consistant.add(format!("node-{}", i));
+        }
+        assert_send_sync(&consistant);
+        let expected = consistant.get_n("david", 3);
+
+        // Shared behind an Arc by threads outliving the scope it was built in.
+        let consistant = Arc::new(consistant);
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let consistant = consistant.clone();
+                thread::spawn(move || consistant.get_n("david", 3))
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_compact() {
+        let mut consistant = Consistant::default();
+        for i in 0..2000 {
+            consistant.add(format!("node-{}", i));
+        }
+        for i in 100..2000 {
+            consistant.remove(format!("node-{}", i));
+        }
+        let keys: Vec<String> = (0..1000).map(|i| format!("key{}", i)).collect();
+        let before: Vec<Option<String>> = keys.iter()
+            .map(|key| consistant.get(key.as_str()))
+            .collect();
+        let usage = consistant.memory_usage();
+
+        consistant.compact();
+        assert!(consistant.memory_usage() < usage / 5,
+                "{} then {}",
+                usage,
+                consistant.memory_usage());
+        for (key, owner) in keys.iter().zip(before) {
+            assert_eq!(consistant.get(key.as_str()), owner);
+        }
+    }
+
+    #[test]
+    fn test_version() {
+        let mut consistant = Consistant::default();
+        assert_eq!(consistant.version(), 0);
+
+        consistant.add("cacheA");
+        assert_eq!(consistant.version(), 1);
+        consistant.add("cacheA");
+        consistant.add_weighted("cacheB", 0);
+        assert_eq!(consistant.version(), 1);
+        consistant.add("cacheB");
+        assert_eq!(consistant.version(), 2);
+        consistant.remove("cacheB");
+        assert_eq!(consistant.version(), 3);
+        consistant.remove("cacheB");
+        assert_eq!(consistant.version(), 3);
+        assert_eq!(consistant.clone().version(), 3);
+        consistant.clear();
+        assert_eq!(consistant.version(), 4);
+        consistant.clear();
+        assert_eq!(consistant.version(), 4);
+
+        let mut other = Consistant::default();
+        other.add("cacheA");
+        consistant.add("cacheA");
+        assert_eq!(other.vnodes(), consistant.vnodes());
+        assert!(other.version() != consistant.version());
+    }
+
+    #[test]
+    fn test_last_disruption() {
+        let mut consistant = Consistant::new(200);
+        assert_eq!(consistant.last_disruption(), None);
+        consistant.add("node-0");
+        assert_eq!(consistant.last_disruption(), Some(1.0));
+        for i in 1..9 {
+            consistant.add(format!("node-{}", i));
+        }
+
+        consistant.add("node-9");
+        let added = consistant.last_disruption().unwrap();
+        assert!((added - 0.1).abs() < 0.05, "{}", added);
+        let moved = (0..10000)
+            .filter(|i| consistant.get(format!("key{}", i)) == Some(String::from("node-9")))
+            .count();
+        assert!((moved as f64 / 10000.0 - added).abs() < 0.02);
+
+        consistant.remove("node-9");
+        assert_eq!(consistant.last_disruption(), Some(added));
+        consistant.remove("node-9");
+        assert_eq!(consistant.last_disruption(), Some(0.0));
+        consistant.add("node-0");
+        assert_eq!(consistant.last_disruption(), Some(0.0));
+
+        let total = consistant.total_disruption();
+        assert!(total > 1.0 + 2.0 * added);
+        consistant.clear();
+        assert_eq!(consistant.last_disruption(), Some(1.0));
+        assert_eq!(consistant.total_disruption(), total + 1.0);
+    }
+
+    /// Get the smallest distance between two virtual nodes of the same
+    /// element, either way around the hash ring.
+    fn min_self_distance(consistant: &Consistant) -> u32 {
+        let mut points: BTreeMap<Arc<str>, Vec<u32>> = BTreeMap::new();
+        for (sum, element) in consistant.vnodes() {
+            points.entry(element).or_default().push(sum);
+        }
+        points.values()
+            .flat_map(|points| {
+                let wrap = points[0].wrapping_sub(points[points.len() - 1]);
+                points.windows(2).map(|pair| pair[1] - pair[0]).chain(Some(wrap))
+            })
+            .min()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_min_self_spacing() {
+        let mut consistant = Consistant::new(20);
+        for element in &["cacheA", "cacheB", "cacheC"] {
+            consistant.add(*element);
+        }
+        let unspaced = consistant.vnodes();
+        let spacing = (1u64 << 32) / 100;
+        assert!(u64::from(min_self_distance(&consistant)) < spacing);
+
+        consistant.set_min_self_spacing(0.01);
+        assert_eq!(consistant.self_spacing() as u64, spacing);
+        assert!(u64::from(min_self_distance(&consistant)) >= spacing);
+        assert!(consistant.vnodes() != unspaced);
+        // The spaced virtual nodes are derived from the names alone.
+        let mut other = Consistant::new(20);
+        other.set_min_self_spacing(0.01);
+        for element in &["cacheC", "cacheA", "cacheB"] {
+            other.add(*element);
+        }
+        assert_eq!(other.vnodes(), consistant.vnodes());
+
+        consistant.add("cacheD");
+        assert!(u64::from(min_self_distance(&consistant)) >= spacing);
+        consistant.remove("cacheD");
+        assert_eq!(consistant.vnodes(), other.vnodes());
+        let loaded = Consistant::from_json(&consistant.to_json()).unwrap();
+        assert_eq!(loaded.vnodes(), consistant.vnodes());
+        assert_eq!(consistant.clone_with_replicas(10).min_self_spacing(),
+                   consistant.min_self_spacing());
+
+        consistant.set_min_self_spacing(0.0);
+        assert_eq!(consistant.vnodes(), unspaced);
+    }
+}
+
+/// The proofs of the walks of the hash ring, for `cargo kani`.
+#[cfg(kani)]
+mod proofs {
+    use super::*;
+
+    /// Any `len` virtual nodes of up to 3 elements, some of them owning
+    /// none, and a walk from any of them.
+    fn any_walk() -> ([u32; 4], usize, usize, usize) {
+        let owners: [u32; 4] = kani::any();
+        kani::assume(owners.iter().all(|owner| *owner < 3));
+        let len = kani::any_where(|len: &usize| *len > 0 && *len <= owners.len());
+        let start = kani::any_where(|start: &usize| *start < len);
+        let count = kani::any_where(|count: &usize| *count <= 3);
+        (owners, len, start, count)
+    }
+
+    #[kani::proof]
+    #[kani::unwind(6)]
+    fn check_walk_owners() {
+        let (owners, len, start, count) = any_walk();
+        let mut met = [u32::MAX; 4];
+        let mut visited = 0;
+        walk_owners(len, |index| owners[index], start, count, |owner| {
+            assert!(visited < count && !met.contains(&owner));
+            assert!(owners[..len].contains(&owner));
+            met[visited] = owner;
+            visited += 1;
+            false
+        });
+        assert!(visited <= count);
+    }
+
+    #[kani::proof]
+    #[kani::unwind(6)]
+    fn check_distinct_owners() {
+        let (owners, len, start, count) = any_walk();
+        let first: Option<u32> = kani::any();
+        kani::assume(first.is_none_or(|first| first < 3) && (first.is_none() || count > 0));
+
+        let found = distinct_owners(len, |index| owners[index], start, first, count);
+        assert!(found.len() <= count);
+        for (i, owner) in found.iter().enumerate() {
+            assert!(!found[..i].contains(owner));
+            assert!(Some(*owner) == first || owners[..len].contains(owner));
+        }
+    }
+}
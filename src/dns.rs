@@ -0,0 +1,323 @@
+use std::collections::btree_set::BTreeSet;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use consistant::Consistant;
+use error::DnsError;
+use membership::SyncReport;
+
+/// DnsFuture is the answer to a query of a `Resolve`.
+pub type DnsFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, DnsError>> + Send + 'a>>;
+
+/// SrvRecord is a record of an SRV answer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrvRecord {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    /// The host name of the record, fully qualified or not.
+    pub target: String,
+}
+
+/// Resolve looks up the records of the members of a hash ring, see
+/// `Consistant::sync_from_dns`, with the `dns` feature. It is implemented
+/// over the resolver of the application, like the one of hickory-dns,
+/// copying the name into its query.
+pub trait Resolve {
+    /// Look the SRV records of the given name up, none if it has none.
+    fn lookup_srv<'a>(&'a self, name: &str) -> DnsFuture<'a, Vec<SrvRecord>>;
+
+    /// Look the A and AAAA records of the given name up, none if it has
+    /// none.
+    fn lookup_ip<'a>(&'a self, name: &str) -> DnsFuture<'a, Vec<IpAddr>>;
+}
+
+/// DnsSync keeps the elements of a hash ring in sync with the records of a
+/// name, see `Consistant::sync_from_dns`. An empty answer is ignored until
+/// as many as `empty_answers` are answered in a row, 3 by default, so that a
+/// transient failure of the DNS does not empty the hash ring.
+#[derive(Debug, Clone)]
+pub struct DnsSync {
+    name: String,
+    port: u16,
+    empty_answers: usize,
+    /// The count of empty answers in a row.
+    empty_streak: usize,
+}
+
+impl DnsSync {
+    /// Crete a new instance of DnsSync of the given name, the members of
+    /// its A and AAAA records listening on the given port.
+    pub fn new<S: Into<String>>(name: S, port: u16) -> DnsSync {
+        DnsSync {
+            name: name.into(),
+            port,
+            empty_answers: 3,
+            empty_streak: 0,
+        }
+    }
+
+    /// Set the count of empty answers in a row removing all the elements, 1
+    /// to remove them at the first one.
+    pub fn empty_answers(mut self, count: usize) -> DnsSync {
+        self.empty_answers = count.max(1);
+        self
+    }
+
+    /// Get the name looked up.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the count of the last answers in a row which were empty.
+    pub fn empty_streak(&self) -> usize {
+        self.empty_streak
+    }
+}
+
+impl Consistant {
+    /// Resolve the members of the hash ring of the given DnsSync and make
+    /// them the elements of the hash ring, see `sync_members`, returning
+    /// what changed.
+    ///
+    /// The SRV records of the name are looked up first, every one of them
+    /// being a `host:port` member whatever its priority, the trailing dot
+    /// of the host left out. Without any, its A and AAAA records are looked
+    /// up, every address being a member of the port of the DnsSync, like
+    /// `10.0.0.1:80` or `[fd00::1]:80`. An empty answer leaves the hash ring
+    /// as it is and returns `DnsError::EmptyAnswer`, until answered as many
+    /// times in a row as the DnsSync tells. A failed lookup leaves it as it
+    /// is too.
+    pub fn sync_from_dns<'a, R>(&'a mut self,
+                                sync: &'a mut DnsSync,
+                                resolver: &'a R)
+                                -> impl Future<Output = Result<SyncReport, DnsError>> + 'a
+        where R: Resolve + ?Sized
+    {
+        let state = State::Srv(resolver.lookup_srv(&sync.name));
+        SyncFuture {
+            consistant: self,
+            sync,
+            resolver,
+            state,
+        }
+    }
+}
+
+enum State<'a> {
+    Srv(DnsFuture<'a, Vec<SrvRecord>>),
+    Ip(DnsFuture<'a, Vec<IpAddr>>),
+    Done,
+}
+
+struct SyncFuture<'a, R: ?Sized + 'a> {
+    consistant: &'a mut Consistant,
+    sync: &'a mut DnsSync,
+    resolver: &'a R,
+    state: State<'a>,
+}
+
+impl<'a, R: Resolve + ?Sized> SyncFuture<'a, R> {
+    fn apply(&mut self, members: BTreeSet<String>) -> Result<SyncReport, DnsError> {
+        self.state = State::Done;
+        if members.is_empty() {
+            self.sync.empty_streak += 1;
+            if self.sync.empty_streak < self.sync.empty_answers {
+                return Err(DnsError::EmptyAnswer(self.sync.empty_streak));
+            }
+        } else {
+            self.sync.empty_streak = 0;
+        }
+        Ok(self.consistant.sync_members(members))
+    }
+}
+
+impl<'a, R: Resolve + ?Sized> Future for SyncFuture<'a, R> {
+    type Output = Result<SyncReport, DnsError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        loop {
+            let ips = match this.state {
+                State::Srv(ref mut lookup) => {
+                    match lookup.as_mut().poll(cx) {
+                        Poll::Pending => return Poll::Pending,
+                        Poll::Ready(Err(err)) => {
+                            this.state = State::Done;
+                            return Poll::Ready(Err(err));
+                        }
+                        Poll::Ready(Ok(ref records)) if records.is_empty() => {
+                            this.resolver.lookup_ip(&this.sync.name)
+                        }
+                        Poll::Ready(Ok(records)) => {
+                            let members = records.iter()
+                                .map(|record| {
+                                    format!("{}:{}",
+                                            record.target.trim_end_matches('.'),
+                                            record.port)
+                                })
+                                .collect();
+                            return Poll::Ready(this.apply(members));
+                        }
+                    }
+                }
+                State::Ip(ref mut lookup) => {
+                    return match lookup.as_mut().poll(cx) {
+                        Poll::Pending => Poll::Pending,
+                        Poll::Ready(Err(err)) => {
+                            this.state = State::Done;
+                            Poll::Ready(Err(err))
+                        }
+                        Poll::Ready(Ok(ips)) => {
+                            let port = this.sync.port;
+                            let members = ips.into_iter()
+                                .map(|ip| SocketAddr::new(ip, port).to_string())
+                                .collect();
+                            Poll::Ready(this.apply(members))
+                        }
+                    };
+                }
+                State::Done => panic!("future polled after completion"),
+            };
+            this.state = State::Ip(ips);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::HashMap;
+    use std::future;
+    use std::sync::Mutex;
+    use std::task::Waker;
+
+    /// FakeResolver answers the records it is given, of any name.
+    #[derive(Default)]
+    struct FakeResolver {
+        srv: Mutex<Vec<SrvRecord>>,
+        ips: Mutex<Vec<IpAddr>>,
+        queries: Mutex<HashMap<&'static str, usize>>,
+        fail: Mutex<bool>,
+    }
+
+    impl FakeResolver {
+        fn answer<T: Clone + Send + 'static>(&self,
+                                             kind: &'static str,
+                                             records: &Mutex<Vec<T>>)
+                                             -> DnsFuture<'static, Vec<T>> {
+            *self.queries.lock().unwrap().entry(kind).or_insert(0) += 1;
+            if *self.fail.lock().unwrap() {
+                return Box::pin(future::ready(Err(DnsError::Lookup(String::from("timed out")))));
+            }
+            Box::pin(future::ready(Ok(records.lock().unwrap().clone())))
+        }
+    }
+
+    impl Resolve for FakeResolver {
+        fn lookup_srv<'a>(&'a self, name: &str) -> DnsFuture<'a, Vec<SrvRecord>> {
+            assert_eq!(name, "_cache._tcp.example.com");
+            self.answer("srv", &self.srv)
+        }
+
+        fn lookup_ip<'a>(&'a self, name: &str) -> DnsFuture<'a, Vec<IpAddr>> {
+            assert_eq!(name, "_cache._tcp.example.com");
+            self.answer("ip", &self.ips)
+        }
+    }
+
+    fn srv(target: &str, port: u16) -> SrvRecord {
+        SrvRecord {
+            priority: 10,
+            weight: 5,
+            port,
+            target: String::from(target),
+        }
+    }
+
+    /// Run the given future, which the fake resolver makes ready at once.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        match future.as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("the fake resolver is pending"),
+        }
+    }
+
+    #[test]
+    fn test_sync_from_dns() {
+        let resolver = FakeResolver::default();
+        let mut sync = DnsSync::new("_cache._tcp.example.com", 11211);
+        let mut consistant = Consistant::new(20);
+
+        *resolver.srv.lock().unwrap() = vec![srv("cache-0.cache.default.svc.cluster.local.", 6379),
+                                             srv("cache-1.cache.default.svc.cluster.local.", 6379),
+                                             srv("cache-2.example.com", 6380)];
+        let report = block_on(consistant.sync_from_dns(&mut sync, &resolver)).unwrap();
+        assert_eq!(report.added,
+                   ["cache-0.cache.default.svc.cluster.local:6379",
+                    "cache-1.cache.default.svc.cluster.local:6379",
+                    "cache-2.example.com:6380"]);
+        assert!(report.removed.is_empty());
+        assert_eq!(resolver.queries.lock().unwrap().get("ip"), None);
+
+        resolver.srv.lock().unwrap().remove(1);
+        resolver.srv.lock().unwrap().push(srv("cache-3.example.com", 6380));
+        let report = block_on(consistant.sync_from_dns(&mut sync, &resolver)).unwrap();
+        assert_eq!(report.added, ["cache-3.example.com:6380"]);
+        assert_eq!(report.removed, ["cache-1.cache.default.svc.cluster.local:6379"]);
+        assert!(block_on(consistant.sync_from_dns(&mut sync, &resolver)).unwrap().is_empty());
+
+        // Without SRV records, the addresses are of the port of the sync.
+        resolver.srv.lock().unwrap().clear();
+        *resolver.ips.lock().unwrap() = vec!["10.0.0.1".parse().unwrap(),
+                                             "fd00::1".parse().unwrap()];
+        let report = block_on(consistant.sync_from_dns(&mut sync, &resolver)).unwrap();
+        assert_eq!(report.added, ["10.0.0.1:11211", "[fd00::1]:11211"]);
+        assert_eq!(report.removed.len(), 3);
+        assert_eq!(consistant.members(), ["10.0.0.1:11211", "[fd00::1]:11211"]);
+
+        *resolver.fail.lock().unwrap() = true;
+        assert_eq!(block_on(consistant.sync_from_dns(&mut sync, &resolver)),
+                   Err(DnsError::Lookup(String::from("timed out"))));
+        assert_eq!(consistant.count(), 2);
+    }
+
+    #[test]
+    fn test_sync_from_dns_empty_answers() {
+        let resolver = FakeResolver::default();
+        let mut sync = DnsSync::new("_cache._tcp.example.com", 11211).empty_answers(3);
+        let mut consistant = Consistant::new(20);
+        *resolver.ips.lock().unwrap() = vec!["10.0.0.1".parse().unwrap()];
+        block_on(consistant.sync_from_dns(&mut sync, &resolver)).unwrap();
+
+        // A blip shorter than the debounce is ignored, and starts over.
+        resolver.ips.lock().unwrap().clear();
+        for streak in 1..3 {
+            assert_eq!(block_on(consistant.sync_from_dns(&mut sync, &resolver)),
+                       Err(DnsError::EmptyAnswer(streak)));
+            assert_eq!(consistant.members(), ["10.0.0.1:11211"]);
+        }
+        *resolver.ips.lock().unwrap() = vec!["10.0.0.1".parse().unwrap(),
+                                             "10.0.0.2".parse().unwrap()];
+        let report = block_on(consistant.sync_from_dns(&mut sync, &resolver)).unwrap();
+        assert_eq!(report.added, ["10.0.0.2:11211"]);
+        assert_eq!(sync.empty_streak(), 0);
+
+        resolver.ips.lock().unwrap().clear();
+        for streak in 1..3 {
+            assert_eq!(block_on(consistant.sync_from_dns(&mut sync, &resolver)),
+                       Err(DnsError::EmptyAnswer(streak)));
+        }
+        let report = block_on(consistant.sync_from_dns(&mut sync, &resolver)).unwrap();
+        assert_eq!(report.removed, ["10.0.0.1:11211", "10.0.0.2:11211"]);
+        assert_eq!((consistant.count(), sync.empty_streak()), (0, 3));
+
+        let mut eager = DnsSync::new("_cache._tcp.example.com", 80).empty_answers(0);
+        let mut consistant = Consistant::new(20);
+        consistant.add("10.0.0.1:80");
+        let report = block_on(consistant.sync_from_dns(&mut eager, &resolver)).unwrap();
+        assert_eq!(report.removed, ["10.0.0.1:80"]);
+    }
+}
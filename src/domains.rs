@@ -0,0 +1,404 @@
+use std::cmp::Reverse;
+use std::sync::Arc;
+use consistant::Consistant;
+use error::PlacementError;
+use selection::SelectionPolicy;
+
+/// SpreadPolicy tells `get_n_spread_by` how to spread the owners of a key
+/// over the failure domains of the elements, see `add_with_domains`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpreadPolicy {
+    levels: Vec<(String, usize)>,
+}
+
+impl SpreadPolicy {
+    /// Crete a new instance of SpreadPolicy, spreading nothing.
+    pub fn new() -> SpreadPolicy {
+        SpreadPolicy::default()
+    }
+
+    /// Spread the owners across the given count of domains of the given
+    /// label, after the labels given before, like
+    /// `SpreadPolicy::new().across("region", 2).across("zone", 3)`.
+    pub fn across<S: Into<String>>(mut self, label: S, count: usize) -> SpreadPolicy {
+        self.levels.push((label.into(), count));
+        self
+    }
+}
+
+/// PlacementSpec tells `get_n_placed` how many owners of a key to place in
+/// which zones, the domains of label `zone` of the elements, see
+/// `add_with_domains`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlacementSpec {
+    zones: Vec<(String, usize)>,
+    other_zones: usize,
+    best_effort: bool,
+}
+
+impl PlacementSpec {
+    /// Crete a new instance of PlacementSpec, placing nothing.
+    pub fn new() -> PlacementSpec {
+        PlacementSpec::default()
+    }
+
+    /// Place the given count of owners in the given zone, like
+    /// `PlacementSpec::new().zone("us-east-1a", 2).any_other_zone(1)`. A zone
+    /// given again takes the last count.
+    pub fn zone<S: Into<String>>(mut self, zone: S, count: usize) -> PlacementSpec {
+        let zone = zone.into();
+        self.zones.retain(|quota| quota.0 != zone);
+        self.zones.push((zone, count));
+        self
+    }
+
+    /// Place the given count of owners in the zones not given to `zone`,
+    /// which may be the same one.
+    pub fn any_other_zone(mut self, count: usize) -> PlacementSpec {
+        self.other_zones = count;
+        self
+    }
+
+    /// Fill the quotas which can not be met with the next elements on the
+    /// hash ring whatever their zones, rather than failing.
+    pub fn best_effort(mut self, best_effort: bool) -> PlacementSpec {
+        self.best_effort = best_effort;
+        self
+    }
+
+    /// Get the count of owners the spec places.
+    pub fn len(&self) -> usize {
+        self.zones.iter().map(|quota| quota.1).sum::<usize>() + self.other_zones
+    }
+
+    /// Check whether the spec places no owner.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Consistant {
+    /// Add an elment to the hash ring in the given failure domains, as
+    /// `(label, value)` from the widest, like
+    /// `&[("region", "eu"), ("zone", "eu-1a"), ("host", "h42")]`. The domains
+    /// are kept as the tags `label=value`, replacing the tags of an element
+    /// already in, so they are serialized along with them and
+    /// `get_with_tag("region=eu")` looks up a region only.
+    pub fn add_with_domains<S: Into<String>>(&mut self, element: S, domains: &[(&str, &str)]) {
+        let tags: Vec<String> =
+            domains.iter().map(|&(label, value)| format!("{}={}", label, value)).collect();
+        let tags: Vec<&str> = tags.iter().map(String::as_str).collect();
+        self.add_with_tags(element, &tags);
+    }
+
+    /// Get the failure domains of the given element, its tags of the form
+    /// `label=value`, sorted by label.
+    pub fn domains_of(&self, name: &str) -> Option<Vec<(String, String)>> {
+        self.tags_of(name).map(|tags| domains(&tags))
+    }
+
+    /// Get the N cloeset elements' names to the given "name", spread over
+    /// their failure domains by the given policy, as `select` does with
+    /// `SelectionPolicy::new().spread_by(policy)`.
+    ///
+    /// The first owner is the one of `get`. Every next one is the element
+    /// following it on the hash ring adding the most wanted domains, the
+    /// labels of the policy being compared in turn: a domain is wanted if
+    /// the owners so far are not in it and are in fewer domains of its label
+    /// than the policy asks for. The domain of a label is the one of every
+    /// label before it as well, so that the zones of two regions differ
+    /// whatever their names. An element without a label is in no domain of
+    /// it, and so never wanted for it.
+    ///
+    /// When a policy can not be met, as with all the elements of a single
+    /// region, the labels it can not spread are then ignored, the elements
+    /// coming in the order of the hash ring, so the owners are always the
+    /// same for the same hash ring. `get_n` and this return the same owners
+    /// for a policy spreading nothing.
+    pub fn get_n_spread_by<S: AsRef<str>>(&self,
+                                          name: S,
+                                          n: usize,
+                                          policy: &SpreadPolicy)
+                                          -> Option<Vec<String>> {
+        self.select(name, n, &SelectionPolicy::new().spread_by(policy.clone()))
+    }
+
+    /// Get the owners of the given "name" placed in zones by the given spec,
+    /// in the order of the hash ring: the elements are walked as `get_n`
+    /// does, each one filling the quota of its zone if it is not full yet.
+    /// Elements without a zone fill no quota. The owners are always the same
+    /// for the same hash ring.
+    ///
+    /// If a quota can not be met, the first one unmet in the order of the
+    /// spec, the zones before the others, is the error, unless the spec is
+    /// of best effort.
+    pub fn get_n_placed<S: AsRef<str>>(&self,
+                                       name: S,
+                                       spec: &PlacementSpec)
+                                       -> Result<Vec<String>, PlacementError> {
+        let _guard = self.read();
+        let mut placed = vec![0; spec.zones.len()];
+        let mut other_zones = 0;
+        let mut owners: Vec<Arc<str>> = Vec::with_capacity(spec.len());
+        let mut skipped: Vec<Arc<str>> = Vec::new();
+        self.walk_members(name.as_ref(), |element| {
+            let zone = self.tags_ref(element).iter().find_map(|tag| tag.strip_prefix("zone="));
+            let quota = zone.map(|zone| spec.zones.iter().position(|quota| quota.0 == zone));
+            match quota {
+                Some(Some(quota)) if placed[quota] < spec.zones[quota].1 => {
+                    placed[quota] += 1;
+                    owners.push(element.clone());
+                }
+                Some(None) if other_zones < spec.other_zones => {
+                    other_zones += 1;
+                    owners.push(element.clone());
+                }
+                _ => skipped.push(element.clone()),
+            }
+            owners.len() == spec.len()
+        });
+
+        if owners.len() < spec.len() && !spec.best_effort {
+            let unmet = spec.zones.iter().zip(&placed).find(|&(quota, placed)| *placed < quota.1);
+            return Err(match unmet {
+                Some((quota, placed)) => {
+                    PlacementError::Zone {
+                        zone: quota.0.clone(),
+                        wanted: quota.1,
+                        placed: *placed,
+                    }
+                }
+                None => {
+                    PlacementError::OtherZones {
+                        wanted: spec.other_zones,
+                        placed: other_zones,
+                    }
+                }
+            });
+        }
+        // Of best effort, the elements skipped fill the quotas unmet.
+        let missing = spec.len() - owners.len();
+        owners.extend(skipped.into_iter().take(missing));
+
+        Ok(owners.into_iter().map(|element| String::from(&*element)).collect())
+    }
+
+    /// Pick N of the given candidates, in the order of the hash ring, by the
+    /// given policy, as `get_n_spread_by` tells. The read lock must be held.
+    pub(crate) fn spread(&self,
+                         candidates: Vec<Arc<str>>,
+                         n: usize,
+                         policy: &SpreadPolicy)
+                         -> Vec<Arc<str>> {
+        let candidates: Vec<(Arc<str>, Vec<Option<String>>)> = candidates.into_iter()
+            .map(|element| {
+                let paths = self.domain_paths(&element, policy);
+                (element, paths)
+            })
+            .collect();
+
+        let count = n.min(candidates.len());
+        let mut owners: Vec<usize> = Vec::with_capacity(count);
+        let mut spread: Vec<Vec<&str>> = vec![Vec::new(); policy.levels.len()];
+        while owners.len() < count {
+            let best = (0..candidates.len())
+                .filter(|index| !owners.contains(index))
+                .take(if owners.is_empty() { 1 } else { candidates.len() })
+                .max_by_key(|&index| {
+                    let wanted: Vec<bool> = candidates[index].1
+                        .iter()
+                        .zip(&policy.levels)
+                        .zip(&spread)
+                        .map(|((path, level), domains)| {
+                            path.as_ref().is_some_and(|path| {
+                                domains.len() < level.1 && !domains.contains(&path.as_str())
+                            })
+                        })
+                        .collect();
+                    // The first of the most wanted in the order of the ring.
+                    (wanted, Reverse(index))
+                })
+                .unwrap();
+            for (path, domains) in candidates[best].1.iter().zip(spread.iter_mut()) {
+                if let Some(path) = path.as_ref() {
+                    if !domains.contains(&path.as_str()) {
+                        domains.push(path);
+                    }
+                }
+            }
+            owners.push(best);
+        }
+
+        owners.into_iter().map(|index| candidates[index].0.clone()).collect()
+    }
+
+    /// Get the domain of the given element for every label of the policy,
+    /// as the values of the labels up to it, `None` once a label is missing.
+    fn domain_paths(&self, element: &str, policy: &SpreadPolicy) -> Vec<Option<String>> {
+        let domains = domains(self.tags_ref(element));
+        let mut path = Some(String::new());
+        policy.levels
+            .iter()
+            .map(|(label, _)| {
+                let value = domains.iter().find(|domain| domain.0 == *label);
+                path = match (path.take(), value) {
+                    (Some(path), Some((_, value))) => Some(format!("{}/{}", path, value)),
+                    _ => None,
+                };
+                path.clone()
+            })
+            .collect()
+    }
+}
+
+/// Get the failure domains of the given tags, those of the form
+/// `label=value`.
+fn domains(tags: &[String]) -> Vec<(String, String)> {
+    tags.iter()
+        .filter_map(|tag| {
+            tag.find('=').map(|i| (String::from(&tag[..i]), String::from(&tag[i + 1..])))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn consistant() -> Consistant {
+        let mut consistant = Consistant::new(20);
+        for &(region, zones) in &[("eu", ["eu-1a", "eu-1b"]), ("us", ["us-1a", "us-1b"])] {
+            for zone in &zones {
+                for host in 0..3 {
+                    let host = format!("{}-h{}", zone, host);
+                    let domains = [("region", region), ("zone", *zone), ("host", &host)];
+                    consistant.add_with_domains(format!("cache-{}", host), &domains);
+                }
+            }
+        }
+        consistant
+    }
+
+    fn region(consistant: &Consistant, element: &str) -> String {
+        consistant.domains_of(element)
+            .unwrap()
+            .into_iter()
+            .find(|domain| domain.0 == "region")
+            .unwrap()
+            .1
+    }
+
+    #[test]
+    fn test_add_with_domains() {
+        let consistant = consistant();
+        assert_eq!(consistant.count(), 12);
+        assert_eq!(consistant.domains_of("cache-us-1b-h2").unwrap(),
+                   [(String::from("host"), String::from("us-1b-h2")),
+                    (String::from("region"), String::from("us")),
+                    (String::from("zone"), String::from("us-1b"))]);
+        assert_eq!(region(&consistant, &consistant.get_with_tag("david", "region=eu").unwrap()),
+                   "eu");
+        assert_eq!(consistant.domains_of("cache-x"), None);
+    }
+
+    #[test]
+    fn test_get_n_spread_by() {
+        let mut consistant = consistant();
+        let policy = SpreadPolicy::new().across("region", 2).across("zone", 3);
+        assert_eq!(consistant.get_n_spread_by("david", 0, &policy), None);
+        assert_eq!(Consistant::default().get_n_spread_by("david", 3, &policy), None);
+
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            let owners = consistant.get_n_spread_by(&key, 3, &policy).unwrap();
+            assert_eq!(owners.len(), 3);
+            assert_eq!(owners[0], consistant.get(&key).unwrap());
+            let mut regions: Vec<String> =
+                owners.iter().map(|owner| region(&consistant, owner)).collect();
+            regions.sort();
+            regions.dedup();
+            assert_eq!(regions.len(), 2, "{:?}", owners);
+            let mut zones: Vec<&str> = owners.iter().map(|owner| &owner[6..11]).collect();
+            zones.sort();
+            zones.dedup();
+            assert_eq!(zones.len(), 3, "{:?}", owners);
+
+            assert_eq!(consistant.get_n_spread_by(&key, 3, &SpreadPolicy::new()),
+                       consistant.get_n(&key, 3));
+        }
+
+        // A single member left in a region still takes one replica of all.
+        consistant.retain(|element| element.starts_with("cache-eu") || element == "cache-us-1a-h0");
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            let owners = consistant.get_n_spread_by(&key, 2, &policy).unwrap();
+            assert!(owners.contains(&String::from("cache-us-1a-h0")), "{:?}", owners);
+        }
+
+        // Without the other region, the zones are still spread.
+        consistant.remove("cache-us-1a-h0");
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            let owners = consistant.get_n_spread_by(&key, 3, &policy).unwrap();
+            assert!(owners.iter().all(|owner| owner.starts_with("cache-eu")));
+            assert_ne!(owners[0][6..11], owners[1][6..11]);
+            assert_eq!(consistant.get_n_spread_by(&key, 3, &policy).unwrap(), owners);
+        }
+    }
+
+    #[test]
+    fn test_get_n_placed() {
+        let mut consistant = consistant();
+        let spec = PlacementSpec::new().zone("eu-1a", 2).any_other_zone(1);
+        assert_eq!(spec.len(), 3);
+        assert!(PlacementSpec::new().zone("eu-1a", 0).is_empty());
+        assert_eq!(consistant.get_n_placed("david", &PlacementSpec::new()), Ok(vec![]));
+
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            let owners = consistant.get_n_placed(&key, &spec).unwrap();
+            let zones: Vec<&str> = owners.iter().map(|owner| &owner[6..11]).collect();
+            assert_eq!(zones.iter().filter(|&&zone| zone == "eu-1a").count(), 2, "{:?}", owners);
+            assert_eq!(zones.len(), 3);
+            // In the order of the hash ring.
+            let all = consistant.get_n(&key, 12).unwrap();
+            let positions: Vec<usize> =
+                owners.iter().map(|owner| all.iter().position(|e| e == owner).unwrap()).collect();
+            assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+            assert_eq!(consistant.get_n_placed(&key, &spec).unwrap(), owners);
+        }
+
+        // An element added without a zone fills no quota.
+        let before: Vec<Vec<String>> = (0..100)
+            .map(|i| consistant.get_n_placed(format!("key{}", i), &spec).unwrap())
+            .collect();
+        consistant.add("cache-unzoned");
+        for (i, owners) in before.iter().enumerate() {
+            let after = consistant.get_n_placed(format!("key{}", i), &spec).unwrap();
+            assert_eq!(after[..], owners[..], "{}", i);
+        }
+
+        let spec = PlacementSpec::new().zone("us-1a", 4).zone("eu-1b", 1);
+        assert_eq!(consistant.get_n_placed("david", &spec),
+                   Err(PlacementError::Zone {
+                       zone: String::from("us-1a"),
+                       wanted: 4,
+                       placed: 3,
+                   }));
+        let spec = PlacementSpec::new().zone("us-1a", 1).zone("mars-1a", 1);
+        assert_eq!(consistant.get_n_placed("david", &spec).unwrap_err().to_string(),
+                   "zone \"mars-1a\": wanted 1, placed 0");
+        let spec = PlacementSpec::new().zone("us-1a", 3).any_other_zone(10);
+        assert_eq!(consistant.get_n_placed("david", &spec),
+                   Err(PlacementError::OtherZones { wanted: 10, placed: 9 }));
+
+        // Of best effort, the next elements fill the quotas unmet.
+        let owners = consistant.get_n_placed("david", &spec.best_effort(true)).unwrap();
+        assert_eq!(owners.len(), 13);
+        assert!(owners.contains(&String::from("cache-unzoned")));
+        let spec = PlacementSpec::new().zone("us-1a", 4).best_effort(true);
+        let owners = consistant.get_n_placed("david", &spec).unwrap();
+        assert_eq!(owners.iter().filter(|owner| owner.starts_with("cache-us-1a")).count(), 3);
+        assert_eq!(owners.len(), 4);
+    }
+}
@@ -0,0 +1,207 @@
+//! A reproduction of the ring of Envoy's `ring_hash` load balancer, for
+//! clusters of `lb_policy: RING_HASH` with the default `hash_function:
+//! XX_HASH`.
+//!
+//! The ring is built and searched as by `RingHashLoadBalancer` of Envoy v1.28
+//! (`source/extensions/load_balancing_policies/ring_hash/ring_hash_lb.cc`):
+//!
+//! - the weights are normalized to sum to 1, and scaled so the least weighted
+//!   host gets `ceil(weight * minimum_ring_size)` entries, the ring size being
+//!   capped at `maximum_ring_size`;
+//! - the entries are dealt out in the order of the hosts from running sums of
+//!   the scaled weights, so a host may get one more or one less than its share;
+//! - the i-th entry of a host hashes `"{address}_{i}"` with XXH64 (seed 0);
+//! - a key hash belongs to the first entry of a hash not lower than it, found
+//!   by the ketama search Envoy ported from libketama, wrapping around.
+//!
+//! Envoy sorts the ring with `std::sort`, which leaves the order of entries of
+//! the same hash unspecified; here the host listed first wins. The hosts are
+//! of a single priority and locality, and `MURMUR_HASH_2` is not supported.
+
+use std::hash::Hasher;
+use twox_hash::XxHash64;
+use error::EnvoyError;
+
+/// The default `minimum_ring_size` of Envoy.
+pub const DEFAULT_MINIMUM_RING_SIZE: u64 = 1024;
+/// The default `maximum_ring_size` of Envoy, also the largest one allowed.
+pub const DEFAULT_MAXIMUM_RING_SIZE: u64 = 8 * 1024 * 1024;
+
+/// EnvoyRingHash maps key hashes to the hosts of a cluster as Envoy does.
+#[derive(Debug, Clone)]
+pub struct EnvoyRingHash {
+    hosts: Vec<String>,
+    // (hash, host index), sorted by hash.
+    ring: Vec<(u64, usize)>,
+}
+
+impl EnvoyRingHash {
+    /// Crete a new instance of EnvoyRingHash of the given hosts, addressed as
+    /// by Envoy, like `10.0.0.1:80` or `[::1]:80`, with their weights.
+    pub fn new<S: AsRef<str>>(hosts: &[(S, u32)],
+                              minimum_ring_size: u64,
+                              maximum_ring_size: u64)
+                              -> Result<Self, EnvoyError> {
+        if minimum_ring_size > maximum_ring_size || maximum_ring_size > DEFAULT_MAXIMUM_RING_SIZE {
+            return Err(EnvoyError::InvalidRingSize {
+                minimum: minimum_ring_size,
+                maximum: maximum_ring_size,
+            });
+        }
+        if let Some((host, _)) = hosts.iter().find(|&&(_, weight)| weight == 0) {
+            return Err(EnvoyError::ZeroWeight(String::from(host.as_ref())));
+        }
+
+        let sum: u32 = hosts.iter().map(|&(_, weight)| weight).sum();
+        let weights: Vec<f64> = hosts.iter()
+            .map(|&(_, weight)| weight as f64 * 1.0 / sum as f64)
+            .collect();
+        let min_weight = weights.iter().cloned().fold(1.0, f64::min);
+        let scale = ((min_weight * minimum_ring_size as f64).ceil() / min_weight)
+            .min(maximum_ring_size as f64);
+
+        let mut ring = Vec::with_capacity(if hosts.is_empty() { 0 } else { scale.ceil() as usize });
+        let (mut current_hashes, mut target_hashes) = (0.0, 0.0);
+        for (index, ((host, _), weight)) in hosts.iter().zip(weights).enumerate() {
+            target_hashes += scale * weight;
+            let mut i = 0u64;
+            while current_hashes < target_hashes {
+                ring.push((hash(format!("{}_{}", host.as_ref(), i)), index));
+                i += 1;
+                current_hashes += 1.0;
+            }
+        }
+        ring.sort_by_key(|&(hash, _)| hash);
+
+        Ok(EnvoyRingHash {
+            hosts: hosts.iter().map(|(host, _)| String::from(host.as_ref())).collect(),
+            ring,
+        })
+    }
+
+    /// Get the hosts, in the order they were given.
+    pub fn hosts(&self) -> &[String] {
+        &self.hosts
+    }
+
+    /// Get the count of entries of the ring.
+    pub fn size(&self) -> usize {
+        self.ring.len()
+    }
+
+    /// Get the host of the given key, hashed as by the hash policies of
+    /// Envoy.
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Option<&str> {
+        self.choose_host(hash(key), 0)
+    }
+
+    /// Get the host of the given key hash as Envoy's `chooseHost`, the given
+    /// attempt picking the entries following the one of the hash.
+    pub fn choose_host(&self, hash: u64, attempt: u32) -> Option<&str> {
+        if self.ring.is_empty() {
+            return None;
+        }
+        // The search relies on signed bounds, as Envoy's.
+        let len = self.ring.len() as i64;
+        let (mut lowp, mut highp) = (0i64, len);
+        let midp = loop {
+            let midp = (lowp + highp) / 2;
+            if midp == len {
+                break 0;
+            }
+            let midval = self.ring[midp as usize].0;
+            let midval1 = if midp == 0 { 0 } else { self.ring[midp as usize - 1].0 };
+            if hash <= midval && hash > midval1 {
+                break midp;
+            }
+            if midval < hash {
+                lowp = midp + 1;
+            } else {
+                highp = midp - 1;
+            }
+            if lowp > highp {
+                break 0;
+            }
+        };
+        let entry = self.ring[((midp + attempt as i64) % len) as usize];
+        Some(self.hosts[entry.1].as_str())
+    }
+}
+
+/// Hash the given key as Envoy's `HashUtil::xxHash64`.
+pub fn hash<K: AsRef<[u8]>>(key: K) -> u64 {
+    let mut hasher = XxHash64::with_seed(0);
+    hasher.write(key.as_ref());
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_ring_size() {
+        assert_eq!(hash(""), 0xef46db3751d8e999);
+
+        let hosts = [("10.0.0.1:80", 1), ("10.0.0.2:80", 1)];
+        let ring = EnvoyRingHash::new(&hosts, DEFAULT_MINIMUM_RING_SIZE, DEFAULT_MAXIMUM_RING_SIZE)
+            .unwrap();
+        assert_eq!(ring.size(), 1024);
+        assert_eq!(EnvoyRingHash::new(&hosts, 10, 20).unwrap().size(), 10);
+        assert_eq!(EnvoyRingHash::new(&hosts, 0, 0).unwrap().get("david"), None);
+
+        assert_eq!(EnvoyRingHash::new(&hosts, 20, 10).unwrap_err(),
+                   EnvoyError::InvalidRingSize { minimum: 20, maximum: 10 });
+        assert!(EnvoyRingHash::new(&hosts, 10, DEFAULT_MAXIMUM_RING_SIZE + 1).is_err());
+        assert_eq!(EnvoyRingHash::new(&[("10.0.0.1:80", 0)], 10, 20).unwrap_err(),
+                   EnvoyError::ZeroWeight(String::from("10.0.0.1:80")));
+        let empty = EnvoyRingHash::new::<&str>(&[], 10, 20).unwrap();
+        assert_eq!(empty.get("david"), None);
+    }
+
+    #[test]
+    fn test_choose_host_attempts() {
+        let hosts = [("10.0.0.1:80", 1), ("10.0.0.2:80", 1), ("10.0.0.3:80", 1)];
+        let ring = EnvoyRingHash::new(&hosts, 3, 3).unwrap();
+        let owners: Vec<_> = (0..4).map(|attempt| ring.choose_host(0, attempt).unwrap()).collect();
+        // A hash of 0 belongs to the first entry, as do those past the last.
+        assert_eq!(ring.choose_host(u64::MAX, 0), Some(owners[0]));
+        assert_eq!(owners[0], owners[3]);
+        let mut distinct = owners[..3].to_vec();
+        distinct.sort();
+        assert_eq!(distinct, ["10.0.0.1:80", "10.0.0.2:80", "10.0.0.3:80"]);
+    }
+
+    #[test]
+    fn test_envoy_vectors() {
+        let fixture = include_str!("../tests/fixtures/envoy-ring_hash.tsv");
+        let mut hosts: BTreeMap<&str, Vec<(&str, u32)>> = BTreeMap::new();
+        let mut rings = BTreeMap::new();
+        let mut checked = 0;
+
+        for line in fixture.lines().filter(|line| !line.starts_with('#')) {
+            let fields: Vec<&str> = line.split('\t').collect();
+            match fields[0] {
+                "host" => {
+                    let weight = fields[3].parse().unwrap();
+                    hosts.entry(fields[1]).or_default().push((fields[2], weight));
+                }
+                "size" => {
+                    let ring = EnvoyRingHash::new(&hosts[fields[1]],
+                                                  fields[2].parse().unwrap(),
+                                                  fields[3].parse().unwrap())
+                        .unwrap();
+                    assert_eq!(ring.size().to_string(), fields[4], "{}", line);
+                    rings.insert(fields[1], ring);
+                }
+                _ => {
+                    assert_eq!(rings[fields[1]].get(fields[2]), Some(fields[3]), "{}", line);
+                    checked += 1;
+                }
+            }
+        }
+        assert_eq!(rings.len(), 4);
+        assert_eq!(checked, 336);
+    }
+}
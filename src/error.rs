@@ -0,0 +1,693 @@
+use std::error;
+use std::fmt;
+use std::io;
+use consistant::Consistant;
+use limits::MAX_LOADED_VNODES;
+
+/// ReplicationError is returned when the hash ring can not place the
+/// requested number of replicas.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplicationError {
+    /// There are fewer distinct elements in the ring than wanted.
+    NotEnoughMembers { wanted: usize, available: usize },
+}
+
+impl fmt::Display for ReplicationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReplicationError::NotEnoughMembers { wanted, available } => {
+                write!(f,
+                       "not enough members: wanted {}, available {}",
+                       wanted,
+                       available)
+            }
+        }
+    }
+}
+
+impl error::Error for ReplicationError {}
+
+/// PlacementError is returned when the owners of a key can not be placed as
+/// a `PlacementSpec` tells, see `Consistant::get_n_placed`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlacementError {
+    /// There are not as many elements in the zone as wanted.
+    Zone { zone: String, wanted: usize, placed: usize },
+    /// There are not as many elements in the other zones as wanted.
+    OtherZones { wanted: usize, placed: usize },
+}
+
+impl fmt::Display for PlacementError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PlacementError::Zone { ref zone, wanted, placed } => {
+                write!(f, "zone {:?}: wanted {}, placed {}", zone, wanted, placed)
+            }
+            PlacementError::OtherZones { wanted, placed } => {
+                write!(f, "other zones: wanted {}, placed {}", wanted, placed)
+            }
+        }
+    }
+}
+
+impl error::Error for PlacementError {}
+
+/// Error is returned when an operation on the hash ring is given invalid
+/// arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The bucket count must be between 1 and 2^32.
+    InvalidBucketCount(usize),
+    /// The capacity must not be 0, nor lower than the initial buckets.
+    InvalidCapacity { capacity: u32, buckets: u32 },
+    /// The element is not in the hash ring.
+    UnknownMember(String),
+    /// The element is already in the hash ring.
+    DuplicateMember(String),
+    /// The element is the last one of a hash ring which must not be empty.
+    LastMember(String),
+    /// The weight of the element is not a positive finite number.
+    InvalidWeight(String),
+    /// The element has explicit virtual nodes, see `replace_node`, so its
+    /// weight can not change.
+    ExplicitTokens(String),
+    /// The range of checksums overlaps the given one, already assigned, see
+    /// `Consistant::assign_range`.
+    OverlappingRange { start: u32, end: u32 },
+    /// The change would take the hash ring over one of its limits, see
+    /// `Consistant::set_limits`.
+    LimitExceeded(LimitExceeded),
+    /// There must be more elements than the failed ones, see
+    /// `Consistant::simulate_failures`.
+    InvalidFailureCount { failures: usize, members: usize },
+    /// No count of virtual nodes balances the given count of elements as
+    /// asked, see `recommend_replicas`.
+    InvalidBalanceTarget { members: usize },
+    /// The element is still in the given count of hash rings, see
+    /// `MemberRegistry::unregister`.
+    MemberInUse { name: String, rings: usize },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidBucketCount(buckets) => {
+                write!(f, "invalid bucket count: {}", buckets)
+            }
+            Error::InvalidCapacity { capacity, buckets } => {
+                write!(f, "invalid capacity {} for {} buckets", capacity, buckets)
+            }
+            Error::UnknownMember(ref name) => write!(f, "unknown member {:?}", name),
+            Error::DuplicateMember(ref name) => write!(f, "duplicate member {:?}", name),
+            Error::LastMember(ref name) => write!(f, "can not remove the last member {:?}", name),
+            Error::InvalidWeight(ref name) => write!(f, "invalid weight for member {:?}", name),
+            Error::ExplicitTokens(ref name) => {
+                write!(f, "member {:?} has explicit virtual nodes", name)
+            }
+            Error::OverlappingRange { start, end } => {
+                write!(f, "range overlaps the assigned range ({}, {}]", start, end)
+            }
+            Error::LimitExceeded(ref err) => err.fmt(f),
+            Error::InvalidFailureCount { failures, members } => {
+                write!(f, "can not fail {} of {} members", failures, members)
+            }
+            Error::InvalidBalanceTarget { members } => {
+                write!(f, "invalid balance target for {} members", members)
+            }
+            Error::MemberInUse { ref name, rings } => {
+                write!(f, "member {:?} is in {} hash rings", name, rings)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// LimitExceeded is returned when adding elements, or raising a weight,
+/// would take the hash ring over one of its limits, see
+/// `Consistant::set_limits`. Nothing changes then.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitExceeded {
+    /// The hash ring would hold `requested` elements, over `limit`.
+    Members { limit: usize, requested: usize },
+    /// The hash ring would hold `requested` virtual nodes, over `limit`.
+    Vnodes { limit: usize, requested: usize },
+}
+
+impl LimitExceeded {
+    /// Get how far over its limit the change would take the hash ring.
+    pub fn excess(&self) -> usize {
+        match *self {
+            LimitExceeded::Members { limit, requested } |
+            LimitExceeded::Vnodes { limit, requested } => requested - limit,
+        }
+    }
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (what, limit, requested) = match *self {
+            LimitExceeded::Members { limit, requested } => ("members", limit, requested),
+            LimitExceeded::Vnodes { limit, requested } => ("virtual nodes", limit, requested),
+        };
+        write!(f,
+               "{} {} requested, {} over the limit of {}",
+               requested,
+               what,
+               requested - limit,
+               limit)
+    }
+}
+
+impl error::Error for LimitExceeded {}
+
+/// EmptyRing is returned when an empty hash ring is converted into a
+/// `NonEmptyRing`, giving the hash ring back.
+#[derive(Debug)]
+pub struct EmptyRing(pub Box<Consistant>);
+
+impl fmt::Display for EmptyRing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the hash ring is empty")
+    }
+}
+
+impl error::Error for EmptyRing {}
+
+/// VersionEvicted is returned by `Consistant::get_at_version` for a version
+/// which is not available, evicted from the history or never reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionEvicted {
+    pub version: u64,
+}
+
+impl fmt::Display for VersionEvicted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "version {} of the hash ring is not kept", self.version)
+    }
+}
+
+impl error::Error for VersionEvicted {}
+
+/// DisruptionExceeded is returned when a checked mutation would move more of
+/// the keyspace than allowed, see `Consistant::set_max_disruption`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisruptionExceeded {
+    /// The share of the keyspace the mutation would move.
+    pub would_move: f64,
+    /// The share of the keyspace allowed to move.
+    pub budget: f64,
+}
+
+impl fmt::Display for DisruptionExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "mutation would move {:.4} of the keyspace, over the budget of {:.4}",
+               self.would_move,
+               self.budget)
+    }
+}
+
+impl error::Error for DisruptionExceeded {}
+
+/// JsonError is returned when a JSON document can not be loaded as a hash
+/// ring.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonError {
+    /// The JSON path of the offending value, like `$.members[2].weight`.
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+impl error::Error for JsonError {}
+
+/// StateError is returned when a `RingState` can not be built into a hash
+/// ring.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    /// The state is of an unsupported format version.
+    UnsupportedVersion(u32),
+    UnknownHasher(u8),
+    UnknownPlacement(u8),
+    DuplicateMember(String),
+    ZeroWeight(String),
+    /// The member has not as many tokens as virtual nodes.
+    InvalidTokens(String),
+    /// The member has a fraction not below one million, or along with a
+    /// weight other than 1, see `Consistant::set_fractional_weight`.
+    InvalidFraction(String),
+    /// The range assignment overlaps another one, see
+    /// `Consistant::assign_range`.
+    OverlappingRange { start: u32, end: u32 },
+    /// The member takes the hash ring past `MAX_LOADED_VNODES` virtual nodes.
+    TooManyVnodes(String),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "unsupported state format version {}", version)
+            }
+            StateError::UnknownHasher(id) => write!(f, "unknown hasher id {}", id),
+            StateError::UnknownPlacement(id) => write!(f, "unknown placement id {}", id),
+            StateError::DuplicateMember(ref name) => write!(f, "duplicate member {:?}", name),
+            StateError::ZeroWeight(ref name) => write!(f, "member {:?} has a zero weight", name),
+            StateError::InvalidTokens(ref name) => {
+                write!(f, "member {:?} has an invalid token count", name)
+            }
+            StateError::InvalidFraction(ref name) => {
+                write!(f, "member {:?} has an invalid fraction", name)
+            }
+            StateError::OverlappingRange { start, end } => {
+                write!(f, "range ({}, {}] overlaps another assigned range", start, end)
+            }
+            StateError::TooManyVnodes(ref name) => too_many_vnodes(f, name),
+        }
+    }
+}
+
+impl error::Error for StateError {}
+
+/// ConfigError is returned when a configuration document can not be loaded
+/// as a hash ring.
+#[cfg(feature = "config")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The document is not valid TOML or YAML, or does not follow the schema.
+    Parse(String),
+    InvalidReplicas(i64),
+    DuplicateMember(String),
+    InvalidWeight { member: String, weight: i64 },
+    /// The member has not as many tokens as virtual nodes.
+    InvalidTokens { member: String, count: usize },
+}
+
+#[cfg(feature = "config")]
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Parse(ref message) => write!(f, "invalid configuration: {}", message),
+            ConfigError::InvalidReplicas(replicas) => {
+                write!(f, "invalid replicas {}", replicas)
+            }
+            ConfigError::DuplicateMember(ref name) => write!(f, "duplicate member {:?}", name),
+            ConfigError::InvalidWeight { ref member, weight } => {
+                write!(f, "member {:?} has an invalid weight {}", member, weight)
+            }
+            ConfigError::InvalidTokens { ref member, count } => {
+                write!(f, "member {:?} has {} tokens", member, count)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "config")]
+impl error::Error for ConfigError {}
+
+/// TwemproxyError is returned when a twemproxy server list can not be built
+/// into a continuum.
+#[cfg(feature = "twemproxy-compat")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TwemproxyError {
+    /// The server is not given as `host:port:weight [name]`.
+    InvalidServer(String),
+    /// The weights of the servers sum to 0.
+    ZeroWeight,
+}
+
+#[cfg(feature = "twemproxy-compat")]
+impl fmt::Display for TwemproxyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TwemproxyError::InvalidServer(ref server) => write!(f, "invalid server {:?}", server),
+            TwemproxyError::ZeroWeight => write!(f, "the servers have no weight"),
+        }
+    }
+}
+
+#[cfg(feature = "twemproxy-compat")]
+impl error::Error for TwemproxyError {}
+
+/// EnvoyError is returned when hosts can not be built into an Envoy ring, for
+/// a configuration Envoy would reject.
+#[cfg(feature = "envoy-compat")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvoyError {
+    /// The minimum ring size is over the maximum one, or the maximum one is
+    /// over 8M.
+    InvalidRingSize { minimum: u64, maximum: u64 },
+    /// The host has a weight of 0.
+    ZeroWeight(String),
+}
+
+#[cfg(feature = "envoy-compat")]
+impl fmt::Display for EnvoyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EnvoyError::InvalidRingSize { minimum, maximum } => {
+                write!(f, "invalid ring size, minimum {} and maximum {}", minimum, maximum)
+            }
+            EnvoyError::ZeroWeight(ref host) => write!(f, "host {:?} has a weight of 0", host),
+        }
+    }
+}
+
+#[cfg(feature = "envoy-compat")]
+impl error::Error for EnvoyError {}
+
+/// ArchiveError is returned when bytes can not be accessed as an archived
+/// hash ring.
+#[cfg(feature = "rkyv")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArchiveError {
+    /// The bytes are not a valid archive, or are not aligned.
+    Invalid(String),
+    /// The archive was written by an unsupported format version.
+    UnsupportedVersion(u32),
+    UnknownHasher(u8),
+}
+
+#[cfg(feature = "rkyv")]
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ArchiveError::Invalid(ref message) => write!(f, "invalid archive: {}", message),
+            ArchiveError::UnsupportedVersion(version) => {
+                write!(f, "unsupported archive format version {}", version)
+            }
+            ArchiveError::UnknownHasher(id) => write!(f, "unknown hasher id {}", id),
+        }
+    }
+}
+
+#[cfg(feature = "rkyv")]
+impl error::Error for ArchiveError {}
+
+/// ProtoError is returned when a protobuf message can not be loaded as a
+/// hash ring.
+#[cfg(feature = "proto")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProtoError {
+    /// The message was written by an unsupported format version, or has none.
+    UnsupportedVersion(u32),
+    UnknownHasher(u32),
+    UnknownPlacement(u32),
+    InvalidReplicas(u32),
+    DuplicateMember(String),
+    ZeroWeight(String),
+    /// The tokens of the member are not the ones derived for it, or not as
+    /// many as its virtual nodes.
+    TokenMismatch(String),
+    /// The member takes the hash ring past `MAX_LOADED_VNODES` virtual nodes.
+    TooManyVnodes(String),
+}
+
+#[cfg(feature = "proto")]
+impl fmt::Display for ProtoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProtoError::UnsupportedVersion(version) => {
+                write!(f, "unsupported proto format version {}", version)
+            }
+            ProtoError::UnknownHasher(id) => write!(f, "unknown hasher id {}", id),
+            ProtoError::UnknownPlacement(id) => write!(f, "unknown placement id {}", id),
+            ProtoError::InvalidReplicas(replicas) => write!(f, "invalid replicas {}", replicas),
+            ProtoError::DuplicateMember(ref name) => write!(f, "duplicate member {:?}", name),
+            ProtoError::ZeroWeight(ref name) => write!(f, "member {:?} has a zero weight", name),
+            ProtoError::TokenMismatch(ref name) => {
+                write!(f, "member {:?} has invalid tokens", name)
+            }
+            ProtoError::TooManyVnodes(ref name) => too_many_vnodes(f, name),
+        }
+    }
+}
+
+#[cfg(feature = "proto")]
+impl error::Error for ProtoError {}
+
+/// SnapshotError is returned when a binary snapshot can not be loaded as a
+/// hash ring.
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    /// The data does not start with the snapshot magic bytes.
+    BadMagic,
+    /// The snapshot was written by an unsupported format version.
+    UnsupportedVersion(u8),
+    UnknownHasher(u8),
+    UnknownVnodeNaming(u8),
+    /// The snapshot ends in the middle of a record.
+    Truncated,
+    InvalidName,
+    DuplicateMember(String),
+    ZeroWeight(String),
+    /// The count of tokens of the member is not the one of its virtual nodes.
+    InvalidTokens(String),
+    /// A tag of the member is not valid UTF-8.
+    InvalidTag(String),
+    /// The prefix or the element of a prefix rule is not valid UTF-8.
+    InvalidRule,
+    /// The element of a range assignment is not valid UTF-8, or the range
+    /// overlaps another one.
+    InvalidRange,
+    /// The element of a fractional weight is not valid UTF-8 or not in the
+    /// hash ring with a weight of 1, or its fraction is not below one
+    /// million.
+    InvalidFraction,
+    /// The id or the representative key of a colocation group is not valid
+    /// UTF-8.
+    InvalidColocationGroup,
+    /// The member takes the hash ring past `MAX_LOADED_VNODES` virtual nodes.
+    TooManyVnodes(String),
+    /// The snapshot is of a keyed hash ring, loaded by
+    /// `Consistant::load_keyed` only.
+    MissingKey,
+    /// A secret is given for the snapshot of a hash ring which is not keyed.
+    UnexpectedKey,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SnapshotError::Io(ref err) => write!(f, "io error: {}", err),
+            SnapshotError::BadMagic => write!(f, "not a hash ring snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {}", version)
+            }
+            SnapshotError::UnknownHasher(id) => write!(f, "unknown hasher id {}", id),
+            SnapshotError::UnknownVnodeNaming(id) => {
+                write!(f, "unknown vnode naming scheme {}", id)
+            }
+            SnapshotError::Truncated => write!(f, "truncated snapshot"),
+            SnapshotError::InvalidName => write!(f, "member name is not valid UTF-8"),
+            SnapshotError::DuplicateMember(ref name) => write!(f, "duplicate member {:?}", name),
+            SnapshotError::ZeroWeight(ref name) => write!(f, "member {:?} has a zero weight", name),
+            SnapshotError::InvalidTokens(ref name) => {
+                write!(f, "member {:?} has an invalid token count", name)
+            }
+            SnapshotError::InvalidTag(ref name) => {
+                write!(f, "member {:?} has a tag which is not valid UTF-8", name)
+            }
+            SnapshotError::InvalidRule => write!(f, "prefix rule is not valid UTF-8"),
+            SnapshotError::InvalidRange => write!(f, "invalid range assignment"),
+            SnapshotError::InvalidFraction => write!(f, "invalid fractional weight"),
+            SnapshotError::InvalidColocationGroup => {
+                write!(f, "colocation group is not valid UTF-8")
+            }
+            SnapshotError::TooManyVnodes(ref name) => too_many_vnodes(f, name),
+            SnapshotError::MissingKey => write!(f, "snapshot of a keyed hash ring"),
+            SnapshotError::UnexpectedKey => write!(f, "snapshot of a hash ring which is not keyed"),
+        }
+    }
+}
+
+impl error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            SnapshotError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Write the message of a member taking a loaded hash ring past
+/// `MAX_LOADED_VNODES` virtual nodes.
+fn too_many_vnodes(f: &mut fmt::Formatter, name: &str) -> fmt::Result {
+    write!(f, "member {:?} takes the hash ring past {} virtual nodes", name, MAX_LOADED_VNODES)
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(err: io::Error) -> SnapshotError {
+        if err.kind() == io::ErrorKind::UnexpectedEof {
+            SnapshotError::Truncated
+        } else {
+            SnapshotError::Io(err)
+        }
+    }
+}
+
+/// BalanceError is returned by the requests of `ConsistentHashBalance`.
+#[cfg(feature = "tower")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BalanceError<E> {
+    /// No backend is ready, or there is none.
+    NoReadyBackend,
+    /// The backend failed the request.
+    Service(E),
+}
+
+#[cfg(feature = "tower")]
+impl<E: fmt::Display> fmt::Display for BalanceError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BalanceError::NoReadyBackend => write!(f, "no ready backend"),
+            BalanceError::Service(ref err) => err.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "tower")]
+impl<E: fmt::Debug + fmt::Display> error::Error for BalanceError<E> {}
+
+/// CapacityFull is returned when adding an element to a `StaticConsistant`
+/// of as many elements as it can hold.
+#[cfg(feature = "heapless")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapacityFull;
+
+#[cfg(feature = "heapless")]
+impl fmt::Display for CapacityFull {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "hash ring is full")
+    }
+}
+
+#[cfg(feature = "heapless")]
+impl error::Error for CapacityFull {}
+
+/// DnsError is returned when the members of a hash ring can not be
+/// resolved, see `Consistant::sync_from_dns`.
+#[cfg(feature = "dns")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DnsError {
+    /// The lookup failed, with the message of the resolver.
+    Lookup(String),
+    /// The name has no records, which is ignored until answered the given
+    /// count of times in a row.
+    EmptyAnswer(usize),
+}
+
+#[cfg(feature = "dns")]
+impl fmt::Display for DnsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DnsError::Lookup(ref message) => write!(f, "dns lookup failed: {}", message),
+            DnsError::EmptyAnswer(streak) => {
+                write!(f, "ignored empty dns answer, {} in a row", streak)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "dns")]
+impl error::Error for DnsError {}
+
+/// GlobalError is returned when the hash ring of the process is used before
+/// it is installed, or installed twice, see `global::install`.
+#[cfg(feature = "global")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlobalError {
+    NotInstalled,
+    AlreadyInstalled,
+}
+
+#[cfg(feature = "global")]
+impl fmt::Display for GlobalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GlobalError::NotInstalled => write!(f, "no hash ring is installed"),
+            GlobalError::AlreadyInstalled => write!(f, "a hash ring is already installed"),
+        }
+    }
+}
+
+#[cfg(feature = "global")]
+impl error::Error for GlobalError {}
+
+/// JournalError is returned when a journal can not be opened, or a change
+/// can not be journaled, see `JournaledRing`.
+#[cfg(feature = "journal")]
+#[derive(Debug)]
+pub enum JournalError {
+    Io(io::Error),
+    /// The snapshot of the last checkpoint can not be loaded.
+    Snapshot(SnapshotError),
+    /// The file does not start with the journal magic bytes.
+    BadMagic,
+    /// The journal was written by an unsupported format version.
+    UnsupportedVersion(u8),
+    /// The journal is of a later checkpoint than the snapshot next to it.
+    EpochMismatch { journal: u64, snapshot: u64 },
+    /// The change is not valid for the hash ring, and so not journaled.
+    Ring(Error),
+}
+
+#[cfg(feature = "journal")]
+impl fmt::Display for JournalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            JournalError::Io(ref err) => write!(f, "io error: {}", err),
+            JournalError::Snapshot(ref err) => write!(f, "invalid checkpoint: {}", err),
+            JournalError::BadMagic => write!(f, "not a hash ring journal"),
+            JournalError::UnsupportedVersion(version) => {
+                write!(f, "unsupported journal version {}", version)
+            }
+            JournalError::EpochMismatch { journal, snapshot } => {
+                write!(f, "journal of checkpoint {} over the snapshot of checkpoint {}",
+                       journal,
+                       snapshot)
+            }
+            JournalError::Ring(ref err) => err.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "journal")]
+impl error::Error for JournalError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            JournalError::Io(ref err) => Some(err),
+            JournalError::Snapshot(ref err) => Some(err),
+            JournalError::Ring(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "journal")]
+impl From<io::Error> for JournalError {
+    fn from(err: io::Error) -> JournalError {
+        JournalError::Io(err)
+    }
+}
+
+#[cfg(feature = "journal")]
+impl From<SnapshotError> for JournalError {
+    fn from(err: SnapshotError) -> JournalError {
+        JournalError::Snapshot(err)
+    }
+}
+
+#[cfg(feature = "journal")]
+impl From<Error> for JournalError {
+    fn from(err: Error) -> JournalError {
+        JournalError::Ring(err)
+    }
+}
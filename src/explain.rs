@@ -0,0 +1,83 @@
+use std::fmt;
+
+/// LookupExplanation describes how a key was resolved to its element.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct LookupExplanation {
+    /// The key being looked up.
+    pub key: String,
+    /// The checksum of the key, once normalized, see
+    /// `Consistant::set_key_normalizer`.
+    pub checksum: u32,
+    /// The index of the matched virtual node in the sorted keys.
+    pub index: usize,
+    /// The hash of the matched virtual node.
+    pub vnode_hash: u32,
+    /// The element the key resolves to, the one owning the matched virtual
+    /// node unless a rule, a range assignment or a fractional weight, see
+    /// `Consistant::set_fractional_weight`, routes it elsewhere.
+    pub owner: String,
+    /// Whether the lookup wrapped around the end of the hash ring.
+    pub wrapped: bool,
+    /// The prefix of the rule routing the key to its owner, whatever the
+    /// matched virtual node, see `Consistant::add_prefix_rule`.
+    pub prefix_rule: Option<String>,
+    /// The range `(start, end]` of the assignment routing the key to its
+    /// owner, whatever the matched virtual node, see
+    /// `Consistant::assign_range`.
+    pub range_assignment: Option<(u32, u32)>,
+    /// The elements passed over on the way from the matched virtual node to
+    /// the owner, in the order they were met, and why.
+    pub skipped: Vec<SkippedCandidate>,
+}
+
+/// SkippedCandidate is an element a lookup passed over, see
+/// `LookupExplanation::skipped`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct SkippedCandidate {
+    pub element: String,
+    pub reason: SkipReason,
+}
+
+/// SkipReason tells why a lookup passed over an element.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum SkipReason {
+    /// The fractional weight of the element, in millionths, does not admit
+    /// the key, see `Consistant::set_fractional_weight`.
+    FractionalWeight { millionths: u32 },
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SkipReason::FractionalWeight { millionths } => {
+                write!(f,
+                       "fractional weight {} not admitting the key",
+                       f64::from(millionths) / 1_000_000.0)
+            }
+        }
+    }
+}
+
+impl fmt::Display for LookupExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "key:        {:?}", self.key)?;
+        writeln!(f, "checksum:   {:#010x}", self.checksum)?;
+        writeln!(f, "index:      {}", self.index)?;
+        writeln!(f, "vnode hash: {:#010x}", self.vnode_hash)?;
+        writeln!(f, "owner:      {}", self.owner)?;
+        write!(f, "wrapped:    {}", self.wrapped)?;
+        for candidate in &self.skipped {
+            write!(f, "\nskipped:    {} ({})", candidate.element, candidate.reason)?;
+        }
+        if let Some(ref prefix) = self.prefix_rule {
+            write!(f, "\nrule:       {:?}", prefix)?;
+        }
+        match self.range_assignment {
+            Some((start, end)) => write!(f, "\nrange:      ({:#010x}, {:#010x}]", start, end),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::hash_map::HashMap;
+use consistant::Consistant;
+use error::Error;
+
+/// The most combinations of failed elements `simulate_failures` evaluates,
+/// past which it samples them.
+pub const MAX_FAILURE_COMBINATIONS: usize = 10000;
+
+/// FailureCase is a surviving element of a combination of failed elements,
+/// see `FailureReport`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailureCase {
+    /// The elements failed, sorted by name.
+    pub failed: Vec<String>,
+    /// The surviving element.
+    pub member: String,
+    /// The share of the keyspace of the element once the others failed.
+    pub share: f64,
+    /// The share of the keyspace the element gained from the failed ones.
+    pub increase: f64,
+}
+
+/// FailureReport is what `Consistant::simulate_failures` found on the
+/// combinations of failed elements it evaluated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailureReport {
+    /// The count of elements failed together.
+    pub failures: usize,
+    /// The count of combinations evaluated.
+    pub combinations: usize,
+    /// Whether the combinations evaluated are a sample of all of them.
+    pub sampled: bool,
+    /// The surviving element of the largest share over the combinations.
+    pub worst: FailureCase,
+    /// The surviving element gaining the largest share over the
+    /// combinations.
+    pub worst_increase: FailureCase,
+    /// The mean over the combinations of the largest share of a surviving
+    /// element.
+    pub average_max_share: f64,
+}
+
+impl Consistant {
+    /// Simulate the failure of every combination of `k` elements, the
+    /// virtual nodes of the failed ones being removed so that their arcs go
+    /// to their clockwise successors, and report the largest shares of the
+    /// keyspace surviving elements are left with. The shares are the ones
+    /// of the virtual nodes, as `histogram`, prefix rules, range assignments
+    /// and fractional weights aside. The hash ring does not change.
+    ///
+    /// If there are more than `MAX_FAILURE_COMBINATIONS` combinations, only
+    /// that many are evaluated, drawn pseudo-randomly with a fixed seed, so
+    /// that the report of a hash ring is always the same. Ties go to the
+    /// combination first in the order of the sorted names. There must be
+    /// more elements than `k`, for one to survive.
+    pub fn simulate_failures(&self, k: usize) -> Result<FailureReport, Error> {
+        let members = self.sorted_members();
+        if k >= members.len() {
+            return Err(Error::InvalidFailureCount {
+                failures: k,
+                members: members.len(),
+            });
+        }
+        let index: HashMap<&str, usize> =
+            members.iter().enumerate().map(|(i, element)| (&**element, i)).collect();
+        let vnodes: Vec<(u32, usize)> = self.vnodes()
+            .iter()
+            .map(|&(hash, ref owner)| (hash, index[&**owner]))
+            .collect();
+
+        let mut failed = vec![false; members.len()];
+        let initial = shares(&vnodes, &failed);
+        let total = combinations(members.len(), k);
+        let sampled = total > MAX_FAILURE_COMBINATIONS as u128;
+        let mut combination: Vec<usize> = (0..k).collect();
+        let mut rng = 0x9e37_79b9_7f4a_7c15u64;
+        let mut report: Option<FailureReport> = None;
+        let mut max_shares = 0.0;
+        let mut evaluated = 0;
+        while evaluated < MAX_FAILURE_COMBINATIONS.min(total as usize) {
+            if sampled {
+                combination = sample(&mut rng, members.len(), k);
+            }
+            for &i in &combination {
+                failed[i] = true;
+            }
+            let after = shares(&vnodes, &failed);
+            let case = |survivor: usize| {
+                FailureCase {
+                    failed: combination.iter().map(|&i| String::from(&*members[i])).collect(),
+                    member: String::from(&*members[survivor]),
+                    share: after[survivor],
+                    increase: after[survivor] - initial[survivor],
+                }
+            };
+            let survivors = || (0..members.len()).filter(|&i| !failed[i]);
+            // The first of the largest, for ties to go to the first name.
+            let largest = survivors().fold(None, |largest: Option<usize>, i| {
+                if largest.is_none_or(|largest| after[i] > after[largest]) {
+                    Some(i)
+                } else {
+                    largest
+                }
+            });
+            let gaining = survivors().fold(None, |gaining: Option<usize>, i| {
+                let increase = after[i] - initial[i];
+                if gaining.is_none_or(|gaining| increase > after[gaining] - initial[gaining]) {
+                    Some(i)
+                } else {
+                    gaining
+                }
+            });
+            let (largest, gaining) = (largest.unwrap_or(0), gaining.unwrap_or(0));
+            max_shares += after[largest];
+            match report {
+                Some(ref mut report) => {
+                    if after[largest] > report.worst.share {
+                        report.worst = case(largest);
+                    }
+                    if after[gaining] - initial[gaining] > report.worst_increase.increase {
+                        report.worst_increase = case(gaining);
+                    }
+                }
+                None => {
+                    report = Some(FailureReport {
+                        failures: k,
+                        combinations: 0,
+                        sampled,
+                        worst: case(largest),
+                        worst_increase: case(gaining),
+                        average_max_share: 0.0,
+                    })
+                }
+            }
+            for &i in &combination {
+                failed[i] = false;
+            }
+            evaluated += 1;
+            if !sampled && !next_combination(&mut combination, members.len()) {
+                break;
+            }
+        }
+
+        let mut report = report.expect("a combination is evaluated");
+        report.combinations = evaluated;
+        report.average_max_share = max_shares / evaluated as f64;
+        Ok(report)
+    }
+}
+
+/// Get the shares of the keyspace of the elements, by index, once the given
+/// ones failed. The virtual nodes are sorted, of at least one survivor.
+fn shares(vnodes: &[(u32, usize)], failed: &[bool]) -> Vec<f64> {
+    let mut owned = vec![0u64; failed.len()];
+    let mut start = 0;
+    for &(hash, owner) in vnodes.iter().filter(|vnode| !failed[vnode.1]) {
+        owned[owner] += u64::from(hash) - start;
+        start = u64::from(hash);
+    }
+    // The arc wrapping around, from the last survivor to the first one.
+    if let Some(&(_, first)) = vnodes.iter().find(|vnode| !failed[vnode.1]) {
+        owned[first] += (1 << 32) - start;
+    }
+    owned.into_iter().map(|owned| owned as f64 / (1u64 << 32) as f64).collect()
+}
+
+/// Get the count of combinations of `k` of `n`, saturating.
+fn combinations(n: usize, k: usize) -> u128 {
+    let mut count: u128 = 1;
+    for i in 0..k as u128 {
+        count = match count.checked_mul(n as u128 - i) {
+            Some(product) => product / (i + 1),
+            None => return u128::MAX,
+        };
+    }
+    count
+}
+
+/// Step the given sorted indexes to the next combination of `0..n`, in
+/// lexicographic order, `false` past the last one.
+fn next_combination(combination: &mut [usize], n: usize) -> bool {
+    let k = combination.len();
+    let i = match (0..k).rev().find(|&i| combination[i] < n - k + i) {
+        Some(i) => i,
+        None => return false,
+    };
+    combination[i] += 1;
+    for j in i + 1..k {
+        combination[j] = combination[j - 1] + 1;
+    }
+    true
+}
+
+/// Draw `k` distinct sorted indexes of `0..n` with the given xorshift state.
+fn sample(rng: &mut u64, n: usize, k: usize) -> Vec<usize> {
+    let mut indexes: Vec<usize> = (0..n).collect();
+    for i in 0..k {
+        *rng ^= *rng << 13;
+        *rng ^= *rng >> 7;
+        *rng ^= *rng << 17;
+        let j = i + (*rng % (n - i) as u64) as usize;
+        indexes.swap(i, j);
+    }
+    indexes.truncate(k);
+    indexes.sort();
+    indexes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A ring of 4 elements owning a quarter each, `cacheA` from 0.
+    fn quarters() -> Consistant {
+        let mut consistant = Consistant::new(1);
+        for (i, element) in ["cacheD", "cacheA", "cacheB", "cacheC"].iter().enumerate() {
+            consistant.add_with_tokens(String::from(*element), 1, vec![(i as u32) << 30]);
+        }
+        consistant
+    }
+
+    #[test]
+    fn test_simulate_failures() {
+        let consistant = quarters();
+        let vnodes = consistant.vnodes();
+
+        let report = consistant.simulate_failures(0).unwrap();
+        assert_eq!((report.combinations, report.sampled), (1, false));
+        assert_eq!((report.worst.share, report.worst.increase), (0.25, 0.0));
+        assert_eq!(report.average_max_share, 0.25);
+
+        // A failed element leaves its quarter to the next one.
+        let report = consistant.simulate_failures(1).unwrap();
+        assert_eq!(report.combinations, 4);
+        assert_eq!(report.worst, FailureCase {
+            failed: vec![String::from("cacheA")],
+            member: String::from("cacheB"),
+            share: 0.5,
+            increase: 0.25,
+        });
+        assert_eq!(report.worst_increase, report.worst);
+
+        // Two next to each other leave both quarters to the one after them,
+        // 4 of the 6 pairs.
+        let report = consistant.simulate_failures(2).unwrap();
+        assert_eq!((report.failures, report.combinations), (2, 6));
+        assert_eq!(report.worst.failed, ["cacheA", "cacheB"]);
+        assert_eq!((report.worst.member.as_str(), report.worst.share), ("cacheC", 0.75));
+        assert_eq!(report.worst_increase.increase, 0.5);
+        assert!((report.average_max_share - (4.0 * 0.75 + 2.0 * 0.5) / 6.0).abs() < 1e-12);
+
+        let report = consistant.simulate_failures(3).unwrap();
+        assert_eq!((report.combinations, report.worst.share), (4, 1.0));
+        assert_eq!(report.worst.member, "cacheD");
+        assert_eq!(report.worst.failed, ["cacheA", "cacheB", "cacheC"]);
+        assert_eq!(consistant.simulate_failures(4),
+                   Err(Error::InvalidFailureCount { failures: 4, members: 4 }));
+        assert_eq!(consistant.simulate_failures(9).unwrap_err().to_string(),
+                   "can not fail 9 of 4 members");
+        assert!(Consistant::default().simulate_failures(0).is_err());
+        assert_eq!(consistant.vnodes(), vnodes);
+    }
+
+    #[test]
+    fn test_simulate_failures_sampled() {
+        let mut consistant = Consistant::new(10);
+        for i in 0..30 {
+            consistant.add(format!("node-{}", i));
+        }
+        let report = consistant.simulate_failures(2).unwrap();
+        assert_eq!((report.combinations, report.sampled), (435, false));
+
+        // C(30, 5) combinations are too many, so a sample is evaluated.
+        let sampled = consistant.simulate_failures(5).unwrap();
+        assert_eq!((sampled.combinations, sampled.sampled), (MAX_FAILURE_COMBINATIONS, true));
+        assert_eq!(sampled, consistant.simulate_failures(5).unwrap());
+        assert_eq!(sampled.worst.failed.len(), 5);
+        assert!(!sampled.worst.failed.contains(&sampled.worst.member));
+        assert!(sampled.worst.share >= report.average_max_share);
+        assert!(sampled.worst.share >= sampled.average_max_share);
+        assert!(sampled.worst_increase.increase <= sampled.worst.share);
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::hash_map::HashMap;
+use consistant::Consistant;
+
+/// The count of candidate keys `find_key_for` and `sample_keys_per_member`
+/// try before giving up, enough for any element owning more than about a
+/// millionth of the keyspace.
+pub const FIND_KEY_ATTEMPTS: usize = 1 << 20;
+
+impl Consistant {
+    /// Find a key resolving to the given element, as `get` does, for tests.
+    /// The candidates `{prefix}0`, `{prefix}1`... are tried in turn, so the
+    /// same key is always found for the same hash ring. `None` is returned
+    /// for an unknown element, or if none of the first `FIND_KEY_ATTEMPTS`
+    /// candidates resolves to it.
+    pub fn find_key_for(&self, member: &str, prefix: &str) -> Option<String> {
+        if !self.contains(member) {
+            return None;
+        }
+        (0..FIND_KEY_ATTEMPTS)
+            .map(|i| format!("{}{}", prefix, i))
+            .find(|key| self.key_owner(key).is_some_and(|owner| &*owner == member))
+    }
+
+    /// Find a key resolving to every element, the first one `find_key_for`
+    /// would, with a single pass over the candidates. Elements none of the
+    /// first `FIND_KEY_ATTEMPTS` candidates resolves to are left out.
+    pub fn sample_keys_per_member(&self, prefix: &str) -> HashMap<String, String> {
+        let count = self.count();
+        let mut keys = HashMap::with_capacity(count);
+        for i in 0..FIND_KEY_ATTEMPTS {
+            if keys.len() == count {
+                break;
+            }
+            let key = format!("{}{}", prefix, i);
+            if let Some(owner) = self.key_owner(&key) {
+                keys.entry(String::from(&*owner)).or_insert(key);
+            }
+        }
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_key_for() {
+        let mut consistant = Consistant::new(20);
+        for i in 0..10 {
+            consistant.add(format!("node-{}", i));
+        }
+        consistant.add_prefix_rule("tenant-42/", "node-3").unwrap();
+
+        for member in consistant.members() {
+            let key = consistant.find_key_for(&member, "user/").unwrap();
+            assert!(key.starts_with("user/"));
+            assert_eq!(consistant.get(&key), Some(member.clone()));
+            assert_eq!(consistant.find_key_for(&member, "user/"), Some(key));
+        }
+        assert_eq!(consistant.find_key_for("node-3", "tenant-42/"),
+                   Some(String::from("tenant-42/0")));
+        assert_eq!(consistant.find_key_for("node-x", "user/"), None);
+        assert_eq!(Consistant::default().find_key_for("node-1", ""), None);
+
+        let keys = consistant.sample_keys_per_member("user/");
+        assert_eq!(keys.len(), 10);
+        for (member, key) in &keys {
+            assert_eq!(consistant.get(key).as_ref(), Some(member));
+            assert_eq!(consistant.find_key_for(member, "user/").as_ref(), Some(key));
+        }
+        assert!(Consistant::default().sample_keys_per_member("user/").is_empty());
+    }
+}
@@ -0,0 +1,236 @@
+use consistant::Consistant;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+impl Consistant {
+    /// Split the keyspace into the given count of equal segments and get a
+    /// fingerprint of the virtual nodes of each, for two hash rings to find
+    /// where they differ without exchanging all their virtual nodes. The
+    /// fingerprints only depend on the virtual nodes and their elements, not
+    /// on the order the elements were added in. No segment yields an empty
+    /// vector.
+    ///
+    /// A fingerprint is the 64-bit FNV-1a hash of the virtual node hashes of
+    /// the segment, in increasing order, each one as 4 little endian bytes
+    /// followed by the length of its element's name as 4 little endian bytes
+    /// and the name.
+    pub fn segment_fingerprints(&self, segments: usize) -> Vec<u64> {
+        let mut fingerprints = vec![FNV_OFFSET_BASIS; segments];
+        if segments == 0 {
+            return fingerprints;
+        }
+        for (hash, element) in self.vnodes() {
+            let fingerprint = &mut fingerprints[segment_of(hash, segments)];
+            *fingerprint = fold_name(fold(*fingerprint, &hash.to_le_bytes()), &element);
+        }
+
+        fingerprints
+    }
+
+    /// Get a fingerprint of the whole hash ring, the one of a single segment
+    /// followed by the id of the key normalizer if any, hashed as a name,
+    /// then by what else routes keys: a byte `k` and a SipHash digest of the
+    /// secret of a keyed hash ring, never the secret itself, a byte `p`
+    /// and the prefix and the element of every prefix rule, a byte `r`, the
+    /// start and the end as 4 little endian bytes and the element of every
+    /// range assignment, and a byte `f`, the element and the fraction as 4
+    /// little endian bytes of every element of a fractional weight, the
+    /// rules and assignments of removed elements being left out. Hash rings
+    /// of equal fingerprints map keys alike, whatever the order their
+    /// elements were added in.
+    pub fn fingerprint(&self) -> u64 {
+        let mut fingerprint = self.segment_fingerprints(1)[0];
+        if let Some(normalizer) = self.key_normalizer() {
+            fingerprint = fold_name(fingerprint, normalizer.id());
+        }
+        if let Some(ref secret) = self.key_hasher().secret {
+            fingerprint = fold(fingerprint, b"k");
+            fingerprint = fold(fingerprint, &secret.digest().to_le_bytes());
+        }
+        for (prefix, member) in self.prefix_rules() {
+            if self.contains(&member) {
+                fingerprint = fold_name(fold_name(fold(fingerprint, b"p"), &prefix), &member);
+            }
+        }
+        for (start, end, member) in self.range_assignments() {
+            if self.contains(&member) {
+                fingerprint = fold(fold(fingerprint, b"r"), &start.to_le_bytes());
+                fingerprint = fold_name(fold(fingerprint, &end.to_le_bytes()), &member);
+            }
+        }
+        for element in self.members() {
+            if let Some(fraction) = self.fraction(&element) {
+                fingerprint = fold_name(fold(fingerprint, b"f"), &element);
+                fingerprint = fold(fingerprint, &fraction.to_le_bytes());
+            }
+        }
+        fingerprint
+    }
+
+    /// Get the virtual nodes of the given segment as `(hash, element)`,
+    /// sorted by hash, the keyspace being split as by `segment_fingerprints`.
+    pub fn segment_entries(&self, segment: usize, segments: usize) -> Vec<(u32, String)> {
+        if segment >= segments {
+            return Vec::new();
+        }
+        self.vnodes()
+            .into_iter()
+            .filter(|&(hash, _)| segment_of(hash, segments) == segment)
+            .map(|(hash, element)| (hash, String::from(&*element)))
+            .collect()
+    }
+}
+
+/// Fold the given bytes into the given FNV-1a hash.
+fn fold(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME))
+}
+
+/// Fold the given name into the given FNV-1a hash, its length as 4 little
+/// endian bytes first.
+fn fold_name(hash: u64, name: &str) -> u64 {
+    fold(fold(hash, &(name.len() as u32).to_le_bytes()), name.as_bytes())
+}
+
+/// Get the segment of the given hash, the i-th of the given count starting at
+/// the hash `ceil(i * 2^32 / segments)`.
+fn segment_of(hash: u32, segments: usize) -> usize {
+    ((u128::from(hash) * segments as u128) >> 32) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    fn ring(members: &[&str]) -> Consistant {
+        let mut consistant = Consistant::new(20);
+        for member in members {
+            consistant.add(*member);
+        }
+        consistant
+    }
+
+    #[test]
+    fn test_segment_fingerprints() {
+        let members = ["cacheA", "cacheB", "cacheC", "cacheD", "cacheE"];
+        let consistant = ring(&members);
+        let reversed: Vec<&str> = members.iter().rev().cloned().collect();
+        let fingerprints = consistant.segment_fingerprints(64);
+        assert_eq!(fingerprints.len(), 64);
+        assert_eq!(ring(&reversed).segment_fingerprints(64), fingerprints);
+        assert_eq!(Consistant::new(20).segment_fingerprints(2),
+                   [FNV_OFFSET_BASIS, FNV_OFFSET_BASIS]);
+        assert!(consistant.segment_fingerprints(0).is_empty());
+        assert_eq!(ring(&reversed).fingerprint(), consistant.fingerprint());
+        assert!(ring(&members[1..]).fingerprint() != consistant.fingerprint());
+
+        // The segments cover the keyspace in order.
+        let mut entries = Vec::new();
+        for segment in 0..64 {
+            entries.extend(consistant.segment_entries(segment, 64));
+        }
+        let vnodes: Vec<(u32, String)> = consistant.vnodes()
+            .into_iter()
+            .map(|(hash, element)| (hash, String::from(&*element)))
+            .collect();
+        assert_eq!(entries, vnodes);
+        assert!(consistant.segment_entries(64, 64).is_empty());
+        assert_eq!(segment_of(u32::MAX, 7), 6);
+        assert_eq!(segment_of(0x8000_0000, 2), 1);
+        assert_eq!(segment_of(0x7fff_ffff, 2), 0);
+    }
+
+    #[test]
+    fn test_segment_fingerprints_mismatch() {
+        let consistant = ring(&["cacheA", "cacheB", "cacheC", "cacheD", "cacheE"]);
+        let mut corrupted = consistant.clone();
+        corrupted.remove("cacheC");
+        corrupted.add_weighted("cacheC", 2);
+
+        // The segments of the virtual nodes in only one of the rings, those
+        // added for the extra weight.
+        let before: BTreeSet<_> = consistant.vnodes().into_iter().collect();
+        let after: BTreeSet<_> = corrupted.vnodes().into_iter().collect();
+        let expected: BTreeSet<usize> = before.symmetric_difference(&after)
+            .map(|&(hash, ref element)| {
+                assert_eq!(&**element, "cacheC");
+                segment_of(hash, 1024)
+            })
+            .collect();
+        let mismatched: BTreeSet<usize> = consistant.segment_fingerprints(1024)
+            .into_iter()
+            .zip(corrupted.segment_fingerprints(1024))
+            .enumerate()
+            .filter(|&(_, (a, b))| a != b)
+            .map(|(segment, _)| segment)
+            .collect();
+        assert_eq!(mismatched, expected);
+        assert!(expected.len() > 10 && expected.len() < 1024);
+
+        for segment in mismatched {
+            assert!(consistant.segment_entries(segment, 1024) !=
+                    corrupted.segment_entries(segment, 1024));
+        }
+    }
+
+    #[test]
+    fn test_fingerprint_keyed() {
+        let members = ["cacheA", "cacheB", "cacheC"];
+        let mut keyed = ring(&members);
+        keyed.set_hash_key(Some([7; 16]));
+        assert!(keyed.fingerprint() != ring(&members).fingerprint());
+        let mut other = keyed.clone();
+        other.set_hash_key(Some([8; 16]));
+        assert!(other.fingerprint() != keyed.fingerprint());
+        other.set_hash_key(Some([7; 16]));
+        assert_eq!(other.fingerprint(), keyed.fingerprint());
+        other.set_hash_key(None);
+        assert_eq!(other.fingerprint(), ring(&members).fingerprint());
+    }
+
+    #[test]
+    fn test_fingerprint_prefix_rules() {
+        let members = ["cacheA", "cacheB", "cacheC"];
+        let mut ruled = ring(&members);
+        ruled.add_prefix_rule("tenant-42/", "cacheB").unwrap();
+        assert!(ruled.fingerprint() != ring(&members).fingerprint());
+        let mut other = ring(&members);
+        other.add_prefix_rule("tenant-42/", "cacheC").unwrap();
+        assert!(other.fingerprint() != ruled.fingerprint());
+        other.remove_prefix_rule("tenant-42/");
+        assert_eq!(other.fingerprint(), ring(&members).fingerprint());
+
+        // The rules of removed elements route no key.
+        other.add_prefix_rule("tenant-42/", "cacheC").unwrap();
+        other.remove("cacheC");
+        assert_eq!(other.fingerprint(), ring(&members[..2]).fingerprint());
+    }
+
+    #[test]
+    fn test_fingerprint_range_assignments() {
+        let members = ["cacheA", "cacheB", "cacheC"];
+        let mut assigned = ring(&members);
+        assigned.assign_range(1 << 30, 1 << 31, "cacheA").unwrap();
+        assert!(assigned.fingerprint() != ring(&members).fingerprint());
+        let mut other = ring(&members);
+        other.assign_range(1 << 30, (1 << 31) + 1, "cacheA").unwrap();
+        assert!(other.fingerprint() != assigned.fingerprint());
+        other.clear_range_assignment(1 << 30, (1 << 31) + 1);
+        assert_eq!(other.fingerprint(), ring(&members).fingerprint());
+    }
+
+    #[test]
+    fn test_fingerprint_fractions() {
+        let members = ["cacheA", "cacheB", "cacheC"];
+        let mut fractional = ring(&members);
+        fractional.set_fractional_weight("cacheB", 0.5).unwrap();
+        assert!(fractional.fingerprint() != ring(&members).fingerprint());
+        let mut other = ring(&members);
+        other.set_fractional_weight("cacheB", 0.25).unwrap();
+        assert!(other.fingerprint() != fractional.fingerprint());
+        other.set_fractional_weight("cacheB", 0.5).unwrap();
+        assert_eq!(other.fingerprint(), fractional.fingerprint());
+    }
+}
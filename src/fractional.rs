@@ -0,0 +1,116 @@
+use std::collections::hash_map::HashMap;
+use std::sync::Arc;
+use crc::crc32::checksum_ieee;
+
+/// The fractions of the weights, in millionths, see
+/// `Consistant::set_fractional_weight`.
+pub(crate) const MILLIONTHS: u32 = 1_000_000;
+
+/// Get the given fractional weight in millionths, `None` unless it is in
+/// `(0, 1]`.
+pub(crate) fn to_millionths(weight: f64) -> Option<u32> {
+    if !(weight > 0.0 && weight <= 1.0) {
+        return None;
+    }
+    Some(((weight * f64::from(MILLIONTHS)).round() as u32).max(1))
+}
+
+/// Check whether the element of the given name and fraction admits the key
+/// of the given checksum. The admission of a key only depends on the name
+/// and the checksum, for other implementations to match it, as:
+///
+/// ```text
+/// x = crc32_ieee(name) << 32 | checksum           as a u64
+/// x ^= x >> 33; x *= 0xff51afd7ed558ccd           wrapping
+/// x ^= x >> 33; x *= 0xc4ceb9fe1a85ec53           wrapping
+/// x ^= x >> 33                                    fmix64 of MurmurHash3
+/// admitted = (x >> 32) * 1000000 < millionths << 32
+/// ```
+///
+/// So a key admitted at a fraction is admitted at every greater one.
+#[inline]
+pub(crate) fn admits(name: &str, millionths: u32, sum: u32) -> bool {
+    if millionths >= MILLIONTHS {
+        return true;
+    }
+    let mut x = u64::from(checksum_ieee(name.as_bytes())) << 32 | u64::from(sum);
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    x ^= x >> 33;
+    (x >> 32) * u64::from(MILLIONTHS) < u64::from(millionths) << 32
+}
+
+/// Get the owner a key goes to when the one of the virtual node at `start`
+/// declines it, the first one after it admitting it, `None` if that one
+/// admits it. `at` gives the owner of the virtual node of the given index,
+/// of `len` ones, and whether it admits the key. If none does, the key
+/// stays with the owner at `start`.
+#[inline]
+pub(crate) fn next_admitting<F: FnMut(usize) -> (u32, bool)>(start: usize,
+                                                      len: usize,
+                                                      mut at: F)
+                                                      -> Option<u32> {
+    if at(start).1 {
+        return None;
+    }
+    (1..len).map(|i| at((start + i) % len)).find(|owner| owner.1).map(|owner| owner.0)
+}
+
+/// Get the fractions of the given sorted elements, by index, for the
+/// immutable copies of a hash ring, none if no element is fractional.
+pub(crate) fn member_fractions(members: &[Arc<str>],
+                               fractions: &HashMap<Arc<str>, u32>)
+                               -> Vec<u32> {
+    if fractions.is_empty() {
+        return Vec::new();
+    }
+    members.iter().map(|element| fractions.get(element).cloned().unwrap_or(MILLIONTHS)).collect()
+}
+
+/// Same as `next_admitting`, for the virtual nodes of the given owners,
+/// indexes of the given fractions of `member_fractions` and of the names
+/// `name` gives.
+#[inline]
+pub(crate) fn reroute_owners<'a, N: Fn(u32) -> &'a str>(owners: &[u32],
+                                                        fractions: &[u32],
+                                                        name: N,
+                                                        start: usize,
+                                                        sum: u32)
+                                                        -> Option<u32> {
+    if fractions.is_empty() {
+        return None;
+    }
+    next_admitting(start, owners.len(), |index| {
+        let owner = owners[index];
+        (owner, admits(name(owner), fractions[owner as usize], sum))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_admits() {
+        assert_eq!(to_millionths(0.05), Some(50_000));
+        assert_eq!(to_millionths(1e-9), Some(1));
+        assert_eq!(to_millionths(0.0), None);
+        assert_eq!(to_millionths(1.5), None);
+        assert_eq!(to_millionths(f64::NAN), None);
+
+        let admitted = |millionths| {
+            (0..100_000u32)
+                .filter(|sum| admits("canary", millionths, sum.wrapping_mul(0x9e37_79b9)))
+                .count()
+        };
+        assert_eq!(admitted(MILLIONTHS), 100_000);
+        let (low, high) = (admitted(50_000), admitted(500_000));
+        assert!((4_500..5_500).contains(&low), "{}", low);
+        assert!((49_000..51_000).contains(&high), "{}", high);
+        for sum in 0..10_000u32 {
+            assert!(!admits("canary", 50_000, sum) || admits("canary", 500_000, sum));
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use std::sync::Arc;
+use consistant::{distinct_owners, Consistant};
+use routing::Routing;
+
+/// FrozenRing is an immutable hash ring made for lookups, see
+/// `Consistant::freeze`: a flat sorted array of virtual node hashes, the
+/// array of their owners, and the names of the elements interned in a
+/// single string. Lookups take no lock and follow no pointer but the one to
+/// these arrays, and map keys exactly as the frozen `Consistant` does, the
+/// fallback of the empty hash ring included.
+/// `CompiledRing` skips the binary search with a table of slots, at the
+/// cost of its memory. See the benchmarks of `benches/lookup.rs`.
+///
+/// It can not change: `into_consistant` thaws it back into the hash ring it
+/// was frozen from, to change that one and freeze it again. Cloning a
+/// FrozenRing only bumps a reference count.
+#[derive(Debug, Clone)]
+pub struct FrozenRing {
+    inner: Arc<Frozen>,
+}
+
+#[derive(Debug)]
+struct Frozen {
+    tables: Tables,
+    /// The hash ring frozen, for thawing it.
+    consistant: Consistant,
+}
+
+/// Tables are the arrays the lookups of a frozen hash ring go through, see
+/// `LocalRing`.
+#[derive(Debug, Clone)]
+pub(crate) struct Tables {
+    routing: Routing,
+    replication_factor: usize,
+    fallback: Option<String>,
+    keys: Vec<u32>,
+    owners: Vec<u32>,
+    /// The names of the elements, sorted, the one of index `i` spanning
+    /// `names[offsets[i]..offsets[i + 1]]`.
+    names: String,
+    offsets: Vec<u32>,
+    /// The fingerprint of the hash ring frozen, see `Consistant::fingerprint`.
+    fingerprint: u64,
+}
+
+impl Consistant {
+    /// Freeze the hash ring into a `FrozenRing`, for the fastest lookups
+    /// once it no longer changes. The key normalizer, the prefix rules, the
+    /// range assignments, the fractional weights, the replication factor
+    /// and the fallback are kept.
+    pub fn freeze(self) -> FrozenRing {
+        let members = self.sorted_members();
+        let vnodes = self.vnodes();
+        let keys = vnodes.iter().map(|vnode| vnode.0).collect();
+        let owners = vnodes.iter()
+            .map(|vnode| members.binary_search(&vnode.1).unwrap() as u32)
+            .collect();
+
+        let mut names = String::new();
+        let mut offsets = vec![0];
+        for element in &members {
+            names.push_str(element);
+            offsets.push(names.len() as u32);
+        }
+
+        let tables = Tables {
+            routing: Routing::new(&self, &members),
+            replication_factor: self.replication_factor(),
+            fallback: self.fallback().map(String::from),
+            keys,
+            owners,
+            names,
+            offsets,
+            fingerprint: self.fingerprint(),
+        };
+        FrozenRing {
+            inner: Arc::new(Frozen {
+                tables,
+                consistant: self,
+            }),
+        }
+    }
+}
+
+impl FrozenRing {
+    /// Get the cloeset element's name to the given key.
+    pub fn get<S: AsRef<str>>(&self, key: S) -> Option<&str> {
+        self.inner.tables.get(key.as_ref())
+    }
+
+    /// Get the N cloeset elements' names to the given key.
+    pub fn get_n<S: AsRef<str>>(&self, key: S, n: usize) -> Option<Vec<&str>> {
+        self.inner.tables.get_n(key.as_ref(), n)
+    }
+
+    /// Get the elements owning the given key, the `replication_factor`
+    /// cloeset ones, as `Consistant::owners` does.
+    pub fn owners<S: AsRef<str>>(&self, key: S) -> Option<Vec<&str>> {
+        self.get_n(key, self.replication_factor())
+    }
+
+    /// Get the names of the elements, sorted.
+    pub fn members(&self) -> Vec<&str> {
+        self.inner.tables.members()
+    }
+
+    /// Get the count of elements.
+    pub fn count(&self) -> usize {
+        self.inner.tables.count()
+    }
+
+    /// Get the count of owners of every key, see `owners`.
+    pub fn replication_factor(&self) -> usize {
+        self.inner.tables.replication_factor
+    }
+
+    /// Get the fingerprint of the hash ring frozen, see
+    /// `Consistant::fingerprint`.
+    pub fn fingerprint(&self) -> u64 {
+        self.inner.tables.fingerprint
+    }
+
+    /// Thaw the frozen hash ring back into the `Consistant` it was frozen
+    /// from, which is copied if the FrozenRing is cloned.
+    pub fn into_consistant(self) -> Consistant {
+        match Arc::try_unwrap(self.inner) {
+            Ok(frozen) => frozen.consistant,
+            Err(shared) => shared.consistant.clone(),
+        }
+    }
+
+    pub(crate) fn tables(&self) -> &Tables {
+        &self.inner.tables
+    }
+}
+
+impl Tables {
+    #[inline]
+    pub(crate) fn get(&self, key: &str) -> Option<&str> {
+        match self.locate(key) {
+            Some((index, routed)) => Some(self.name(routed.unwrap_or(self.owners[index]))),
+            None => self.fallback.as_deref(),
+        }
+    }
+
+    pub(crate) fn get_n(&self, key: &str, n: usize) -> Option<Vec<&str>> {
+        if n == 0 {
+            return None;
+        }
+        let (index, routed) = match self.locate(key) {
+            Some(located) => located,
+            None => return self.fallback.as_deref().map(|fallback| vec![fallback]),
+        };
+        let count = n.min(self.count());
+
+        let owners = distinct_owners(self.keys.len(), |i| self.owners[i], index, routed, count);
+
+        Some(owners.into_iter().map(|owner| self.name(owner)).collect())
+    }
+
+    pub(crate) fn members(&self) -> Vec<&str> {
+        (0..self.count() as u32).map(|owner| self.name(owner)).collect()
+    }
+
+    pub(crate) fn count(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    pub(crate) fn replication_factor(&self) -> usize {
+        self.replication_factor
+    }
+
+    pub(crate) fn fingerprint(&self) -> u64 {
+        self.fingerprint
+    }
+
+    #[inline]
+    fn name(&self, owner: u32) -> &str {
+        let offsets = &self.offsets;
+        &self.names[offsets[owner as usize] as usize..offsets[owner as usize + 1] as usize]
+    }
+
+    /// Get the index of the virtual node of the given key, and the element
+    /// its longest prefix rule or range assignment routes it to, or the one
+    /// past a fractional element declining it, as `Consistant::get` does.
+    #[inline]
+    fn locate(&self, key: &str) -> Option<(usize, Option<u32>)> {
+        if self.keys.is_empty() {
+            return None;
+        }
+        let index_of = |sum| {
+            let index = self.keys.partition_point(|hash| *hash <= sum);
+            Some(if index == self.keys.len() { 0 } else { index })
+        };
+        self.routing.locate(key, &self.owners, index_of, |owner| self.name(owner))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn consistant() -> Consistant {
+        let mut consistant = Consistant::with_seed(20, 7);
+        for i in 0..20 {
+            consistant.add_weighted(format!("node-{}", i), 1 + i % 3);
+        }
+        consistant.replace_node("node-7", "node-x").unwrap();
+        consistant.add_prefix_rule("tenant-42/", "node-3").unwrap();
+        consistant.assign_range(u32::MAX - (1 << 28), 1 << 28, "node-5").unwrap();
+        consistant.set_fractional_weight("node-9", 0.2).unwrap();
+        consistant.set_replication_factor(3);
+        consistant
+    }
+
+    #[test]
+    fn test_freeze() {
+        let consistant = consistant();
+        let frozen = consistant.clone().freeze();
+        assert_eq!(frozen.count(), 20);
+        assert_eq!(frozen.members(), consistant.members());
+        assert_eq!(frozen.fingerprint(), consistant.fingerprint());
+
+        for i in 0..10000 {
+            let key = format!("key{}", i);
+            assert_eq!(frozen.get(&key).map(String::from), consistant.get(&key));
+            assert_eq!(frozen.get_n(&key, 2).unwrap(), consistant.get_n(&key, 2).unwrap());
+            assert_eq!(frozen.owners(&key).unwrap(), consistant.owners(&key).unwrap());
+        }
+        assert_eq!(frozen.get("tenant-42/david"), Some("node-3"));
+        assert_eq!(frozen.get_n("david", 0), None);
+        assert_eq!(frozen.get_n("david", 30).unwrap().len(), 20);
+
+        let empty = Consistant::default().freeze();
+        assert_eq!(empty.get("david"), None);
+        assert_eq!(empty.owners("david"), None);
+        assert!(empty.members().is_empty());
+
+        let mut consistant = Consistant::default();
+        consistant.set_fallback(Some(String::from("local-cache")));
+        let empty = consistant.freeze();
+        assert_eq!(empty.get("david"), Some("local-cache"));
+        assert_eq!(empty.owners("david"), Some(vec!["local-cache"]));
+        assert_eq!(empty.get_n("david", 0), None);
+        assert_eq!(empty.count(), 0);
+    }
+
+    #[test]
+    fn test_into_consistant() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+        let consistant = consistant();
+        let frozen = consistant.clone().freeze();
+        assert_send_sync(&frozen);
+        let cloned = frozen.clone();
+        assert!(Arc::ptr_eq(&cloned.inner, &frozen.inner));
+
+        let mut thawed = cloned.into_consistant();
+        assert_eq!(thawed.vnodes(), consistant.vnodes());
+        thawed.remove("node-1");
+        let thawed = frozen.into_consistant();
+        assert_eq!(thawed.vnodes(), consistant.vnodes());
+        assert_eq!(thawed.export_state(), consistant.export_state());
+    }
+}
@@ -0,0 +1,148 @@
+//! The hash ring of the process, with the `global` feature, for the services
+//! embedding a single one rather than passing it through all their layers.
+//!
+//! `install` freezes it once, `current` gets it and `reload` swaps another
+//! one in. The `FrozenRing` of `current` is a consistent view, unaffected by
+//! the reloads until `current` is called again. Neither of them takes a
+//! lock: the hash ring is an `Arc` swapped atomically, so readers never wait
+//! for a reload, and the rings swapped out are freed once their last reader
+//! drops them.
+//!
+//! Before `install`, `current` and `reload` return
+//! `GlobalError::NotInstalled` rather than an empty hash ring, for a missing
+//! install to fail loudly instead of routing every key nowhere.
+
+use std::sync::{Arc, OnceLock};
+use arc_swap::ArcSwap;
+use consistant::Consistant;
+use error::GlobalError;
+use frozen::FrozenRing;
+
+static GLOBAL: OnceLock<Global> = OnceLock::new();
+
+/// Install the hash ring of the process, frozen, or return
+/// `GlobalError::AlreadyInstalled` if one is, see `reload`.
+pub fn install(consistant: Consistant) -> Result<(), GlobalError> {
+    if GLOBAL.get().is_some() {
+        return Err(GlobalError::AlreadyInstalled);
+    }
+    GLOBAL.set(Global::new(consistant)).map_err(|_| GlobalError::AlreadyInstalled)
+}
+
+/// Get the hash ring of the process, see `install`.
+pub fn current() -> Result<Arc<FrozenRing>, GlobalError> {
+    GLOBAL.get().map(Global::current).ok_or(GlobalError::NotInstalled)
+}
+
+/// Replace the hash ring of the process with the given one, frozen. The
+/// rings `current` returned before are kept by their readers.
+pub fn reload(consistant: Consistant) -> Result<(), GlobalError> {
+    GLOBAL.get().ok_or(GlobalError::NotInstalled)?.reload(consistant);
+    Ok(())
+}
+
+#[derive(Debug)]
+struct Global {
+    ring: ArcSwap<FrozenRing>,
+}
+
+impl Global {
+    fn new(consistant: Consistant) -> Global {
+        Global { ring: ArcSwap::from_pointee(consistant.freeze()) }
+    }
+
+    fn current(&self) -> Arc<FrozenRing> {
+        self.ring.load_full()
+    }
+
+    fn reload(&self, consistant: Consistant) {
+        // Frozen before the swap, for readers to never wait on it.
+        self.ring.store(Arc::new(consistant.freeze()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::HashMap;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    fn consistant(count: usize) -> Consistant {
+        let mut consistant = Consistant::new(20);
+        for i in 0..count {
+            consistant.add(format!("cache{}", i));
+        }
+        consistant
+    }
+
+    fn owners(ring: &FrozenRing) -> Vec<String> {
+        (0..200).map(|i| String::from(ring.get(format!("key{}", i)).unwrap())).collect()
+    }
+
+    #[test]
+    fn test_global() {
+        assert_eq!(current().unwrap_err(), GlobalError::NotInstalled);
+        assert_eq!(reload(consistant(3)), Err(GlobalError::NotInstalled));
+
+        install(consistant(3)).unwrap();
+        assert_eq!(install(consistant(4)), Err(GlobalError::AlreadyInstalled));
+        let ring = current().unwrap();
+        assert_eq!(ring.count(), 3);
+
+        reload(consistant(5)).unwrap();
+        assert_eq!(current().unwrap().count(), 5);
+        assert_eq!(ring.count(), 3);
+    }
+
+    #[test]
+    fn test_reload_readers() {
+        let expected: HashMap<u64, Vec<String>> = (1..10)
+            .map(|count| {
+                let ring = consistant(count).freeze();
+                (ring.fingerprint(), owners(&ring))
+            })
+            .collect();
+        assert_eq!(expected.len(), 9);
+        let global = Global::new(consistant(1));
+        let done = AtomicBool::new(false);
+
+        // Every ring read maps the keys as the one of its fingerprint.
+        thread::scope(|scope| {
+            let readers: Vec<_> = (0..4)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut reads = 0;
+                        while !done.load(Ordering::Relaxed) || reads == 0 {
+                            let ring = global.current();
+                            assert_eq!(Some(&owners(&ring)), expected.get(&ring.fingerprint()));
+                            reads += 1;
+                        }
+                    })
+                })
+                .collect();
+            for round in 0..50 {
+                global.reload(consistant(round % 9 + 1));
+            }
+            done.store(true, Ordering::Relaxed);
+            for reader in readers {
+                reader.join().unwrap();
+            }
+        });
+    }
+
+    #[test]
+    fn test_reload_drops() {
+        let global = Global::new(consistant(3));
+        let mut swapped = Vec::new();
+        for round in 0..20 {
+            let ring = global.current();
+            assert_eq!(Arc::strong_count(&ring), 2);
+            swapped.push(Arc::downgrade(&ring));
+            global.reload(consistant(round % 5 + 1));
+            assert_eq!(Arc::strong_count(&ring), 1);
+        }
+        assert!(swapped.iter().all(|ring| ring.upgrade().is_none()));
+        assert_eq!(Arc::strong_count(&global.current()), 2);
+    }
+}
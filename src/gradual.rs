@@ -0,0 +1,205 @@
+use consistant::Consistant;
+use error::Error;
+
+/// ReweightPlan ramps the weight of an element over steps, see
+/// `Consistant::reweight_gradually`. It holds no reference to the hash
+/// ring, so that it can be kept, or serialized with the `serde` feature,
+/// between the steps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ReweightPlan {
+    member: String,
+    /// The weights of the steps, the target last.
+    weights: Vec<usize>,
+    /// The count of steps applied.
+    applied: usize,
+}
+
+/// StepReport describes a step applied by `ReweightPlan::advance`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepReport {
+    /// The step applied, from 1.
+    pub step: usize,
+    /// The count of steps of the plan.
+    pub steps: usize,
+    /// The weight of the element after the step.
+    pub weight: usize,
+    /// The share of the keyspace the step moved, see
+    /// `Consistant::last_disruption`.
+    pub disruption: f64,
+}
+
+impl Consistant {
+    /// Plan changing the weight of the given element to the target over the
+    /// given count of steps, of weights as evenly spaced as whole weights
+    /// allow, fewer steps being planned if there are not as many weights in
+    /// between. Nothing changes until `ReweightPlan::advance`. The element
+    /// must be in the hash ring and not have explicit virtual nodes, see
+    /// `replace_node`.
+    pub fn reweight_gradually(&self,
+                          member: &str,
+                          target: usize,
+                          steps: usize)
+                          -> Result<ReweightPlan, Error> {
+        let current = match self.weight(member) {
+            Some(current) => current,
+            None => return Err(Error::UnknownMember(String::from(member))),
+        };
+        if target == 0 {
+            return Err(Error::InvalidWeight(String::from(member)));
+        }
+        if current != target && self.explicit_tokens(member).is_some() {
+            return Err(Error::ExplicitTokens(String::from(member)));
+        }
+
+        let steps = steps.max(1);
+        let mut weights: Vec<usize> = (1..steps + 1)
+            .map(|step| {
+                let delta = (target as f64 - current as f64) * step as f64 / steps as f64;
+                (current as f64 + delta).round() as usize
+            })
+            .collect();
+        weights.dedup();
+        weights.retain(|weight| *weight != current);
+
+        Ok(ReweightPlan {
+            member: String::from(member),
+            weights,
+            applied: 0,
+        })
+    }
+}
+
+impl ReweightPlan {
+    /// Apply the next step to the given hash ring, a single reweight of the
+    /// element, so stopping between steps leaves it at the weight of the
+    /// last one. `None` once every step is applied, or if the element left
+    /// the hash ring, which ends the plan.
+    pub fn advance(&mut self, consistant: &mut Consistant) -> Option<StepReport> {
+        let weight = *self.weights.get(self.applied)?;
+        if consistant.member(self.member.as_str()).weight(weight).apply().is_err() {
+            self.applied = self.weights.len();
+            return None;
+        }
+        self.applied += 1;
+
+        Some(StepReport {
+            step: self.applied,
+            steps: self.weights.len(),
+            weight,
+            disruption: consistant.last_disruption().unwrap_or(0.0),
+        })
+    }
+
+    /// Get the element of the plan.
+    pub fn member(&self) -> &str {
+        &self.member
+    }
+
+    /// Get the weights of the steps, the target last.
+    pub fn weights(&self) -> &[usize] {
+        &self.weights
+    }
+
+    /// Get the count of steps applied.
+    pub fn applied(&self) -> usize {
+        self.applied
+    }
+
+    /// Check whether every step is applied.
+    pub fn is_done(&self) -> bool {
+        self.applied == self.weights.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn consistant() -> Consistant {
+        let mut consistant = Consistant::new(20);
+        for i in 0..10 {
+            consistant.add(format!("node-{}", i));
+        }
+        consistant
+    }
+
+    #[test]
+    fn test_reweight_gradually() {
+        let keys: Vec<String> = (0..5000).map(|i| format!("key{}", i)).collect();
+        for &(target, steps) in &[(8, 4), (1, 3)] {
+            let mut consistant = consistant();
+            if target == 1 {
+                consistant.member("node-3").weight(8).apply().unwrap();
+            }
+            let initial: Vec<Option<String>> = keys.iter().map(|key| consistant.get(key)).collect();
+            let mut jumped = consistant.clone();
+            jumped.member("node-3").weight(target).apply().unwrap();
+            let last: Vec<Option<String>> = keys.iter().map(|key| jumped.get(key)).collect();
+
+            let mut plan = consistant.reweight_gradually("node-3", target, steps).unwrap();
+            assert_eq!(plan.weights().len(), steps);
+            assert_eq!(plan.weights().last(), Some(&target));
+            let mut moved = 0.0;
+            while let Some(report) = plan.advance(&mut consistant) {
+                assert_eq!(report.step, plan.applied());
+                assert_eq!(consistant.weight("node-3"), Some(report.weight));
+                moved += report.disruption;
+                // Keys only ever move between their first and last owners.
+                for (i, key) in keys.iter().enumerate() {
+                    let owner = consistant.get(key);
+                    assert!(owner == initial[i] || owner == last[i], "{}", key);
+                }
+            }
+            assert!(plan.is_done());
+            assert_eq!(consistant.vnodes(), jumped.vnodes());
+            assert!((moved - jumped.last_disruption().unwrap()).abs() < 1e-9,
+                    "{} {}",
+                    moved,
+                    jumped.last_disruption().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_reweight_plan() {
+        let mut consistant = consistant();
+        assert_eq!(consistant.reweight_gradually("node-x", 2, 2),
+                   Err(Error::UnknownMember(String::from("node-x"))));
+        assert_eq!(consistant.reweight_gradually("node-1", 0, 2),
+                   Err(Error::InvalidWeight(String::from("node-1"))));
+        consistant.replace_node("node-2", "node-y").unwrap();
+        assert_eq!(consistant.reweight_gradually("node-y", 2, 2),
+                   Err(Error::ExplicitTokens(String::from("node-y"))));
+
+        assert_eq!(consistant.reweight_gradually("node-1", 3, 10).unwrap().weights(), [2, 3]);
+        assert_eq!(consistant.reweight_gradually("node-1", 7, 0).unwrap().weights(), [7]);
+        let mut unchanged = consistant.reweight_gradually("node-1", 1, 4).unwrap();
+        assert!(unchanged.is_done());
+        assert_eq!(unchanged.advance(&mut consistant), None);
+
+        // Stopped midway, the plan resumes where it left.
+        let version = consistant.version();
+        let mut plan = consistant.reweight_gradually("node-1", 5, 4).unwrap();
+        assert_eq!(consistant.version(), version);
+        plan.advance(&mut consistant).unwrap();
+        let mut resumed = plan.clone();
+        assert_eq!(consistant.weight("node-1"), Some(2));
+        assert_eq!(resumed.advance(&mut consistant).unwrap().weight, 3);
+
+        consistant.remove("node-1");
+        assert_eq!(resumed.advance(&mut consistant), None);
+        assert!(resumed.is_done());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_reweight_plan() {
+        let mut consistant = consistant();
+        let mut plan = consistant.reweight_gradually("node-1", 4, 3).unwrap();
+        plan.advance(&mut consistant).unwrap();
+        let json = ::serde_json::to_string(&plan).unwrap();
+        let mut restored: ReweightPlan = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, plan);
+        assert_eq!(restored.advance(&mut consistant).unwrap().step, 2);
+    }
+}
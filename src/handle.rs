@@ -0,0 +1,197 @@
+use std::collections::hash_map::HashMap;
+use std::mem;
+use std::sync::Arc;
+use consistant::Consistant;
+
+/// NodeHandle is a cheap token identifying an element of a hash ring, to be
+/// resolved to its name by the ring which produced it. A handle is no longer
+/// resolved once its element is removed, even if the element is added again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeHandle {
+    index: u32,
+    generation: u32,
+}
+
+/// Slots numbers the elements of a hash ring for their handles and virtual
+/// nodes. A slot is reused by later elements, its generation being bumped on
+/// removal.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Slots {
+    slots: Vec<(Option<Arc<str>>, u32)>,
+    indexes: HashMap<Arc<str>, u32>,
+    free: Vec<u32>,
+}
+
+impl Slots {
+    /// Insert the given element, returning its slot.
+    pub(crate) fn insert(&mut self, element: Arc<str>) -> u32 {
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.slots.push((None, 0));
+                (self.slots.len() - 1) as u32
+            }
+        };
+        self.slots[index as usize].0 = Some(element.clone());
+        self.indexes.insert(element, index);
+        index
+    }
+
+    pub(crate) fn remove(&mut self, element: &str) {
+        if let Some(index) = self.indexes.remove(element) {
+            let slot = &mut self.slots[index as usize];
+            slot.0 = None;
+            slot.1 = slot.1.wrapping_add(1);
+            self.free.push(index);
+        }
+    }
+
+    /// Rename the given element, keeping its slot and generation.
+    pub(crate) fn rename(&mut self, element: &str, name: Arc<str>) {
+        if let Some(index) = self.indexes.remove(element) {
+            self.slots[index as usize].0 = Some(name.clone());
+            self.indexes.insert(name, index);
+        }
+    }
+
+    /// Get the slot of the given element.
+    pub(crate) fn index(&self, element: &str) -> Option<u32> {
+        self.indexes.get(element).cloned()
+    }
+
+    /// Get the element of the given slot, which must not be free.
+    #[inline]
+    pub(crate) fn name(&self, index: u32) -> &Arc<str> {
+        match self.slots[index as usize].0 {
+            Some(ref element) => element,
+            None => unreachable!(),
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        let members: Vec<Arc<str>> = self.indexes.keys().cloned().collect();
+        for element in members {
+            self.remove(&element);
+        }
+    }
+
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.slots.shrink_to_fit();
+        self.indexes.shrink_to_fit();
+        self.free.shrink_to_fit();
+    }
+
+    /// Get an estimate of the heap bytes held, the element names excepted.
+    pub(crate) fn memory_usage(&self) -> usize {
+        self.slots.capacity() * mem::size_of::<(Option<Arc<str>>, u32)>() +
+        self.indexes.capacity() * (mem::size_of::<(Arc<str>, u32)>() + 1) +
+        self.free.capacity() * mem::size_of::<u32>()
+    }
+
+    fn handle(&self, element: &str) -> Option<NodeHandle> {
+        self.indexes.get(element).map(|index| {
+            NodeHandle {
+                index: *index,
+                generation: self.slots[*index as usize].1,
+            }
+        })
+    }
+
+    fn resolve(&self, handle: NodeHandle) -> Option<&str> {
+        match self.slots.get(handle.index as usize) {
+            Some(&(Some(ref element), generation)) if generation == handle.generation => {
+                Some(element)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Consistant {
+    /// Same as `get`, but returns a handle of the cloeset element rather than
+    /// its name.
+    pub fn get_handle<S: AsRef<str>>(&self, key: S) -> Option<NodeHandle> {
+        self.key_owner(key.as_ref())
+            .and_then(|element| self.slots().handle(&element))
+    }
+
+    /// Get the name of the element of the given handle, `None` if it was
+    /// removed since.
+    pub fn resolve(&self, handle: NodeHandle) -> Option<&str> {
+        self.slots().resolve(handle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use auto_replicas::AutoReplicas;
+
+    #[test]
+    fn test_get_handle() {
+        let mut consistant = Consistant::default();
+        assert_eq!(consistant.get_handle("david"), None);
+
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+        consistant.add("cacheC");
+        for i in 0..100 {
+            let key = format!("key{}", i);
+            let handle = consistant.get_handle(&key).unwrap();
+            assert_eq!(consistant.resolve(handle).map(String::from),
+                       consistant.get(key.as_str()));
+        }
+    }
+
+    #[test]
+    fn test_resolve_removed() {
+        let mut consistant = Consistant::default();
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+        let handle_a = consistant.get_handle("david").unwrap();
+        let name_a = String::from(consistant.resolve(handle_a).unwrap());
+
+        consistant.remove(&name_a);
+        assert_eq!(consistant.resolve(handle_a), None);
+
+        consistant.add(name_a.as_str());
+        assert_eq!(consistant.resolve(handle_a), None);
+        let handle_b = consistant.get_handle("david").unwrap();
+        assert!(handle_b != handle_a);
+        assert_eq!(consistant.resolve(handle_b), Some(name_a.as_str()));
+        assert_eq!(consistant.get_handle("david"), Some(handle_b));
+
+        consistant.clear();
+        assert_eq!(consistant.resolve(handle_b), None);
+        assert_eq!(consistant.get_handle("david"), None);
+    }
+
+    #[test]
+    fn test_resolve_rescaled() {
+        let mut consistant = Consistant::new(20);
+        consistant.set_auto_replicas(Some(AutoReplicas::new(100, 1_000)));
+        consistant.add("z-node");
+        consistant.add("a-node");
+        consistant.remove("a-node");
+        let handle = consistant.get_handle("david").unwrap();
+        let replicas = consistant.replicas_num();
+
+        // Rebuilt with fewer virtual nodes, the hash ring keeps its handles.
+        for i in 0..20 {
+            consistant.add(format!("cache{}", i));
+        }
+        assert!(consistant.replicas_num() < replicas);
+        assert_eq!(consistant.resolve(handle), Some("z-node"));
+        for i in 0..100 {
+            let key = format!("key{}", i);
+            let handle = consistant.get_handle(&key).unwrap();
+            assert_eq!(consistant.resolve(handle).map(String::from),
+                       consistant.get(key.as_str()));
+        }
+        consistant.remove("z-node");
+        assert_eq!(consistant.resolve(handle), None);
+        let copy = consistant.clone_with_replicas(30);
+        let handle = consistant.get_handle("david").unwrap();
+        assert_eq!(copy.resolve(handle), consistant.resolve(handle));
+    }
+}
@@ -0,0 +1,218 @@
+//! A drop-in adapter for the API of the `hash_ring` crate (version 0.2).
+//!
+//! By default `HashRing` is implemented on top of `Consistant`, so its
+//! distribution differs from the `hash_ring` crate in that:
+//!
+//! - keys and virtual nodes are hashed with CRC32 (IEEE) rather than XxHash64;
+//! - the i-th virtual node of a node is named `"{node}{i}"` rather than
+//!   `"{node}:{i}"`;
+//! - a key hashing exactly onto a virtual node belongs to the next one rather
+//!   than to that one.
+//!
+//! `HashRing::new_parity` instead reproduces the `hash_ring` crate mappings
+//! exactly, which eases migrating persisted data.
+
+use std::collections::hash_map::HashMap;
+use std::hash::Hasher;
+use twox_hash::XxHash64;
+use consistant::Consistant;
+
+/// HashRing mirrors `hash_ring::HashRing`, nodes being identified by their
+/// `to_string()`.
+pub struct HashRing<T> {
+    nodes: HashMap<String, T>,
+    ring: Ring,
+}
+
+enum Ring {
+    Consistant(Box<Consistant>),
+    Parity(ParityRing),
+}
+
+impl<T: ToString + Clone> HashRing<T> {
+    /// Creates a new hash ring with the specified nodes, each having
+    /// `replicas` virtual nodes.
+    pub fn new(nodes: Vec<T>, replicas: isize) -> HashRing<T> {
+        let ring = Consistant::new(replicas.max(0) as usize);
+        Self::with_ring(nodes, Ring::Consistant(Box::new(ring)))
+    }
+
+    /// Same as `new`, but the mappings are identical to the ones of the
+    /// `hash_ring` crate.
+    pub fn new_parity(nodes: Vec<T>, replicas: isize) -> HashRing<T> {
+        Self::with_ring(nodes,
+                        Ring::Parity(ParityRing {
+                            replicas: replicas.max(0) as usize,
+                            ring: HashMap::new(),
+                            sorted_keys: Vec::new(),
+                        }))
+    }
+
+    fn with_ring(nodes: Vec<T>, ring: Ring) -> HashRing<T> {
+        let mut hash_ring = HashRing {
+            nodes: HashMap::new(),
+            ring,
+        };
+        for node in &nodes {
+            hash_ring.add_node(node);
+        }
+        hash_ring
+    }
+
+    /// Adds a node to the hash ring.
+    pub fn add_node(&mut self, node: &T) {
+        let name = node.to_string();
+        match self.ring {
+            Ring::Consistant(ref mut ring) => ring.add(name.as_str()),
+            Ring::Parity(ref mut ring) => ring.add(&name),
+        }
+        self.nodes.insert(name, node.clone());
+    }
+
+    /// Deletes a node from the hash ring.
+    pub fn remove_node(&mut self, node: &T) {
+        let name = node.to_string();
+        match self.ring {
+            Ring::Consistant(ref mut ring) => ring.remove(&name),
+            Ring::Parity(ref mut ring) => ring.remove(&name),
+        }
+        self.nodes.remove(&name);
+    }
+
+    /// Gets the node a specific key belongs to.
+    pub fn get_node(&self, key: String) -> Option<&T> {
+        let name = match self.ring {
+            Ring::Consistant(ref ring) => ring.get(key),
+            Ring::Parity(ref ring) => ring.get(&key),
+        };
+        name.and_then(|name| self.nodes.get(&name))
+    }
+}
+
+struct ParityRing {
+    replicas: usize,
+    ring: HashMap<u64, String>,
+    sorted_keys: Vec<u64>,
+}
+
+impl ParityRing {
+    fn add(&mut self, name: &str) {
+        for i in 0..self.replicas {
+            let key = gen_key(&format!("{}:{}", name, i));
+            if self.ring.insert(key, String::from(name)).is_none() {
+                self.sorted_keys.push(key);
+            }
+        }
+        self.sorted_keys.sort();
+    }
+
+    fn remove(&mut self, name: &str) {
+        for i in 0..self.replicas {
+            let key = gen_key(&format!("{}:{}", name, i));
+            if self.ring.remove(&key).is_some() {
+                if let Ok(index) = self.sorted_keys.binary_search(&key) {
+                    self.sorted_keys.remove(index);
+                }
+            }
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        if self.sorted_keys.is_empty() {
+            return None;
+        }
+        let key = gen_key(key);
+        let index = self.sorted_keys.partition_point(|node| *node < key);
+        let node = self.sorted_keys.get(index).unwrap_or(&self.sorted_keys[0]);
+        self.ring.get(node).cloned()
+    }
+}
+
+fn gen_key(key: &str) -> u64 {
+    let mut hasher = XxHash64::with_seed(0);
+    hasher.write(key.as_bytes());
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct NodeInfo {
+        host: &'static str,
+        port: u16,
+    }
+
+    impl fmt::Display for NodeInfo {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+
+    fn node(port: u16) -> NodeInfo {
+        NodeInfo {
+            host: "localhost",
+            port,
+        }
+    }
+
+    #[test]
+    fn test_api_shape() {
+        let empty: HashRing<NodeInfo> = HashRing::new(vec![], 10);
+        assert_eq!(empty.get_node(String::from("hello")), None);
+
+        let mut hash_ring = HashRing::new(vec![node(15324), node(15325)], 10);
+        let owner = hash_ring.get_node(String::from("hello")).cloned().unwrap();
+
+        hash_ring.add_node(&node(15326));
+        hash_ring.remove_node(&node(15326));
+        assert_eq!(hash_ring.get_node(String::from("hello")), Some(&owner));
+
+        hash_ring.remove_node(&node(15324));
+        hash_ring.remove_node(&node(15325));
+        assert_eq!(hash_ring.get_node(String::from("hello")), None);
+    }
+
+    #[test]
+    fn test_parity_default_nodes() {
+        // The vectors of the `hash_ring` crate's own tests.
+        let nodes = (15324..15330).map(node).collect();
+        let mut hash_ring = HashRing::new_parity(nodes, 10);
+
+        assert_eq!(Some(&node(15324)), hash_ring.get_node(String::from("two")));
+        assert_eq!(Some(&node(15325)), hash_ring.get_node(String::from("seven")));
+        assert_eq!(Some(&node(15326)), hash_ring.get_node(String::from("hello")));
+        assert_eq!(Some(&node(15327)), hash_ring.get_node(String::from("dude")));
+        assert_eq!(Some(&node(15328)), hash_ring.get_node(String::from("fourteen")));
+        assert_eq!(Some(&node(15329)), hash_ring.get_node(String::from("five")));
+
+        hash_ring.remove_node(&node(15329));
+        assert_eq!(Some(&node(15326)), hash_ring.get_node(String::from("hello")));
+
+        hash_ring.add_node(&node(15329));
+        assert_eq!(Some(&node(15326)), hash_ring.get_node(String::from("hello")));
+    }
+
+    #[test]
+    fn test_parity_recorded_vectors() {
+        let nodes: Vec<String> = (0..5).map(|i| format!("node-{}", i)).collect();
+        let mut hash_ring = HashRing::new_parity(nodes.clone(), 10);
+        let mut removed = false;
+
+        let lines = include_str!("../tests/fixtures/hash_ring-0.2.0.tsv").lines();
+        for line in lines.filter(|line| !line.starts_with('#')) {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields[0] == "removed-node-2" && !removed {
+                hash_ring.remove_node(&nodes[2]);
+                removed = true;
+            }
+            assert_eq!(hash_ring.get_node(String::from(fields[1])).map(|node| node.as_str()),
+                       Some(fields[2]),
+                       "{}",
+                       line);
+        }
+        assert!(removed);
+    }
+}
@@ -0,0 +1,216 @@
+use heapless::Vec;
+use error::CapacityFull;
+use placement::checksum;
+use static_ring::vnode_checksum;
+
+/// StaticConsistant is a hash ring of up to `MAX_MEMBERS` elements of
+/// `REPLICAS` virtual nodes each, with the `heapless` feature, for targets
+/// without a heap: it is held in fixed-size arrays, and neither its
+/// mutations nor its lookups allocate. Keys are mapped as a `Consistant` of
+/// the same replicas and members would.
+///
+/// It takes `MAX_MEMBERS * (2 * W + 8 * REPLICAS) + 2 * W` bytes, `W` being
+/// the size of a pointer: a name and `REPLICAS` virtual nodes of 8 bytes for
+/// every element, and the counts of elements and virtual nodes.
+pub struct StaticConsistant<const MAX_MEMBERS: usize, const REPLICAS: usize> {
+    members: Vec<&'static str, MAX_MEMBERS>,
+    /// The `(hash, index of the element)` virtual nodes, the first `len`
+    /// of them being sorted by hash.
+    tokens: [[(u32, u32); REPLICAS]; MAX_MEMBERS],
+    len: usize,
+}
+
+impl<const MAX_MEMBERS: usize, const REPLICAS: usize> StaticConsistant<MAX_MEMBERS, REPLICAS> {
+    /// Crete a new instance of StaticConsistant, without any element.
+    pub const fn new() -> Self {
+        StaticConsistant {
+            members: Vec::new(),
+            tokens: [[(0, 0); REPLICAS]; MAX_MEMBERS],
+            len: 0,
+        }
+    }
+
+    /// Add an elment to the hash ring, returning whether it was added, or
+    /// `CapacityFull` if there are `MAX_MEMBERS` elements already.
+    pub fn add(&mut self, name: &'static str) -> Result<bool, CapacityFull> {
+        if self.contains(name) {
+            return Ok(false);
+        }
+        self.members.push(name).map_err(|_| CapacityFull)?;
+        let index = self.members.len() as u32 - 1;
+        let tokens = self.tokens.as_flattened_mut();
+        for i in 0..REPLICAS {
+            let hash = vnode_checksum(name, i);
+            match tokens[..self.len].binary_search_by_key(&hash, |token| token.0) {
+                // A colliding virtual node of another element is taken over.
+                Ok(j) => tokens[j].1 = index,
+                Err(j) => {
+                    tokens.copy_within(j..self.len, j + 1);
+                    tokens[j] = (hash, index);
+                    self.len += 1;
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Remove the given element, returning whether it was in.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let index = match self.members.iter().position(|member| *member == name) {
+            Some(index) => index as u32,
+            None => return false,
+        };
+        self.members.remove(index as usize);
+
+        // The order of the others is kept, their indexes shifting down.
+        let tokens = self.tokens.as_flattened_mut();
+        let mut kept = 0;
+        for i in 0..self.len {
+            let (hash, owner) = tokens[i];
+            if owner != index {
+                tokens[kept] = (hash, if owner > index { owner - 1 } else { owner });
+                kept += 1;
+            }
+        }
+        self.len = kept;
+        true
+    }
+
+    /// Get the cloeset element's name to the given key.
+    pub fn get<S: AsRef<str>>(&self, key: S) -> Option<&'static str> {
+        let tokens = self.tokens();
+        if tokens.is_empty() {
+            return None;
+        }
+        let start = successor(tokens, checksum(0, key.as_ref().as_bytes()));
+        Some(self.members[tokens[start].1 as usize])
+    }
+
+    /// Get the N cloeset distinct elements' names to the given key, as
+    /// `Consistant::get_n` does, or all of them if there are not as many.
+    pub fn get_n<S: AsRef<str>>(&self, key: S, n: usize) -> Vec<&'static str, MAX_MEMBERS> {
+        let mut owners = Vec::new();
+        let tokens = self.tokens();
+        if tokens.is_empty() {
+            return owners;
+        }
+        let count = n.min(self.members.len());
+        let start = successor(tokens, checksum(0, key.as_ref().as_bytes()));
+        for i in (start..tokens.len()).chain(0..start) {
+            if owners.len() == count {
+                break;
+            }
+            let owner = self.members[tokens[i].1 as usize];
+            if !owners.contains(&owner) {
+                let _ = owners.push(owner);
+            }
+        }
+        owners
+    }
+
+    /// Check whether the given element is in the hash ring.
+    pub fn contains(&self, name: &str) -> bool {
+        self.members.contains(&name)
+    }
+
+    /// Get the names of the elements, in the order they were added.
+    pub fn members(&self) -> &[&'static str] {
+        &self.members
+    }
+
+    /// Get the count of elements.
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Check whether there is no element.
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    fn tokens(&self) -> &[(u32, u32)] {
+        &self.tokens.as_flattened()[..self.len]
+    }
+}
+
+impl<const MAX_MEMBERS: usize, const REPLICAS: usize> Default
+    for StaticConsistant<MAX_MEMBERS, REPLICAS> {
+    fn default() -> Self {
+        StaticConsistant::new()
+    }
+}
+
+/// Get the index of the first token past the given checksum, wrapping around.
+fn successor(tokens: &[(u32, u32)], sum: u32) -> usize {
+    match tokens.partition_point(|token| token.0 <= sum) {
+        index if index == tokens.len() => 0,
+        index => index,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem;
+    use consistant::Consistant;
+
+    const NAMES: &[&str] = &["node-1", "node-11", "node-2", "node-3", "node-31", "node-4",
+                             "node-5", "node-6"];
+
+    fn assert_same(ring: &StaticConsistant<8, 20>, consistant: &Consistant) {
+        for i in 0..2000 {
+            let key = format!("key{}", i);
+            assert_eq!(ring.get(&key).map(String::from), consistant.get(&key));
+            let owners: std::vec::Vec<String> =
+                ring.get_n(&key, 3).iter().map(|owner| String::from(*owner)).collect();
+            assert_eq!(Some(owners), consistant.get_n(&key, 3), "{}", key);
+        }
+    }
+
+    #[test]
+    fn test_static_consistant() {
+        let mut ring: StaticConsistant<8, 20> = StaticConsistant::new();
+        assert!(ring.is_empty());
+        assert_eq!((ring.get("david"), ring.get_n("david", 3).len()), (None, 0));
+        assert!(!ring.remove("node-1"));
+
+        // "node-1" followed by 1x is also "node-11" followed by x.
+        let mut consistant = Consistant::new(20);
+        for name in NAMES {
+            assert_eq!(ring.add(name), Ok(true));
+            consistant.add(*name);
+        }
+        assert_eq!(ring.add("node-1"), Ok(false));
+        assert_eq!(ring.add("node-7"), Err(CapacityFull));
+        assert_eq!((ring.len(), ring.members()), (8, NAMES));
+        assert_same(&ring, &consistant);
+        assert_eq!(ring.get_n("david", 20).len(), 8);
+
+        // The virtual nodes taken over go with the elements removed, and
+        // an element added back takes its colliding ones over again.
+        for name in &["node-3", "node-11", "node-6"] {
+            assert!(ring.remove(name));
+            consistant.remove(*name);
+            assert_same(&ring, &consistant);
+        }
+        assert!(!ring.remove("node-3"));
+        assert_eq!(ring.add("node-3"), Ok(true));
+        consistant.add("node-3");
+        assert_same(&ring, &consistant);
+        assert!(ring.contains("node-3") && !ring.contains("node-11"));
+
+        for name in ring.members().to_vec() {
+            ring.remove(name);
+        }
+        assert_eq!(ring.get("david"), None);
+    }
+
+    #[test]
+    fn test_static_consistant_size() {
+        let pointer = mem::size_of::<usize>();
+        assert_eq!(mem::size_of::<StaticConsistant<8, 20>>(),
+                   8 * (2 * pointer + 8 * 20) + 2 * pointer);
+        assert_eq!(mem::size_of::<StaticConsistant<64, 160>>(),
+                   64 * (2 * pointer + 8 * 160) + 2 * pointer);
+    }
+}
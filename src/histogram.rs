@@ -0,0 +1,104 @@
+use std::collections::hash_map::HashMap;
+use consistant::Consistant;
+use error::Error;
+
+/// BucketOwnership describes which elements own a bucket of the keyspace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BucketOwnership {
+    /// The first checksum of the bucket.
+    pub start: u32,
+    /// The last checksum of the bucket, inclusive.
+    pub end: u32,
+    /// The fraction of the bucket owned by each element.
+    pub shares: HashMap<String, f64>,
+}
+
+impl Consistant {
+    /// Divide the u32 keyspace into equal buckets and get the fraction of each
+    /// bucket owned by each element. The shares are empty if the hash ring is
+    /// empty.
+    pub fn histogram(&self, buckets: usize) -> Result<Vec<BucketOwnership>, Error> {
+        if buckets == 0 || buckets as u64 > 1 << 32 {
+            return Err(Error::InvalidBucketCount(buckets));
+        }
+
+        let arcs = self.arcs();
+        let mut res = Vec::with_capacity(buckets);
+        let mut i = 0;
+
+        for b in 0..buckets as u64 {
+            let start = b * (1 << 32) / buckets as u64;
+            let end = (b + 1) * (1 << 32) / buckets as u64;
+            let width = (end - start) as f64;
+            let mut shares: HashMap<String, f64> = HashMap::new();
+
+            while i < arcs.len() && arcs[i].0 < end {
+                let (arc_start, arc_end, ref element) = arcs[i];
+                let overlap = arc_end.min(end) - arc_start.max(start);
+                *shares.entry(String::from(&**element)).or_insert(0.0) += overlap as f64 / width;
+                if arc_end > end {
+                    break;
+                }
+                i += 1;
+            }
+
+            res.push(BucketOwnership {
+                start: start as u32,
+                end: (end - 1) as u32,
+                shares,
+            });
+        }
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let mut consistant = Consistant::default();
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+        consistant.add("cacheC");
+
+        for buckets in &[1, 7, 64, 1000] {
+            let histogram = consistant.histogram(*buckets).unwrap();
+            assert_eq!(histogram.len(), *buckets);
+            assert_eq!(histogram[0].start, 0);
+            assert_eq!(histogram[buckets - 1].end, u32::MAX);
+
+            for (i, bucket) in histogram.iter().enumerate() {
+                if i > 0 {
+                    assert_eq!(bucket.start, histogram[i - 1].end + 1);
+                }
+                let sum: f64 = bucket.shares.values().sum();
+                assert!((sum - 1.0).abs() < 1e-9);
+            }
+        }
+
+        let histogram = consistant.histogram(1).unwrap();
+        assert_eq!(histogram[0].shares.len(), 3);
+    }
+
+    #[test]
+    fn test_histogram_single_member() {
+        let mut consistant = Consistant::default();
+        consistant.add("cacheA");
+
+        for bucket in consistant.histogram(16).unwrap() {
+            assert_eq!(bucket.shares.len(), 1);
+            assert!((bucket.shares["cacheA"] - 1.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_histogram_invalid() {
+        let consistant = Consistant::default();
+        assert_eq!(consistant.histogram(0),
+                   Err(Error::InvalidBucketCount(0)));
+        assert!(consistant.histogram(4).unwrap().iter().all(|bucket| bucket.shares.is_empty()));
+    }
+}
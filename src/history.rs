@@ -0,0 +1,254 @@
+use std::borrow::Cow;
+use std::collections::hash_map::HashMap;
+use std::collections::vec_deque::VecDeque;
+use std::sync::Arc;
+use consistant::Consistant;
+use error::VersionEvicted;
+use fractional::{admits, next_admitting};
+use handle::Slots;
+use normalize::KeyNormalizer;
+use range_override::RangeOverrides;
+use segments::SortedKeys;
+use siphash::KeyHasher;
+
+/// History keeps the snapshots of the last versions of a hash ring, see
+/// `Consistant::set_keep_versions`, the oldest first.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct History {
+    capacity: usize,
+    snapshots: VecDeque<Arc<VersionSnapshot>>,
+}
+
+/// VersionSnapshot is what lookups need of a hash ring at a version. The
+/// slots share the names of the elements with the hash ring.
+#[derive(Debug)]
+pub(crate) struct VersionSnapshot {
+    version: u64,
+    hasher: KeyHasher,
+    normalizer: Option<KeyNormalizer>,
+    prefix_rules: Vec<(String, u32)>,
+    range_overrides: RangeOverrides<u32>,
+    /// The fractions of the fractional elements, by slot.
+    fractions: HashMap<u32, u32>,
+    keys: SortedKeys,
+    slots: Slots,
+}
+
+impl History {
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Keep the given snapshot, evicting the oldest one past the capacity.
+    /// A version already kept is not kept again.
+    pub(crate) fn record(&mut self, snapshot: Option<VersionSnapshot>) {
+        let snapshot = match snapshot {
+            Some(snapshot) => snapshot,
+            None => return,
+        };
+        if self.snapshots.back().is_some_and(|last| last.version == snapshot.version) {
+            return;
+        }
+        self.snapshots.push_back(Arc::new(snapshot));
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.snapshots.len() > self.capacity {
+            self.snapshots.pop_front();
+        }
+    }
+
+    fn find(&self, version: u64) -> Option<&VersionSnapshot> {
+        self.snapshots
+            .binary_search_by_key(&version, |snapshot| snapshot.version)
+            .ok()
+            .map(|index| &*self.snapshots[index])
+    }
+}
+
+impl VersionSnapshot {
+    /// Take the snapshot of the given hash ring, of the given version and
+    /// virtual nodes, which must be sorted. Its lock must be held.
+    pub(crate) fn new(consistant: &Consistant, version: u64, keys: &SortedKeys) -> VersionSnapshot {
+        let slots = consistant.slots();
+        VersionSnapshot {
+            version,
+            hasher: consistant.key_hasher(),
+            normalizer: consistant.key_normalizer().cloned(),
+            prefix_rules: consistant.prefix_rule_map()
+                .iter()
+                .filter_map(|(prefix, member)| {
+                    slots.index(member).map(|owner| (prefix.clone(), owner))
+                })
+                .collect(),
+            range_overrides: consistant.range_overrides().filter_map(|member| slots.index(member)),
+            fractions: consistant.fractions()
+                .iter()
+                .filter_map(|(member, fraction)| {
+                    slots.index(member).map(|owner| (owner, *fraction))
+                })
+                .collect(),
+            keys: keys.clone(),
+            slots: slots.clone(),
+        }
+    }
+
+    /// Get the element of the given key, as `Consistant::get` did.
+    fn get(&self, key: &str) -> Option<&str> {
+        if self.keys.is_empty() {
+            return None;
+        }
+        let key = match self.normalizer {
+            Some(ref normalizer) => normalizer.normalize(key),
+            None => Cow::Borrowed(key),
+        };
+        let routed = self.prefix_rules
+            .iter()
+            .filter(|rule| key.starts_with(rule.0.as_str()))
+            .max_by_key(|rule| rule.0.len());
+        let sum = self.hasher.checksum(key.as_bytes());
+        let owner = match (routed, self.range_overrides.find(sum)) {
+            (Some(rule), _) => rule.1,
+            (None, Some(range)) => *range.2,
+            (None, None) => {
+                let start = self.keys.successor(sum);
+                let routed = next_admitting(start, self.keys.len(), |index| {
+                    let owner = self.keys[index].1;
+                    let admitted = self.fractions
+                        .get(&owner)
+                        .is_none_or(|fraction| admits(self.slots.name(owner), *fraction, sum));
+                    (owner, admitted)
+                });
+                routed.unwrap_or(self.keys[start].1)
+            }
+        };
+        Some(self.slots.name(owner))
+    }
+}
+
+impl Consistant {
+    /// Keep the routing of the last `versions` versions of the hash ring, for
+    /// `get_at_version`, 0 for none, the default. With it, every mutation
+    /// bumping the version first takes a snapshot of the virtual nodes, the
+    /// oldest one past the count being evicted. The snapshots share the
+    /// element names with the hash ring, and are not serialized.
+    pub fn set_keep_versions(&mut self, versions: usize) {
+        let history = self.history_mut();
+        history.capacity = versions;
+        history.evict();
+    }
+
+    /// Get the count of past versions kept, see `set_keep_versions`.
+    pub fn keep_versions(&self) -> usize {
+        self.history().capacity
+    }
+
+    /// Get the versions `get_at_version` answers for, the oldest first, the
+    /// current one last.
+    pub fn available_versions(&self) -> Vec<u64> {
+        let version = self.version();
+        let mut versions: Vec<u64> = self.history()
+            .snapshots
+            .iter()
+            .map(|snapshot| snapshot.version)
+            .filter(|kept| *kept != version)
+            .collect();
+        versions.push(version);
+        versions
+    }
+
+    /// Get the cloeset element's name to the given key as of the given
+    /// version of the hash ring, the current one being the one of `get`.
+    /// The routing of a past version is the one just before the mutation
+    /// leaving it, prefix rules, range assignments, fractional weights and
+    /// key normalizer included.
+    pub fn get_at_version<S: AsRef<str>>(&self,
+                                         version: u64,
+                                         key: S)
+                                         -> Result<Option<String>, VersionEvicted> {
+        if version == self.version() {
+            return Ok(self.get(key));
+        }
+        match self.history().find(version) {
+            Some(snapshot) => Ok(snapshot.get(key.as_ref()).map(String::from)),
+            None => Err(VersionEvicted { version }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_at_version() {
+        let mut consistant = Consistant::new(20);
+        consistant.set_keep_versions(3);
+        let mut rings = Vec::new();
+        for i in 0..6 {
+            rings.push(consistant.clone());
+            consistant.add(format!("node-{}", i));
+        }
+        consistant.add("node-0");
+        rings.push(consistant.clone());
+        consistant.remove("node-2");
+        consistant.assign_range(1 << 31, 0, "node-4").unwrap();
+        rings.push(consistant.clone());
+        consistant.add_prefix_rule("tenant-42/", "node-1").unwrap();
+        consistant.set_fractional_weight("node-5", 0.1).unwrap();
+        rings.push(consistant.clone());
+        consistant.retain(|element| element != "node-3");
+
+        assert_eq!(consistant.version(), 9);
+        assert_eq!(consistant.available_versions(), [6, 7, 8, 9]);
+        assert_eq!(consistant.get_at_version(5, "david"), Err(VersionEvicted { version: 5 }));
+        assert_eq!(consistant.get_at_version(10, "david"), Err(VersionEvicted { version: 10 }));
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            assert_eq!(consistant.get_at_version(9, &key), Ok(consistant.get(&key)));
+            for version in 6..9 {
+                let ring = &rings[version as usize];
+                assert_eq!(ring.version(), version);
+                assert_eq!(consistant.get_at_version(version, &key), Ok(ring.get(&key)));
+            }
+        }
+        // The rule was added at version 7, before the mutation leaving it.
+        assert_eq!(consistant.get_at_version(7, "tenant-42/david"),
+                   Ok(Some(String::from("node-1"))));
+        assert_eq!(consistant.get_at_version(6, "tenant-42/david"),
+                   Ok(rings[6].get("tenant-42/david")));
+    }
+
+    #[test]
+    fn test_keep_versions() {
+        let mut consistant = Consistant::new(20);
+        assert_eq!(consistant.keep_versions(), 0);
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+        assert_eq!(consistant.available_versions(), [2]);
+
+        consistant.set_keep_versions(2);
+        for i in 0..50 {
+            consistant.add(format!("node-{}", i));
+            assert!(consistant.available_versions().len() <= 3);
+        }
+        assert_eq!(consistant.available_versions(), [50, 51, 52]);
+        assert_eq!(consistant.get_at_version(50, "david").unwrap(),
+                   consistant.clone().get_at_version(50, "david").unwrap());
+
+        // The snapshots share the element names with the hash ring.
+        let snapshot = consistant.history().find(51).unwrap();
+        let owner = consistant.slots().index("cacheA").unwrap();
+        assert!(Arc::ptr_eq(consistant.slots().name(owner), snapshot.slots.name(owner)));
+
+        // Failed or empty mutations take no snapshot.
+        consistant.add("cacheA");
+        consistant.remove("cacheC");
+        assert_eq!(consistant.available_versions(), [50, 51, 52]);
+        consistant.set_keep_versions(1);
+        assert_eq!(consistant.available_versions(), [51, 52]);
+        consistant.set_keep_versions(0);
+        assert_eq!(consistant.available_versions(), [52]);
+    }
+}
@@ -0,0 +1,541 @@
+use std::convert::TryFrom;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::str;
+use crc::crc32::checksum_ieee;
+use consistant::Consistant;
+use error::{Error, JournalError, SnapshotError};
+use siphash::SipKey;
+
+/// The version of the journal format. A journal of another version is not
+/// replayed.
+pub const JOURNAL_VERSION: u8 = 1;
+
+const MAGIC: &[u8; 4] = b"CSJL";
+/// The magic bytes, the version and the epoch.
+const HEADER_LEN: usize = 13;
+
+const ADD: u8 = 1;
+const REMOVE: u8 = 2;
+const REWEIGHT: u8 = 3;
+const CLEAR: u8 = 4;
+
+/// JournaledRing keeps a hash ring durable by appending each of its
+/// membership changes to a journal, synced to disk before the change is
+/// made, and replaying them when opened again. `checkpoint` writes a
+/// snapshot of the hash ring next to the journal, see `Consistant::save`,
+/// at the path of the journal with `.snapshot` appended, and empties the
+/// journal.
+///
+/// Every record is framed by its length and CRC-32, so that a record torn
+/// by a crash ends the replay instead of failing it: the journal is cut back
+/// to the last whole record, see `discarded_bytes`. The journal and the
+/// snapshot carry the count of checkpoints taken, so that a journal left
+/// behind by a checkpoint cut short is not replayed on its snapshot.
+#[derive(Debug)]
+pub struct JournaledRing {
+    consistant: Consistant,
+    path: PathBuf,
+    journal: File,
+    /// The length of the journal, up to its last whole record.
+    len: u64,
+    epoch: u64,
+    discarded: u64,
+}
+
+impl JournaledRing {
+    /// Open the journal at the given path, replaying it on the snapshot of
+    /// its last checkpoint, or start a fresh one of an empty
+    /// `Consistant::default()` if there is none.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<JournaledRing, JournalError> {
+        JournaledRing::open_with(path, Consistant::default())
+    }
+
+    /// Same as `open`, a fresh journal starting with the given hash ring,
+    /// which is checkpointed at once. It is ignored if the journal exists,
+    /// but for its secret, which a keyed snapshot is loaded with, see
+    /// `Consistant::load_keyed`.
+    pub fn open_with<P: AsRef<Path>>(path: P,
+                                     initial: Consistant)
+                                     -> Result<JournaledRing, JournalError> {
+        let path = path.as_ref().to_path_buf();
+        let snapshot = snapshot_path(&path);
+        let (epoch, mut consistant) = match File::open(&snapshot) {
+            Ok(file) => read_snapshot(file, initial.key_hasher().secret)?,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                write_snapshot(&snapshot, 0, &initial)?;
+                (0, initial)
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut journal =
+            OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+        let mut bytes = Vec::new();
+        journal.read_to_end(&mut bytes)?;
+        let mut discarded = 0;
+        let len = match check_header(&bytes, epoch)? {
+            // A header torn or left behind by a checkpoint is rewritten.
+            None => write_header(&mut journal, epoch)?,
+            Some(records) => {
+                let valid = replay(&mut consistant, records);
+                let len = (HEADER_LEN + valid) as u64;
+                discarded = bytes.len() as u64 - len;
+                if discarded > 0 {
+                    journal.set_len(len)?;
+                    journal.sync_data()?;
+                }
+                len
+            }
+        };
+        journal.seek(SeekFrom::Start(len))?;
+
+        Ok(JournaledRing {
+            consistant,
+            path,
+            journal,
+            len,
+            epoch,
+            discarded,
+        })
+    }
+
+    /// Get the hash ring, for lookups.
+    pub fn ring(&self) -> &Consistant {
+        &self.consistant
+    }
+
+    /// Get the hash ring, leaving the journal as it is.
+    pub fn into_inner(self) -> Consistant {
+        self.consistant
+    }
+
+    /// Get the path of the journal.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the count of bytes cut from the end of the journal when it was
+    /// opened, those of a record torn or corrupted, and of every one after.
+    pub fn discarded_bytes(&self) -> u64 {
+        self.discarded
+    }
+
+    /// Add an elment to the hash ring, see `Consistant::add`.
+    pub fn add<S: Into<String>>(&mut self, element: S) -> Result<(), JournalError> {
+        self.add_weighted(element, 1)
+    }
+
+    /// Add an elment to the hash ring with the given weight, see
+    /// `Consistant::add_weighted`. Nothing is journaled if nothing is added,
+    /// nor for an element over the limits of the hash ring, which fails, see
+    /// `Consistant::set_limits`.
+    pub fn add_weighted<S: Into<String>>(&mut self,
+                                         element: S,
+                                         weight: usize)
+                                         -> Result<(), JournalError> {
+        let element = element.into();
+        if weight == 0 || self.consistant.contains(&element) {
+            return Ok(());
+        }
+        let vnodes = self.consistant.replicas_num * weight;
+        self.consistant.check_limits(1, vnodes).map_err(Error::LimitExceeded)?;
+        self.append(&record(ADD, Some(weight), &element))?;
+        self.consistant.add_weighted(element, weight);
+        Ok(())
+    }
+
+    /// Remove the given element. Nothing is journaled if it is not in.
+    pub fn remove<S: AsRef<str>>(&mut self, name: S) -> Result<(), JournalError> {
+        let name = name.as_ref();
+        if !self.consistant.contains(name) {
+            return Ok(());
+        }
+        self.append(&record(REMOVE, None, name))?;
+        self.consistant.remove(name);
+        Ok(())
+    }
+
+    /// Change the weight of the given element, as `MemberEntry::weight`
+    /// does. Nothing is journaled if the weight does not change, nor for an
+    /// error.
+    pub fn reweight(&mut self, name: &str, weight: usize) -> Result<(), JournalError> {
+        match self.consistant.weight(name) {
+            None => return Err(Error::UnknownMember(String::from(name)).into()),
+            Some(_) if weight == 0 => return Err(Error::InvalidWeight(String::from(name)).into()),
+            Some(current) if current == weight => return Ok(()),
+            Some(_) if self.consistant.explicit_tokens(name).is_some() => {
+                return Err(Error::ExplicitTokens(String::from(name)).into());
+            }
+            Some(current) if weight > current => {
+                let vnodes = self.consistant.replicas_num * (weight - current);
+                self.consistant.check_limits(0, vnodes).map_err(Error::LimitExceeded)?;
+            }
+            Some(_) => {}
+        }
+        self.append(&record(REWEIGHT, Some(weight), name))?;
+        self.consistant.member(name).weight(weight).apply()?;
+        Ok(())
+    }
+
+    /// Remove all the elements. Nothing is journaled if there are none.
+    pub fn clear(&mut self) -> Result<(), JournalError> {
+        if self.consistant.count() == 0 {
+            return Ok(());
+        }
+        self.append(&[CLEAR])?;
+        self.consistant.clear();
+        Ok(())
+    }
+
+    /// Write a snapshot of the hash ring, replacing the one of the last
+    /// checkpoint once it is synced, then empty the journal.
+    pub fn checkpoint(&mut self) -> Result<(), JournalError> {
+        let epoch = self.epoch + 1;
+        write_snapshot(&snapshot_path(&self.path), epoch, &self.consistant)?;
+        self.epoch = epoch;
+        self.len = write_header(&mut self.journal, epoch)?;
+        Ok(())
+    }
+
+    /// Append the given record, framed, and sync it. A record written in
+    /// part is cut back, for the next one not to follow it.
+    fn append(&mut self, record: &[u8]) -> io::Result<()> {
+        let mut frame = Vec::with_capacity(8 + record.len());
+        frame.extend_from_slice(&(record.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&checksum_ieee(record).to_le_bytes());
+        frame.extend_from_slice(record);
+        let written = self.journal.write_all(&frame).and_then(|_| self.journal.sync_data());
+        if let Err(err) = written {
+            let _ = self.journal.set_len(self.len);
+            let _ = self.journal.seek(SeekFrom::Start(self.len));
+            return Err(err);
+        }
+        self.len += frame.len() as u64;
+        Ok(())
+    }
+}
+
+fn snapshot_path(path: &Path) -> PathBuf {
+    let mut snapshot = path.as_os_str().to_owned();
+    snapshot.push(".snapshot");
+    PathBuf::from(snapshot)
+}
+
+/// Read the epoch and the hash ring of a snapshot written by
+/// `write_snapshot`, hashing its lookup keys with the given secret.
+fn read_snapshot(file: File, secret: Option<SipKey>) -> Result<(u64, Consistant), JournalError> {
+    let mut r = BufReader::new(file);
+    let mut epoch = [0; 8];
+    r.read_exact(&mut epoch).map_err(SnapshotError::from)?;
+    let consistant = Consistant::load_with_secret(r, secret)?;
+    Ok((u64::from_le_bytes(epoch), consistant))
+}
+
+/// Write the given epoch and a snapshot of the hash ring to a file next to
+/// the given path, then move it there once synced.
+fn write_snapshot(path: &Path, epoch: u64, consistant: &Consistant) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let mut w = BufWriter::new(File::create(&tmp)?);
+    w.write_all(&epoch.to_le_bytes())?;
+    consistant.save(&mut w)?;
+    w.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+    fs::rename(&tmp, path)?;
+    #[cfg(unix)]
+    {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            File::open(dir)?.sync_all()?;
+        }
+    }
+    Ok(())
+}
+
+/// Empty the journal but for the header of the given epoch, returning its
+/// length.
+fn write_header(journal: &mut File, epoch: u64) -> io::Result<u64> {
+    journal.set_len(0)?;
+    journal.seek(SeekFrom::Start(0))?;
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(MAGIC);
+    header.push(JOURNAL_VERSION);
+    header.extend_from_slice(&epoch.to_le_bytes());
+    journal.write_all(&header)?;
+    journal.sync_data()?;
+    Ok(HEADER_LEN as u64)
+}
+
+/// Get the records of the given journal, checked against the epoch of the
+/// snapshot, `None` if they are to be dropped.
+fn check_header(bytes: &[u8], epoch: u64) -> Result<Option<&[u8]>, JournalError> {
+    if bytes.len() < HEADER_LEN {
+        return Ok(None);
+    }
+    if &bytes[..4] != MAGIC {
+        return Err(JournalError::BadMagic);
+    }
+    if bytes[4] != JOURNAL_VERSION {
+        return Err(JournalError::UnsupportedVersion(bytes[4]));
+    }
+    let mut logged = [0; 8];
+    logged.copy_from_slice(&bytes[5..HEADER_LEN]);
+    let logged = u64::from_le_bytes(logged);
+    if logged > epoch {
+        return Err(JournalError::EpochMismatch {
+            journal: logged,
+            snapshot: epoch,
+        });
+    }
+    // The records of an older epoch are in the snapshot already.
+    Ok(if logged < epoch { None } else { Some(&bytes[HEADER_LEN..]) })
+}
+
+/// Replay the given records on the hash ring, up to the first incomplete,
+/// corrupted or invalid one, returning the length of those replayed.
+fn replay(consistant: &mut Consistant, records: &[u8]) -> usize {
+    let mut offset = 0;
+    while let Some((record, len)) = next_record(&records[offset..]) {
+        if apply(consistant, record).is_none() {
+            break;
+        }
+        offset += len;
+    }
+    offset
+}
+
+/// Get the first record of the given bytes and the length of its frame, if
+/// it is whole and matches its checksum.
+fn next_record(bytes: &[u8]) -> Option<(&[u8], usize)> {
+    if bytes.len() < 8 {
+        return None;
+    }
+    let (len, rest) = bytes.split_at(4);
+    let (sum, rest) = rest.split_at(4);
+    let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+    let sum = u32::from_le_bytes([sum[0], sum[1], sum[2], sum[3]]);
+    if rest.len() < len || checksum_ieee(&rest[..len]) != sum {
+        return None;
+    }
+    Some((&rest[..len], 8 + len))
+}
+
+/// Encode the given change as a record: its kind, its weight if any as 8
+/// little endian bytes, and the name of its element.
+fn record(kind: u8, weight: Option<usize>, name: &str) -> Vec<u8> {
+    let mut record = vec![kind];
+    if let Some(weight) = weight {
+        record.extend_from_slice(&(weight as u64).to_le_bytes());
+    }
+    record.extend_from_slice(name.as_bytes());
+    record
+}
+
+fn apply(consistant: &mut Consistant, record: &[u8]) -> Option<()> {
+    let (&kind, rest) = record.split_first()?;
+    match kind {
+        ADD => {
+            let (weight, name) = weighted(rest)?;
+            consistant.add_weighted(name, weight);
+        }
+        REMOVE => consistant.remove(str::from_utf8(rest).ok()?),
+        REWEIGHT => {
+            let (weight, name) = weighted(rest)?;
+            consistant.member(name).weight(weight).apply().ok()?;
+        }
+        CLEAR if rest.is_empty() => consistant.clear(),
+        _ => return None,
+    }
+    Some(())
+}
+
+fn weighted(rest: &[u8]) -> Option<(usize, &str)> {
+    if rest.len() < 8 {
+        return None;
+    }
+    let (weight, name) = rest.split_at(8);
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(weight);
+    let weight = usize::try_from(u64::from_le_bytes(bytes)).ok().filter(|weight| *weight > 0)?;
+    Some((weight, str::from_utf8(name).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn journal_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("consistent-journal-{}-{}", process::id(), name));
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(snapshot_path(&path));
+        path
+    }
+
+    fn assert_same(journaled: &JournaledRing, consistant: &Consistant) {
+        let ring = journaled.ring();
+        assert_eq!(ring.fingerprint(), consistant.fingerprint());
+        assert_eq!(ring.weighted_members(), consistant.weighted_members());
+        assert_eq!(ring.version(), consistant.version());
+    }
+
+    /// Apply the same changes to the journal and the hash ring.
+    fn mutate(journaled: &mut JournaledRing, consistant: &mut Consistant, round: usize) {
+        let element = format!("node-{}", round % 7);
+        match round % 5 {
+            0 | 1 => {
+                journaled.add_weighted(element.as_str(), 1 + round % 3).unwrap();
+                consistant.add_weighted(element, 1 + round % 3);
+            }
+            2 => {
+                journaled.remove(&element).unwrap();
+                consistant.remove(&element);
+            }
+            3 => {
+                let weight = 1 + round % 4;
+                if journaled.reweight(&element, weight).is_ok() {
+                    consistant.member(element).weight(weight).apply().unwrap();
+                }
+            }
+            _ if round % 20 == 19 => {
+                journaled.clear().unwrap();
+                consistant.clear();
+            }
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn test_journaled_ring() {
+        let path = journal_path("replay");
+        let mut journaled = JournaledRing::open_with(&path, Consistant::with_seed(30, 7)).unwrap();
+        let mut consistant = Consistant::with_seed(30, 7);
+        for round in 0..100 {
+            mutate(&mut journaled, &mut consistant, round);
+        }
+        assert_same(&journaled, &consistant);
+        assert!(consistant.version() > 0);
+        assert_eq!(journaled.reweight("node-x", 2).unwrap_err().to_string(),
+                   "unknown member \"node-x\"");
+        drop(journaled);
+
+        let mut journaled = JournaledRing::open(&path).unwrap();
+        assert_same(&journaled, &consistant);
+        assert_eq!(journaled.discarded_bytes(), 0);
+        assert_eq!(journaled.ring().replicas_num, 30);
+        for round in 100..150 {
+            mutate(&mut journaled, &mut consistant, round);
+        }
+        let journaled = JournaledRing::open(&path).unwrap();
+        assert_same(&journaled, &consistant);
+
+        // A file which is not a journal is left alone.
+        fs::write(&path, b"not a journal at all").unwrap();
+        assert!(matches!(JournaledRing::open(&path), Err(JournalError::BadMagic)));
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(snapshot_path(&path));
+    }
+
+    #[test]
+    fn test_torn_journal() {
+        let path = journal_path("torn");
+        let mut journaled = JournaledRing::open(&path).unwrap();
+        let mut consistant = Consistant::default();
+        for i in 0..10 {
+            journaled.add(format!("node-{}", i)).unwrap();
+            consistant.add(format!("node-{}", i));
+        }
+        let whole = fs::metadata(&path).unwrap().len();
+        journaled.add("node-torn").unwrap();
+        let torn = fs::metadata(&path).unwrap().len();
+        drop(journaled);
+
+        // The process was killed in the middle of writing the last record.
+        for cut in 1..torn - whole {
+            let file = OpenOptions::new().write(true).open(&path).unwrap();
+            file.set_len(torn - cut).unwrap();
+            drop(file);
+            let journaled = JournaledRing::open(&path).unwrap();
+            assert_same(&journaled, &consistant);
+            assert_eq!(journaled.discarded_bytes(), torn - cut - whole);
+            assert_eq!(fs::metadata(&path).unwrap().len(), whole);
+        }
+
+        // Appending after a recovery follows the last whole record.
+        let mut journaled = JournaledRing::open(&path).unwrap();
+        journaled.remove("node-3").unwrap();
+        consistant.remove("node-3");
+        drop(journaled);
+        let mut bytes = fs::read(&path).unwrap();
+        let journaled = JournaledRing::open(&path).unwrap();
+        assert_same(&journaled, &consistant);
+
+        // A corrupted record ends the replay, with the ones after it.
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        fs::write(&path, &bytes).unwrap();
+        let journaled = JournaledRing::open(&path).unwrap();
+        assert!(journaled.ring().contains("node-3"));
+        assert_eq!(journaled.ring().count(), 10);
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(snapshot_path(&path));
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let path = journal_path("checkpoint");
+        let mut journaled = JournaledRing::open(&path).unwrap();
+        let mut consistant = Consistant::default();
+        for round in 0..60 {
+            mutate(&mut journaled, &mut consistant, round);
+        }
+        let stale = fs::read(&path).unwrap();
+        journaled.checkpoint().unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), HEADER_LEN as u64);
+        assert_same(&JournaledRing::open(&path).unwrap(), &consistant);
+
+        for round in 60..90 {
+            mutate(&mut journaled, &mut consistant, round);
+        }
+        journaled.checkpoint().unwrap();
+        mutate(&mut journaled, &mut consistant, 90);
+        drop(journaled);
+        assert_same(&JournaledRing::open(&path).unwrap(), &consistant);
+
+        // A checkpoint cut short between the snapshot and the journal left
+        // the records of the snapshot, which are not replayed again.
+        let mut journaled = JournaledRing::open(&path).unwrap();
+        journaled.checkpoint().unwrap();
+        drop(journaled);
+        fs::write(&path, &stale).unwrap();
+        let journaled = JournaledRing::open(&path).unwrap();
+        assert_same(&journaled, &consistant);
+        drop(journaled);
+        assert_eq!(fs::metadata(&path).unwrap().len(), HEADER_LEN as u64);
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(snapshot_path(&path));
+    }
+
+    #[test]
+    fn test_keyed_journal() {
+        let path = journal_path("keyed");
+        let key = [7; 16];
+        let mut journaled = JournaledRing::open_with(&path, Consistant::with_key(20, key)).unwrap();
+        journaled.add("node-a").unwrap();
+        journaled.add("node-b").unwrap();
+        let expected = journaled.ring().get("david");
+        drop(journaled);
+
+        // The snapshot of the journal is only loaded with its secret.
+        assert!(matches!(JournaledRing::open(&path),
+                         Err(JournalError::Snapshot(SnapshotError::MissingKey))));
+        let journaled = JournaledRing::open_with(&path, Consistant::with_key(1, key)).unwrap();
+        assert!(journaled.ring().is_keyed());
+        assert_eq!(journaled.ring().count(), 2);
+        assert_eq!(journaled.ring().get("david"), expected);
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(snapshot_path(&path));
+    }
+}
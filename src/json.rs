@@ -0,0 +1,1068 @@
+//! JSON representation of the hash ring configuration.
+//!
+//! The schema of format version 15 is:
+//!
+//! ```text
+//! {
+//!   "version": 15,
+//!   "hasher": "crc32-ieee",
+//!   "vnode_naming": 1,
+//!   "seed": 0,
+//!   "keyed": true,
+//!   "ring_version": 3,
+//!   "replicas": 20,
+//!   "replication_factor": 3,
+//!   "min_self_spacing": 4294967,
+//!   "max_members": 1000,
+//!   "max_vnodes": 20000,
+//!   "members": [
+//!     {"name": "cacheA", "weight": 1, "fraction": 50000, "incarnation": 2,
+//!      "tags": ["ssd"], "tokens": [1161674923, ...]},
+//!     ...
+//!   ],
+//!   "prefix_rules": [{"prefix": "tenant-42/", "member": "cacheA"}, ...],
+//!   "range_assignments": [{"start": 4026531840, "end": 268435456,
+//!                          "member": "cacheB"}, ...],
+//!   "colocation_groups": [{"group": "session-42", "representative": "session-42"},
+//!                         ...]
+//! }
+//! ```
+//!
+//! `to_json` writes the fields in this order without whitespace, members
+//! sorted by name. `from_json` ignores unknown fields. The schema only changes
+//! along with `JSON_FORMAT_VERSION`.
+//!
+//! `keyed` is true for a hash ring whose lookup keys are hashed with a
+//! secret, see `Consistant::with_key`, and is left out for the others.
+//! `ring_version` is the value of `Consistant::version`, restored on load.
+//! `replication_factor` is the value of `Consistant::replication_factor`,
+//! left out when 1. `min_self_spacing` is the smallest distance between two
+//! virtual nodes of the same member in checksums, see
+//! `Consistant::set_min_self_spacing`, left out when 0. `max_members` and
+//! `max_vnodes` are the limits of `Consistant::set_limits`, each left out
+//! when there is none.
+//! `tokens` lists the virtual node hashes of a member which does not derive
+//! them from its name, see `Consistant::replace_node`, and is left out for the
+//! others. `fraction` is the share of its keys a member of a fractional
+//! weight admits in millionths, see `Consistant::set_fractional_weight`, and is
+//! left out for the others. `incarnation` is the value of `Consistant::incarnation`, left out
+//! when 0. The incarnations of the removed elements are not written, an
+//! element added again after loading starting over. `tags` lists the tags of
+//! a member, sorted, and is left out when it has none. `prefix_rules` lists
+//! the rules of `Consistant::add_prefix_rule` sorted by prefix, and is left
+//! out when there is none. `range_assignments` lists the ranges of
+//! `Consistant::assign_range` sorted by start, and is left out when there is
+//! none. `colocation_groups` lists the groups of
+//! `Consistant::define_colocation_group` sorted by group, and is left out
+//! when there is none.
+//!
+//! The key normalizer and the fallback of `Consistant::set_fallback` are not
+//! written, a loaded hash ring having none until they are set again. Nor is
+//! the secret of a keyed hash ring, which `from_json` refuses,
+//! `from_json_keyed` being given the secret again.
+//!
+//! Older documents are still loaded, the fields they lack being defaulted:
+//! `keyed` (from version 14) to whether a secret is given,
+//! `colocation_groups` (from version 13) to none, `max_members` and
+//! `max_vnodes` (from version 12) to none, `fraction` (from version 11) to
+//! none, `range_assignments` (from version 10) to none, `min_self_spacing` (from
+//! version 9) to 0, `replication_factor` (from
+//! version 8) to 1, `prefix_rules` (from version
+//! 7) to none, `tags` (from version 6) to none,
+//! `incarnation` (from version 5) to 0, `tokens` (from version 4) to the
+//! derived ones, `ring_version` (from version 3) to 0, `seed` (from version
+//! 2) to 0 and `vnode_naming` (from version 1) to the vnode naming scheme 1.
+
+use std::collections::hash_map::HashMap;
+use std::fmt::Write;
+use consistant::Consistant;
+use error::JsonError;
+use fractional::MILLIONTHS;
+use limits::{loaded_vnodes, ResourceLimits, MAX_LOADED_VNODES};
+use placement::PlacementStrategy;
+use siphash::SipKey;
+use snapshot::VNODE_NAMING_VERSION;
+
+/// The version of the JSON schema written by `to_json`.
+pub const JSON_FORMAT_VERSION: u64 = 15;
+
+/// The name of the hash function used by the hash ring.
+pub const HASHER_NAME: &str = "crc32-ieee";
+
+impl Consistant {
+    /// Get the JSON representation of the hash ring configuration.
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        write!(json,
+               "{{\"version\":{},\"hasher\":",
+               JSON_FORMAT_VERSION)
+            .unwrap();
+        write_string(&mut json, HASHER_NAME);
+        write!(json, ",\"vnode_naming\":{},\"seed\":{}", self.placement().id(), self.seed())
+            .unwrap();
+        if self.is_keyed() {
+            json.push_str(",\"keyed\":true");
+        }
+        write!(json,
+               ",\"ring_version\":{},\"replicas\":{}",
+               self.version(),
+               self.replicas_num)
+            .unwrap();
+        if self.replication_factor() != 1 {
+            write!(json, ",\"replication_factor\":{}", self.replication_factor()).unwrap();
+        }
+        if self.self_spacing() != 0 {
+            write!(json, ",\"min_self_spacing\":{}", self.self_spacing()).unwrap();
+        }
+        if let Some(limit) = self.limits().max_members {
+            write!(json, ",\"max_members\":{}", limit).unwrap();
+        }
+        if let Some(limit) = self.limits().max_vnodes {
+            write!(json, ",\"max_vnodes\":{}", limit).unwrap();
+        }
+        json.push_str(",\"members\":[");
+        for (i, (element, weight)) in self.weighted_members().into_iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            json.push_str("{\"name\":");
+            write_string(&mut json, &element);
+            write!(json, ",\"weight\":{}", weight).unwrap();
+            if let Some(fraction) = self.fraction(&element) {
+                write!(json, ",\"fraction\":{}", fraction).unwrap();
+            }
+            match self.incarnation(&element) {
+                Some(0) | None => {}
+                Some(incarnation) => write!(json, ",\"incarnation\":{}", incarnation).unwrap(),
+            }
+            match self.tags_of(&element) {
+                Some(ref tags) if !tags.is_empty() => {
+                    json.push_str(",\"tags\":[");
+                    for (j, tag) in tags.iter().enumerate() {
+                        if j > 0 {
+                            json.push(',');
+                        }
+                        write_string(&mut json, tag);
+                    }
+                    json.push(']');
+                }
+                _ => {}
+            }
+            if let Some(tokens) = self.explicit_tokens(&element) {
+                json.push_str(",\"tokens\":[");
+                for (j, token) in tokens.iter().enumerate() {
+                    if j > 0 {
+                        json.push(',');
+                    }
+                    write!(json, "{}", token).unwrap();
+                }
+                json.push(']');
+            }
+            json.push('}');
+        }
+        json.push(']');
+        let rules = self.prefix_rules();
+        if !rules.is_empty() {
+            json.push_str(",\"prefix_rules\":[");
+            for (i, (prefix, member)) in rules.iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                json.push_str("{\"prefix\":");
+                write_string(&mut json, prefix);
+                json.push_str(",\"member\":");
+                write_string(&mut json, member);
+                json.push('}');
+            }
+            json.push(']');
+        }
+        let ranges = self.range_assignments();
+        if !ranges.is_empty() {
+            json.push_str(",\"range_assignments\":[");
+            for (i, (start, end, member)) in ranges.iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                write!(json, "{{\"start\":{},\"end\":{},\"member\":", start, end).unwrap();
+                write_string(&mut json, member);
+                json.push('}');
+            }
+            json.push(']');
+        }
+        let groups = self.colocation_groups();
+        if !groups.is_empty() {
+            json.push_str(",\"colocation_groups\":[");
+            for (i, (group, representative)) in groups.iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                json.push_str("{\"group\":");
+                write_string(&mut json, group);
+                json.push_str(",\"representative\":");
+                write_string(&mut json, representative);
+                json.push('}');
+            }
+            json.push(']');
+        }
+        json.push('}');
+
+        json
+    }
+
+    /// Load a hash ring from its JSON representation, see `to_json`. The
+    /// document of a keyed hash ring is refused, see `from_json_keyed`.
+    pub fn from_json(json: &str) -> Result<Consistant, JsonError> {
+        Consistant::from_json_with_secret(json, None)
+    }
+
+    /// Load a keyed hash ring from its JSON representation, its lookup keys
+    /// being hashed with the given secret, see `with_key`. The document of a
+    /// hash ring which is not keyed is refused, unless it predates `keyed`.
+    pub fn from_json_keyed(json: &str, key: [u8; 16]) -> Result<Consistant, JsonError> {
+        Consistant::from_json_with_secret(json, Some(SipKey::new(key)))
+    }
+
+    fn from_json_with_secret(json: &str,
+                             secret: Option<SipKey>)
+                             -> Result<Consistant, JsonError> {
+        let value = Parser {
+                bytes: json.as_bytes(),
+                pos: 0,
+            }
+            .parse_document()?;
+
+        let root = value.as_object("$")?;
+        let version = root.field("$", "version")?.as_u64("$.version")?;
+        if version == 0 || version > JSON_FORMAT_VERSION {
+            return Err(error("$.version", format!("unsupported version {}", version)));
+        }
+        let hasher = root.field("$", "hasher")?.as_str("$.hasher")?;
+        if hasher != HASHER_NAME {
+            return Err(error("$.hasher", format!("unknown hasher {:?}", hasher)));
+        }
+        // Version 1 predates the field, its mappings are those of scheme 1.
+        let mut naming = u64::from(VNODE_NAMING_VERSION);
+        if version > 1 {
+            naming = root.field("$", "vnode_naming")?.as_u64("$.vnode_naming")?;
+        }
+        let placement = if naming <= u64::from(u8::MAX) {
+            PlacementStrategy::from_id(naming as u8)
+        } else {
+            None
+        };
+        let placement = match placement {
+            Some(placement) => placement,
+            None => {
+                return Err(error("$.vnode_naming",
+                                 format!("unknown vnode naming scheme {}", naming)))
+            }
+        };
+        let mut seed = 0;
+        if version > 2 {
+            seed = root.field("$", "seed")?.as_u64("$.seed")?;
+        }
+        // Version 14 predates the field, its secret is the one given.
+        if version > 14 {
+            let keyed = match root.iter().find(|field| field.0 == "keyed") {
+                Some(field) => field.1.as_bool("$.keyed")?,
+                None => false,
+            };
+            match (keyed, secret.is_some()) {
+                (true, false) => {
+                    return Err(error("$.keyed", String::from("expected the secret of a keyed \
+                                                              hash ring")))
+                }
+                (false, true) => {
+                    return Err(error("$", String::from("expected a keyed hash ring")))
+                }
+                _ => {}
+            }
+        }
+        let mut ring_version = 0;
+        if version > 3 {
+            ring_version = root.field("$", "ring_version")?.as_u64("$.ring_version")?;
+        }
+        let replicas = root.field("$", "replicas")?.as_u64("$.replicas")?;
+
+        let mut consistant = Consistant::with_config(replicas as usize, placement, seed);
+        if let Some(field) = root.iter().find(|field| field.0 == "replication_factor") {
+            if version > 8 {
+                let factor = field.1.as_u64("$.replication_factor")?;
+                consistant.set_replication_factor(factor as usize);
+            }
+        }
+        // Set before the members are added, for their virtual nodes.
+        if let Some(field) = root.iter().find(|field| field.0 == "min_self_spacing") {
+            if version > 9 {
+                match field.1.as_u64("$.min_self_spacing")? {
+                    spacing if spacing <= u64::from(u32::MAX) => {
+                        consistant.set_self_spacing(spacing as u32)
+                    }
+                    _ => return Err(error("$.min_self_spacing", String::from("expected a u32"))),
+                }
+            }
+        }
+        let mut seen = HashMap::new();
+        let mut vnodes = 0;
+        for (i, member) in root.field("$", "members")?.as_array("$.members")?.iter().enumerate() {
+            let path = format!("$.members[{}]", i);
+            let member = member.as_object(&path)?;
+            let name = member.field(&path, "name")?.as_str(&format!("{}.name", path))?;
+            let weight_path = format!("{}.weight", path);
+            let weight = member.field(&path, "weight")?.as_u64(&weight_path)?;
+            if weight == 0 {
+                return Err(error(&weight_path, String::from("expected a positive integer")));
+            }
+            if seen.insert(name, i).is_some() {
+                return Err(error(&format!("{}.name", path),
+                                 format!("duplicate member {:?}", name)));
+            }
+            let count = match loaded_vnodes(consistant.replicas_num, weight, vnodes) {
+                Some(count) => count,
+                None => {
+                    return Err(error(&path,
+                                     format!("expected at most {} virtual nodes",
+                                             MAX_LOADED_VNODES)))
+                }
+            };
+            vnodes += count;
+            let fraction = match member.iter().find(|field| field.0 == "fraction") {
+                Some(field) if version > 11 => {
+                    let fraction_path = format!("{}.fraction", path);
+                    match field.1.as_u64(&fraction_path)? {
+                        fraction if fraction == 0 || fraction >= u64::from(MILLIONTHS) => {
+                            return Err(error(&fraction_path,
+                                             String::from("expected an integer in 1..1000000")));
+                        }
+                        _ if weight != 1 => {
+                            return Err(error(&fraction_path,
+                                             String::from("expected a member of weight 1")));
+                        }
+                        fraction => Some(fraction as u32),
+                    }
+                }
+                _ => None,
+            };
+            let incarnation = match member.iter().find(|field| field.0 == "incarnation") {
+                Some(field) if version > 5 => {
+                    field.1.as_u64(&format!("{}.incarnation", path))?
+                }
+                _ => 0,
+            };
+            let mut tags = Vec::new();
+            if let Some(field) = member.iter().find(|field| field.0 == "tags" && version > 6) {
+                let tags_path = format!("{}.tags", path);
+                for (j, tag) in field.1.as_array(&tags_path)?.iter().enumerate() {
+                    tags.push(String::from(tag.as_str(&format!("{}[{}]", tags_path, j))?));
+                }
+            }
+            let tokens = match member.iter().find(|field| field.0 == "tokens") {
+                Some(field) if version > 4 => field.1.as_array(&format!("{}.tokens", path))?,
+                _ => {
+                    consistant.add_weighted(name, weight as usize);
+                    consistant.set_incarnation(name, incarnation);
+                    consistant.insert_tags(name, tags);
+                    if let Some(fraction) = fraction {
+                        consistant.insert_fraction(name, fraction);
+                    }
+                    continue;
+                }
+            };
+            if tokens.len() != count {
+                return Err(error(&format!("{}.tokens", path),
+                                 format!("expected {} tokens, found {}", count, tokens.len())));
+            }
+            let mut sums = Vec::with_capacity(count);
+            for (j, token) in tokens.iter().enumerate() {
+                let token_path = format!("{}.tokens[{}]", path, j);
+                match token.as_u64(&token_path)? {
+                    sum if sum <= u64::from(u32::MAX) => sums.push(sum as u32),
+                    _ => return Err(error(&token_path, String::from("expected a u32"))),
+                }
+            }
+            consistant.add_with_tokens(String::from(name), weight as usize, sums);
+            consistant.set_incarnation(name, incarnation);
+            consistant.insert_tags(name, tags);
+            if let Some(fraction) = fraction {
+                consistant.insert_fraction(name, fraction);
+            }
+        }
+        if let Some(field) = root.iter().find(|field| field.0 == "prefix_rules" && version > 7) {
+            for (i, rule) in field.1.as_array("$.prefix_rules")?.iter().enumerate() {
+                let path = format!("$.prefix_rules[{}]", i);
+                let rule = rule.as_object(&path)?;
+                let prefix = rule.field(&path, "prefix")?.as_str(&format!("{}.prefix", path))?;
+                let member = rule.field(&path, "member")?.as_str(&format!("{}.member", path))?;
+                consistant.insert_prefix_rule(String::from(prefix), String::from(member));
+            }
+        }
+        if let Some(field) = root.iter().find(|field| field.0 == "range_assignments") {
+            if version > 10 {
+                for (i, range) in field.1.as_array("$.range_assignments")?.iter().enumerate() {
+                    let path = format!("$.range_assignments[{}]", i);
+                    let range = range.as_object(&path)?;
+                    let mut bounds = [0; 2];
+                    for (bound, name) in bounds.iter_mut().zip(&["start", "end"]) {
+                        let bound_path = format!("{}.{}", path, name);
+                        match range.field(&path, name)?.as_u64(&bound_path)? {
+                            sum if sum <= u64::from(u32::MAX) => *bound = sum as u32,
+                            _ => return Err(error(&bound_path, String::from("expected a u32"))),
+                        }
+                    }
+                    let member = range.field(&path, "member")?.as_str(&format!("{}.member", path))?;
+                    if let Err(err) = consistant.insert_range_assignment(bounds[0],
+                                                                         bounds[1],
+                                                                         String::from(member)) {
+                        return Err(error(&path, err.to_string()));
+                    }
+                }
+            }
+        }
+        // Set once the members are added, which they do not limit.
+        let limit = |name: &str| -> Result<Option<usize>, JsonError> {
+            match root.iter().find(|field| field.0 == name) {
+                Some(field) if version > 12 => {
+                    Ok(Some(field.1.as_u64(&format!("$.{}", name))? as usize))
+                }
+                _ => Ok(None),
+            }
+        };
+        consistant.set_limits(ResourceLimits {
+            max_members: limit("max_members")?,
+            max_vnodes: limit("max_vnodes")?,
+        });
+        // Checked once the members are added, for their owners.
+        if let Some(field) = root.iter()
+            .find(|field| field.0 == "colocation_groups" && version > 13) {
+            for (i, group) in field.1.as_array("$.colocation_groups")?.iter().enumerate() {
+                let path = format!("$.colocation_groups[{}]", i);
+                let group = group.as_object(&path)?;
+                let id = group.field(&path, "group")?.as_str(&format!("{}.group", path))?;
+                let representative = group.field(&path, "representative")?
+                    .as_str(&format!("{}.representative", path))?;
+                consistant.insert_colocation_group(String::from(id), String::from(representative));
+            }
+        }
+        consistant.set_secret(secret);
+        consistant.set_version(ring_version);
+
+        Ok(consistant)
+    }
+}
+
+fn error(path: &str, message: String) -> JsonError {
+    JsonError {
+        path: String::from(path),
+        message,
+    }
+}
+
+fn write_string(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    fn kind(&self) -> &'static str {
+        match *self {
+            Value::Null => "null",
+            Value::Bool(_) => "a boolean",
+            Value::Number(_) => "a number",
+            Value::String(_) => "a string",
+            Value::Array(_) => "an array",
+            Value::Object(_) => "an object",
+        }
+    }
+
+    fn unexpected(&self, path: &str, expected: &str) -> JsonError {
+        error(path, format!("expected {}, found {}", expected, self.kind()))
+    }
+
+    fn as_object(&self, path: &str) -> Result<&[(String, Value)], JsonError> {
+        match *self {
+            Value::Object(ref fields) => Ok(fields),
+            _ => Err(self.unexpected(path, "an object")),
+        }
+    }
+
+    fn as_array(&self, path: &str) -> Result<&[Value], JsonError> {
+        match *self {
+            Value::Array(ref values) => Ok(values),
+            _ => Err(self.unexpected(path, "an array")),
+        }
+    }
+
+    fn as_str(&self, path: &str) -> Result<&str, JsonError> {
+        match *self {
+            Value::String(ref s) => Ok(s),
+            _ => Err(self.unexpected(path, "a string")),
+        }
+    }
+
+    fn as_bool(&self, path: &str) -> Result<bool, JsonError> {
+        match *self {
+            Value::Bool(b) => Ok(b),
+            _ => Err(self.unexpected(path, "a boolean")),
+        }
+    }
+
+    fn as_u64(&self, path: &str) -> Result<u64, JsonError> {
+        match *self {
+            Value::Number(ref n) => {
+                n.parse().map_err(|_| error(path, String::from("expected a non-negative integer")))
+            }
+            _ => Err(self.unexpected(path, "a number")),
+        }
+    }
+}
+
+trait Fields {
+    fn field(&self, path: &str, name: &str) -> Result<&Value, JsonError>;
+}
+
+impl Fields for [(String, Value)] {
+    fn field(&self, path: &str, name: &str) -> Result<&Value, JsonError> {
+        match self.iter().find(|field| field.0 == name) {
+            Some(field) => Ok(&field.1),
+            None => Err(error(path, format!("missing field {:?}", name))),
+        }
+    }
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn parse_document(&mut self) -> Result<Value, JsonError> {
+        let value = self.parse_value()?;
+        self.skip_whitespace();
+        if self.pos < self.bytes.len() {
+            return Err(self.syntax_error("trailing characters"));
+        }
+        Ok(value)
+    }
+
+    fn syntax_error(&self, message: &str) -> JsonError {
+        error("$", format!("invalid JSON at byte {}: {}", self.pos, message))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.bytes.len() && b" \t\r\n".contains(&self.bytes[self.pos]) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.bytes.get(self.pos).cloned()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), JsonError> {
+        if self.peek() != Some(byte) {
+            return Err(self.syntax_error(&format!("expected {:?}", byte as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn parse_literal(&mut self, literal: &str, value: Value) -> Result<Value, JsonError> {
+        if !self.bytes[self.pos..].starts_with(literal.as_bytes()) {
+            return Err(self.syntax_error("unexpected character"));
+        }
+        self.pos += literal.len();
+        Ok(value)
+    }
+
+    fn parse_value(&mut self) -> Result<Value, JsonError> {
+        match self.peek() {
+            Some(b'{') => self.parse_object(),
+            Some(b'[') => self.parse_array(),
+            Some(b'"') => self.parse_string().map(Value::String),
+            Some(b't') => self.parse_literal("true", Value::Bool(true)),
+            Some(b'f') => self.parse_literal("false", Value::Bool(false)),
+            Some(b'n') => self.parse_literal("null", Value::Null),
+            Some(b'-') | Some(b'0'..=b'9') => self.parse_number(),
+            Some(_) => Err(self.syntax_error("unexpected character")),
+            None => Err(self.syntax_error("unexpected end of input")),
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Value, JsonError> {
+        self.expect(b'{')?;
+        let mut fields = Vec::new();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Value::Object(fields));
+        }
+        loop {
+            if self.peek() != Some(b'"') {
+                return Err(self.syntax_error("expected a field name"));
+            }
+            let name = self.parse_string()?;
+            self.expect(b':')?;
+            fields.push((name, self.parse_value()?));
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Value::Object(fields));
+                }
+                _ => return Err(self.syntax_error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value, JsonError> {
+        self.expect(b'[')?;
+        let mut values = Vec::new();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Value::Array(values));
+        }
+        loop {
+            values.push(self.parse_value()?);
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Value::Array(values));
+                }
+                _ => return Err(self.syntax_error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<Value, JsonError> {
+        let start = self.pos;
+        while self.pos < self.bytes.len() && b"+-.eE0123456789".contains(&self.bytes[self.pos]) {
+            self.pos += 1;
+        }
+        let number = String::from_utf8_lossy(&self.bytes[start..self.pos]).into_owned();
+        if number.parse::<f64>().is_err() {
+            self.pos = start;
+            return Err(self.syntax_error("invalid number"));
+        }
+        Ok(Value::Number(number))
+    }
+
+    fn parse_hex(&mut self) -> Result<u32, JsonError> {
+        let hex = self.bytes.get(self.pos..self.pos + 4).ok_or_else(|| {
+            self.syntax_error("unexpected end of input")
+        })?;
+        let code = ::std::str::from_utf8(hex)
+            .ok()
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| self.syntax_error("invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+
+    fn parse_string(&mut self) -> Result<String, JsonError> {
+        self.expect(b'"')?;
+        let mut bytes = Vec::new();
+        loop {
+            let byte = match self.bytes.get(self.pos) {
+                Some(byte) => *byte,
+                None => return Err(self.syntax_error("unterminated string")),
+            };
+            self.pos += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let escape = self.bytes.get(self.pos).cloned();
+                    self.pos += 1;
+                    let c = match escape {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            let mut code = self.parse_hex()?;
+                            if (0xd800..0xdc00).contains(&code) &&
+                               self.bytes[self.pos..].starts_with(b"\\u") {
+                                self.pos += 2;
+                                let low = self.parse_hex()?;
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00));
+                            }
+                            match ::std::char::from_u32(code) {
+                                Some(c) => c,
+                                None => return Err(self.syntax_error("invalid unicode escape")),
+                            }
+                        }
+                        _ => return Err(self.syntax_error("invalid escape")),
+                    };
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                byte if byte < 0x20 => return Err(self.syntax_error("control character in string")),
+                byte => bytes.push(byte),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| self.syntax_error("invalid UTF-8"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn consistant() -> Consistant {
+        let mut consistant = Consistant::new(20);
+        consistant.add("cacheB");
+        consistant.add_weighted("cacheA", 2);
+        consistant.add("cache\"C\"");
+        consistant
+    }
+
+    #[test]
+    fn test_to_json() {
+        assert_eq!(consistant().to_json(),
+                   "{\"version\":15,\"hasher\":\"crc32-ieee\",\"vnode_naming\":1,\"seed\":0,\
+                    \"ring_version\":3,\"replicas\":20,\"members\":[\
+                    {\"name\":\"cache\\\"C\\\"\",\"weight\":1},\
+                    {\"name\":\"cacheA\",\"weight\":2},\
+                    {\"name\":\"cacheB\",\"weight\":1}]}");
+        assert_eq!(Consistant::new(3).to_json(),
+                   "{\"version\":15,\"hasher\":\"crc32-ieee\",\"vnode_naming\":1,\"seed\":0,\
+                    \"ring_version\":0,\"replicas\":3,\"members\":[]}");
+    }
+
+    #[test]
+    fn test_from_json() {
+        let consistant = consistant();
+        let loaded = Consistant::from_json(&consistant.to_json()).unwrap();
+
+        assert_eq!(loaded.to_json(), consistant.to_json());
+        assert_eq!(loaded.weight("cacheA"), Some(2));
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            assert_eq!(loaded.get(key.as_str()), consistant.get(key.as_str()));
+        }
+
+        let loaded = Consistant::from_json(" { \"extra\": [null, true, 1.5e3, {}],
+            \"members\": [ {\"weight\": 1, \"name\": \"caf\\u00e9 \\ud83d\\ude00\"} ],
+            \"replicas\": 5, \"hasher\": \"crc32-ieee\", \"version\": 1 } ")
+            .unwrap();
+        assert_eq!(loaded.replicas_num, 5);
+        assert!(loaded.contains("café 😀"));
+    }
+
+    #[test]
+    fn test_json_placement() {
+        let mut consistant = Consistant::with_placement(20, PlacementStrategy::DoubleHash);
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+
+        let json = consistant.to_json();
+        assert!(json.contains("\"vnode_naming\":2"));
+        let loaded = Consistant::from_json(&json).unwrap();
+        assert_eq!(loaded.placement(), PlacementStrategy::DoubleHash);
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            assert_eq!(loaded.get(key.as_str()), consistant.get(key.as_str()));
+        }
+    }
+
+    #[test]
+    fn test_json_seed() {
+        let mut consistant = Consistant::with_seed(20, u64::MAX);
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+
+        let json = consistant.to_json();
+        assert!(json.contains("\"seed\":18446744073709551615"));
+        let loaded = Consistant::from_json(&json).unwrap();
+        assert_eq!(loaded.seed(), u64::MAX);
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            assert_eq!(loaded.get(key.as_str()), consistant.get(key.as_str()));
+        }
+    }
+
+    #[test]
+    fn test_json_fixtures() {
+        let v1 = Consistant::from_json(include_str!("../tests/fixtures/ring-v1.json")).unwrap();
+        let v2 = Consistant::from_json(include_str!("../tests/fixtures/ring-v2.json")).unwrap();
+        let v3 = Consistant::from_json(include_str!("../tests/fixtures/ring-v3.json")).unwrap();
+        let v4 = Consistant::from_json(include_str!("../tests/fixtures/ring-v4.json")).unwrap();
+        let v5 = Consistant::from_json(include_str!("../tests/fixtures/ring-v5.json")).unwrap();
+        let v6 = Consistant::from_json(include_str!("../tests/fixtures/ring-v6.json")).unwrap();
+        let v7 = Consistant::from_json(include_str!("../tests/fixtures/ring-v7.json")).unwrap();
+        let v8 = Consistant::from_json(include_str!("../tests/fixtures/ring-v8.json")).unwrap();
+        let v9 = Consistant::from_json(include_str!("../tests/fixtures/ring-v9.json")).unwrap();
+        let v10 = Consistant::from_json(include_str!("../tests/fixtures/ring-v10.json")).unwrap();
+        let v11 = Consistant::from_json(include_str!("../tests/fixtures/ring-v11.json")).unwrap();
+        let v12 = Consistant::from_json(include_str!("../tests/fixtures/ring-v12.json")).unwrap();
+        let v13 = Consistant::from_json(include_str!("../tests/fixtures/ring-v13.json")).unwrap();
+        let v14 = Consistant::from_json(include_str!("../tests/fixtures/ring-v14.json")).unwrap();
+        let v15 = Consistant::from_json_keyed(include_str!("../tests/fixtures/ring-v15.json"),
+                                              [7; 16])
+            .unwrap();
+
+        assert_eq!(v15.to_json(), include_str!("../tests/fixtures/ring-v15.json").trim_end());
+        assert!(v15.is_keyed());
+        assert_eq!(v15.vnodes(), v14.vnodes());
+        let mut keyed = Consistant::from_json(&v14.to_json()).unwrap();
+        keyed.set_hash_key(Some([7; 16]));
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            assert_eq!(v15.get(key.as_str()), keyed.get(key.as_str()));
+        }
+        assert_eq!(v14.to_json(), v15.to_json().replace(",\"keyed\":true", ""));
+        assert_eq!(v14.to_json(), include_str!("../tests/fixtures/ring-v14.json").trim_end()
+            .replace("\"version\":14", "\"version\":15"));
+        assert_eq!(v14.colocation_groups(),
+                   [(String::from("session-42"), String::from("tenant-42/session"))]);
+        assert_eq!(v14.get_grouped("session-42", "david"), Some(String::from("cacheC")));
+        assert_eq!(v14.vnodes(), v13.vnodes());
+        let ungrouped = v14.to_json()
+            .replace(",\"colocation_groups\":[{\"group\":\"session-42\",\
+                      \"representative\":\"tenant-42/session\"}]",
+                     "");
+        assert_eq!(v13.to_json(), ungrouped);
+        assert_eq!(v13.to_json(), include_str!("../tests/fixtures/ring-v13.json").trim_end()
+            .replace("\"version\":13", "\"version\":15"));
+        assert_eq!(v13.limits(), ResourceLimits::new().max_members(3).max_vnodes(80));
+        assert_eq!(v13.vnodes(), v12.vnodes());
+        let unlimited = v13.to_json().replace(",\"max_members\":3,\"max_vnodes\":80", "");
+        assert_eq!(v12.to_json(), unlimited);
+        assert_eq!(v12.to_json(), include_str!("../tests/fixtures/ring-v12.json").trim_end()
+            .replace("\"version\":12", "\"version\":15"));
+        assert_eq!(v12.fractional_weight("cacheA"), Some(0.05));
+        assert_eq!(v12.vnodes(), v11.vnodes());
+        assert_eq!(v11.to_json(), v12.to_json().replace(",\"fraction\":50000", ""));
+        assert_eq!(v11.to_json(), include_str!("../tests/fixtures/ring-v11.json").trim_end()
+            .replace("\"version\":11", "\"version\":15"));
+        assert_eq!(v11.range_assignments(),
+                   [(4026531840, 268435456, String::from("cacheB"))]);
+        assert_eq!(v11.get_by_hash(0), Some(String::from("cacheB")));
+        let unassigned = v11.to_json().replace(",\"range_assignments\":[{\"start\":4026531840,\
+                                                \"end\":268435456,\"member\":\"cacheB\"}]",
+                                               "");
+        assert_eq!(v10.to_json(), unassigned);
+        assert_eq!(v10.to_json(), include_str!("../tests/fixtures/ring-v10.json").trim_end()
+            .replace("\"version\":10", "\"version\":15"));
+        assert_eq!(v10.self_spacing(), 42949673);
+        assert!(v10.vnodes() != v9.vnodes());
+        assert_eq!(v9.to_json(), v10.to_json().replace(",\"min_self_spacing\":42949673", ""));
+        assert_eq!(v9.to_json(), include_str!("../tests/fixtures/ring-v9.json").trim_end()
+            .replace("\"version\":9", "\"version\":15"));
+        assert_eq!(v9.version(), 7);
+        assert_eq!(v9.replication_factor(), 3);
+        assert_eq!(v9.owners("david").unwrap().len(), 3);
+        assert_eq!(v8.to_json(), v9.to_json().replace(",\"replication_factor\":3", ""));
+        assert_eq!(v8.get("tenant-42/david"), Some(String::from("cacheC")));
+        let unruled = v8.to_json()
+            .replace(",\"prefix_rules\":[{\"prefix\":\"tenant-42/\",\"member\":\"cacheC\"}]", "");
+        assert_eq!(v7.to_json(), unruled);
+        assert_eq!(v7.tags_of("cacheC"), Some(vec![String::from("eu"), String::from("ssd")]));
+        let untagged = unruled
+            .replace(",\"tags\":[\"ssd\"]", "")
+            .replace(",\"tags\":[\"eu\",\"ssd\"]", "");
+        assert_eq!(v6.to_json(), untagged);
+        assert_eq!(v6.incarnation("cacheB"), Some(2));
+        let reborn = untagged.replace(",\"incarnation\":2", "");
+        assert_eq!(v5.to_json(), reborn);
+        assert_eq!(v4.to_json(), reborn);
+        let unversioned = reborn.replace("\"ring_version\":7", "\"ring_version\":0");
+        for older in &[v1, v2, v3] {
+            assert_eq!(older.to_json(), unversioned);
+        }
+    }
+
+    #[test]
+    fn test_json_keyed() {
+        let mut consistant = Consistant::with_key(20, [7; 16]);
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+
+        let json = consistant.to_json();
+        assert!(json.contains("\"seed\":0,\"keyed\":true,"));
+        let err = Consistant::from_json(&json).unwrap_err();
+        assert_eq!((err.path.as_str(), err.message.as_str()),
+                   ("$.keyed", "expected the secret of a keyed hash ring"));
+        let loaded = Consistant::from_json_keyed(&json, [7; 16]).unwrap();
+        assert_eq!(loaded.to_json(), json);
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            assert_eq!(loaded.get(key.as_str()), consistant.get(key.as_str()));
+        }
+
+        let unkeyed = json.replace(",\"keyed\":true", "");
+        let err = Consistant::from_json_keyed(&unkeyed, [7; 16]).unwrap_err();
+        assert_eq!((err.path.as_str(), err.message.as_str()), ("$", "expected a keyed hash ring"));
+        let err = Consistant::from_json(&json.replace("\"keyed\":true", "\"keyed\":1"))
+            .unwrap_err();
+        assert_eq!((err.path.as_str(), err.message.as_str()),
+                   ("$.keyed", "expected a boolean, found a number"));
+
+        // Older documents do not tell, and are loaded either way.
+        let older = json.replace("\"version\":15", "\"version\":14");
+        assert!(!Consistant::from_json(&older).unwrap().is_keyed());
+        assert!(Consistant::from_json_keyed(&older, [7; 16]).unwrap().is_keyed());
+    }
+
+    #[test]
+    fn test_json_tokens() {
+        let mut consistant = Consistant::new(2);
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+        consistant.replace_node("cacheA", "cacheC").unwrap();
+
+        let json = consistant.to_json();
+        assert_eq!(json,
+                   "{\"version\":15,\"hasher\":\"crc32-ieee\",\"vnode_naming\":1,\"seed\":0,\
+                    \"ring_version\":3,\"replicas\":2,\"members\":[\
+                    {\"name\":\"cacheB\",\"weight\":1},\
+                    {\"name\":\"cacheC\",\"weight\":1,\"tokens\":[1161674923,842723389]}]}");
+        let loaded = Consistant::from_json(&json).unwrap();
+        assert_eq!(loaded.vnodes(), consistant.vnodes());
+        assert_eq!(loaded.to_json(), json);
+
+        // Tokens are not read from older documents.
+        let loaded = Consistant::from_json(&json.replace("\"version\":15", "\"version\":4"))
+            .unwrap();
+        assert!(loaded.vnodes() != consistant.vnodes());
+    }
+
+    #[test]
+    fn test_from_json_errors() {
+        let cases = [("[]", "$", "expected an object, found an array"),
+                     ("{\"version\":16,\"hasher\":\"crc32-ieee\",\"replicas\":1,\"members\":[]}",
+                      "$.version",
+                      "unsupported version 16"),
+                     ("{\"version\":4,\"hasher\":\"crc32-ieee\",\"vnode_naming\":1,\"seed\":0,\
+                       \"replicas\":1,\"members\":[]}",
+                      "$",
+                      "missing field \"ring_version\""),
+                     ("{\"version\":3,\"hasher\":\"crc32-ieee\",\"vnode_naming\":1,\
+                       \"replicas\":1,\"members\":[]}",
+                      "$",
+                      "missing field \"seed\""),
+                     ("{\"version\":2,\"hasher\":\"crc32-ieee\",\"replicas\":1,\"members\":[]}",
+                      "$",
+                      "missing field \"vnode_naming\""),
+                     ("{\"version\":2,\"hasher\":\"crc32-ieee\",\"vnode_naming\":9,\"replicas\":1,\
+                       \"members\":[]}",
+                      "$.vnode_naming",
+                      "unknown vnode naming scheme 9"),
+                     ("{\"version\":1,\"hasher\":\"md5\",\"replicas\":1,\"members\":[]}",
+                      "$.hasher",
+                      "unknown hasher \"md5\""),
+                     ("{\"version\":1,\"hasher\":\"crc32-ieee\",\"members\":[]}",
+                      "$",
+                      "missing field \"replicas\""),
+                     ("{\"version\":1,\"hasher\":\"crc32-ieee\",\"replicas\":1,\"members\":[\
+                       {\"name\":\"a\",\"weight\":1},{\"name\":\"b\",\"weight\":-1}]}",
+                      "$.members[1].weight",
+                      "expected a non-negative integer"),
+                     ("{\"version\":1,\"hasher\":\"crc32-ieee\",\"replicas\":1,\"members\":[\
+                       {\"name\":\"a\",\"weight\":0}]}",
+                      "$.members[0].weight",
+                      "expected a positive integer"),
+                     ("{\"version\":1,\"hasher\":\"crc32-ieee\",\"replicas\":1,\"members\":[\
+                       {\"name\":\"a\",\"weight\":1},{\"name\":\"a\",\"weight\":2}]}",
+                      "$.members[1].name",
+                      "duplicate member \"a\""),
+                     ("{\"version\":9,\"hasher\":\"crc32-ieee\",\"vnode_naming\":1,\"seed\":0,\
+                       \"ring_version\":0,\"replicas\":2,\"members\":[\
+                       {\"name\":\"a\",\"weight\":1,\"tokens\":[1]}]}",
+                      "$.members[0].tokens",
+                      "expected 2 tokens, found 1"),
+                     ("{\"version\":9,\"hasher\":\"crc32-ieee\",\"vnode_naming\":1,\"seed\":0,\
+                       \"ring_version\":0,\"replicas\":1,\"members\":[\
+                       {\"name\":\"a\",\"weight\":1,\"tokens\":[4294967296]}]}",
+                      "$.members[0].tokens[0]",
+                      "expected a u32"),
+                     ("{\"version\":9,\"hasher\":\"crc32-ieee\",\"vnode_naming\":1,\"seed\":0,\
+                       \"ring_version\":0,\"replicas\":1,\"members\":[\
+                       {\"name\":\"a\",\"weight\":1,\"tags\":[\"ssd\",1]}]}",
+                      "$.members[0].tags[1]",
+                      "expected a string, found a number"),
+                     ("{\"version\":12,\"hasher\":\"crc32-ieee\",\"vnode_naming\":1,\"seed\":0,\
+                       \"ring_version\":0,\"replicas\":1,\"members\":[],\"range_assignments\":[\
+                       {\"start\":1,\"end\":5,\"member\":\"a\"},\
+                       {\"start\":4,\"end\":4294967296,\"member\":\"b\"}]}",
+                      "$.range_assignments[1].end",
+                      "expected a u32"),
+                     ("{\"version\":12,\"hasher\":\"crc32-ieee\",\"vnode_naming\":1,\"seed\":0,\
+                       \"ring_version\":0,\"replicas\":1,\"members\":[],\"range_assignments\":[\
+                       {\"start\":1,\"end\":5,\"member\":\"a\"},\
+                       {\"start\":4,\"end\":0,\"member\":\"b\"}]}",
+                      "$.range_assignments[1]",
+                      "range overlaps the assigned range (1, 5]"),
+                     ("{\"version\":12,\"hasher\":\"crc32-ieee\",\"vnode_naming\":1,\"seed\":0,\
+                       \"ring_version\":0,\"replicas\":1,\"members\":[\
+                       {\"name\":\"a\",\"weight\":1,\"fraction\":1000000}]}",
+                      "$.members[0].fraction",
+                      "expected an integer in 1..1000000"),
+                     ("{\"version\":12,\"hasher\":\"crc32-ieee\",\"vnode_naming\":1,\"seed\":0,\
+                       \"ring_version\":0,\"replicas\":1,\"members\":[\
+                       {\"name\":\"a\",\"weight\":2,\"fraction\":50000}]}",
+                      "$.members[0].fraction",
+                      "expected a member of weight 1"),
+                     ("{\"version\":13,\"hasher\":\"crc32-ieee\",\"vnode_naming\":1,\"seed\":0,\
+                       \"ring_version\":0,\"replicas\":1,\"max_vnodes\":-1,\"members\":[]}",
+                      "$.max_vnodes",
+                      "expected a non-negative integer"),
+                     ("{\"version\":1,\"hasher\":\"crc32-ieee\",\"replicas\":1,\"members\":[\
+                       {\"name\":7,\"weight\":1}]}",
+                      "$.members[0].name",
+                      "expected a string, found a number"),
+                     ("{\"version\":1,", "$", "invalid JSON at byte 13: expected a field name")];
+
+        for &(json, path, message) in &cases {
+            let err = Consistant::from_json(json).unwrap_err();
+            assert_eq!((err.path.as_str(), err.message.as_str()), (path, message));
+        }
+    }
+
+    #[test]
+    fn test_from_json_oversized() {
+        let message = format!("expected at most {} virtual nodes", MAX_LOADED_VNODES);
+        let cases = [// The count of virtual nodes overflows.
+                     "{\"replicas\":2,\"members\":[\
+                      {\"name\":\"a\",\"weight\":9223372036854775808,\"tokens\":[]}]}",
+                     "{\"replicas\":18446744073709551615,\"members\":[\
+                      {\"name\":\"a\",\"weight\":2}]}",
+                     // It does not, but is not allocated.
+                     "{\"replicas\":1000000000000,\"members\":[{\"name\":\"a\",\"weight\":1}]}",
+                     "{\"replicas\":1,\"members\":[\
+                      {\"name\":\"a\",\"weight\":1},{\"name\":\"b\",\"weight\":16777216}]}"];
+        for (i, case) in cases.iter().enumerate() {
+            let json = case.replace("{\"replicas\"",
+                                    "{\"version\":9,\"hasher\":\"crc32-ieee\",\"vnode_naming\":1,\
+                                     \"seed\":0,\"ring_version\":0,\"replicas\"");
+            let err = Consistant::from_json(&json).unwrap_err();
+            let path = if i == 3 { "$.members[1]" } else { "$.members[0]" };
+            assert_eq!((err.path.as_str(), err.message.as_str()), (path, message.as_str()));
+        }
+    }
+}
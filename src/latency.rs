@@ -0,0 +1,200 @@
+use std::collections::hash_map::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, PoisonError};
+use std::time::Duration;
+use consistant::Consistant;
+use error::Error;
+use sync::RwLock;
+
+/// The weight of a new latency in the average of an element, see
+/// `Consistant::report_latency`.
+const SMOOTHING: f64 = 0.2;
+
+/// Latencies holds the moving averages of the latencies of the elements, in
+/// nanoseconds as the bits of `f64`s. Reporting a latency only takes the
+/// write lock of the map for the first one of an element, the next ones
+/// being swapped in atomically.
+#[derive(Debug, Default)]
+pub(crate) struct Latencies {
+    averages: RwLock<HashMap<Arc<str>, AtomicU64>>,
+}
+
+impl Clone for Latencies {
+    fn clone(&self) -> Latencies {
+        let averages = self.averages.read().unwrap_or_else(PoisonError::into_inner);
+        let averages = averages.iter()
+            .map(|(element, average)| {
+                (element.clone(), AtomicU64::new(average.load(Ordering::Relaxed)))
+            })
+            .collect();
+        Latencies { averages: RwLock::new(averages) }
+    }
+}
+
+impl Latencies {
+    /// Get the average latency of the given element, in nanoseconds.
+    pub(crate) fn get(&self, element: &str) -> Option<f64> {
+        let averages = self.averages.read().unwrap_or_else(PoisonError::into_inner);
+        averages.get(element).map(|average| f64::from_bits(average.load(Ordering::Relaxed)))
+    }
+
+    /// Fold the given latency, in nanoseconds, into the average of the
+    /// given element, the first one being the average.
+    pub(crate) fn report(&self, element: &Arc<str>, nanos: f64) {
+        {
+            let averages = self.averages.read().unwrap_or_else(PoisonError::into_inner);
+            if let Some(average) = averages.get(element) {
+                return update(average, nanos);
+            }
+        }
+        let mut averages = self.averages.write().unwrap_or_else(PoisonError::into_inner);
+        match averages.get(element) {
+            Some(average) => update(average, nanos),
+            None => {
+                averages.insert(element.clone(), AtomicU64::new(nanos.to_bits()));
+            }
+        }
+    }
+
+    pub(crate) fn remove(&mut self, element: &str) {
+        self.averages_mut().remove(element);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.averages_mut().clear();
+    }
+
+    fn averages_mut(&mut self) -> &mut HashMap<Arc<str>, AtomicU64> {
+        self.averages.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Fold the given latency into the given average, retrying when another
+/// report changed it meanwhile.
+fn update(average: &AtomicU64, nanos: f64) {
+    let _ = average.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+        let current = f64::from_bits(bits);
+        Some((current + SMOOTHING * (nanos - current)).to_bits())
+    });
+}
+
+impl Consistant {
+    /// Report a latency of the given element, folded into its exponentially
+    /// weighted moving average for `get_latency_aware`, each new latency
+    /// weighing 0.2. Reports take no write lock of the hash ring, so they
+    /// may come from the threads doing lookups. The averages are dropped
+    /// along with their element, and are not serialized.
+    pub fn report_latency(&self, member: &str, latency: Duration) -> Result<(), Error> {
+        let _guard = self.read();
+        let slots = self.slots();
+        let element = match slots.index(member) {
+            Some(owner) => slots.name(owner),
+            None => return Err(Error::UnknownMember(String::from(member))),
+        };
+        self.latencies().report(element, latency.as_nanos() as f64);
+        Ok(())
+    }
+
+    /// Get the moving average of the latencies reported for the given
+    /// element, see `report_latency`.
+    pub fn latency_of(&self, name: &str) -> Option<Duration> {
+        self.latencies().get(name).map(|nanos| Duration::from_nanos(nanos as u64))
+    }
+
+    /// Get the cloeset element's name to the given "name" unless it is slow:
+    /// if its average latency exceeds the lowest one of the first `k` of
+    /// `get_n` by more than `threshold`, the first of them of that lowest
+    /// one is returned instead. Elements without any latency reported are
+    /// never chosen over the cloeset element, which is returned as `get`
+    /// does when it has none, so the choice only depends on the averages.
+    pub fn get_latency_aware<S: AsRef<str>>(&self,
+                                            name: S,
+                                            k: usize,
+                                            threshold: Duration)
+                                            -> Option<String> {
+        let mut owners = self.get_n(name, k.max(1))?;
+        let latencies = self.latencies();
+        let primary = match latencies.get(&owners[0]) {
+            Some(primary) => primary,
+            None => return Some(owners.swap_remove(0)),
+        };
+        let (best, index) = owners.iter()
+            .enumerate()
+            .skip(1)
+            .filter_map(|(index, element)| latencies.get(element).map(|latency| (latency, index)))
+            .fold((primary, 0), |best, other| if other.0 < best.0 { other } else { best });
+
+        if primary - best > threshold.as_nanos() as f64 {
+            return Some(owners.swap_remove(index));
+        }
+        Some(owners.swap_remove(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_latency_aware() {
+        let mut consistant = Consistant::new(20);
+        for i in 0..5 {
+            consistant.add(format!("node-{}", i));
+        }
+        let keys: Vec<String> = (0..1000).map(|i| format!("key{}", i)).collect();
+        let threshold = Duration::from_millis(5);
+        let ms = Duration::from_millis;
+        for key in &keys {
+            assert_eq!(consistant.get_latency_aware(key, 3, threshold), consistant.get(key));
+        }
+        assert_eq!(consistant.report_latency("node-x", ms(1)),
+                   Err(Error::UnknownMember(String::from("node-x"))));
+
+        for i in 0..5 {
+            consistant.report_latency(&format!("node-{}", i), ms(2)).unwrap();
+        }
+        assert_eq!(consistant.latency_of("node-0"), Some(ms(2)));
+        let shifted = |consistant: &Consistant| {
+            let shifted = |key: &&String| {
+                consistant.get_latency_aware(key, 3, threshold) != consistant.get(key)
+            };
+            keys.iter().filter(shifted).count()
+        };
+        assert_eq!(shifted(&consistant), 0);
+
+        // node-0 degrades: its keys shift to the best of their next owners.
+        for _ in 0..10 {
+            consistant.report_latency("node-0", ms(100)).unwrap();
+        }
+        consistant.report_latency("node-3", ms(1)).unwrap();
+        let primaries = keys.iter().filter(|key| consistant.get(key).unwrap() == "node-0").count();
+        assert_eq!(shifted(&consistant), primaries);
+        for key in &keys {
+            let routed = consistant.get_latency_aware(key, 3, threshold).unwrap();
+            assert_ne!(routed, "node-0");
+            let owners = consistant.get_n(key, 3).unwrap();
+            if owners[0] == "node-0" {
+                let expected = if owners.contains(&String::from("node-3")) {
+                    "node-3"
+                } else {
+                    owners[1].as_str()
+                };
+                assert_eq!(routed, expected);
+            }
+            assert_eq!(consistant.clone().get_latency_aware(key, 3, threshold), Some(routed));
+        }
+
+        // Its keys come back once it recovers.
+        for _ in 0..30 {
+            consistant.report_latency("node-0", ms(2)).unwrap();
+        }
+        assert!(consistant.latency_of("node-0").unwrap() < ms(3));
+        assert_eq!(shifted(&consistant), 0);
+
+        consistant.remove("node-0");
+        consistant.add("node-0");
+        assert_eq!(consistant.latency_of("node-0"), None);
+        consistant.clear();
+        assert_eq!(consistant.latency_of("node-3"), None);
+    }
+}
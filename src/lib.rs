@@ -1,7 +1,187 @@
 //! Consistent hash package for Rust.
 
+#[cfg(feature = "global")]
+extern crate arc_swap;
 extern crate crc;
+// The pyo3 macros refer to `::core`, which needs declaring in edition 2015.
+#[cfg(feature = "python")]
+extern crate core;
+#[cfg(feature = "heapless")]
+extern crate heapless;
+#[cfg(feature = "twemproxy-compat")]
+extern crate md5;
+#[cfg(feature = "node")]
+extern crate napi;
+#[cfg(feature = "node")]
+#[macro_use]
+extern crate napi_derive;
+#[cfg(feature = "proto")]
+extern crate prost;
+#[cfg(feature = "python")]
+extern crate pyo3;
+#[cfg(feature = "config")]
+extern crate serde_yaml;
+#[cfg(any(feature = "hash-ring-compat", feature = "envoy-compat"))]
+extern crate twox_hash;
+#[cfg(all(test, loom))]
+extern crate loom;
+#[cfg(feature = "rand")]
+extern crate rand;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "rkyv")]
+extern crate rkyv;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+#[cfg(feature = "config")]
+extern crate toml;
+#[cfg(feature = "tower")]
+extern crate tower;
+#[cfg(all(test, feature = "tower"))]
+extern crate tower_test;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
 
+mod advisor;
+mod anchor;
+#[cfg(feature = "rkyv")]
+mod archive;
+mod auto_replicas;
+#[cfg(feature = "tower")]
+mod balance;
+mod breaker;
+mod btree_ring;
+mod capacity;
+#[cfg(feature = "tokio")]
+mod async_ring;
+mod capped;
+mod carp;
+mod colocation;
+mod compiled;
+#[cfg(feature = "config")]
+pub mod config;
 mod consistant;
+#[cfg(feature = "envoy-compat")]
+pub mod envoy;
+#[cfg(feature = "dns")]
+mod dns;
+mod domains;
+mod error;
+mod explain;
+mod failures;
+mod find_key;
+mod fingerprint;
+mod fractional;
+mod frozen;
+#[cfg(feature = "global")]
+pub mod global;
+mod gradual;
+mod handle;
+#[cfg(feature = "heapless")]
+mod heapless_ring;
+#[cfg(feature = "hash-ring-compat")]
+pub mod hash_ring;
+mod histogram;
+mod history;
+#[cfg(feature = "journal")]
+mod journal;
+mod json;
+mod latency;
+mod limits;
+mod local;
+mod member_entry;
+mod membership;
+#[cfg(feature = "node")]
+mod node;
+mod non_empty;
+mod normalize;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod placement;
+#[cfg(feature = "proto")]
+mod proto;
+#[cfg(feature = "python")]
+mod python;
+mod range;
+mod range_override;
+mod rebalance;
+mod registry;
+mod rendezvous;
+mod ring;
+mod router;
+mod routing;
+#[cfg(feature = "rand")]
+mod sample;
+mod segments;
+mod selection;
+mod shared;
+mod siphash;
+mod snapshot;
+mod state;
+mod static_ring;
+mod sync;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+#[cfg(feature = "twemproxy-compat")]
+pub mod twemproxy;
+mod visualize;
+#[cfg(test)]
+mod xorshift;
 
+pub use advisor::*;
+pub use anchor::*;
+#[cfg(feature = "rkyv")]
+pub use archive::ArchivedRing;
+pub use auto_replicas::*;
+#[cfg(feature = "tower")]
+pub use balance::*;
+pub use breaker::*;
+pub use btree_ring::*;
+pub use capacity::*;
+#[cfg(feature = "tokio")]
+pub use async_ring::*;
+pub use capped::*;
+pub use carp::*;
+pub use colocation::*;
+pub use compiled::*;
 pub use consistant::*;
+#[cfg(feature = "dns")]
+pub use dns::*;
+pub use domains::*;
+pub use error::*;
+pub use explain::*;
+pub use failures::*;
+pub use find_key::*;
+pub use frozen::*;
+pub use gradual::*;
+pub use handle::*;
+#[cfg(feature = "heapless")]
+pub use heapless_ring::*;
+pub use histogram::*;
+#[cfg(feature = "journal")]
+pub use journal::*;
+pub use json::*;
+pub use limits::*;
+pub use local::*;
+pub use member_entry::*;
+pub use membership::*;
+pub use non_empty::*;
+pub use normalize::*;
+pub use placement::*;
+#[cfg(feature = "proto")]
+pub use proto::*;
+pub use range::*;
+pub use rebalance::*;
+pub use registry::*;
+pub use rendezvous::*;
+pub use ring::*;
+pub use router::*;
+pub use selection::*;
+pub use shared::*;
+pub use snapshot::*;
+pub use state::*;
+pub use static_ring::*;
+pub use visualize::*;
@@ -0,0 +1,143 @@
+use error::LimitExceeded;
+
+/// The most virtual nodes a hash ring loaded from a document or a snapshot
+/// may hold, over all its elements, checked before they are allocated, so
+/// that untrusted input can not exhaust the memory.
+pub const MAX_LOADED_VNODES: usize = 1 << 24;
+
+/// Get the count of virtual nodes of an element of the given weight loaded
+/// into a hash ring of the given replicas, already holding the given count
+/// of them, `None` if it overflows or goes past `MAX_LOADED_VNODES`.
+pub(crate) fn loaded_vnodes(replicas: usize, weight: u64, held: usize) -> Option<usize> {
+    if weight > MAX_LOADED_VNODES as u64 {
+        return None;
+    }
+    (weight as usize)
+        .checked_mul(replicas)
+        .filter(|count| *count <= MAX_LOADED_VNODES.saturating_sub(held))
+}
+
+/// ResourceLimits bounds the size of a hash ring, see
+/// `Consistant::set_limits`. There is no limit by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// The most elements the hash ring may hold.
+    pub max_members: Option<usize>,
+    /// The most virtual nodes the hash ring may hold, over all its elements.
+    pub max_vnodes: Option<usize>,
+}
+
+impl ResourceLimits {
+    /// Crete a new instance of ResourceLimits, without any limit.
+    pub fn new() -> ResourceLimits {
+        ResourceLimits::default()
+    }
+
+    /// Limit the count of elements of the hash ring.
+    pub fn max_members(mut self, limit: usize) -> ResourceLimits {
+        self.max_members = Some(limit);
+        self
+    }
+
+    /// Limit the count of virtual nodes of the hash ring.
+    pub fn max_vnodes(mut self, limit: usize) -> ResourceLimits {
+        self.max_vnodes = Some(limit);
+        self
+    }
+
+    /// Check whether a hash ring of the given counts of elements and virtual
+    /// nodes is within the limits, the elements being checked first.
+    pub(crate) fn check(&self, members: usize, vnodes: usize) -> Result<(), LimitExceeded> {
+        if let Some(limit) = self.max_members.filter(|limit| members > *limit) {
+            return Err(LimitExceeded::Members {
+                limit,
+                requested: members,
+            });
+        }
+        if let Some(limit) = self.max_vnodes.filter(|limit| vnodes > *limit) {
+            return Err(LimitExceeded::Vnodes {
+                limit,
+                requested: vnodes,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consistant::Consistant;
+    use error::Error;
+
+    #[test]
+    fn test_limits() {
+        let limits = ResourceLimits::new().max_members(3).max_vnodes(50);
+        let mut consistant = Consistant::new(10);
+        consistant.set_limits(limits);
+        assert_eq!(consistant.limits(), limits);
+        consistant.try_add("cacheA").unwrap();
+        consistant.try_add_weighted("cacheB", 1).unwrap();
+        consistant.add("cacheC");
+        assert_eq!(consistant.count(), 3);
+        assert_eq!(consistant.clone().limits(), limits);
+
+        // One past the limit is refused, saying by how much.
+        let version = consistant.version();
+        let err = consistant.try_add("cacheD").unwrap_err();
+        assert_eq!(err, LimitExceeded::Members { limit: 3, requested: 4 });
+        assert_eq!(err.excess(), 1);
+        assert_eq!(err.to_string(), "4 members requested, 1 over the limit of 3");
+        consistant.add("cacheD");
+        assert_eq!(consistant.try_add("cacheA"), Ok(()));
+        assert_eq!(consistant.member("cacheD").or_insert().apply(),
+                   Err(Error::LimitExceeded(err)));
+        assert_eq!((consistant.count(), consistant.version()), (3, version));
+
+        let mut consistant = Consistant::new(10);
+        consistant.set_limits(ResourceLimits::new().max_vnodes(30));
+        consistant.try_add_weighted("cacheA", 2).unwrap();
+        consistant.try_add("cacheB").unwrap();
+        assert_eq!(consistant.vnodes().len(), 30);
+        assert_eq!(consistant.try_add_weighted("cacheC", 2),
+                   Err(LimitExceeded::Vnodes { limit: 30, requested: 50 }));
+        assert_eq!(consistant.member("cacheB").weight(2).apply(),
+                   Err(Error::LimitExceeded(LimitExceeded::Vnodes {
+                       limit: 30,
+                       requested: 40,
+                   })));
+        consistant.member("cacheA").weight(1).apply().unwrap();
+        consistant.member("cacheB").weight(2).apply().unwrap();
+        assert_eq!(consistant.weight("cacheB"), Some(2));
+        consistant.add_fractional("cacheC", 0.5);
+        assert!(!consistant.contains("cacheC"));
+    }
+
+    #[test]
+    fn test_add_all_limits() {
+        let mut consistant = Consistant::new(10);
+        consistant.set_limits(ResourceLimits::new().max_members(4).max_vnodes(60));
+        consistant.add("cacheA");
+        let vnodes = consistant.vnodes();
+        let version = consistant.version();
+
+        // A batch crossing a limit adds none of its elements.
+        let batch = vec![("cacheB", 1), ("cacheC", 1), ("cacheD", 1), ("cacheE", 1)];
+        assert_eq!(consistant.try_add_all(batch.clone()),
+                   Err(LimitExceeded::Members { limit: 4, requested: 5 }));
+        assert_eq!(consistant.try_add_all(vec![("cacheB", 2), ("cacheC", 4)]),
+                   Err(LimitExceeded::Vnodes { limit: 60, requested: 70 }));
+        consistant.add_all(batch);
+        assert_eq!(consistant.vnodes(), vnodes);
+        assert_eq!(consistant.version(), version);
+        assert_eq!(consistant.members(), ["cacheA"]);
+
+        // Elements already in, or repeated, do not count.
+        let batch = vec![("cacheA", 1), ("cacheB", 2), ("cacheB", 2), ("cacheC", 1)];
+        consistant.try_add_all(batch).unwrap();
+        consistant.try_add_all(vec![("cacheD", 1)]).unwrap();
+        assert_eq!((consistant.count(), consistant.vnodes().len()), (4, 50));
+        assert_eq!(consistant.try_add_all(vec![("cacheE", 1)]),
+                   Err(LimitExceeded::Members { limit: 4, requested: 5 }));
+    }
+}
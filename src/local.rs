@@ -0,0 +1,160 @@
+use std::rc::Rc;
+use frozen::{FrozenRing, Tables};
+
+/// LocalRing is a `FrozenRing` copied for a single thread, as the cores of a
+/// thread-per-core runtime keep their own data. It is neither `Send` nor
+/// `Sync`, and cloning it bumps a reference count without any atomic
+/// operation:
+///
+/// ```compile_fail
+/// # extern crate consistent_rs;
+/// # use consistent_rs::{Consistant, LocalRing};
+/// fn assert_send<T: Send>(_: T) {}
+///
+/// # fn main() {
+/// assert_send(LocalRing::from_shared(&Consistant::default().freeze()));
+/// # }
+/// ```
+///
+/// Share a `FrozenRing` between threads, and build a LocalRing from it on
+/// every core which clones the hash ring for its tasks, refreshing it from
+/// the shared one published by `to_shared` or `freeze` when it changes.
+/// `from_shared` copies the arrays of the virtual nodes and of the names
+/// into the memory of the calling thread, so it takes time proportional to
+/// the count of virtual nodes, and the lookups are then the ones of the
+/// FrozenRing, mapping keys as it does.
+#[derive(Debug, Clone)]
+pub struct LocalRing {
+    inner: Rc<Local>,
+}
+
+#[derive(Debug)]
+struct Local {
+    tables: Tables,
+    /// The shared hash ring copied, for `to_shared`.
+    shared: FrozenRing,
+}
+
+impl LocalRing {
+    /// Crete a new instance of LocalRing, copying the given shared hash
+    /// ring.
+    pub fn from_shared(shared: &FrozenRing) -> LocalRing {
+        LocalRing {
+            inner: Rc::new(Local {
+                tables: shared.tables().clone(),
+                shared: shared.clone(),
+            }),
+        }
+    }
+
+    /// Get the shared hash ring this one was copied from, for other threads
+    /// to copy it in turn.
+    pub fn to_shared(&self) -> FrozenRing {
+        self.inner.shared.clone()
+    }
+
+    /// Get the cloeset element's name to the given key.
+    pub fn get<S: AsRef<str>>(&self, key: S) -> Option<&str> {
+        self.inner.tables.get(key.as_ref())
+    }
+
+    /// Get the N cloeset elements' names to the given key.
+    pub fn get_n<S: AsRef<str>>(&self, key: S, n: usize) -> Option<Vec<&str>> {
+        self.inner.tables.get_n(key.as_ref(), n)
+    }
+
+    /// Get the elements owning the given key, the `replication_factor`
+    /// cloeset ones, as `Consistant::owners` does.
+    pub fn owners<S: AsRef<str>>(&self, key: S) -> Option<Vec<&str>> {
+        self.get_n(key, self.replication_factor())
+    }
+
+    /// Get the names of the elements, sorted.
+    pub fn members(&self) -> Vec<&str> {
+        self.inner.tables.members()
+    }
+
+    /// Get the count of elements.
+    pub fn count(&self) -> usize {
+        self.inner.tables.count()
+    }
+
+    /// Get the count of owners of every key, see `owners`.
+    pub fn replication_factor(&self) -> usize {
+        self.inner.tables.replication_factor()
+    }
+
+    /// Get the fingerprint of the hash ring copied, see
+    /// `Consistant::fingerprint`.
+    pub fn fingerprint(&self) -> u64 {
+        self.inner.tables.fingerprint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consistant::Consistant;
+
+    fn consistant() -> Consistant {
+        let mut consistant = Consistant::with_seed(20, 3);
+        for i in 0..16 {
+            consistant.add_weighted(format!("node-{}", i), 1 + i % 2);
+        }
+        consistant.add_prefix_rule("tenant-7/", "node-2").unwrap();
+        consistant.set_fractional_weight("node-5", 0.4).unwrap();
+        consistant.set_replication_factor(2);
+        consistant
+    }
+
+    #[test]
+    fn test_local_ring() {
+        let consistant = consistant();
+        let shared = consistant.clone().freeze();
+        let local = LocalRing::from_shared(&shared);
+        assert_eq!((local.count(), local.members()), (16, shared.members()));
+        assert_eq!(local.fingerprint(), consistant.fingerprint());
+        assert_eq!(local.replication_factor(), 2);
+        for i in 0..10000 {
+            let key = format!("key{}", i);
+            assert_eq!(local.get(&key), shared.get(&key));
+            assert_eq!(local.get(&key).map(String::from), consistant.get(&key));
+            assert_eq!(local.get_n(&key, 3), shared.get_n(&key, 3));
+            assert_eq!(local.owners(&key), shared.owners(&key));
+        }
+        assert_eq!(local.get("tenant-7/david"), Some("node-2"));
+        assert_eq!(local.get_n("david", 0), None);
+
+        let empty = LocalRing::from_shared(&Consistant::default().freeze());
+        assert_eq!((empty.get("david"), empty.owners("david")), (None, None));
+    }
+
+    #[test]
+    fn test_to_shared() {
+        let local = LocalRing::from_shared(&consistant().freeze());
+        let cloned = local.clone();
+        assert!(Rc::ptr_eq(&cloned.inner, &local.inner));
+
+        // Another thread copies the shared ring of this one.
+        let shared = local.to_shared();
+        let fingerprint = local.fingerprint();
+        let keys = ::std::thread::spawn(move || {
+            let local = LocalRing::from_shared(&shared);
+            assert_eq!(local.fingerprint(), fingerprint);
+            (0..1000)
+                .map(|i| local.get(format!("key{}", i)).map(String::from))
+                .collect::<Vec<_>>()
+        });
+        let expected: Vec<_> =
+            (0..1000).map(|i| local.get(format!("key{}", i)).map(String::from)).collect();
+        assert_eq!(keys.join().unwrap(), expected);
+
+        // Refreshing from the shared ring of a changed hash ring.
+        let mut consistant = local.to_shared().into_consistant();
+        consistant.remove("node-3");
+        let refreshed = LocalRing::from_shared(&consistant.freeze());
+        assert_eq!(refreshed.count(), 15);
+        assert_ne!(refreshed.fingerprint(), local.fingerprint());
+        assert_eq!(local.count(), 16);
+    }
+}
@@ -0,0 +1,185 @@
+use consistant::Consistant;
+use error::Error;
+use state::MemberState;
+
+/// MemberEntry stages changes to one element of a hash ring, see
+/// `Consistant::member`. Nothing changes until `apply`, which checks every
+/// staged change first and then makes them all, adjusting the virtual nodes
+/// once.
+#[derive(Debug)]
+pub struct MemberEntry<'a> {
+    consistant: &'a mut Consistant,
+    name: String,
+    insert: bool,
+    weight: Option<usize>,
+    tags: Option<Vec<String>>,
+}
+
+impl Consistant {
+    /// Get the entry of the given element, which may not be in the hash ring
+    /// yet, to configure it at once.
+    pub fn member<'a, S: Into<String>>(&'a mut self, name: S) -> MemberEntry<'a> {
+        MemberEntry {
+            consistant: self,
+            name: name.into(),
+            insert: false,
+            weight: None,
+            tags: None,
+        }
+    }
+}
+
+impl<'a> MemberEntry<'a> {
+    /// Get the current configuration of the element, or `None` if it is not
+    /// in the hash ring. Staged changes are not included.
+    pub fn get(&self) -> Option<MemberState> {
+        let consistant = &*self.consistant;
+        consistant.weight(&self.name).map(|weight| {
+            MemberState {
+                name: self.name.clone(),
+                weight,
+                incarnation: consistant.incarnation(&self.name).unwrap_or(0),
+                tags: consistant.tags_of(&self.name).unwrap_or_default(),
+                tokens: consistant.explicit_tokens(&self.name),
+                fraction: consistant.fraction(&self.name),
+            }
+        })
+    }
+
+    /// Add the element by `apply` if it is not in the hash ring, of weight
+    /// 1 unless one is staged. Without it, `apply` fails for an element not
+    /// in the hash ring.
+    pub fn or_insert(mut self) -> Self {
+        self.insert = true;
+        self
+    }
+
+    /// Stage the weight of the element, which must not be 0. The weight of
+    /// an element of explicit virtual nodes, see `replace_node`, can not
+    /// change.
+    pub fn weight(mut self, weight: usize) -> Self {
+        self.weight = Some(weight);
+        self
+    }
+
+    /// Stage the tags of the element, replacing its current ones, see
+    /// `Consistant::set_tags`.
+    pub fn tags(mut self, tags: &[&str]) -> Self {
+        self.tags = Some(tags.iter().map(|tag| String::from(*tag)).collect());
+        self
+    }
+
+    /// Apply the staged changes. Either all of them are made, the version of
+    /// the hash ring being bumped once if virtual nodes change, or none is
+    /// and the error of the first invalid one is returned.
+    pub fn apply(self) -> Result<(), Error> {
+        let current = self.consistant.weight(&self.name);
+        if current.is_none() && !self.insert {
+            return Err(Error::UnknownMember(self.name));
+        }
+        if self.weight == Some(0) {
+            return Err(Error::InvalidWeight(self.name));
+        }
+        let weight = match (current, self.weight) {
+            (Some(current), Some(weight)) if current != weight => {
+                if self.consistant.explicit_tokens(&self.name).is_some() {
+                    return Err(Error::ExplicitTokens(self.name));
+                }
+                Some(weight)
+            }
+            _ => None,
+        };
+        let limits = match (current, weight) {
+            (None, _) => {
+                let weight = self.weight.unwrap_or(1);
+                self.consistant.check_limits(1, self.consistant.replicas_num * weight)
+            }
+            (Some(current), Some(weight)) if weight > current => {
+                self.consistant.check_limits(0, self.consistant.replicas_num * (weight - current))
+            }
+            _ => Ok(()),
+        };
+        if let Err(err) = limits {
+            return Err(Error::LimitExceeded(err));
+        }
+
+        match current {
+            Some(_) => {
+                if let Some(weight) = weight {
+                    self.consistant.reweight(&self.name, weight);
+                }
+            }
+            None => self.consistant.add_weighted(self.name.as_str(), self.weight.unwrap_or(1)),
+        }
+        if let Some(tags) = self.tags {
+            self.consistant.insert_tags(&self.name, tags);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_member_entry() {
+        let mut consistant = Consistant::new(20);
+        consistant.add("cacheA");
+        assert_eq!(consistant.member("cacheB").get(), None);
+        consistant.member("cacheB").or_insert().weight(3).tags(&["ssd"]).apply().unwrap();
+        assert_eq!(consistant.version(), 2);
+
+        let mut expected = Consistant::new(20);
+        expected.add("cacheA");
+        expected.add_weighted("cacheB", 3);
+        assert_eq!(consistant.vnodes(), expected.vnodes());
+        let member = consistant.member("cacheB").get().unwrap();
+        assert_eq!((member.weight, member.incarnation), (3, 0));
+        assert_eq!(member.tags, ["ssd"]);
+
+        // Existing members are changed in place, without or_insert.
+        consistant.member("cacheB").weight(1).tags(&[]).apply().unwrap();
+        assert_eq!(consistant.version(), 3);
+        assert!(consistant.last_disruption().unwrap() > 0.0);
+        let mut expected = Consistant::new(20);
+        expected.add("cacheA");
+        expected.add("cacheB");
+        assert_eq!(consistant.vnodes(), expected.vnodes());
+        assert_eq!(consistant.tags_of("cacheB"), Some(Vec::new()));
+        consistant.member("cacheB").weight(2).apply().unwrap();
+        expected.remove("cacheB");
+        expected.add_weighted("cacheB", 2);
+        assert_eq!(consistant.vnodes(), expected.vnodes());
+
+        // Tags and unchanged weights move no virtual node.
+        consistant.member("cacheA").or_insert().weight(1).tags(&["hdd"]).apply().unwrap();
+        assert_eq!(consistant.version(), 4);
+        assert_eq!(consistant.member("cacheA").get().unwrap().tags, ["hdd"]);
+    }
+
+    #[test]
+    fn test_member_entry_errors() {
+        let mut consistant = Consistant::new(20);
+        consistant.add_with_tags("cacheA", &["ssd"]);
+        consistant.add("cacheB");
+        consistant.replace_node("cacheB", "cacheC").unwrap();
+        let vnodes = consistant.vnodes();
+        let state = consistant.export_state();
+
+        assert_eq!(consistant.member("cacheD").weight(2).apply(),
+                   Err(Error::UnknownMember(String::from("cacheD"))));
+        assert_eq!(consistant.member("cacheA").weight(0).tags(&["hdd"]).apply(),
+                   Err(Error::InvalidWeight(String::from("cacheA"))));
+        assert_eq!(consistant.member("cacheD").or_insert().weight(0).apply(),
+                   Err(Error::InvalidWeight(String::from("cacheD"))));
+        assert_eq!(consistant.member("cacheC").tags(&["hdd"]).weight(2).apply(),
+                   Err(Error::ExplicitTokens(String::from("cacheC"))));
+
+        // A failing entry leaves the hash ring untouched.
+        assert_eq!(consistant.vnodes(), vnodes);
+        assert_eq!(consistant.export_state(), state);
+        consistant.member("cacheC").weight(1).tags(&["hdd"]).apply().unwrap();
+        assert_eq!(consistant.tags_of("cacheC"), Some(vec![String::from("hdd")]));
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::btree_set::BTreeSet;
+use consistant::Consistant;
+
+/// SyncReport is what `Consistant::sync_members` changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    /// The elements added, sorted.
+    pub added: Vec<String>,
+    /// The elements removed, sorted.
+    pub removed: Vec<String>,
+}
+
+impl SyncReport {
+    /// Check whether nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl Consistant {
+    /// Make the elements of the hash ring the given ones: those not in it
+    /// are added, those not given are removed, and the others are left as
+    /// they are, their weights included. An element over the limits is not
+    /// added, see `set_limits`.
+    pub fn sync_members<I, S>(&mut self, members: I) -> SyncReport
+        where I: IntoIterator<Item = S>,
+              S: AsRef<str>
+    {
+        let desired: BTreeSet<String> = members.into_iter()
+            .map(|member| String::from(member.as_ref()))
+            .collect();
+        let mut report = SyncReport::default();
+        for element in self.members() {
+            if !desired.contains(&element) {
+                self.remove(element.as_str());
+                report.removed.push(element);
+            }
+        }
+        for element in desired {
+            if !self.contains(&element) {
+                self.add(element.as_str());
+                if self.contains(&element) {
+                    report.added.push(element);
+                }
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use limits::ResourceLimits;
+
+    #[test]
+    fn test_sync_members() {
+        let mut consistant = Consistant::new(20);
+        consistant.add_weighted("cacheA", 2);
+        consistant.add("cacheB");
+
+        let report = consistant.sync_members(["cacheC", "cacheA", "cacheD", "cacheC"]);
+        assert_eq!(report.added, ["cacheC", "cacheD"]);
+        assert_eq!(report.removed, ["cacheB"]);
+        assert_eq!(consistant.members(), ["cacheA", "cacheC", "cacheD"]);
+        assert_eq!(consistant.weight("cacheA"), Some(2));
+        assert!(consistant.sync_members(["cacheA", "cacheC", "cacheD"]).is_empty());
+
+        consistant.set_limits(ResourceLimits::new().max_members(3));
+        let report = consistant.sync_members(["cacheA", "cacheC", "cacheD", "cacheE"]);
+        assert!(report.is_empty());
+        let report = consistant.sync_members(Vec::<String>::new());
+        assert_eq!(report.removed, ["cacheA", "cacheC", "cacheD"]);
+        assert_eq!(consistant.count(), 0);
+    }
+}
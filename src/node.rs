@@ -0,0 +1,129 @@
+//! Node.js bindings, exposing the hash ring as the `Consistent` class.
+//!
+//! The addon is built by compiling the crate as a `cdylib` with the `node`
+//! feature, e.g. with the napi CLI, and mapping keys exactly as the Rust
+//! hash ring does. Snapshots are the JSON of `Consistant::to_json`. Errors
+//! are thrown as JS exceptions, and a panic is caught before it crosses the
+//! boundary, being thrown as well. The Node-API symbols being resolved from
+//! the host runtime, executables cannot be linked with the feature, so the
+//! tests drive the class from Rust: `cargo test --features node --lib`.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use napi::{Error, Result, Status};
+use consistant::Consistant;
+
+/// Consistent is the JS class wrapping a hash ring.
+#[napi(js_name = "Consistent")]
+pub struct JsConsistent {
+    inner: Consistant,
+}
+
+#[napi]
+impl JsConsistent {
+    #[napi(constructor)]
+    pub fn new(replicas_num: Option<u32>) -> Self {
+        JsConsistent { inner: Consistant::new(replicas_num.unwrap_or(20) as usize) }
+    }
+
+    /// Build a hash ring from a JSON snapshot, see `toSnapshot`.
+    #[napi(factory)]
+    pub fn from_snapshot(json: String) -> Result<Self> {
+        match guard(|| Consistant::from_json(&json))? {
+            Ok(inner) => Ok(JsConsistent { inner }),
+            Err(err) => Err(Error::new(Status::InvalidArg, err.to_string())),
+        }
+    }
+
+    /// Get the JSON snapshot of the hash ring.
+    #[napi]
+    pub fn to_snapshot(&self) -> Result<String> {
+        guard(|| self.inner.to_json())
+    }
+
+    #[napi]
+    pub fn add(&mut self, element: String, weight: Option<u32>) -> Result<()> {
+        let weight = weight.unwrap_or(1);
+        if weight == 0 {
+            return Err(Error::new(Status::InvalidArg, "weight must be positive"));
+        }
+        let inner = &mut self.inner;
+        guard(move || inner.add_weighted(element, weight as usize))
+    }
+
+    #[napi]
+    pub fn remove(&mut self, element: String) -> Result<()> {
+        let inner = &mut self.inner;
+        guard(move || inner.remove(element))
+    }
+
+    #[napi]
+    pub fn get(&self, key: String) -> Result<Option<String>> {
+        guard(|| self.inner.get(key))
+    }
+
+    #[napi]
+    pub fn get_n(&self, key: String, n: u32) -> Result<Vec<String>> {
+        guard(|| self.inner.get_n(key, n as usize).unwrap_or_default())
+    }
+
+    #[napi]
+    pub fn members(&self) -> Vec<String> {
+        self.inner.members()
+    }
+
+    #[napi(getter)]
+    pub fn count(&self) -> u32 {
+        self.inner.count() as u32
+    }
+}
+
+/// Run the given function, turning a panic into an error to throw.
+fn guard<T, F: FnOnce() -> T>(f: F) -> Result<T> {
+    catch_unwind(AssertUnwindSafe(f))
+        .map_err(|_| Error::new(Status::GenericFailure, "consistent-rs panicked"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_parity() {
+        let mut native = Consistant::new(20);
+        native.add("cacheA");
+        native.add_weighted("cacheB", 2);
+        native.add("cacheC");
+
+        let mut ring = JsConsistent::new(None);
+        ring.add(String::from("cacheA"), None).unwrap();
+        ring.add(String::from("cacheB"), Some(2)).unwrap();
+        ring.add(String::from("cacheC"), None).unwrap();
+        ring.add(String::from("cacheA"), None).unwrap();
+        let loaded = JsConsistent::from_snapshot(ring.to_snapshot().unwrap()).unwrap();
+        for i in 0..500 {
+            let key = format!("key{}", i);
+            assert_eq!(ring.get(key.clone()).unwrap(), native.get(&key));
+            assert_eq!(ring.get_n(key.clone(), 2).unwrap(), native.get_n(&key, 2).unwrap());
+            assert_eq!(loaded.get(key.clone()).unwrap(), native.get(&key));
+        }
+        assert_eq!(ring.members(), native.members());
+        assert_eq!(ring.count(), 3);
+
+        ring.remove(String::from("cacheB")).unwrap();
+        native.remove("cacheB");
+        assert_eq!(ring.get_n(String::from("david"), 0).unwrap(), Vec::<String>::new());
+        assert_eq!(ring.get(String::from("david")).unwrap(), native.get("david"));
+    }
+
+    #[test]
+    fn test_node_errors() {
+        let mut ring = JsConsistent::new(Some(10));
+        let err = ring.add(String::from("cacheA"), Some(0)).unwrap_err();
+        assert_eq!(err.status, Status::InvalidArg);
+        let err = JsConsistent::from_snapshot(String::from("{")).err().unwrap();
+        assert_eq!(err.status, Status::InvalidArg);
+
+        let err = guard(|| -> u32 { panic!("boom") }).unwrap_err();
+        assert_eq!(err.status, Status::GenericFailure);
+    }
+}
@@ -0,0 +1,126 @@
+use consistant::Consistant;
+use error::{EmptyRing, Error};
+
+/// NonEmptyRing is a hash ring which always has an element, so its lookups
+/// never fail. The last element can not be removed.
+///
+/// Converting from and into a `Consistant` moves it, without copying.
+#[derive(Debug, Clone)]
+pub struct NonEmptyRing {
+    ring: Consistant,
+}
+
+impl NonEmptyRing {
+    /// Crete a new instance of NonEmptyRing of the given elements, with the
+    /// default count of virtual nodes.
+    pub fn new<S, I>(first: S, rest: I) -> Self
+        where S: Into<String>,
+              I: IntoIterator<Item = S>
+    {
+        let mut ring = Consistant::default();
+        ring.add(first);
+        for element in rest {
+            ring.add(element);
+        }
+        NonEmptyRing { ring }
+    }
+
+    /// Add an elment to the hash ring.
+    pub fn add<S: Into<String>>(&mut self, element: S) {
+        self.ring.add(element)
+    }
+
+    /// Add an elment with the given weight to the hash ring.
+    pub fn add_weighted<S: Into<String>>(&mut self, element: S, weight: usize) {
+        self.ring.add_weighted(element, weight)
+    }
+
+    /// Remove the given element, unless it is the last one. Removing an
+    /// element not in the hash ring does nothing, as for `Consistant`.
+    pub fn remove<S: AsRef<str>>(&mut self, name: S) -> Result<(), Error> {
+        let name = name.as_ref();
+        if self.ring.count() == 1 && self.ring.contains(name) {
+            return Err(Error::LastMember(String::from(name)));
+        }
+        self.ring.remove(name);
+        Ok(())
+    }
+
+    /// Get the cloeset element's name to the given "name".
+    pub fn get<S: AsRef<str>>(&self, name: S) -> String {
+        self.ring.get(name).expect("a non-empty ring has an owner for every key")
+    }
+
+    /// Get the N cloeset elements' names to the given "name", all of them if
+    /// there are not as many, and none for N = 0.
+    pub fn get_n<S: AsRef<str>>(&self, name: S, n: usize) -> Vec<String> {
+        self.ring.get_n(name, n).unwrap_or_default()
+    }
+
+    /// Get the hash ring, for the other lookups.
+    pub fn as_ring(&self) -> &Consistant {
+        &self.ring
+    }
+
+    /// Get the hash ring back, which may then become empty.
+    pub fn into_inner(self) -> Consistant {
+        self.ring
+    }
+}
+
+impl From<NonEmptyRing> for Consistant {
+    fn from(ring: NonEmptyRing) -> Consistant {
+        ring.ring
+    }
+}
+
+impl Consistant {
+    /// Convert the hash ring into a `NonEmptyRing`, or give it back in the
+    /// error if it is empty, or if its elements have no virtual node, as for
+    /// 0 virtual nodes per element.
+    pub fn into_non_empty(self) -> Result<NonEmptyRing, EmptyRing> {
+        if self.vnode_count() == 0 {
+            return Err(EmptyRing(Box::new(self)));
+        }
+        Ok(NonEmptyRing { ring: self })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_non_empty_ring() {
+        let empty = Consistant::new(10).into_non_empty().unwrap_err();
+        assert_eq!(empty.0.replicas_num, 10);
+        let mut no_vnodes = Consistant::new(0);
+        no_vnodes.add("a");
+        assert_eq!(no_vnodes.into_non_empty().unwrap_err().0.members(), ["a"]);
+
+        let mut consistant = Consistant::new(10);
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+        let mut ring = consistant.clone().into_non_empty().unwrap();
+        for i in 0..100 {
+            let key = format!("key{}", i);
+            assert_eq!(Some(ring.get(key.as_str())), consistant.get(key.as_str()));
+            assert_eq!(ring.get_n(key.as_str(), 3), consistant.get_n(key.as_str(), 3).unwrap());
+        }
+        assert!(ring.get_n("david", 0).is_empty());
+
+        // The last element stays.
+        assert_eq!(ring.remove("cacheC"), Ok(()));
+        assert_eq!(ring.remove("cacheA"), Ok(()));
+        assert_eq!(ring.remove("cacheB"), Err(Error::LastMember(String::from("cacheB"))));
+        assert_eq!(ring.get("david"), "cacheB");
+        assert_eq!(ring.as_ring().members(), ["cacheB"]);
+        ring.add_weighted("cacheC", 2);
+        assert_eq!(ring.remove("cacheB"), Ok(()));
+        assert_eq!(Consistant::from(ring).members(), ["cacheC"]);
+
+        let ring = NonEmptyRing::new("cacheA", vec!["cacheB", "cacheC"]);
+        assert_eq!(ring.as_ring().count(), 3);
+        assert_eq!(NonEmptyRing::new("cacheA", None).into_inner().members(), ["cacheA"]);
+    }
+}
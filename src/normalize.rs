@@ -0,0 +1,103 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::Arc;
+
+type Normalize = dyn Fn(&str) -> Cow<str> + Send + Sync;
+
+/// KeyNormalizer rewrites the lookup keys before they are hashed, for keys
+/// told apart only by e.g. their casing to map to the same element, see
+/// `Consistant::set_key_normalizer`. The names of the elements are never
+/// normalized.
+///
+/// A normalizer is named by an id, which `Consistant::fingerprint` captures
+/// since normalizing changes the mappings. Normalizers of the same id are
+/// expected to normalize alike.
+#[derive(Clone)]
+pub struct KeyNormalizer {
+    id: Arc<str>,
+    f: Arc<Normalize>,
+}
+
+impl KeyNormalizer {
+    /// Crete a new instance of KeyNormalizer of the given id. Keys left as
+    /// they are should be returned as `Cow::Borrowed`, for their lookups not
+    /// to allocate.
+    pub fn new<S, F>(id: S, f: F) -> Self
+        where S: Into<String>,
+              F: Fn(&str) -> Cow<str> + Send + Sync + 'static
+    {
+        KeyNormalizer {
+            id: Arc::from(id.into()),
+            f: Arc::new(f),
+        }
+    }
+
+    /// Get the id of the normalizer.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Normalize the given key.
+    pub fn normalize<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        (self.f)(key)
+    }
+}
+
+impl fmt::Debug for KeyNormalizer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("KeyNormalizer").field("id", &self.id).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consistant::Consistant;
+
+    fn lowercase() -> KeyNormalizer {
+        KeyNormalizer::new("lowercase", |key: &str| Cow::Owned(key.to_lowercase()))
+    }
+
+    #[test]
+    fn test_key_normalizer() {
+        let mut consistant = Consistant::new(10);
+        for i in 0..5 {
+            consistant.add(format!("Cache{}", i));
+        }
+        let fingerprint = consistant.fingerprint();
+        assert!(consistant.key_normalizer().is_none());
+        consistant.set_key_normalizer(Some(lowercase()));
+        assert_eq!(consistant.key_normalizer().map(KeyNormalizer::id), Some("lowercase"));
+
+        // Every lookup normalizes the keys alike.
+        let compiled = consistant.compile(1 << 8);
+        for i in 0..200 {
+            let (upper, lower) = (format!("UserA{}", i), format!("usera{}", i));
+            assert_eq!(consistant.get(upper.as_str()), consistant.get(lower.as_str()));
+            assert_eq!(consistant.get_n(upper.as_str(), 3), consistant.get_n(lower.as_str(), 3));
+            assert_eq!(consistant.get_nth_owner(upper.as_str(), 1),
+                       consistant.get_nth_owner(lower.as_str(), 1));
+            assert_eq!(consistant.get_handle(upper.as_str()),
+                       consistant.get_handle(lower.as_str()));
+            assert_eq!(compiled.get(upper.as_str()).map(String::from),
+                       consistant.get(lower.as_str()));
+            let owner = consistant.get(lower.as_str()).unwrap();
+            assert!(consistant.is_owner(&owner, upper.as_str(), 1));
+            let (explained, expected) = (consistant.explain(upper.as_str()).unwrap(),
+                                         consistant.explain(lower.as_str()).unwrap());
+            assert_eq!((explained.checksum, explained.owner), (expected.checksum, expected.owner));
+        }
+
+        // The names of the elements are left alone.
+        assert!(consistant.members().iter().all(|member| member.starts_with("Cache")));
+        assert!(!consistant.contains("cache0"));
+        assert_eq!(consistant.weight("Cache0"), Some(1));
+
+        // The normalizer changes the fingerprint, and survives clones.
+        assert!(consistant.fingerprint() != fingerprint);
+        assert_eq!(consistant.clone().fingerprint(), consistant.fingerprint());
+        assert_eq!(consistant.subset(|_| true).fingerprint(), consistant.fingerprint());
+        consistant.set_key_normalizer(None);
+        assert_eq!(consistant.fingerprint(), fingerprint);
+    }
+}
@@ -0,0 +1,90 @@
+use std::sync::Arc;
+use rayon::prelude::*;
+use consistant::{merge_vnodes, Consistant};
+
+/// The count of virtual nodes from which `Consistant::add_all` hashes and
+/// sorts them across threads, below which the sequential path is faster.
+pub(crate) const PARALLEL_VNODES: usize = 1 << 16;
+
+impl Consistant {
+    /// Get the cloeset element's name to each of the given keys, as
+    /// `(index of the key, element)` in the order of the keys. The keys are
+    /// resolved across the threads of the rayon pool, the hash ring being
+    /// locked once for all of them, with the same results as `get`.
+    pub fn par_assign(&self, keys: &[String]) -> Vec<(usize, Option<&str>)> {
+        let _guard = self.read();
+
+        keys.par_iter()
+            .enumerate()
+            .map(|(i, key)| {
+                let element = self.key_owner_ref(key);
+                (i, element.map(|element| &**element))
+            })
+            .collect()
+    }
+
+    /// Same as `bulk_vnodes`, the virtual nodes being hashed into buffers of
+    /// the threads of the rayon pool, then sorted across them, and the
+    /// owners of the hashes shared merged in a single pass.
+    pub(crate) fn par_bulk_vnodes(&self,
+                                  added: &[(Arc<str>, usize)],
+                                  owners: &[u32])
+                                  -> Vec<(u32, u32)> {
+        let mut vnodes: Vec<(u32, u32)> = added.par_iter()
+            .enumerate()
+            .flat_map_iter(|(index, &(ref element, weight))| {
+                self.derived_points(element, weight).into_iter().map(move |sum| (sum, index as u32))
+            })
+            .collect();
+        vnodes.par_sort_unstable();
+        merge_vnodes(vnodes, owners)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_par_assign() {
+        let mut consistant = Consistant::with_seed(20, 3);
+        for i in 0..10 {
+            consistant.add(format!("node-{}", i));
+        }
+        let keys: Vec<String> = (0..1000000).map(|i| format!("key{}", i)).collect();
+
+        let assignments = consistant.par_assign(&keys);
+        assert_eq!(assignments.len(), keys.len());
+        for (i, &(index, element)) in assignments.iter().enumerate() {
+            assert_eq!(index, i);
+            assert_eq!(element.map(String::from), consistant.get(keys[i].as_str()));
+        }
+
+        let empty = Consistant::default();
+        assert_eq!(empty.par_assign(&keys[..2]), vec![(0, None), (1, None)]);
+    }
+
+    #[test]
+    fn test_par_add_all() {
+        let members: Vec<(String, usize)> =
+            (0..5000).map(|i| (format!("node-{}", i), 1 + i % 3)).collect();
+        assert!(members.iter().map(|member| member.1).sum::<usize>() * 20 >= PARALLEL_VNODES);
+
+        let mut sequential = Consistant::new(20);
+        for &(ref element, weight) in &members {
+            sequential.add_weighted(element.as_str(), weight);
+        }
+        let mut parallel = Consistant::new(20);
+        parallel.add_all(members[..10].iter().cloned());
+        parallel.add_all(members[10..].iter().cloned());
+        assert_eq!(parallel.vnodes(), sequential.vnodes());
+
+        let parallel = Consistant::from_members(20, members);
+        assert_eq!(parallel.vnodes(), sequential.vnodes());
+        assert_eq!(parallel.members(), sequential.members());
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            assert_eq!(parallel.get(&key), sequential.get(&key));
+        }
+    }
+}
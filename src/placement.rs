@@ -0,0 +1,151 @@
+use std::collections::btree_set::BTreeSet;
+use crc::crc32::{self, checksum_ieee};
+
+/// The count of times a virtual node too close to another one of its element
+/// is derived again, see `Consistant::set_min_self_spacing`.
+const SPACING_ATTEMPTS: u32 = 16;
+
+/// PlacementStrategy decides where the virtual nodes of an element are placed
+/// on the hash ring. It changes the mappings, so the rings sharing keys must
+/// use the same one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum PlacementStrategy {
+    /// The i-th virtual node is the checksum of the element name followed by
+    /// the decimal i.
+    #[default]
+    Classic,
+    /// The i-th virtual node is `h1 + i * h2` (wrapping), where `h1` is the
+    /// checksum of the element name and `h2` the checksum of the element name
+    /// followed by `#`, forced odd. It spreads the virtual nodes of small
+    /// rings far better than `Classic`.
+    DoubleHash,
+    /// The virtual nodes are drawn from a pseudo-random generator seeded
+    /// with the seed of the ring xor the FNV-1a 64 hash of the element name,
+    /// the i-th one being the high 32 bits of the i-th SplitMix64 output, so
+    /// that elements of similar names do not interleave alike. The draws are
+    /// recorded as the virtual nodes of the element, as by `replace_node`,
+    /// and so carried by the serialized hash rings, and its weight can not
+    /// change.
+    SeededRandom,
+}
+
+impl PlacementStrategy {
+    /// Get the id of the strategy, recorded as the vnode naming scheme in
+    /// serialized hash rings.
+    pub fn id(self) -> u8 {
+        match self {
+            PlacementStrategy::Classic => 1,
+            PlacementStrategy::DoubleHash => 2,
+            PlacementStrategy::SeededRandom => 3,
+        }
+    }
+
+    /// Get the strategy of the given id.
+    pub fn from_id(id: u8) -> Option<PlacementStrategy> {
+        match id {
+            1 => Some(PlacementStrategy::Classic),
+            2 => Some(PlacementStrategy::DoubleHash),
+            3 => Some(PlacementStrategy::SeededRandom),
+            _ => None,
+        }
+    }
+
+    /// Get the hashes of the first `count` virtual nodes of the element on a
+    /// ring of the given seed.
+    pub(crate) fn points(self, seed: u64, element: &str, count: usize) -> Vec<u32> {
+        match self {
+            PlacementStrategy::Classic => {
+                (0..count)
+                    .map(|i| checksum(seed, (String::from(element) + &i.to_string()).as_bytes()))
+                    .collect()
+            }
+            PlacementStrategy::DoubleHash => {
+                let h1 = checksum(seed, element.as_bytes());
+                let h2 = checksum(seed, (String::from(element) + "#").as_bytes()) | 1;
+                (0..count as u32).map(|i| h1.wrapping_add(i.wrapping_mul(h2))).collect()
+            }
+            PlacementStrategy::SeededRandom => {
+                let mut state = seed ^ fnv1a64(element.as_bytes());
+                (0..count).map(|_| (splitmix64(&mut state) >> 32) as u32).collect()
+            }
+        }
+    }
+
+    /// Same as `points`, but the i-th virtual node closer than `min_spacing`
+    /// to one of the previous ones is derived again as the checksum of the
+    /// element name followed by the decimal i, `#` and the decimal attempt,
+    /// until it is far enough or the attempts run out. A spacing of 0 changes
+    /// nothing.
+    pub(crate) fn spaced_points(self,
+                                seed: u64,
+                                element: &str,
+                                count: usize,
+                                min_spacing: u32)
+                                -> Vec<u32> {
+        let mut points = self.points(seed, element, count);
+        if min_spacing == 0 {
+            return points;
+        }
+        let mut placed = BTreeSet::new();
+        for (i, point) in points.iter_mut().enumerate() {
+            let mut attempt = 0;
+            while attempt < SPACING_ATTEMPTS && too_close(&placed, *point, min_spacing) {
+                attempt += 1;
+                *point = checksum(seed, format!("{}{}#{}", element, i, attempt).as_bytes());
+            }
+            placed.insert(*point);
+        }
+        points
+    }
+}
+
+/// Check whether the given point is closer than `min_spacing` to one of the
+/// placed ones, either way around the hash ring.
+fn too_close(placed: &BTreeSet<u32>, point: u32, min_spacing: u32) -> bool {
+    let next = placed.range(point..).next().or_else(|| placed.iter().next());
+    let previous = placed.range(..point).next_back().or_else(|| placed.iter().next_back());
+    next.is_some_and(|next| next.wrapping_sub(point) < min_spacing) ||
+    previous.is_some_and(|previous| point.wrapping_sub(*previous) < min_spacing)
+}
+
+/// Get the FNV-1a 64 hash of the given bytes.
+fn fnv1a64(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100_0000_01b3)
+    })
+}
+
+/// Step the given SplitMix64 state, getting its next output.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Get the checksum of the given bytes on a ring of the given seed: the CRC32
+/// (IEEE) of the 8 little endian bytes of the seed followed by the bytes, or
+/// of the bytes alone for the seed 0.
+pub(crate) fn checksum(seed: u64, bytes: &[u8]) -> u32 {
+    if seed == 0 {
+        return checksum_ieee(bytes);
+    }
+    let prefix = crc32::update(0, &crc32::IEEE_TABLE, &seed.to_le_bytes());
+    crc32::update(prefix, &crc32::IEEE_TABLE, bytes)
+}
+
+/// Get the checksum of the key made of the given parts, each one encoded as
+/// its length in 4 little endian bytes followed by its bytes, as `checksum`
+/// of the encoding but without allocating it.
+pub(crate) fn checksum_parts(seed: u64, parts: &[&[u8]]) -> u32 {
+    let mut sum = 0;
+    if seed != 0 {
+        sum = crc32::update(sum, &crc32::IEEE_TABLE, &seed.to_le_bytes());
+    }
+    for part in parts {
+        sum = crc32::update(sum, &crc32::IEEE_TABLE, &(part.len() as u32).to_le_bytes());
+        sum = crc32::update(sum, &crc32::IEEE_TABLE, part);
+    }
+    sum
+}
@@ -0,0 +1,202 @@
+//! Protobuf representation of the hash ring, with the `proto` feature. The
+//! schema is `proto/ring.proto`, which `RingProto` and `MemberProto` are
+//! generated from.
+//!
+//! Unknown fields are skipped on decoding, so that messages of later schemas
+//! are still loaded.
+
+use std::collections::hash_set::HashSet;
+use consistant::Consistant;
+use error::ProtoError;
+use limits::loaded_vnodes;
+use placement::PlacementStrategy;
+use snapshot::HASHER_ID;
+
+mod generated {
+    include!("../proto/consistent.ring.v1.rs");
+}
+
+pub use self::generated::{Member as MemberProto, Ring as RingProto};
+
+/// The version of the protobuf schema written by `to_proto`.
+pub const PROTO_FORMAT_VERSION: u32 = 1;
+
+impl Consistant {
+    /// Get the protobuf representation of the hash ring, members sorted by
+    /// name along with their tokens.
+    pub fn to_proto(&self) -> RingProto {
+        let placement = self.placement();
+        RingProto {
+            format_version: PROTO_FORMAT_VERSION,
+            hasher_id: u32::from(HASHER_ID),
+            placement_id: u32::from(placement.id()),
+            seed: self.seed(),
+            ring_version: self.version(),
+            replicas: self.replicas_num as u32,
+            members: self.weighted_members()
+                .into_iter()
+                .map(|(element, weight)| {
+                    let explicit = self.explicit_tokens(&element);
+                    MemberProto {
+                        // The message has no spacing, see `set_min_self_spacing`.
+                        explicit_tokens: explicit.is_some() || self.self_spacing() != 0,
+                        tokens: explicit.unwrap_or_else(|| self.derived_points(&element, weight)),
+                        name: String::from(&*element),
+                        weight: weight as u32,
+                        zone: String::new(),
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    /// Load a hash ring from its protobuf representation, see `to_proto`.
+    /// Tokens may be left out, but those given must be the ones derived for
+    /// their member, unless marked as explicit.
+    pub fn from_proto(proto: RingProto) -> Result<Consistant, ProtoError> {
+        if proto.format_version == 0 || proto.format_version > PROTO_FORMAT_VERSION {
+            return Err(ProtoError::UnsupportedVersion(proto.format_version));
+        }
+        if proto.hasher_id != u32::from(HASHER_ID) {
+            return Err(ProtoError::UnknownHasher(proto.hasher_id));
+        }
+        let placement = match PlacementStrategy::from_id(proto.placement_id as u8) {
+            Some(placement) if u32::from(placement.id()) == proto.placement_id => placement,
+            _ => return Err(ProtoError::UnknownPlacement(proto.placement_id)),
+        };
+        if proto.replicas == 0 {
+            return Err(ProtoError::InvalidReplicas(proto.replicas));
+        }
+
+        let replicas = proto.replicas as usize;
+        let mut consistant = Consistant::with_config(replicas, placement, proto.seed);
+        let mut seen = HashSet::new();
+        let mut vnodes = 0;
+        for member in proto.members {
+            if member.weight == 0 {
+                return Err(ProtoError::ZeroWeight(member.name));
+            }
+            if !seen.insert(member.name.clone()) {
+                return Err(ProtoError::DuplicateMember(member.name));
+            }
+            let count = match loaded_vnodes(replicas, u64::from(member.weight), vnodes) {
+                Some(count) => count,
+                None => return Err(ProtoError::TooManyVnodes(member.name)),
+            };
+            vnodes += count;
+            if member.explicit_tokens {
+                if member.tokens.len() != count {
+                    return Err(ProtoError::TokenMismatch(member.name));
+                }
+                consistant.add_with_tokens(member.name, member.weight as usize, member.tokens);
+                continue;
+            }
+            if !member.tokens.is_empty() &&
+               member.tokens != placement.points(proto.seed, &member.name, count) {
+                return Err(ProtoError::TokenMismatch(member.name));
+            }
+            consistant.add_weighted(member.name, member.weight as usize);
+        }
+        consistant.set_version(proto.ring_version);
+
+        Ok(consistant)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+
+    const FIXTURE: &[u8] = include_bytes!("../tests/fixtures/ring-v1.pb");
+
+    fn consistant() -> Consistant {
+        let mut consistant = Consistant::with_seed(2, 42);
+        consistant.add("cacheA");
+        consistant.add_weighted("cacheB", 2);
+        consistant
+    }
+
+    #[test]
+    fn test_proto() {
+        let consistant = consistant();
+        assert_eq!(consistant.to_proto().encode_to_vec(), FIXTURE);
+
+        let loaded = Consistant::from_proto(RingProto::decode(FIXTURE).unwrap()).unwrap();
+        assert_eq!(loaded.to_json(), consistant.to_json());
+        assert_eq!(loaded.vnodes(), consistant.vnodes());
+
+        // Without tokens.
+        let mut proto = consistant.to_proto();
+        for member in &mut proto.members {
+            member.tokens.clear();
+        }
+        assert_eq!(Consistant::from_proto(proto).unwrap().vnodes(), consistant.vnodes());
+    }
+
+    #[test]
+    fn test_proto_explicit_tokens() {
+        let mut consistant = consistant();
+        consistant.replace_node("cacheB", "cacheC").unwrap();
+        let proto = consistant.to_proto();
+        assert!(!proto.members[0].explicit_tokens && proto.members[1].explicit_tokens);
+
+        let bytes = proto.encode_to_vec();
+        let loaded = Consistant::from_proto(RingProto::decode(&*bytes).unwrap()).unwrap();
+        assert_eq!(loaded.vnodes(), consistant.vnodes());
+
+        let mut proto = consistant.to_proto();
+        proto.members[1].tokens.pop();
+        assert_eq!(Consistant::from_proto(proto).unwrap_err(),
+                   ProtoError::TokenMismatch(String::from("cacheC")));
+        let mut proto = consistant.to_proto();
+        proto.members[1].explicit_tokens = false;
+        assert_eq!(Consistant::from_proto(proto).unwrap_err(),
+                   ProtoError::TokenMismatch(String::from("cacheC")));
+    }
+
+    #[test]
+    fn test_proto_unknown_fields() {
+        // Field 15 as a varint, then field 16 of the first member as bytes.
+        let mut bytes = FIXTURE.to_vec();
+        bytes.extend_from_slice(&[15 << 3, 1]);
+        let mut proto = RingProto::decode(&*bytes).unwrap();
+        assert_eq!(proto, consistant().to_proto());
+
+        let mut member = proto.members[0].encode_to_vec();
+        member.extend_from_slice(&[0x82, 0x01, 2, b'h', b'i']);
+        proto.members[0] = MemberProto::decode(&*member).unwrap();
+        assert_eq!(Consistant::from_proto(proto).unwrap().vnodes(), consistant().vnodes());
+    }
+
+    #[test]
+    fn test_proto_errors() {
+        let load = |f: &dyn Fn(&mut RingProto)| {
+            let mut proto = consistant().to_proto();
+            f(&mut proto);
+            Consistant::from_proto(proto).unwrap_err()
+        };
+
+        assert_eq!(load(&|proto| proto.format_version = 0),
+                   ProtoError::UnsupportedVersion(0));
+        assert_eq!(load(&|proto| proto.format_version = 2),
+                   ProtoError::UnsupportedVersion(2));
+        assert_eq!(load(&|proto| proto.hasher_id = 2), ProtoError::UnknownHasher(2));
+        assert_eq!(load(&|proto| proto.placement_id = 9), ProtoError::UnknownPlacement(9));
+        assert_eq!(load(&|proto| proto.placement_id = 257),
+                   ProtoError::UnknownPlacement(257));
+        assert_eq!(load(&|proto| proto.replicas = 0), ProtoError::InvalidReplicas(0));
+        assert_eq!(load(&|proto| proto.members[1].name = String::from("cacheA")),
+                   ProtoError::DuplicateMember(String::from("cacheA")));
+        assert_eq!(load(&|proto| proto.members[0].weight = 0),
+                   ProtoError::ZeroWeight(String::from("cacheA")));
+        assert_eq!(load(&|proto| proto.members[1].weight = 1),
+                   ProtoError::TokenMismatch(String::from("cacheB")));
+        assert_eq!(load(&|proto| proto.seed = 0),
+                   ProtoError::TokenMismatch(String::from("cacheA")));
+        assert_eq!(load(&|proto| proto.replicas = u32::MAX),
+                   ProtoError::TooManyVnodes(String::from("cacheA")));
+        assert_eq!(load(&|proto| proto.members[1].weight = u32::MAX),
+                   ProtoError::TooManyVnodes(String::from("cacheB")));
+    }
+}
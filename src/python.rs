@@ -0,0 +1,127 @@
+//! Python bindings, exposing the hash ring as `consistent_rs.Consistent`.
+//!
+//! The extension module is built by compiling the crate as a `cdylib` with
+//! the `python` and `pyo3/extension-module` features, e.g. with maturin. The
+//! tests embed an interpreter instead:
+//! `cargo test --features python,pyo3/auto-initialize`.
+
+// Triggered by the code generated for `PyResult` returning methods.
+#![allow(clippy::useless_conversion)]
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use consistant::Consistant;
+
+/// Consistent is the Python class wrapping a hash ring.
+#[pyclass(name = "Consistent")]
+pub struct PyConsistent {
+    inner: Consistant,
+}
+
+#[pymethods]
+impl PyConsistent {
+    #[new]
+    #[pyo3(signature = (replicas_num = 20))]
+    fn new(replicas_num: usize) -> Self {
+        PyConsistent { inner: Consistant::new(replicas_num) }
+    }
+
+    #[pyo3(signature = (element, weight = 1))]
+    fn add(&mut self, element: &str, weight: usize) -> PyResult<()> {
+        if weight == 0 {
+            return Err(PyValueError::new_err("weight must be positive"));
+        }
+        self.inner.add_weighted(element, weight);
+        Ok(())
+    }
+
+    fn remove(&mut self, element: &str) {
+        self.inner.remove(element)
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        self.inner.get(key)
+    }
+
+    fn get_n(&self, key: &str, n: usize) -> Vec<String> {
+        self.inner.get_n(key, n).unwrap_or_default()
+    }
+
+    /// Get the closest element of every key, releasing the GIL meanwhile.
+    fn get_many(&self, py: Python, keys: Vec<String>) -> Vec<Option<String>> {
+        let inner = &self.inner;
+        py.allow_threads(|| keys.iter().map(|key| inner.get(key.as_str())).collect())
+    }
+
+    fn members(&self) -> Vec<String> {
+        self.inner.members()
+    }
+
+    fn count(&self) -> usize {
+        self.inner.count()
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.count()
+    }
+
+    fn __contains__(&self, element: &str) -> bool {
+        self.inner.contains(element)
+    }
+}
+
+#[pymodule]
+fn consistent_rs(m: &Bound<PyModule>) -> PyResult<()> {
+    m.add_class::<PyConsistent>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::PyDict;
+
+    #[test]
+    fn test_python_parity() {
+        let mut native = Consistant::new(20);
+        native.add("cacheA");
+        native.add_weighted("cacheB", 2);
+        native.add("cacheC");
+        let keys: Vec<String> = (0..500).map(|i| format!("key{}", i)).collect();
+
+        Python::with_gil(|py| {
+            let class = py.get_type_bound::<PyConsistent>();
+            let locals = PyDict::new_bound(py);
+            locals.set_item("Consistent", class).unwrap();
+            locals.set_item("keys", keys.clone()).unwrap();
+            py.run_bound("ring = Consistent(20)
+ring.add('cacheA')
+ring.add('cacheB', weight=2)
+ring.add('cacheC')
+ring.add('cacheA')
+owners = ring.get_many(keys)
+first = [ring.get(key) for key in keys]
+top = [ring.get_n(key, 2) for key in keys]
+",
+                         Some(&locals),
+                         None)
+                .unwrap();
+
+            let owners: Vec<Option<String>> = locals.get_item("owners").unwrap().unwrap().extract().unwrap();
+            let first: Vec<Option<String>> = locals.get_item("first").unwrap().unwrap().extract().unwrap();
+            let top: Vec<Vec<String>> = locals.get_item("top").unwrap().unwrap().extract().unwrap();
+            for (i, key) in keys.iter().enumerate() {
+                assert_eq!(owners[i], native.get(key.as_str()));
+                assert_eq!(first[i], native.get(key.as_str()));
+                assert_eq!(top[i], native.get_n(key.as_str(), 2).unwrap());
+            }
+
+            let ring = locals.get_item("ring").unwrap().unwrap();
+            let members: Vec<String> = ring.call_method0("members").unwrap().extract().unwrap();
+            assert_eq!(members, native.members());
+            assert_eq!(ring.len().unwrap(), 3);
+
+            let err = ring.call_method1("add", ("cacheD", 0)).unwrap_err();
+            assert!(err.is_instance_of::<PyValueError>(py));
+        });
+    }
+}
@@ -0,0 +1,143 @@
+use std::sync::Arc;
+use consistant::Consistant;
+
+/// RangeOwnership is a range of checksums owned by a single element, see
+/// `Consistant::nodes_for_range`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeOwnership {
+    /// The checksum before the range, which is excluded.
+    pub start: u32,
+    /// The last checksum of the range, which is included.
+    pub end: u32,
+    /// The element owning the checksums of the range.
+    pub owner: String,
+}
+
+impl Consistant {
+    /// Split the range of checksums `(start, end]` by owner, the range
+    /// wrapping past `u32::MAX` if `end` is lower than `start`. A range of
+    /// `start == end` is the whole keyspace. The returned ranges are
+    /// adjacent, in order, cover exactly the given one, and are each owned
+    /// by another element than the next. An empty hash ring yields no range.
+    /// The owners are those of the virtual nodes, whatever the assignments
+    /// of `assign_range`.
+    pub fn nodes_for_range(&self, start: u32, end: u32) -> Vec<RangeOwnership> {
+        let vnodes = self.vnodes();
+        let mut ranges: Vec<RangeOwnership> = Vec::new();
+        if vnodes.is_empty() {
+            return ranges;
+        }
+        let length = match u64::from(end.wrapping_sub(start)) {
+            0 => 1 << 32,
+            length => length,
+        };
+        let mut push = |start: u32, end: u32, owner: &Arc<str>| {
+            match ranges.last_mut() {
+                Some(last) if *last.owner == **owner => last.end = end,
+                _ => {
+                    ranges.push(RangeOwnership {
+                        start,
+                        end,
+                        owner: String::from(&**owner),
+                    })
+                }
+            }
+        };
+
+        // The checksums up to the one before a virtual node are its own, so
+        // the ranges end before the virtual nodes, in order from `start`.
+        let first = vnodes.partition_point(|vnode| vnode.0 <= start);
+        let mut from = start;
+        for i in 0..vnodes.len() {
+            let (hash, ref owner) = vnodes[(first + i) % vnodes.len()];
+            let before = hash.wrapping_sub(1);
+            let offset = u64::from(before.wrapping_sub(start));
+            if offset >= length {
+                break;
+            }
+            if offset > 0 {
+                push(from, before, owner);
+                from = before;
+            }
+        }
+        let (_, ref owner) = vnodes[vnodes.partition_point(|vnode| vnode.0 <= end) % vnodes.len()];
+        push(from, end, owner);
+
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xorshift::Xorshift;
+
+    /// Check that the ranges cover `(start, end]` and are owned as stated.
+    fn check(consistant: &Consistant, start: u32, end: u32) -> Vec<RangeOwnership> {
+        let ranges = consistant.nodes_for_range(start, end);
+        assert_eq!(ranges[0].start, start);
+        assert_eq!(ranges[ranges.len() - 1].end, end);
+        for pair in ranges.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+            assert!(pair[0].owner != pair[1].owner);
+        }
+        let mut covered = 0;
+        for range in &ranges {
+            let length = match u64::from(range.end.wrapping_sub(range.start)) {
+                0 => 1 << 32,
+                length => length,
+            };
+            covered += length;
+            let middle = range.start.wrapping_add((length / 2) as u32);
+            for sum in &[range.start.wrapping_add(1), middle, range.end] {
+                assert_eq!(consistant.get_by_hash(*sum).as_ref(), Some(&range.owner));
+            }
+        }
+        let length = match u64::from(end.wrapping_sub(start)) {
+            0 => 1 << 32,
+            length => length,
+        };
+        assert_eq!(covered, length);
+        ranges
+    }
+
+    #[test]
+    fn test_nodes_for_range() {
+        let mut rng = Xorshift::new(42);
+
+        let mut consistant = Consistant::new(10);
+        assert!(consistant.nodes_for_range(1, 2).is_empty());
+        for i in 0..5 {
+            consistant.add(format!("cache{}", i));
+        }
+        for _ in 0..500 {
+            let (start, end) = (rng.next_u32(), rng.next_u32());
+            check(&consistant, start, end);
+            check(&consistant, start, start.wrapping_add(rng.next_u32() % 1000));
+        }
+
+        // Wrapping past u32::MAX, and starting or ending at virtual nodes.
+        let vnodes = consistant.vnodes();
+        let (lowest, highest) = (vnodes[0].0, vnodes[vnodes.len() - 1].0);
+        assert!(check(&consistant, highest, lowest).len() <= 2);
+        assert_eq!(check(&consistant, highest, lowest.wrapping_sub(1)).len(), 1);
+        check(&consistant, u32::MAX, 0);
+        check(&consistant, lowest, lowest);
+        check(&consistant, lowest.wrapping_sub(1), lowest.wrapping_sub(1));
+        check(&consistant, 0, 0);
+    }
+
+    #[test]
+    fn test_nodes_for_range_single_member() {
+        let mut consistant = Consistant::new(10);
+        consistant.add("cacheA");
+        for &(start, end) in &[(0, 0), (5, 1), (u32::MAX, 3), (1, u32::MAX)] {
+            assert_eq!(check(&consistant, start, end),
+                       [RangeOwnership {
+                            start,
+                            end,
+                            owner: String::from("cacheA"),
+                        }]);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::btree_map::BTreeMap;
+use error::Error;
+
+/// RangeOverrides holds the ranges of checksums assigned to an owner, see
+/// `Consistant::assign_range`. A range `(start, end]` is the one of
+/// `Consistant::nodes_for_range`, wrapping past `u32::MAX` if `end` is lower
+/// than `start`, `start == end` being the whole keyspace. The ranges never
+/// overlap, so a checksum is in one range at most.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RangeOverrides<T> {
+    /// The `(end, owner)` of every range, by start.
+    ranges: BTreeMap<u32, (u32, T)>,
+}
+
+impl<T> Default for RangeOverrides<T> {
+    fn default() -> RangeOverrides<T> {
+        RangeOverrides { ranges: BTreeMap::new() }
+    }
+}
+
+impl<T> RangeOverrides<T> {
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Assign the given range to the given owner, replacing the owner of
+    /// the very same range. A range overlapping another one is refused.
+    pub(crate) fn insert(&mut self, start: u32, end: u32, owner: T) -> Result<(), Error> {
+        let overlapping = self.iter()
+            .filter(|range| (range.0, range.1) != (start, end))
+            .find(|range| overlap((start, end), (range.0, range.1)));
+        if let Some((start, end, _)) = overlapping {
+            return Err(Error::OverlappingRange { start, end });
+        }
+        self.ranges.insert(start, (end, owner));
+        Ok(())
+    }
+
+    /// Remove the given range, returning its owner.
+    pub(crate) fn remove(&mut self, start: u32, end: u32) -> Option<T> {
+        match self.ranges.get(&start) {
+            Some(range) if range.0 == end => self.ranges.remove(&start).map(|range| range.1),
+            _ => None,
+        }
+    }
+
+    /// Get the range of the given checksum, as `(start, end, owner)`.
+    #[inline]
+    pub(crate) fn find(&self, sum: u32) -> Option<(u32, u32, &T)> {
+        // The range wrapping around, if any, has the greatest start, so it is
+        // the one before a checksum it holds, or the last one.
+        let (&start, &(end, ref owner)) = self.ranges
+            .range(..sum)
+            .next_back()
+            .or_else(|| self.ranges.iter().next_back())?;
+        if range_contains(start, end, sum) {
+            Some((start, end, owner))
+        } else {
+            None
+        }
+    }
+
+    /// Get the ranges as `(start, end, owner)`, sorted by start.
+    pub(crate) fn iter<'a>(&'a self) -> impl Iterator<Item = (u32, u32, &'a T)> + 'a {
+        self.ranges.iter().map(|(&start, &(end, ref owner))| (start, end, owner))
+    }
+
+    /// Map the owners of the ranges, leaving out those mapped to `None`.
+    pub(crate) fn filter_map<U, F: Fn(&T) -> Option<U>>(&self, f: F) -> RangeOverrides<U> {
+        RangeOverrides {
+            ranges: self.ranges
+                .iter()
+                .filter_map(|(&start, &(end, ref owner))| {
+                    f(owner).map(|owner| (start, (end, owner)))
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Check whether the range `(start, end]` holds the given checksum.
+#[inline]
+pub(crate) fn range_contains(start: u32, end: u32, sum: u32) -> bool {
+    let offset = match sum.wrapping_sub(start) {
+        0 => 1 << 32,
+        offset => u64::from(offset),
+    };
+    let length = match end.wrapping_sub(start) {
+        0 => 1 << 32,
+        length => u64::from(length),
+    };
+    offset <= length
+}
+
+/// Check whether two ranges overlap, one of them holding the first checksum
+/// of the other.
+fn overlap(a: (u32, u32), b: (u32, u32)) -> bool {
+    range_contains(a.0, a.1, b.0.wrapping_add(1)) || range_contains(b.0, b.1, a.0.wrapping_add(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_range_overrides() {
+        let mut ranges = RangeOverrides::default();
+        ranges.insert(100, 200, "cacheA").unwrap();
+        ranges.insert(u32::MAX - 10, 10, "cacheB").unwrap();
+        ranges.insert(200, 201, "cacheC").unwrap();
+        assert_eq!(ranges.insert(150, 300, "cacheD"),
+                   Err(Error::OverlappingRange { start: 100, end: 200 }));
+        assert_eq!(ranges.insert(50, 101, "cacheD"),
+                   Err(Error::OverlappingRange { start: 100, end: 200 }));
+        assert_eq!(ranges.insert(9, 20, "cacheD"),
+                   Err(Error::OverlappingRange { start: u32::MAX - 10, end: 10 }));
+        assert_eq!(ranges.insert(u32::MAX - 1, u32::MAX, "cacheD"),
+                   Err(Error::OverlappingRange { start: u32::MAX - 10, end: 10 }));
+        assert_eq!(ranges.insert(300, 300, "cacheD"),
+                   Err(Error::OverlappingRange { start: 100, end: 200 }));
+        ranges.insert(50, 100, "cacheD").unwrap();
+        assert_eq!(ranges.remove(50, 100), Some("cacheD"));
+
+        assert_eq!(ranges.find(100), None);
+        assert_eq!(ranges.find(101), Some((100, 200, &"cacheA")));
+        assert_eq!(ranges.find(200), Some((100, 200, &"cacheA")));
+        assert_eq!(ranges.find(201), Some((200, 201, &"cacheC")));
+        assert_eq!(ranges.find(0), Some((u32::MAX - 10, 10, &"cacheB")));
+        assert_eq!(ranges.find(u32::MAX), Some((u32::MAX - 10, 10, &"cacheB")));
+        assert_eq!(ranges.find(u32::MAX - 10), None);
+        assert_eq!(ranges.find(11), None);
+        assert_eq!(ranges.find(202), None);
+
+        ranges.insert(100, 200, "cacheD").unwrap();
+        assert_eq!(ranges.find(150), Some((100, 200, &"cacheD")));
+        assert_eq!(ranges.remove(100, 150), None);
+        assert_eq!(ranges.remove(100, 200), Some("cacheD"));
+        assert_eq!(ranges.find(150), None);
+        let lengths = ranges.filter_map(|owner| if *owner == "cacheB" { None } else { Some(1) });
+        assert_eq!(lengths.iter().collect::<Vec<_>>(), [(200, 201, &1)]);
+
+        let mut whole = RangeOverrides::default();
+        whole.insert(7, 7, "cacheA").unwrap();
+        assert_eq!(whole.find(7), Some((7, 7, &"cacheA")));
+        assert_eq!(whole.find(8), Some((7, 7, &"cacheA")));
+        assert_eq!(whole.insert(1, 2, "cacheB"), Err(Error::OverlappingRange { start: 7, end: 7 }));
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::hash_map::HashMap;
+use consistant::{Consistant, RouteSource};
+
+/// Move describes a key whose closest element differs between two hash rings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Move {
+    pub key: String,
+    /// The element owning the key before, `None` if the ring was empty.
+    pub from: Option<String>,
+    /// The element owning the key after, `None` if the ring is empty.
+    pub to: Option<String>,
+}
+
+/// Get the moves needed to go from the "before" hash ring to the "after" one
+/// for the given keys. Keys whose owner is unchanged are skipped.
+pub fn rebalance_plan<'a, I>(before: &Consistant, after: &Consistant, keys: I) -> Vec<Move>
+    where I: IntoIterator<Item = &'a str>
+{
+    let mut moves = Vec::new();
+
+    for key in keys {
+        let from = before.get(key);
+        let to = after.get(key);
+        if from != to {
+            moves.push(Move {
+                key: String::from(key),
+                from,
+                to,
+            });
+        }
+    }
+
+    moves
+}
+
+/// Same as `rebalance_plan`, but the moved keys are grouped by their
+/// `(from, to)` pair for batching.
+pub fn rebalance_plan_grouped<'a, I>(before: &Consistant,
+                                     after: &Consistant,
+                                     keys: I)
+                                     -> HashMap<(Option<String>, Option<String>), Vec<String>>
+    where I: IntoIterator<Item = &'a str>
+{
+    let mut groups: HashMap<(Option<String>, Option<String>), Vec<String>> = HashMap::new();
+
+    for m in rebalance_plan(before, after, keys) {
+        groups.entry((m.from, m.to)).or_default().push(m.key);
+    }
+
+    groups
+}
+
+/// MutationPreview tells how adding or removing an element would change a
+/// hash ring, see `Consistant::preview_add`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MutationPreview {
+    /// The share of the keyspace which would change owner.
+    pub disruption: f64,
+    /// The `(from, to)` pairs of elements the moved arcs would go between,
+    /// sorted, `None` standing for an empty hash ring.
+    pub moves: Vec<(Option<String>, Option<String>)>,
+    /// The count of elements after the mutation.
+    pub count: usize,
+    /// The count of virtual nodes after the mutation.
+    pub vnodes: usize,
+}
+
+/// AgreementReport tells how often two hash rings route keys to the same
+/// element.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgreementReport {
+    /// The share of the keys routed identically, 1 if there were no keys.
+    pub ratio: f64,
+    /// The count of keys routed to the same element, or by neither ring.
+    pub agreed: usize,
+    /// The count of keys routed differently.
+    pub disagreed: usize,
+    /// The count of keys which either ring could not route.
+    pub unroutable: usize,
+    /// The count of keys which either ring only resolved to its fallback,
+    /// being empty, see `Consistant::set_fallback`. They are still counted
+    /// as agreed or not by the name resolved.
+    pub fallback: usize,
+}
+
+/// Compare how the two hash rings route the given keys. The rings need not
+/// share any element or configuration.
+pub fn agreement_ratio<'a, I>(a: &Consistant, b: &Consistant, keys: I) -> AgreementReport
+    where I: IntoIterator<Item = &'a str>
+{
+    let mut report = AgreementReport {
+        ratio: 1.0,
+        agreed: 0,
+        disagreed: 0,
+        unroutable: 0,
+        fallback: 0,
+    };
+
+    for key in keys {
+        let (from, to) = (a.get_with_source(key), b.get_with_source(key));
+        if from.is_none() || to.is_none() {
+            report.unroutable += 1;
+        }
+        let fallback = Some(RouteSource::Fallback);
+        if from.as_ref().map(|from| from.1) == fallback || to.as_ref().map(|to| to.1) == fallback {
+            report.fallback += 1;
+        }
+        let (from, to) = (from.map(|from| from.0), to.map(|to| to.0));
+        if from == to {
+            report.agreed += 1;
+        } else {
+            report.disagreed += 1;
+        }
+    }
+    if report.agreed + report.disagreed > 0 {
+        report.ratio = report.agreed as f64 / (report.agreed + report.disagreed) as f64;
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys() -> Vec<String> {
+        (0..1000).map(|i| format!("key{}", i)).collect()
+    }
+
+    #[test]
+    fn test_rebalance_plan() {
+        let mut before = Consistant::default();
+        before.add("cacheA");
+        before.add("cacheB");
+        before.add("cacheC");
+
+        let mut after = Consistant::default();
+        after.add("cacheA");
+        after.add("cacheB");
+        after.add("cacheD");
+
+        let keys = keys();
+        let moves = rebalance_plan(&before, &after, keys.iter().map(|key| key.as_str()));
+        assert!(!moves.is_empty());
+
+        let mut assignment: HashMap<&str, Option<String>> = keys.iter()
+            .map(|key| (key.as_str(), before.get(key.as_str())))
+            .collect();
+        for m in &moves {
+            assert_eq!(assignment[m.key.as_str()], m.from);
+            assignment.insert(m.key.as_str(), m.to.clone());
+        }
+        for key in &keys {
+            assert_eq!(assignment[key.as_str()], after.get(key.as_str()));
+        }
+    }
+
+    #[test]
+    fn test_rebalance_plan_empty_ring() {
+        let before = Consistant::default();
+        let mut after = Consistant::default();
+        after.add("cacheA");
+
+        let moves = rebalance_plan(&before, &after, vec!["david", "kally"]);
+        assert_eq!(moves.len(), 2);
+        for m in &moves {
+            assert_eq!(m.from, None);
+            assert_eq!(m.to, Some(String::from("cacheA")));
+        }
+
+        let moves = rebalance_plan(&after, &before, vec!["david"]);
+        assert_eq!(moves[0].from, Some(String::from("cacheA")));
+        assert_eq!(moves[0].to, None);
+    }
+
+    #[test]
+    fn test_rebalance_plan_grouped() {
+        let mut before = Consistant::default();
+        before.add("cacheA");
+        before.add("cacheB");
+
+        let mut after = Consistant::default();
+        after.add("cacheA");
+        after.add("cacheB");
+        after.add("cacheC");
+
+        let keys = keys();
+        let moves = rebalance_plan(&before, &after, keys.iter().map(|key| key.as_str()));
+        let groups = rebalance_plan_grouped(&before, &after, keys.iter().map(|key| key.as_str()));
+
+        assert_eq!(groups.values().map(|group| group.len()).sum::<usize>(),
+                   moves.len());
+        for ((from, to), group) in &groups {
+            assert_eq!(to, &Some(String::from("cacheC")));
+            for key in group {
+                assert_eq!(&before.get(key.as_str()), from);
+            }
+        }
+    }
+
+    #[test]
+    fn test_agreement_ratio() {
+        let mut a = Consistant::default();
+        for i in 0..9 {
+            a.add(format!("node-{}", i));
+        }
+        let keys = keys();
+        let keys = || keys.iter().map(|key| key.as_str());
+
+        let report = agreement_ratio(&a, &a.clone(), keys());
+        assert_eq!(report,
+                   AgreementReport {
+                       ratio: 1.0,
+                       agreed: 1000,
+                       disagreed: 0,
+                       unroutable: 0,
+                       fallback: 0,
+                   });
+
+        let mut b = a.clone();
+        b.add("node-9");
+        let report = agreement_ratio(&a, &b, keys());
+        let expected = 1.0 - b.last_disruption().unwrap();
+        assert!((expected - 0.9).abs() < 0.1, "{}", expected);
+        assert!((report.ratio - expected).abs() < 0.03, "{}", report.ratio);
+        assert_eq!(report.agreed + report.disagreed, 1000);
+
+        let mut disjoint = Consistant::default();
+        disjoint.add("cacheA");
+        assert_eq!(agreement_ratio(&a, &disjoint, keys()).ratio, 0.0);
+
+        let empty = Consistant::default();
+        let report = agreement_ratio(&empty, &disjoint, keys());
+        assert_eq!((report.disagreed, report.unroutable), (1000, 1000));
+        let report = agreement_ratio(&empty, &empty, keys());
+        assert_eq!((report.ratio, report.unroutable), (1.0, 1000));
+        assert_eq!(agreement_ratio(&a, &b, vec![]).ratio, 1.0);
+
+        // The keys resolved through the fallback are told apart.
+        let mut fallback = Consistant::default();
+        fallback.set_fallback(Some(String::from("cacheA")));
+        let report = agreement_ratio(&fallback, &disjoint, keys());
+        assert_eq!(report,
+                   AgreementReport {
+                       ratio: 1.0,
+                       agreed: 1000,
+                       disagreed: 0,
+                       unroutable: 0,
+                       fallback: 1000,
+                   });
+        assert_eq!(agreement_ratio(&a, &disjoint, keys()).fallback, 0);
+    }
+}
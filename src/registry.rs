@@ -0,0 +1,359 @@
+use std::collections::btree_map::BTreeMap;
+use std::collections::hash_map::HashMap;
+use std::sync::{Arc, PoisonError};
+use consistant::Consistant;
+use error::Error;
+use sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// MemberRecord is the canonical record of a member, as a `MemberRegistry`
+/// holds it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemberRecord {
+    /// The name, shared by the registry and every hash ring holding the
+    /// member.
+    pub name: Arc<str>,
+    pub weight: usize,
+    pub zone: Option<String>,
+    /// The tags, sorted.
+    pub tags: Vec<String>,
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// MemberRegistry holds the records of the members shared by several hash
+/// rings, see `Consistant::with_registry`. Its clones are handles of the
+/// same records, so that a zone, tags or metadata updated through any of
+/// them is seen by every ring at once, while each ring adds the members it
+/// holds.
+#[derive(Debug, Clone, Default)]
+pub struct MemberRegistry {
+    entries: Arc<RwLock<HashMap<Arc<str>, Arc<Entry>>>>,
+}
+
+/// Entry is a record of the registry, which the hash rings holding its
+/// member share, counted by the references to it.
+#[derive(Debug)]
+pub(crate) struct Entry {
+    record: RwLock<MemberRecord>,
+}
+
+impl Entry {
+    fn read<'a>(&'a self) -> RwLockReadGuard<'a, MemberRecord> {
+        self.record.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write<'a>(&'a self) -> RwLockWriteGuard<'a, MemberRecord> {
+        self.record.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl MemberRegistry {
+    /// Crete a new instance of MemberRegistry, without any member.
+    pub fn new() -> Self {
+        MemberRegistry::default()
+    }
+
+    /// Register a member of the given weight, without zone, tags nor
+    /// metadata. The weight is the one of the member in the hash rings it is
+    /// added to afterwards.
+    pub fn register<S: Into<String>>(&self, name: S, weight: usize) -> Result<(), Error> {
+        let name: Arc<str> = Arc::from(name.into());
+        if weight == 0 {
+            return Err(Error::InvalidWeight(String::from(&*name)));
+        }
+        let mut entries = self.write();
+        if entries.contains_key(&name) {
+            return Err(Error::DuplicateMember(String::from(&*name)));
+        }
+        let record = MemberRecord {
+            name: name.clone(),
+            weight,
+            zone: None,
+            tags: Vec::new(),
+            metadata: BTreeMap::new(),
+        };
+        entries.insert(name, Arc::new(Entry { record: RwLock::new(record) }));
+        Ok(())
+    }
+
+    /// Set the zone of the given member, `None` clearing it.
+    pub fn set_zone(&self, name: &str, zone: Option<&str>) -> Result<(), Error> {
+        self.update(name, |record| record.zone = zone.map(String::from))
+    }
+
+    /// Set the tags of the given member, replacing its previous ones.
+    pub fn set_tags<I, S>(&self, name: &str, tags: I) -> Result<(), Error>
+        where I: IntoIterator<Item = S>,
+              S: Into<String>
+    {
+        let mut tags: Vec<String> = tags.into_iter().map(Into::into).collect();
+        tags.sort();
+        tags.dedup();
+        self.update(name, |record| record.tags = tags)
+    }
+
+    /// Set the given metadata of the given member, `None` removing it.
+    pub fn set_metadata(&self, name: &str, key: &str, value: Option<&str>) -> Result<(), Error> {
+        self.update(name, |record| match value {
+            Some(value) => {
+                record.metadata.insert(String::from(key), String::from(value));
+            }
+            None => {
+                record.metadata.remove(key);
+            }
+        })
+    }
+
+    /// Get the record of the given member.
+    pub fn record(&self, name: &str) -> Option<MemberRecord> {
+        self.read().get(name).map(|entry| entry.read().clone())
+    }
+
+    /// Get the names of the members, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.read().keys().map(|name| String::from(&**name)).collect();
+        names.sort();
+        names
+    }
+
+    /// Get the count of members.
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    /// Check whether there is no member.
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+
+    /// Get the count of hash rings holding the given member, 0 for a member
+    /// not registered.
+    pub fn ring_count(&self, name: &str) -> usize {
+        self.read().get(name).map_or(0, |entry| Arc::strong_count(entry) - 1)
+    }
+
+    /// Unregister the given member, or return `MemberInUse` with the count
+    /// of hash rings still holding it, which must remove it first.
+    pub fn unregister(&self, name: &str) -> Result<(), Error> {
+        let mut entries = self.write();
+        let rings = match entries.get(name) {
+            Some(entry) => Arc::strong_count(entry) - 1,
+            None => return Err(Error::UnknownMember(String::from(name))),
+        };
+        if rings > 0 {
+            return Err(Error::MemberInUse {
+                name: String::from(name),
+                rings,
+            });
+        }
+        entries.remove(name);
+        Ok(())
+    }
+
+    /// Unregister the given member even if hash rings still hold it,
+    /// returning whether it was registered. The rings keep the member, with
+    /// its record as it was, no longer updated, until they remove it, and it
+    /// can not be added to any other ring but registered again.
+    pub fn force_unregister(&self, name: &str) -> bool {
+        self.write().remove(name).is_some()
+    }
+
+    pub(crate) fn entry(&self, name: &str) -> Option<Arc<Entry>> {
+        self.read().get(name).cloned()
+    }
+
+    fn update<F: FnOnce(&mut MemberRecord)>(&self, name: &str, f: F) -> Result<(), Error> {
+        match self.read().get(name) {
+            Some(entry) => {
+                f(&mut entry.write());
+                Ok(())
+            }
+            None => Err(Error::UnknownMember(String::from(name))),
+        }
+    }
+
+    fn read<'a>(&'a self) -> RwLockReadGuard<'a, HashMap<Arc<str>, Arc<Entry>>> {
+        self.entries.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write<'a>(&'a self) -> RwLockWriteGuard<'a, HashMap<Arc<str>, Arc<Entry>>> {
+        self.entries.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Registered is the registry of a hash ring, and the entries of the
+/// elements added from it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Registered {
+    registry: Option<MemberRegistry>,
+    entries: HashMap<Arc<str>, Arc<Entry>>,
+}
+
+impl Registered {
+    pub(crate) fn remove(&mut self, element: &str) {
+        self.entries.remove(element);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Get a copy holding the entries of the given elements only.
+    pub(crate) fn kept(&self, members: &HashMap<Arc<str>, usize>) -> Registered {
+        Registered {
+            registry: self.registry.clone(),
+            entries: self.entries
+                .iter()
+                .filter(|&(element, _)| members.contains_key(element))
+                .map(|(element, entry)| (element.clone(), entry.clone()))
+                .collect(),
+        }
+    }
+}
+
+impl Consistant {
+    /// Crete a new instance of Consistant adding its elements from the given
+    /// registry, see `add_registered`.
+    pub fn with_registry(registry: MemberRegistry, replicas_num: usize) -> Self {
+        let mut consistant = Consistant::new(replicas_num);
+        consistant.registered_mut().registry = Some(registry);
+        consistant
+    }
+
+    /// Get the registry the elements are added from, see `with_registry`.
+    pub fn registry(&self) -> Option<&MemberRegistry> {
+        self.registered().registry.as_ref()
+    }
+
+    /// Add the given member of the registry, sharing its name, with its
+    /// weight in the registry. The weight is not updated afterwards. An
+    /// element already in the hash ring is left as it is, and a member not
+    /// registered is refused with `UnknownMember`, as is any without a
+    /// registry.
+    pub fn add_registered(&mut self, name: &str) -> Result<(), Error> {
+        if self.contains(name) {
+            return Ok(());
+        }
+        let entry = match self.registry().and_then(|registry| registry.entry(name)) {
+            Some(entry) => entry,
+            None => return Err(Error::UnknownMember(String::from(name))),
+        };
+        let (name, weight) = {
+            let record = entry.read();
+            (record.name.clone(), record.weight)
+        };
+        self.try_add_interned(name.clone(), weight).map_err(Error::LimitExceeded)?;
+        self.registered_mut().entries.insert(name, entry);
+        Ok(())
+    }
+
+    /// Get the current record of the given element, if it was added from
+    /// the registry.
+    pub fn record(&self, name: &str) -> Option<MemberRecord> {
+        self.registered().entries.get(name).map(|entry| entry.read().clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> MemberRegistry {
+        let registry = MemberRegistry::new();
+        for i in 0..6 {
+            registry.register(format!("cache{}", i), 1 + i % 2).unwrap();
+        }
+        registry
+    }
+
+    #[test]
+    fn test_registry() {
+        let registry = registry();
+        assert_eq!(registry.register("cache0", 1),
+                   Err(Error::DuplicateMember(String::from("cache0"))));
+        assert_eq!(registry.register("cache9", 0),
+                   Err(Error::InvalidWeight(String::from("cache9"))));
+        assert_eq!(registry.set_zone("cache9", Some("a")),
+                   Err(Error::UnknownMember(String::from("cache9"))));
+        assert_eq!((registry.len(), registry.names()[0].as_str()), (6, "cache0"));
+
+        // Both rings see the updates at once, holding the members they add.
+        let mut first = Consistant::with_registry(registry.clone(), 20);
+        let mut second = Consistant::with_registry(registry.clone(), 20);
+        for i in 0..6 {
+            first.add_registered(&format!("cache{}", i)).unwrap();
+        }
+        second.add_registered("cache1").unwrap();
+        second.add_registered("cache2").unwrap();
+        assert_eq!(second.add_registered("cache9"),
+                   Err(Error::UnknownMember(String::from("cache9"))));
+        assert_eq!((first.count(), second.count()), (6, 2));
+        assert_eq!(first.record("cache1").unwrap().weight, 2);
+        assert_eq!(second.record("cache0"), None);
+
+        registry.set_zone("cache1", Some("us-east-1a")).unwrap();
+        registry.set_tags("cache1", vec!["ssd", "big", "ssd"]).unwrap();
+        registry.set_metadata("cache1", "version", Some("2.4")).unwrap();
+        for ring in &[&first, &second] {
+            let record = ring.record("cache1").unwrap();
+            assert_eq!(record, registry.record("cache1").unwrap());
+            assert_eq!(record.zone.as_deref(), Some("us-east-1a"));
+            assert_eq!(record.tags, vec!["big", "ssd"]);
+            assert_eq!(record.metadata["version"], "2.4");
+        }
+        registry.set_metadata("cache1", "version", None).unwrap();
+        assert!(second.record("cache1").unwrap().metadata.is_empty());
+
+        // The names are not copied.
+        let name = registry.record("cache2").unwrap().name;
+        for ring in &[&first, &second] {
+            let members = ring.weighted_members();
+            let member = members.iter().find(|member| &*member.0 == "cache2").unwrap();
+            assert!(Arc::ptr_eq(&member.0, &name));
+            assert!(Arc::ptr_eq(&ring.record("cache2").unwrap().name, &name));
+        }
+        let copy = first.clone_with_replicas(40);
+        assert!(Arc::ptr_eq(&copy.record("cache2").unwrap().name, &name));
+        assert_eq!(registry.ring_count("cache2"), 3);
+        drop(copy);
+        assert_eq!(registry.ring_count("cache2"), 2);
+    }
+
+    #[test]
+    fn test_unregister() {
+        let registry = registry();
+        let mut first = Consistant::with_registry(registry.clone(), 20);
+        let mut second = Consistant::with_registry(registry.clone(), 20);
+        first.add_registered("cache0").unwrap();
+        second.add_registered("cache0").unwrap();
+        let subset = first.subset(|_| true);
+        assert_eq!(registry.ring_count("cache0"), 3);
+        assert_eq!(registry.unregister("cache0"),
+                   Err(Error::MemberInUse {
+                       name: String::from("cache0"),
+                       rings: 3,
+                   }));
+        assert_eq!(registry.unregister("cache9"),
+                   Err(Error::UnknownMember(String::from("cache9"))));
+
+        // Rings release the members they remove, and the ones dropped.
+        first.remove("cache0");
+        drop(subset);
+        assert_eq!(registry.ring_count("cache0"), 1);
+        second.clear();
+        assert_eq!(registry.unregister("cache0"), Ok(()));
+        assert_eq!(registry.record("cache0"), None);
+
+        // A member forcibly unregistered stays in the rings holding it.
+        first.add_registered("cache1").unwrap();
+        assert!(registry.force_unregister("cache1"));
+        assert!(!registry.force_unregister("cache1"));
+        registry.set_zone("cache1", Some("a")).unwrap_err();
+        assert!(first.contains("cache1") && first.record("cache1").unwrap().zone.is_none());
+        assert_eq!(second.add_registered("cache1"),
+                   Err(Error::UnknownMember(String::from("cache1"))));
+        registry.register("cache1", 1).unwrap();
+        assert_eq!(registry.ring_count("cache1"), 0);
+        let mut plain = Consistant::new(20);
+        assert!(plain.add_registered("cache1").is_err() && plain.registry().is_none());
+    }
+}
@@ -0,0 +1,244 @@
+use std::cmp::Ordering;
+use crc::crc32::checksum_ieee;
+use error::Error;
+
+/// Rendezvous maps keys to weighted members by highest random weight
+/// hashing (Thaler and Ravishankar, 1996), with the logarithmic weighting of
+/// Schindelhauer and Schomaker (2005): every member scores every key, and
+/// the key goes to the member of the highest score. A member gets a share
+/// of the keys in proportion to its weight, without virtual nodes.
+///
+/// The score of a member of weight `w` for a key is computed, for other
+/// implementations to match it, as:
+///
+/// ```text
+/// x = crc32_ieee(name) << 32 | crc32_ieee(key)    as a u64
+/// x ^= x >> 33; x *= 0xff51afd7ed558ccd           wrapping
+/// x ^= x >> 33; x *= 0xc4ceb9fe1a85ec53           wrapping
+/// x ^= x >> 33                                    fmix64 of MurmurHash3
+/// u = ((x >> 11) + 0.5) / 2^53                    in (0, 1), as an f64
+/// score = -w / ln(u)
+/// ```
+///
+/// Equal scores are ranked by name. Like `Carp`, it is a hashing scheme of
+/// its own beside `Consistant`, a lookup scoring every member.
+#[derive(Debug, Clone, Default)]
+pub struct Rendezvous {
+    /// Sorted by name.
+    members: Vec<RendezvousMember>,
+}
+
+#[derive(Debug, Clone)]
+struct RendezvousMember {
+    name: String,
+    weight: f64,
+    hash: u32,
+}
+
+impl Rendezvous {
+    /// Crete a new instance of Rendezvous.
+    pub fn new() -> Rendezvous {
+        Rendezvous::default()
+    }
+
+    /// Add an elment of weight 1.
+    pub fn add<S: Into<String>>(&mut self, element: S) {
+        self.add_weighted(element, 1.0)
+    }
+
+    /// Add an elment with the given weight, an element of weight 2 getting
+    /// twice as many keys as one of weight 1. A weight which is not a
+    /// positive finite number, or an element already in, adds nothing.
+    pub fn add_weighted<S: Into<String>>(&mut self, element: S, weight: f64) {
+        let element = element.into();
+        if !valid_weight(weight) {
+            return;
+        }
+        if let Err(index) = self.position(&element) {
+            self.members.insert(index,
+                                RendezvousMember {
+                                    hash: checksum_ieee(element.as_bytes()),
+                                    name: element,
+                                    weight,
+                                });
+        }
+    }
+
+    /// Change the weight of the given element. Only keys moving to the
+    /// element, for a greater weight, or away from it, for a lower one,
+    /// change owner.
+    pub fn update_weight(&mut self, name: &str, weight: f64) -> Result<(), Error> {
+        if !valid_weight(weight) {
+            return Err(Error::InvalidWeight(String::from(name)));
+        }
+        match self.position(name) {
+            Ok(index) => {
+                self.members[index].weight = weight;
+                Ok(())
+            }
+            Err(_) => Err(Error::UnknownMember(String::from(name))),
+        }
+    }
+
+    /// Remove the given element, only its keys moving.
+    pub fn remove<S: AsRef<str>>(&mut self, name: S) {
+        if let Ok(index) = self.position(name.as_ref()) {
+            self.members.remove(index);
+        }
+    }
+
+    /// Get the member of the given key, the one of the highest score.
+    pub fn get<S: AsRef<str>>(&self, key: S) -> Option<String> {
+        let key = checksum_ieee(key.as_ref().as_bytes());
+        let mut best: Option<(&RendezvousMember, f64)> = None;
+        for member in &self.members {
+            let score = member.score(key);
+            // The members being sorted, equal scores keep the first name.
+            if best.is_none_or(|(_, high)| score > high) {
+                best = Some((member, score));
+            }
+        }
+
+        best.map(|(member, _)| member.name.clone())
+    }
+
+    /// Get the N members of the highest scores for the given key, the first
+    /// one being the one of `get`, or all of them if there are not as many.
+    pub fn get_n<S: AsRef<str>>(&self, key: S, n: usize) -> Option<Vec<String>> {
+        if n == 0 || self.members.is_empty() {
+            return None;
+        }
+        let key = checksum_ieee(key.as_ref().as_bytes());
+        let mut scores: Vec<(f64, &RendezvousMember)> =
+            self.members.iter().map(|member| (member.score(key), member)).collect();
+        // Stable, so equal scores keep the order of the names.
+        scores.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+
+        Some(scores.into_iter().take(n).map(|(_, member)| member.name.clone()).collect())
+    }
+
+    /// Get the score of the given element for the given key, see the
+    /// formula above.
+    pub fn score(&self, name: &str, key: &str) -> Option<f64> {
+        self.position(name)
+            .ok()
+            .map(|index| self.members[index].score(checksum_ieee(key.as_bytes())))
+    }
+
+    /// Get the weight of the given element.
+    pub fn weight(&self, name: &str) -> Option<f64> {
+        self.position(name).ok().map(|index| self.members[index].weight)
+    }
+
+    /// Get the count of added elements.
+    pub fn count(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Get the names of the added elements, sorted.
+    pub fn members(&self) -> Vec<String> {
+        self.members.iter().map(|member| member.name.clone()).collect()
+    }
+
+    /// Check whether the given element is added.
+    pub fn contains(&self, name: &str) -> bool {
+        self.position(name).is_ok()
+    }
+
+    fn position(&self, name: &str) -> Result<usize, usize> {
+        self.members.binary_search_by(|member| member.name.as_str().cmp(name))
+    }
+}
+
+impl RendezvousMember {
+    /// Score the key of the given checksum.
+    fn score(&self, key: u32) -> f64 {
+        let mut x = u64::from(self.hash) << 32 | u64::from(key);
+        x ^= x >> 33;
+        x = x.wrapping_mul(0xff51_afd7_ed55_8ccd);
+        x ^= x >> 33;
+        x = x.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+        x ^= x >> 33;
+        let unit = ((x >> 11) as f64 + 0.5) / (1u64 << 53) as f64;
+        -self.weight / unit.ln()
+    }
+}
+
+fn valid_weight(weight: f64) -> bool {
+    weight.is_finite() && weight > 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score() {
+        let mut rendezvous = Rendezvous::new();
+        rendezvous.add_weighted("cacheA", 2.5);
+        rendezvous.add("cacheB");
+        // Computed from the formula by a Python transcription of it.
+        let vectors = [("cacheA", "david", 2.5386952848396716),
+                       ("cacheB", "david", 2.28569447330703),
+                       ("cacheA", "key1", 4.123093945537069 * 2.5)];
+        for &(name, key, expected) in &vectors {
+            let score = rendezvous.score(name, key).unwrap();
+            assert!((score - expected).abs() < 1e-12, "{} {} {}", name, key, score);
+        }
+        assert_eq!(rendezvous.get("david"), Some(String::from("cacheA")));
+        assert_eq!(rendezvous.score("cacheC", "david"), None);
+    }
+
+    #[test]
+    fn test_add_remove() {
+        let mut rendezvous = Rendezvous::new();
+        assert_eq!(rendezvous.get("david"), None);
+        assert_eq!(rendezvous.get_n("david", 2), None);
+        rendezvous.add("cacheB");
+        rendezvous.add_weighted("cacheB", 2.0);
+        rendezvous.add_weighted("cacheA", 0.0);
+        rendezvous.add_weighted("cacheA", f64::NAN);
+        rendezvous.add_weighted("cacheA", f64::INFINITY);
+        assert_eq!(rendezvous.members(), ["cacheB"]);
+        assert_eq!(rendezvous.weight("cacheB"), Some(1.0));
+        rendezvous.add_weighted("cacheA", 0.5);
+        rendezvous.add("cacheC");
+        assert_eq!(rendezvous.count(), 3);
+        assert!(rendezvous.contains("cacheA"));
+
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            let owners = rendezvous.get_n(&key, 5).unwrap();
+            assert_eq!(owners.len(), 3);
+            assert_eq!(owners.first(), rendezvous.get(&key).as_ref());
+            assert_eq!(rendezvous.get_n(&key, 2).unwrap(), owners[..2]);
+        }
+
+        assert_eq!(rendezvous.update_weight("cacheD", 1.0),
+                   Err(Error::UnknownMember(String::from("cacheD"))));
+        assert_eq!(rendezvous.update_weight("cacheA", -1.0),
+                   Err(Error::InvalidWeight(String::from("cacheA"))));
+        rendezvous.remove("cacheA");
+        rendezvous.remove("cacheA");
+        assert_eq!(rendezvous.members(), ["cacheB", "cacheC"]);
+    }
+
+    #[test]
+    fn test_weighted_distribution() {
+        let weights = [("cacheA", 1.0), ("cacheB", 2.0), ("cacheC", 3.5), ("cacheD", 0.5)];
+        let mut rendezvous = Rendezvous::new();
+        for &(member, weight) in &weights {
+            rendezvous.add_weighted(member, weight);
+        }
+
+        let mut counts = vec![0; weights.len()];
+        for i in 0..100000 {
+            let owner = rendezvous.get(format!("key{}", i)).unwrap();
+            counts[weights.iter().position(|member| member.0 == owner).unwrap()] += 1;
+        }
+        for (&(_, weight), count) in weights.iter().zip(&counts) {
+            let expected = 100000.0 * weight / 7.0;
+            assert!((*count as f64 / expected - 1.0).abs() < 0.05, "{:?}", counts);
+        }
+    }
+}
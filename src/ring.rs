@@ -0,0 +1,268 @@
+use anchor::AnchorRing;
+use btree_ring::BTreeRing;
+use carp::Carp;
+use consistant::Consistant;
+use error::Error;
+use rendezvous::Rendezvous;
+
+/// Ring is what every hashing scheme of the crate has in common, so that
+/// they can be chosen at runtime, see `ring_for`. The schemes keep their
+/// richer APIs of their own, weights included: through the trait, every
+/// element is of the default weight.
+pub trait Ring {
+    /// Add an elment, returning whether it was added.
+    fn add(&mut self, name: &str) -> bool;
+
+    /// Remove the given element, returning whether it was in.
+    fn remove(&mut self, name: &str) -> bool;
+
+    /// Get the cloeset element's name to the given key.
+    fn get(&self, key: &str) -> Option<String>;
+
+    /// Get the N cloeset distinct elements' names to the given key, the one
+    /// of `get` first, or all of them if there are not as many.
+    fn get_n(&self, key: &str, n: usize) -> Vec<String>;
+
+    /// Get the names of the added elements, sorted.
+    fn members(&self) -> Vec<String>;
+
+    /// Get the count of added elements.
+    fn len(&self) -> usize;
+
+    /// Check whether no element is added.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// RingKind is the hashing scheme `ring_for` builds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RingKind {
+    /// A `Consistant` of the given count of virtual nodes per element.
+    Consistent { replicas: usize },
+    /// A `BTreeRing` of the given count of virtual nodes per element.
+    BTree { replicas: usize },
+    /// A `Rendezvous`.
+    Rendezvous,
+    /// An `AnchorRing` of up to the given count of elements.
+    Anchor { capacity: u32 },
+    /// A `Carp` of equal load factors.
+    Carp,
+}
+
+/// Build an empty hash ring of the given scheme.
+pub fn ring_for(kind: RingKind) -> Result<Box<dyn Ring>, Error> {
+    Ok(match kind {
+        RingKind::Consistent { replicas } => Box::new(Consistant::new(replicas)),
+        RingKind::BTree { replicas } => Box::new(BTreeRing::new(replicas)),
+        RingKind::Rendezvous => Box::new(Rendezvous::new()),
+        RingKind::Anchor { capacity } => Box::new(AnchorRing::new(capacity)?),
+        RingKind::Carp => Box::new(Carp::new()),
+    })
+}
+
+impl Ring for Consistant {
+    fn add(&mut self, name: &str) -> bool {
+        !self.contains(name) && self.try_add(name).is_ok()
+    }
+
+    fn remove(&mut self, name: &str) -> bool {
+        let removed = self.contains(name);
+        Consistant::remove(self, name);
+        removed
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        Consistant::get(self, key)
+    }
+
+    fn get_n(&self, key: &str, n: usize) -> Vec<String> {
+        Consistant::get_n(self, key, n).unwrap_or_default()
+    }
+
+    fn members(&self) -> Vec<String> {
+        Consistant::members(self)
+    }
+
+    fn len(&self) -> usize {
+        self.count()
+    }
+}
+
+impl Ring for BTreeRing {
+    fn add(&mut self, name: &str) -> bool {
+        let added = !self.contains(name);
+        BTreeRing::add(self, name);
+        added
+    }
+
+    fn remove(&mut self, name: &str) -> bool {
+        let removed = self.contains(name);
+        BTreeRing::remove(self, name);
+        removed
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        BTreeRing::get(self, key)
+    }
+
+    fn get_n(&self, key: &str, n: usize) -> Vec<String> {
+        BTreeRing::get_n(self, key, n).unwrap_or_default()
+    }
+
+    fn members(&self) -> Vec<String> {
+        BTreeRing::members(self)
+    }
+
+    fn len(&self) -> usize {
+        self.count()
+    }
+}
+
+impl Ring for Rendezvous {
+    fn add(&mut self, name: &str) -> bool {
+        let added = !self.contains(name);
+        Rendezvous::add(self, name);
+        added
+    }
+
+    fn remove(&mut self, name: &str) -> bool {
+        let removed = self.contains(name);
+        Rendezvous::remove(self, name);
+        removed
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        Rendezvous::get(self, key)
+    }
+
+    fn get_n(&self, key: &str, n: usize) -> Vec<String> {
+        Rendezvous::get_n(self, key, n).unwrap_or_default()
+    }
+
+    fn members(&self) -> Vec<String> {
+        Rendezvous::members(self)
+    }
+
+    fn len(&self) -> usize {
+        self.count()
+    }
+}
+
+impl Ring for AnchorRing {
+    fn add(&mut self, name: &str) -> bool {
+        AnchorRing::add(self, name)
+    }
+
+    fn remove(&mut self, name: &str) -> bool {
+        let removed = self.contains(name);
+        AnchorRing::remove(self, name);
+        removed
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        AnchorRing::get(self, key)
+    }
+
+    fn get_n(&self, key: &str, n: usize) -> Vec<String> {
+        AnchorRing::get_n(self, key, n).unwrap_or_default()
+    }
+
+    fn members(&self) -> Vec<String> {
+        AnchorRing::members(self)
+    }
+
+    fn len(&self) -> usize {
+        self.count()
+    }
+}
+
+impl Ring for Carp {
+    fn add(&mut self, name: &str) -> bool {
+        let added = !self.contains(name);
+        Carp::add(self, name, 1);
+        added
+    }
+
+    fn remove(&mut self, name: &str) -> bool {
+        let removed = self.contains(name);
+        Carp::remove(self, name);
+        removed
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        Carp::get(self, key)
+    }
+
+    fn get_n(&self, key: &str, n: usize) -> Vec<String> {
+        Carp::get_n(self, key, n).unwrap_or_default()
+    }
+
+    fn members(&self) -> Vec<String> {
+        Carp::members(self)
+    }
+
+    fn len(&self) -> usize {
+        self.count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: &[RingKind] = &[RingKind::Consistent { replicas: 20 },
+                                 RingKind::BTree { replicas: 20 },
+                                 RingKind::Rendezvous,
+                                 RingKind::Anchor { capacity: 16 },
+                                 RingKind::Carp];
+
+    fn owners(ring: &dyn Ring, keys: &[String]) -> Vec<String> {
+        keys.iter().map(|key| ring.get(key).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_ring() {
+        let keys: Vec<String> = (0..1000).map(|i| format!("key{}", i)).collect();
+        for &kind in KINDS {
+            let mut ring = ring_for(kind).unwrap();
+            assert!(ring.is_empty());
+            assert_eq!((ring.get("david"), ring.get_n("david", 2)), (None, vec![]));
+            assert!(!ring.remove("cacheA"));
+            for i in 0..8 {
+                assert!(ring.add(&format!("cache{}", i)), "{:?}", kind);
+            }
+            assert!(!ring.add("cache3"), "{:?}", kind);
+            assert_eq!(ring.len(), 8);
+            assert_eq!(ring.members(), (0..8).map(|i| format!("cache{}", i)).collect::<Vec<_>>());
+
+            for key in &keys {
+                let owners = ring.get_n(key, 3);
+                assert_eq!(owners.len(), 3, "{:?}", kind);
+                assert_eq!(Some(&owners[0]), ring.get(key).as_ref(), "{:?}", kind);
+                assert!(owners[0] != owners[1] && owners[1] != owners[2] &&
+                        owners[0] != owners[2]);
+                assert_eq!(ring.get_n(key, 20).len(), 8);
+                assert!(ring.get_n(key, 0).is_empty());
+            }
+
+            // Only the keys of a removed element move, and only the keys
+            // moving to an added one.
+            let before = owners(&*ring, &keys);
+            assert!(ring.remove("cache5"));
+            let after = owners(&*ring, &keys);
+            for (old, new) in before.iter().zip(&after) {
+                assert!(old == new || old == "cache5", "{:?}", kind);
+                assert!(new != "cache5");
+            }
+            assert!(ring.add("cache8"));
+            for (old, new) in after.iter().zip(owners(&*ring, &keys)) {
+                assert!(*old == new || new == "cache8", "{:?}", kind);
+            }
+            assert_eq!(ring.len(), 8);
+            assert!(!ring.is_empty());
+        }
+        assert!(ring_for(RingKind::Anchor { capacity: 0 }).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::hash_map::HashMap;
+use consistant::Consistant;
+
+/// Route maps keys to stable slot indexes, like indexes into a pool of
+/// clients.
+pub trait Route {
+    /// Get the slot of the closest element to the given key.
+    fn route<S: AsRef<str>>(&self, key: S) -> Option<usize>;
+}
+
+/// PoolRouter routes keys to the pool slots registered for the elements of
+/// its hash ring.
+#[derive(Debug, Default)]
+pub struct PoolRouter {
+    ring: Consistant,
+    slots: HashMap<String, usize>,
+}
+
+impl PoolRouter {
+    /// Crete a new PoolRouter over the given hash ring, with no slots
+    /// registered.
+    pub fn new(ring: Consistant) -> Self {
+        PoolRouter {
+            ring,
+            slots: HashMap::new(),
+        }
+    }
+
+    /// Get the underlying hash ring.
+    pub fn ring(&self) -> &Consistant {
+        &self.ring
+    }
+
+    /// Add an element to the hash ring.
+    pub fn add<S: Into<String>>(&mut self, element: S) {
+        self.ring.add(element)
+    }
+
+    /// Remove an element from the hash ring along with its slot.
+    pub fn remove(&mut self, element: &str) {
+        self.ring.remove(element);
+        self.slots.remove(element);
+    }
+
+    /// Register the pool slot of the given element, replacing any previous
+    /// one. Returns false if the element is not in the hash ring.
+    pub fn register(&mut self, element: &str, slot: usize) -> bool {
+        if !self.ring.contains(element) {
+            return false;
+        }
+        self.slots.insert(String::from(element), slot);
+        true
+    }
+
+    /// Get the slot registered for the given element.
+    pub fn slot(&self, element: &str) -> Option<usize> {
+        self.slots.get(element).cloned()
+    }
+
+    /// Get the slots of the N closest elements to the given key, in order,
+    /// skipping the elements with no registered slot.
+    pub fn route_n<S: AsRef<str>>(&self, key: S, n: usize) -> Vec<usize> {
+        self.ring
+            .get_n(key.as_ref(), n)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|element| self.slot(element))
+            .collect()
+    }
+}
+
+impl Route for PoolRouter {
+    fn route<S: AsRef<str>>(&self, key: S) -> Option<usize> {
+        self.ring.get(key.as_ref()).and_then(|element| self.slot(&element))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FakeClient {
+        name: &'static str,
+        healthy: bool,
+    }
+
+    fn router() -> (PoolRouter, Vec<FakeClient>) {
+        let pool = vec![FakeClient {
+                            name: "cacheA",
+                            healthy: true,
+                        },
+                        FakeClient {
+                            name: "cacheB",
+                            healthy: true,
+                        },
+                        FakeClient {
+                            name: "cacheC",
+                            healthy: true,
+                        }];
+
+        let mut router = PoolRouter::default();
+        for (slot, client) in pool.iter().enumerate() {
+            router.add(client.name);
+            assert!(router.register(client.name, slot));
+        }
+
+        (router, pool)
+    }
+
+    #[test]
+    fn test_route() {
+        let (router, pool) = router();
+
+        for i in 0..100 {
+            let key = format!("key{}", i);
+            let slot = router.route(&key).unwrap();
+            assert_eq!(pool[slot].name, router.ring().get(key.as_str()).unwrap());
+        }
+        assert_eq!(PoolRouter::default().route("david"), None);
+    }
+
+    #[test]
+    fn test_register() {
+        let (mut router, _) = router();
+        assert!(!router.register("cacheD", 3));
+
+        router.remove("cacheA");
+        assert_eq!(router.slot("cacheA"), None);
+        assert!(!router.register("cacheA", 0));
+
+        router.add("cacheA");
+        assert_eq!(router.slot("cacheA"), None);
+        assert!(router.register("cacheA", 0));
+        assert_eq!(router.slot("cacheA"), Some(0));
+    }
+
+    #[test]
+    fn test_route_n_failover() {
+        let (router, mut pool) = router();
+        let primary = router.route("david").unwrap();
+        pool[primary].healthy = false;
+
+        let slots = router.route_n("david", 3);
+        assert_eq!(slots.len(), 3);
+        assert_eq!(slots[0], primary);
+
+        let slot = slots.into_iter().find(|slot| pool[*slot].healthy).unwrap();
+        assert_eq!(pool[slot].name,
+                   router.ring().get_n("david", 2).unwrap()[1]);
+    }
+}
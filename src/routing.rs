@@ -0,0 +1,103 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+use consistant::Consistant;
+use fractional::{member_fractions, reroute_owners};
+use normalize::KeyNormalizer;
+use range_override::RangeOverrides;
+use siphash::KeyHasher;
+
+/// Routing is how the immutable copies of a hash ring, `FrozenRing` and
+/// `CompiledRing`, route keys around their virtual nodes: the hashing and
+/// normalizing of the keys, the prefix rules, the range assignments and the
+/// fractional weights, the elements being the indexes of the sorted members
+/// the copy was built of.
+#[derive(Debug, Clone)]
+pub(crate) struct Routing {
+    hasher: KeyHasher,
+    normalizer: Option<KeyNormalizer>,
+    prefix_rules: Vec<(String, u32)>,
+    range_overrides: RangeOverrides<u32>,
+    /// The fractions of the elements by index, none if none is fractional.
+    fractions: Vec<u32>,
+}
+
+impl Routing {
+    /// Crete a new instance of Routing of the given hash ring, for its
+    /// given sorted members, the rules and ranges of the others left out.
+    pub(crate) fn new(consistant: &Consistant, members: &[Arc<str>]) -> Routing {
+        let index = |member: &str| {
+            let owner = members.binary_search_by(|element| (**element).cmp(member));
+            owner.ok().map(|owner| owner as u32)
+        };
+        Routing {
+            hasher: consistant.key_hasher(),
+            normalizer: consistant.key_normalizer().cloned(),
+            prefix_rules: consistant.prefix_rules()
+                .into_iter()
+                .filter_map(|(prefix, member)| index(&member).map(|owner| (prefix, owner)))
+                .collect(),
+            range_overrides: consistant.range_overrides().filter_map(|member| index(member)),
+            fractions: member_fractions(members, consistant.fractions()),
+        }
+    }
+
+    /// Get the index of the virtual node of the given key, which `index_of`
+    /// finds for its checksum, `None` if there is none, and the element its
+    /// longest prefix rule or range assignment routes it to, or the one past
+    /// a fractional element declining it, as `Consistant::get` does. `owners`
+    /// are the owners of the virtual nodes, whose names `name` gives.
+    #[inline]
+    pub(crate) fn locate<'a, I, N>(&self,
+                                   key: &str,
+                                   owners: &[u32],
+                                   index_of: I,
+                                   name: N)
+                                   -> Option<(usize, Option<u32>)>
+        where I: FnOnce(u32) -> Option<usize>,
+              N: Fn(u32) -> &'a str
+    {
+        let key = match self.normalizer {
+            Some(ref normalizer) => normalizer.normalize(key),
+            None => Cow::Borrowed(key),
+        };
+        let routed = self.prefix_rules
+            .iter()
+            .filter(|rule| key.starts_with(rule.0.as_str()))
+            .max_by_key(|rule| rule.0.len())
+            .map(|rule| rule.1);
+        let sum = self.hasher.checksum(key.as_bytes());
+        let routed = routed.or_else(|| self.range_overrides.find(sum).map(|range| *range.2));
+        index_of(sum).map(|index| {
+            let routed =
+                routed.or_else(|| reroute_owners(owners, &self.fractions, name, index, sum));
+            (index, routed)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routing() {
+        let mut consistant = Consistant::new(20);
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+        consistant.add_prefix_rule("tenant-7/", "cacheB").unwrap();
+        consistant.assign_range(10, 20, "cacheA").unwrap();
+        consistant.set_fractional_weight("cacheA", 0.5).unwrap();
+
+        let members = consistant.sorted_members();
+        let routing = Routing::new(&consistant, &members);
+        assert_eq!(routing.prefix_rules, [(String::from("tenant-7/"), 1)]);
+        assert_eq!(routing.range_overrides.find(15).map(|range| *range.2), Some(0));
+        assert_eq!(routing.fractions, [500_000, 1_000_000]);
+
+        // The rules and ranges of the elements left out are not kept.
+        let routing = Routing::new(&consistant, &members[..1]);
+        assert!(routing.prefix_rules.is_empty());
+        assert_eq!(routing.range_overrides.find(15).map(|range| *range.2), Some(0));
+        assert_eq!(routing.locate("tenant-7/david", &[0], |_| None, |_| "cacheA"), None);
+    }
+}
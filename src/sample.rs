@@ -0,0 +1,41 @@
+use rand::Rng;
+use consistant::Consistant;
+
+impl Consistant {
+    /// Pick an element at random, the chance of each one being its share of
+    /// the keyspace: a random checksum is resolved as `get_by_hash` does.
+    pub fn sample_node<R: Rng>(&self, rng: &mut R) -> Option<&str> {
+        let _guard = self.read();
+        self.owner_ref(rng.gen()).map(|element| &**element)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::HashMap;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_sample_node() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut consistant = Consistant::default();
+        assert_eq!(consistant.sample_node(&mut rng), None);
+
+        consistant.add("cacheA");
+        consistant.add_weighted("cacheB", 2);
+        consistant.add("cacheC");
+        let mut draws: HashMap<String, usize> = HashMap::new();
+        for _ in 0..100000 {
+            *draws.entry(String::from(consistant.sample_node(&mut rng).unwrap())).or_default() += 1;
+        }
+
+        let shares = consistant.histogram(1).unwrap().remove(0).shares;
+        assert_eq!(draws.len(), shares.len());
+        for (element, share) in shares {
+            let frequency = draws[&element] as f64 / 100000.0;
+            assert!((frequency - share).abs() < 0.01, "{}: {} {}", element, frequency, share);
+        }
+    }
+}
@@ -0,0 +1,423 @@
+use std::mem;
+use std::ops::Index;
+
+const SEGMENT_BITS: u32 = 8;
+const SEGMENTS: usize = 1 << SEGMENT_BITS;
+/// The count of inserted keys past which a segment is sorted right away, so
+/// that `predecessor` only scans a few of them.
+const MAX_INSERTED: usize = 32;
+
+/// SortedKeys holds the virtual nodes of a hash ring as `(hash, owner)`
+/// sorted by hash, the owner being the slot of an element, so that a search
+/// yields the owner without another lookup.
+///
+/// The u32 keyspace is split into equal segments which are each sorted on
+/// their own. Inserted keys are only appended to their segments, after the
+/// sorted keys, and are merged by `sort` before the next search, so a run of
+/// insertions sorts once. Keys are indexed in their overall order.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SortedKeys {
+    /// The keys of each segment, empty until a key is inserted.
+    segments: Vec<Vec<(u32, u32)>>,
+    /// The count of keys before each segment, and the count of all the keys.
+    offsets: Vec<usize>,
+    /// The count of sorted keys leading each segment, the keys inserted
+    /// since it was last sorted following them.
+    sorted: Vec<usize>,
+    unsorted: bool,
+}
+
+impl SortedKeys {
+    pub(crate) fn len(&self) -> usize {
+        self.offsets.last().cloned().unwrap_or(0)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Check whether the keys are sorted, as searching them requires.
+    pub(crate) fn is_sorted(&self) -> bool {
+        !self.unsorted
+    }
+
+    /// Insert the given keys with their owners, leaving the keys unsorted
+    /// until `sort`. The owner of a key already in is replaced.
+    pub(crate) fn extend<I: IntoIterator<Item = (u32, u32)>>(&mut self, keys: I) {
+        if self.segments.is_empty() {
+            self.segments = vec![Vec::new(); SEGMENTS];
+            self.offsets = vec![0; SEGMENTS + 1];
+            self.sorted = vec![0; SEGMENTS];
+        }
+        for (key, owner) in keys {
+            let segment = segment(key);
+            if let Some(index) = self.position(segment, key) {
+                self.segments[segment][index].1 = owner;
+                continue;
+            }
+
+            let keys = &mut self.segments[segment];
+            keys.push((key, owner));
+            if keys.len() - self.sorted[segment] > MAX_INSERTED {
+                keys.sort_by_key(|entry| entry.0);
+                self.sorted[segment] = keys.len();
+            }
+        }
+        self.unsorted = self.segments
+            .iter()
+            .zip(self.sorted.iter())
+            .any(|(keys, sorted)| *sorted < keys.len());
+        self.update_offsets(0);
+    }
+
+    /// Same as `extend`, for keys sorted by hash, of distinct hashes, which
+    /// are laid out as they come when there are no keys yet.
+    pub(crate) fn extend_sorted(&mut self, keys: Vec<(u32, u32)>) {
+        if !self.is_empty() {
+            return self.extend(keys);
+        }
+        debug_assert!(keys.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        self.segments = vec![Vec::new(); SEGMENTS];
+        self.offsets = vec![0; SEGMENTS + 1];
+        for key in keys {
+            self.segments[segment(key.0)].push(key);
+        }
+        self.sorted = self.segments.iter().map(Vec::len).collect();
+        self.unsorted = false;
+        self.update_offsets(0);
+    }
+
+    /// Sort the segments having keys inserted since they were last sorted.
+    pub(crate) fn sort(&mut self) {
+        if !self.unsorted {
+            return;
+        }
+        for (keys, sorted) in self.segments.iter_mut().zip(self.sorted.iter_mut()) {
+            if *sorted < keys.len() {
+                // Merges the runs of sorted and inserted keys.
+                keys.sort_by_key(|entry| entry.0);
+                *sorted = keys.len();
+            }
+        }
+        self.unsorted = false;
+    }
+
+    /// Get the owner of the given key. The keys need not be sorted.
+    pub(crate) fn get(&self, key: u32) -> Option<u32> {
+        if self.is_empty() {
+            return None;
+        }
+        let segment = segment(key);
+        self.position(segment, key).map(|index| self.segments[segment][index].1)
+    }
+
+    /// Remove the given key if it is owned by the given owner, returning
+    /// whether it was. The keys need not be sorted.
+    pub(crate) fn remove(&mut self, key: u32, owner: u32) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        let segment = segment(key);
+        let index = match self.position(segment, key) {
+            Some(index) if self.segments[segment][index].1 == owner => index,
+            _ => return false,
+        };
+
+        let keys = &mut self.segments[segment];
+        if index < self.sorted[segment] {
+            keys.remove(index);
+            self.sorted[segment] -= 1;
+        } else {
+            keys.swap_remove(index);
+        }
+        self.update_offsets(segment);
+        true
+    }
+
+    /// Get the greatest key lower than the given one, wrapping around the
+    /// hash ring to the greatest key. The keys must not be empty, but need
+    /// not be sorted.
+    pub(crate) fn predecessor(&self, key: u32) -> u32 {
+        let segment = segment(key);
+        let (sorted, inserted) = self.segments[segment].split_at(self.sorted[segment]);
+        let lower = sorted[..sorted.partition_point(|entry| entry.0 < key)]
+            .last()
+            .into_iter()
+            .chain(inserted.iter().filter(|entry| entry.0 < key))
+            .map(|entry| entry.0)
+            .max();
+        if let Some(lower) = lower {
+            return lower;
+        }
+
+        (0..segment)
+            .rev()
+            .chain((segment..SEGMENTS).rev())
+            .filter_map(|segment| self.max_of(segment))
+            .next()
+            .expect("no keys")
+    }
+
+    /// Keep only the keys passing the given predicate.
+    pub(crate) fn retain<F: FnMut(&(u32, u32)) -> bool>(&mut self, mut pred: F) {
+        for (keys, sorted) in self.segments.iter_mut().zip(self.sorted.iter_mut()) {
+            let inserted = keys.split_off(*sorted);
+            keys.retain(&mut pred);
+            *sorted = keys.len();
+            keys.extend(inserted.into_iter().filter(&mut pred));
+        }
+        if !self.segments.is_empty() {
+            self.update_offsets(0);
+        }
+    }
+
+    /// Replace the owners of the keys with the given function.
+    pub(crate) fn map_owners<F: FnMut(u32) -> u32>(&mut self, mut f: F) {
+        for keys in &mut self.segments {
+            for entry in keys.iter_mut() {
+                entry.1 = f(entry.1);
+            }
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.segments.clear();
+        self.offsets.clear();
+        self.sorted.clear();
+        self.unsorted = false;
+    }
+
+    pub(crate) fn shrink_to_fit(&mut self) {
+        if self.is_empty() {
+            self.clear();
+        }
+        for keys in &mut self.segments {
+            keys.shrink_to_fit();
+        }
+        self.segments.shrink_to_fit();
+        self.offsets.shrink_to_fit();
+        self.sorted.shrink_to_fit();
+    }
+
+    /// Get an estimate of the heap bytes held.
+    pub(crate) fn memory_usage(&self) -> usize {
+        self.segments
+            .iter()
+            .map(|keys| keys.capacity() * mem::size_of::<(u32, u32)>())
+            .sum::<usize>() +
+        self.segments.capacity() * mem::size_of::<Vec<(u32, u32)>>() +
+        (self.offsets.capacity() + self.sorted.capacity()) * mem::size_of::<usize>()
+    }
+
+    /// Get the index of the first key greater than the given hash, 0 if there
+    /// is none, the lookup wrapping around the hash ring.
+    pub(crate) fn successor(&self, sum: u32) -> usize {
+        if self.is_empty() {
+            return 0;
+        }
+        let segment = segment(sum);
+        self.debug_assert_sorted(segment);
+        successor_in(&self.segments[segment], self.offsets[segment], self.len(), sum)
+    }
+
+    pub(crate) fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = &'a (u32, u32)> + 'a> {
+        debug_assert!(self.is_sorted(), "iterating unsorted keys");
+        Box::new(self.segments.iter().flat_map(|keys| keys.iter()))
+    }
+
+    fn position(&self, segment: usize, key: u32) -> Option<usize> {
+        let (sorted, inserted) = self.segments[segment].split_at(self.sorted[segment]);
+        match sorted.binary_search_by_key(&key, |entry| entry.0) {
+            Ok(index) => Some(index),
+            Err(_) => {
+                inserted.iter().position(|entry| entry.0 == key).map(|index| sorted.len() + index)
+            }
+        }
+    }
+
+    fn max_of(&self, segment: usize) -> Option<u32> {
+        let (sorted, inserted) = self.segments[segment].split_at(self.sorted[segment]);
+        sorted.last().into_iter().chain(inserted).map(|entry| entry.0).max()
+    }
+
+    #[inline]
+    fn debug_assert_sorted(&self, segment: usize) {
+        debug_assert!(self.sorted[segment] == self.segments[segment].len() &&
+                      self.segments[segment].windows(2).all(|pair| pair[0].0 < pair[1].0),
+                      "searching unsorted keys");
+    }
+
+    fn update_offsets(&mut self, from: usize) {
+        for segment in from..SEGMENTS {
+            self.offsets[segment + 1] = self.offsets[segment] + self.segments[segment].len();
+        }
+    }
+}
+
+impl Index<usize> for SortedKeys {
+    type Output = (u32, u32);
+
+    fn index(&self, index: usize) -> &(u32, u32) {
+        assert!(index < self.len(), "index {} out of {} keys", index, self.len());
+        let segment = self.offsets.partition_point(|offset| *offset <= index) - 1;
+        self.debug_assert_sorted(segment);
+        &self.segments[segment][index - self.offsets[segment]]
+    }
+}
+
+#[inline]
+/// Get the index of the first of the `len` keys past the given checksum,
+/// wrapping around to 0, within the sorted keys of its segment which follow
+/// `offset` others. The keys of the later segments are all past it.
+fn successor_in(segment: &[(u32, u32)], offset: usize, len: usize, sum: u32) -> usize {
+    let index = offset + segment.partition_point(|entry| entry.0 <= sum);
+    if index == len { 0 } else { index }
+}
+
+fn segment(key: u32) -> usize {
+    (key >> (32 - SEGMENT_BITS)) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xorshift::Xorshift;
+
+    #[test]
+    fn test_sorted_keys() {
+        let mut keys = SortedKeys::default();
+        let mut flat: Vec<(u32, u32)> = Vec::new();
+        let mut state = Xorshift::new(42);
+        assert_eq!(keys.successor(7), 0);
+        assert_eq!(keys.get(7), None);
+
+        for round in 0..500 {
+            let owner = round % 7;
+            let mut added: Vec<(u32, u32)> = (0..state.next_u64() % 40)
+                .map(|_| (state.next_u64() as u32, owner))
+                .collect();
+            // Some keys already in, taken over.
+            if !flat.is_empty() {
+                added.push((flat[state.next_u64() as usize % flat.len()].0, owner));
+            }
+            keys.extend(added.iter().cloned());
+            for (key, owner) in added {
+                flat.retain(|entry| entry.0 != key);
+                flat.push((key, owner));
+            }
+            flat.sort();
+
+            // The keys are left unsorted for most removals.
+            if round % 5 == 0 {
+                keys.sort();
+            }
+            if round % 3 == 2 && !flat.is_empty() {
+                for _ in 0..state.next_u64() % 30 {
+                    let (key, owner) = flat[state.next_u64() as usize % flat.len()];
+                    assert_eq!(keys.get(key), Some(owner));
+                    assert!(!keys.remove(key, owner + 1));
+                    assert!(keys.remove(key, owner));
+                    flat.retain(|entry| entry.0 != key);
+                }
+                assert_eq!(keys.get(u32::MAX - round), None);
+                assert!(!keys.remove(u32::MAX - round, owner));
+            }
+            if flat.is_empty() {
+                continue;
+            }
+
+            assert_eq!(keys.len(), flat.len());
+            for (index, entry) in flat.iter().enumerate().step_by(7) {
+                let expected = flat[(index + flat.len() - 1) % flat.len()].0;
+                assert_eq!(keys.predecessor(entry.0), expected);
+            }
+            keys.sort();
+            assert!(keys.is_sorted());
+            assert_eq!(keys.iter().cloned().collect::<Vec<(u32, u32)>>(), flat);
+            for _ in 0..20 {
+                let sum = state.next_u64() as u32;
+                let expected = flat.iter().position(|entry| sum < entry.0).unwrap_or(0);
+                assert_eq!(keys.successor(sum), expected);
+            }
+            for (i, entry) in flat.iter().enumerate() {
+                assert_eq!(keys[i], *entry);
+            }
+        }
+
+        let added: Vec<(u32, u32)> = (0..1000u32)
+            .map(|i| (i * 4_000_037, 9))
+            .filter(|added| flat.iter().all(|entry| entry.0 != added.0))
+            .collect();
+        keys.extend(added.iter().cloned());
+        flat.extend(added);
+        flat.sort();
+        keys.retain(|entry| entry.0 % 2 == 0);
+        flat.retain(|entry| entry.0 % 2 == 0);
+        keys.map_owners(|owner| owner * 2);
+        keys.sort();
+        let expected: Vec<(u32, u32)> = flat.iter().map(|entry| (entry.0, entry.1 * 2)).collect();
+        assert_eq!(keys.iter().cloned().collect::<Vec<(u32, u32)>>(), expected);
+        keys.clear();
+        assert!(keys.is_empty());
+        assert_eq!(keys.successor(7), 0);
+    }
+}
+
+/// The proofs of the searches and removals of the keys, for `cargo kani`.
+#[cfg(kani)]
+mod proofs {
+    use super::*;
+
+    #[kani::proof]
+    fn check_successor_in() {
+        let keys: [(u32, u32); 3] = kani::any();
+        let count = kani::any_where(|count: &usize| *count <= keys.len());
+        let segment = &keys[..count];
+        kani::assume(segment.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        let offset = kani::any_where(|offset: &usize| *offset <= 4);
+        let len = kani::any_where(|len: &usize| *len > 0 && *len <= 8 && offset + count <= *len);
+        let sum: u32 = kani::any();
+
+        // Checksums past the greatest key of the segment included.
+        let index = successor_in(segment, offset, len, sum);
+        assert!(index < len);
+        if offset == 0 && count == len {
+            assert!(segment[index].0 > sum ||
+                    (index == 0 && segment.iter().all(|entry| entry.0 <= sum)));
+        }
+    }
+
+    #[kani::proof]
+    #[kani::unwind(260)]
+    fn check_successor() {
+        let mut keys = SortedKeys::default();
+        let added: [(u32, u32); 2] = kani::any();
+        keys.extend(added.iter().cloned());
+        keys.sort();
+        let sum: u32 = kani::any();
+
+        let index = keys.successor(sum);
+        assert!(index < keys.len());
+        assert!(keys[index].0 > sum || index == 0);
+    }
+
+    #[kani::proof]
+    #[kani::unwind(260)]
+    fn check_remove() {
+        let mut keys = SortedKeys::default();
+        let removed: [u32; 2] = kani::any();
+        let kept: [u32; 2] = kani::any();
+        keys.extend(removed.iter().map(|key| (*key, 0)));
+        // Colliding keys are taken over by the other owner.
+        keys.extend(kept.iter().map(|key| (*key, 1)));
+        keys.sort();
+        for key in &removed {
+            keys.remove(*key, 0);
+        }
+
+        // No key is left to the owner removed, nor taken from the other.
+        keys.sort();
+        assert!(keys.iter().all(|entry| entry.1 != 0));
+        assert!(kept.iter().all(|key| keys.get(*key) == Some(1)));
+    }
+}
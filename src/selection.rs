@@ -0,0 +1,217 @@
+use std::collections::hash_set::HashSet;
+use std::sync::Arc;
+use consistant::Consistant;
+use domains::SpreadPolicy;
+
+/// SelectionPolicy tells `select` which elements may own a key and how to
+/// prefer among them, combining the constraints of the `get_*` variants.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SelectionPolicy {
+    excluded: HashSet<String>,
+    tags: Vec<String>,
+    spread: Option<SpreadPolicy>,
+    skip_disabled: bool,
+}
+
+impl SelectionPolicy {
+    /// Crete a new instance of SelectionPolicy, selecting every element in
+    /// the order of `get_n`.
+    pub fn new() -> SelectionPolicy {
+        SelectionPolicy::default()
+    }
+
+    /// Never select the given elements, along with those excluded before.
+    pub fn exclude<I, S>(mut self, elements: I) -> SelectionPolicy
+        where I: IntoIterator<Item = S>,
+              S: Into<String>
+    {
+        self.excluded.extend(elements.into_iter().map(Into::into));
+        self
+    }
+
+    /// Only select the elements having the given tag, along with the tags
+    /// required before, see `Consistant::set_tags`.
+    pub fn require_tag<S: Into<String>>(mut self, tag: S) -> SelectionPolicy {
+        let tag = tag.into();
+        if let Err(index) = self.tags.binary_search(&tag) {
+            self.tags.insert(index, tag);
+        }
+        self
+    }
+
+    /// Spread the elements over their failure domains by the given policy,
+    /// as `Consistant::get_n_spread_by` does, replacing any spreading set
+    /// before.
+    pub fn spread_by(mut self, policy: SpreadPolicy) -> SelectionPolicy {
+        self.spread = Some(policy);
+        self
+    }
+
+    /// Spread the elements over as many zones as they are in, their domains
+    /// of the label `zone`, see `Consistant::add_with_domains`.
+    pub fn spread_by_zone(self) -> SelectionPolicy {
+        self.spread_by(SpreadPolicy::new().across("zone", usize::MAX))
+    }
+
+    /// Never select the elements whose circuit breaker does not let the key
+    /// through, as `Consistant::get_n_healthy` skips them, if told so. Without
+    /// circuit breakers no element is skipped, see
+    /// `Consistant::set_circuit_breaker`.
+    pub fn skip_disabled(mut self, skip: bool) -> SelectionPolicy {
+        self.skip_disabled = skip;
+        self
+    }
+
+    /// Check whether the given element of the given sorted tags may be
+    /// selected.
+    fn is_eligible(&self, element: &str, tags: &[String]) -> bool {
+        !self.excluded.contains(element) &&
+        self.tags.iter().all(|tag| tags.binary_search(tag).is_ok())
+    }
+}
+
+impl Consistant {
+    /// Get the N cloeset elements' names to the given "name" allowed by the
+    /// given policy, in a single walk of the hash ring.
+    ///
+    /// The constraints apply in this order. First, the excluded elements,
+    /// those missing a required tag and, if skipped, the disabled ones are
+    /// never selected, whatever the other constraints. Then, the eligible
+    /// elements are preferred in the order `get_n` gives, the first one
+    /// being the first of them in that order, and, with a spread policy,
+    /// every next one being the one adding the most wanted domains as
+    /// `get_n_spread_by` tells. `None` if N is 0 or no element is eligible,
+    /// the fallback never being selected.
+    pub fn select<S: AsRef<str>>(&self,
+                                 name: S,
+                                 n: usize,
+                                 policy: &SelectionPolicy)
+                                 -> Option<Vec<String>> {
+        if n == 0 {
+            return None;
+        }
+        let name = name.as_ref();
+        let guard = self.read();
+        // Spreading may prefer any eligible element, so all are walked.
+        let wanted = if policy.spread.is_some() { usize::MAX } else { n };
+        let mut eligible: Vec<Arc<str>> = Vec::new();
+        let mut walk = |admitted: &dyn Fn(&str) -> bool| {
+            self.walk_members(name, |element| {
+                if policy.is_eligible(element, self.tags_ref(element)) && admitted(element) {
+                    eligible.push(element.clone());
+                }
+                eligible.len() == wanted
+            })
+        };
+        if policy.skip_disabled {
+            self.breakers().with_admission(self.pin(name).0, walk);
+        } else {
+            walk(&|_| true);
+        }
+        if eligible.is_empty() {
+            return None;
+        }
+
+        let selected = match policy.spread {
+            Some(ref spread) => self.spread(eligible, n, spread),
+            None => eligible,
+        };
+        drop(guard);
+        Some(selected.into_iter().map(|element| String::from(&*element)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use breaker::CircuitBreaker;
+
+    fn consistant() -> Consistant {
+        let mut consistant = Consistant::new(20);
+        for zone in &["eu-1a", "eu-1b", "eu-1c"] {
+            for host in 0..4 {
+                let element = format!("cache-{}-h{}", zone, host);
+                consistant.add(element.as_str());
+                let mut tags = vec![format!("zone={}", zone)];
+                if host % 2 == 0 {
+                    tags.push(String::from("ssd"));
+                }
+                let tags: Vec<&str> = tags.iter().map(String::as_str).collect();
+                consistant.set_tags(&element, &tags).unwrap();
+            }
+        }
+        consistant
+    }
+
+    #[test]
+    fn test_select() {
+        let consistant = consistant();
+        let me = "cache-eu-1a-h0";
+        let policy = SelectionPolicy::new().exclude(vec![me]).require_tag("ssd").spread_by_zone();
+        let eligible = SelectionPolicy::new().exclude(vec![me]).require_tag("ssd");
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            let selected = consistant.select(&key, 3, &policy).unwrap();
+            assert_eq!(selected.len(), 3);
+            assert!(!selected.contains(&String::from(me)));
+            for element in &selected {
+                assert!(consistant.tags_of(element).unwrap().contains(&String::from("ssd")));
+            }
+            let mut zones: Vec<&str> = selected.iter().map(|element| &element[6..11]).collect();
+            zones.sort();
+            zones.dedup();
+            assert_eq!(zones.len(), 3, "{:?}", selected);
+
+            // The eligible elements come in the order of the hash ring.
+            let ordered = consistant.get_n(&key, 12).unwrap();
+            let ordered: Vec<String> = ordered.into_iter()
+                .filter(|element| element != me && element.ends_with(['0', '2']))
+                .collect();
+            assert_eq!(consistant.select(&key, 3, &eligible).unwrap(), ordered[..3]);
+            assert_eq!(selected[0], ordered[0]);
+            let first_in_zone = |zone: &str| ordered.iter().find(|e| e.contains(zone)).cloned();
+            for element in &selected {
+                assert_eq!(first_in_zone(&element[6..11]).as_ref(), Some(element));
+            }
+
+            assert_eq!(consistant.select(&key, 4, &SelectionPolicy::new()),
+                       consistant.get_n(&key, 4));
+        }
+
+        assert_eq!(consistant.select("david", 0, &policy), None);
+        assert_eq!(consistant.select("david", 9, &policy).unwrap().len(), 5);
+        assert_eq!(consistant.select("david", 1, &eligible.clone().require_tag("hdd")), None);
+        assert_eq!(Consistant::default().select("david", 1, &SelectionPolicy::new()), None);
+    }
+
+    #[test]
+    fn test_select_skip_disabled() {
+        let mut consistant = consistant();
+        let policy = SelectionPolicy::new().require_tag("ssd").skip_disabled(true);
+        let tagged = SelectionPolicy::new().require_tag("ssd");
+        assert_eq!(consistant.select("david", 3, &policy),
+                   consistant.select("david", 3, &tagged));
+
+        consistant.set_circuit_breaker(Some(CircuitBreaker::new(1, Duration::from_secs(60))));
+        let tripped = "cache-eu-1b-h0";
+        consistant.report_failure(tripped).unwrap();
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            let selected = consistant.select(&key, 3, &policy).unwrap();
+            assert!(!selected.contains(&String::from(tripped)));
+            let healthy: Vec<String> = consistant.get_n_healthy(&key, 12)
+                .unwrap()
+                .into_iter()
+                .filter(|element| element.ends_with(['0', '2']))
+                .collect();
+            assert_eq!(selected, healthy[..3]);
+        }
+        assert!((0..1000).any(|i| {
+            let key = format!("key{}", i);
+            consistant.select(&key, 3, &tagged).unwrap().contains(&String::from(tripped))
+        }));
+        assert_eq!(consistant.select("david", 3, &policy.clone().skip_disabled(false)),
+                   consistant.select("david", 3, &tagged));
+    }
+}
@@ -0,0 +1,166 @@
+use std::sync::PoisonError;
+use consistant::Consistant;
+use sync::{Arc, Mutex, RwLock};
+
+/// SharedConsistant shares a hash ring between threads. Readers take an
+/// immutable snapshot of it, which writers replace with an updated copy, so
+/// lookups never wait for a mutation to finish.
+#[derive(Debug)]
+pub struct SharedConsistant {
+    current: RwLock<Arc<Consistant>>,
+    writer: Mutex<()>,
+}
+
+impl SharedConsistant {
+    /// Crete a new instance of SharedConsistant sharing the given hash ring.
+    pub fn new(consistant: Consistant) -> Self {
+        SharedConsistant {
+            current: RwLock::new(Arc::new(consistant)),
+            writer: Mutex::new(()),
+        }
+    }
+
+    /// Get a snapshot of the hash ring, unaffected by later updates.
+    pub fn load(&self) -> Arc<Consistant> {
+        self.current.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Update a copy of the hash ring with the given function, then make it
+    /// the one of later snapshots. Updates are applied one at a time.
+    pub fn update<F: FnOnce(&mut Consistant)>(&self, f: F) {
+        let _guard = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut consistant = (*self.load()).clone();
+        f(&mut consistant);
+        *self.current.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(consistant);
+    }
+
+    /// Add an elment to the hash ring.
+    pub fn add<S: Into<String>>(&self, element: S) {
+        self.update(|consistant| consistant.add(element))
+    }
+
+    /// Remove the given element.
+    pub fn remove<S: AsRef<str>>(&self, name: S) {
+        self.update(|consistant| consistant.remove(name))
+    }
+
+    /// Get the cloeset element's name to the given "name".
+    pub fn get<S: AsRef<str>>(&self, name: S) -> Option<String> {
+        self.load().get(name)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_shared_consistant() {
+        let shared = Arc::new(SharedConsistant::new(Consistant::default()));
+        shared.add("cacheA");
+        let snapshot = shared.load();
+
+        let writer = {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                for i in 0..10 {
+                    shared.add(format!("cache{}", i));
+                }
+                shared.remove("cacheA");
+            })
+        };
+        for _ in 0..100 {
+            assert!(shared.get("david").is_some());
+        }
+        writer.join().unwrap();
+
+        assert_eq!(snapshot.members(), ["cacheA"]);
+        assert_eq!(shared.load().count(), 10);
+        assert!(!shared.load().contains("cacheA"));
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::thread;
+
+    fn shared(members: &[&str]) -> Arc<SharedConsistant> {
+        let mut consistant = Consistant::new(2);
+        for member in members {
+            consistant.add(*member);
+        }
+        Arc::new(SharedConsistant::new(consistant))
+    }
+
+    #[test]
+    fn loom_get_add() {
+        loom::model(|| {
+            let shared = shared(&["cacheA"]);
+            let writer = {
+                let shared = shared.clone();
+                thread::spawn(move || shared.add("cacheB"))
+            };
+
+            let owner = shared.get("david").unwrap();
+            assert!(owner == "cacheA" || owner == "cacheB");
+            writer.join().unwrap();
+            assert_eq!(shared.load().count(), 2);
+        });
+    }
+
+    #[test]
+    fn loom_add_remove() {
+        loom::model(|| {
+            let shared = shared(&["cacheA"]);
+            let writer = {
+                let shared = shared.clone();
+                thread::spawn(move || shared.add("cacheB"))
+            };
+
+            shared.remove("cacheA");
+            writer.join().unwrap();
+            assert_eq!(shared.load().members(), ["cacheB"]);
+        });
+    }
+
+    #[test]
+    fn loom_consistent_snapshot() {
+        loom::model(|| {
+            let shared = shared(&["cacheA", "cacheB"]);
+            let writer = {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    shared.remove("cacheA");
+                    shared.add("cacheC");
+                })
+            };
+
+            let snapshot = shared.load();
+            let members = snapshot.members();
+            for element in snapshot.get_n("david", 3).unwrap() {
+                assert!(members.contains(&element));
+            }
+            writer.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn loom_concurrent_sort() {
+        loom::model(|| {
+            // The virtual nodes are left unsorted by the add, each lookup
+            // racing to sort them.
+            let shared = shared(&["cacheA", "cacheB"]);
+            let snapshot = shared.load();
+            let expected = (*snapshot).clone().get("david");
+            let reader = {
+                let snapshot = snapshot.clone();
+                thread::spawn(move || snapshot.get("david"))
+            };
+
+            assert_eq!(snapshot.get("david"), expected);
+            assert_eq!(reader.join().unwrap(), expected);
+        });
+    }
+}
@@ -0,0 +1,248 @@
+use std::fmt;
+use placement::{checksum, checksum_parts};
+
+/// SipKey is the secret of a keyed hash ring, see `Consistant::with_key`.
+/// Its `Debug` output leaves the secret out.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SipKey {
+    k0: u64,
+    k1: u64,
+}
+
+impl fmt::Debug for SipKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SipKey(..)")
+    }
+}
+
+impl SipKey {
+    pub(crate) fn new(key: [u8; 16]) -> SipKey {
+        let mut k0 = [0; 8];
+        let mut k1 = [0; 8];
+        k0.copy_from_slice(&key[..8]);
+        k1.copy_from_slice(&key[8..]);
+        SipKey {
+            k0: u64::from_le_bytes(k0),
+            k1: u64::from_le_bytes(k1),
+        }
+    }
+
+    /// Get a digest of the secret, telling secrets apart without revealing
+    /// them: SipHash-2-4 of no bytes keyed by it.
+    pub(crate) fn digest(&self) -> u64 {
+        SipHasher::new(self).finish()
+    }
+}
+
+/// KeyHasher hashes the lookup keys of a hash ring: by `checksum` of its
+/// seed, or by SipHash-2-4 of its secret if it is keyed, folded to 32 bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct KeyHasher {
+    pub(crate) seed: u64,
+    pub(crate) secret: Option<SipKey>,
+}
+
+impl KeyHasher {
+    /// Get the checksum of the given lookup key.
+    #[inline]
+    pub(crate) fn checksum(&self, bytes: &[u8]) -> u32 {
+        match self.secret {
+            Some(ref secret) => {
+                let mut hasher = SipHasher::new(secret);
+                hasher.write(bytes);
+                hasher.finish32()
+            }
+            None => checksum(self.seed, bytes),
+        }
+    }
+
+    /// Get the checksum of the lookup key made of the given parts, encoded as
+    /// `checksum_parts` does.
+    pub(crate) fn checksum_parts(&self, parts: &[&[u8]]) -> u32 {
+        match self.secret {
+            Some(ref secret) => {
+                let mut hasher = SipHasher::new(secret);
+                for part in parts {
+                    hasher.write(&(part.len() as u32).to_le_bytes());
+                    hasher.write(part);
+                }
+                hasher.finish32()
+            }
+            None => checksum_parts(self.seed, parts),
+        }
+    }
+}
+
+/// SipHasher computes SipHash-2-4 over the bytes written to it.
+struct SipHasher {
+    v: [u64; 4],
+    /// The bytes of the last incomplete word, little endian.
+    tail: u64,
+    length: usize,
+}
+
+impl SipHasher {
+    fn new(key: &SipKey) -> SipHasher {
+        SipHasher {
+            v: [key.k0 ^ 0x736f_6d65_7073_6575,
+                key.k1 ^ 0x646f_7261_6e64_6f6d,
+                key.k0 ^ 0x6c79_6765_6e65_7261,
+                key.k1 ^ 0x7465_6462_7974_6573],
+            tail: 0,
+            length: 0,
+        }
+    }
+
+    fn write(&mut self, mut bytes: &[u8]) {
+        let filled = self.length % 8;
+        self.length += bytes.len();
+        if filled > 0 {
+            let taken = bytes.len().min(8 - filled);
+            self.tail |= word(&bytes[..taken]) << (8 * filled);
+            bytes = &bytes[taken..];
+            if filled + taken < 8 {
+                return;
+            }
+            let tail = self.tail;
+            self.compress(tail);
+        }
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            self.compress(word(chunk));
+        }
+        self.tail = word(chunks.remainder());
+    }
+
+    fn finish(mut self) -> u64 {
+        let last = ((self.length as u64) << 56) | self.tail;
+        self.compress(last);
+        self.v[2] ^= 0xff;
+        for _ in 0..4 {
+            self.round();
+        }
+        self.v[0] ^ self.v[1] ^ self.v[2] ^ self.v[3]
+    }
+
+    /// Get the hash folded to 32 bits.
+    fn finish32(self) -> u32 {
+        let hash = self.finish();
+        (hash ^ (hash >> 32)) as u32
+    }
+
+    fn compress(&mut self, m: u64) {
+        self.v[3] ^= m;
+        self.round();
+        self.round();
+        self.v[0] ^= m;
+    }
+
+    #[inline]
+    fn round(&mut self) {
+        let v = &mut self.v;
+        v[0] = v[0].wrapping_add(v[1]);
+        v[1] = v[1].rotate_left(13) ^ v[0];
+        v[0] = v[0].rotate_left(32);
+        v[2] = v[2].wrapping_add(v[3]);
+        v[3] = v[3].rotate_left(16) ^ v[2];
+        v[0] = v[0].wrapping_add(v[3]);
+        v[3] = v[3].rotate_left(21) ^ v[0];
+        v[2] = v[2].wrapping_add(v[1]);
+        v[1] = v[1].rotate_left(17) ^ v[2];
+        v[2] = v[2].rotate_left(32);
+    }
+}
+
+/// Get the little endian word of the given bytes, at most 8.
+fn word(bytes: &[u8]) -> u64 {
+    bytes.iter().enumerate().fold(0, |word, (i, byte)| word | (u64::from(*byte) << (8 * i)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consistant::Consistant;
+
+    fn key(first: u8) -> [u8; 16] {
+        let mut key = [0; 16];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = first + i as u8;
+        }
+        key
+    }
+
+    #[test]
+    fn test_siphash() {
+        // The vectors of the reference implementation, of the key 00..0f.
+        let secret = SipKey::new(key(0));
+        let message: Vec<u8> = (0..15).collect();
+        assert_eq!(SipHasher::new(&secret).finish(), 0x726f_db47_dd0e_0e31);
+        let mut hasher = SipHasher::new(&secret);
+        hasher.write(&message);
+        assert_eq!(hasher.finish(), 0xa129_ca61_49be_45e5);
+
+        // Written in pieces, the message hashes the same.
+        let mut hasher = SipHasher::new(&secret);
+        for piece in message.chunks(3) {
+            hasher.write(piece);
+        }
+        assert_eq!(hasher.finish(), 0xa129_ca61_49be_45e5);
+        assert_eq!(format!("{:?}", secret), "SipKey(..)");
+    }
+
+    #[test]
+    fn test_with_key() {
+        let keys: Vec<String> = (0..1000).map(|i| format!("key{}", i)).collect();
+        let ring = |mut consistant: Consistant| {
+            for i in 0..10 {
+                consistant.add(format!("node-{}", i));
+            }
+            consistant
+        };
+        let mut keyed = ring(Consistant::with_key(20, key(1)));
+        let same = ring(Consistant::with_key(20, key(1)));
+        let other = ring(Consistant::with_key(20, key(2)));
+        let mut plain = ring(Consistant::new(20));
+
+        // The virtual nodes are placed as unkeyed, the keys hashed by secret.
+        assert!(keyed.is_keyed() && !plain.is_keyed());
+        assert_eq!(keyed.vnodes(), plain.vnodes());
+        let differ = |a: &Consistant, b: &Consistant| {
+            keys.iter().filter(|key| a.get(key.as_str()) != b.get(key.as_str())).count()
+        };
+        assert_eq!(differ(&keyed, &same), 0);
+        assert!(differ(&keyed, &other) > 700, "{}", differ(&keyed, &other));
+        assert!(differ(&keyed, &plain) > 700, "{}", differ(&keyed, &plain));
+        let hasher = keyed.key_hasher();
+        for key in &keys {
+            assert_eq!(plain.get(key.as_str()), plain.get_by_hash(checksum(0, key.as_bytes())));
+            assert_eq!(keyed.get(key.as_str()),
+                       keyed.get_by_hash(hasher.checksum(key.as_bytes())));
+        }
+        assert_eq!(keyed.get_composite(&[b"ab", b"c"]),
+                   keyed.get_by_hash(hasher.checksum(b"\x02\x00\x00\x00ab\x01\x00\x00\x00c")));
+
+        // The derived rings and copies keep the secret.
+        let (frozen, compiled) = (keyed.clone().freeze(), keyed.compile(1024));
+        let version = keyed.version();
+        keyed.set_keep_versions(1);
+        keyed.add("node-x");
+        for key in &keys {
+            let owner = same.get(key.as_str());
+            assert_eq!(frozen.get(key.as_str()), owner.as_deref());
+            assert_eq!(compiled.get(key.as_str()), owner.as_deref());
+            assert_eq!(keyed.get_at_version(version, key.as_str()).unwrap(), owner);
+        }
+        assert_eq!(differ(&keyed, &keyed.clone_with_replicas(20)), 0);
+        assert!(keyed.subset(|_| true).is_keyed());
+
+        // The secret is not printed, and can be set again or dropped.
+        let debug = format!("{:?}", Consistant::with_key(20, [0xab; 16]));
+        let secret = u64::from_le_bytes([0xab; 8]).to_string();
+        assert!(debug.contains("SipKey(..)") && !debug.contains(&secret), "{}", debug);
+        plain.set_hash_key(Some(key(1)));
+        assert_eq!(differ(&same, &plain), 0);
+        keyed.set_hash_key(None);
+        assert!(!keyed.is_keyed());
+        assert_eq!(keyed.explain("key").unwrap().checksum, checksum(0, b"key"));
+    }
+}
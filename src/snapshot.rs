@@ -0,0 +1,1044 @@
+//! Binary snapshots of the hash ring.
+//!
+//! A snapshot of format version 13 is laid out as, integers being little
+//! endian:
+//!
+//! ```text
+//! magic     4 bytes   "CSRG"
+//! version   u8        SNAPSHOT_VERSION
+//! hasher    u8        HASHER_ID
+//! naming    u8        the id of the placement strategy
+//! seed      u64
+//! keyed     u8        1 if the lookup keys are hashed with a secret, which is
+//!                     not written, see `Consistant::with_key`, else 0
+//! version   u64       the version of the hash ring, see `Consistant::version`
+//! replicas  u32
+//! count     u32       number of members, then for each member sorted by name:
+//!   length  u32
+//!   name    length bytes of UTF-8
+//!   weight  u32
+//!   tokens  u32       0, or the count of virtual node hashes which follow
+//!                     as u32, if the member does not derive them from its
+//!                     name, see `Consistant::replace_node`
+//!   tags    u32       count of tags, then for each tag sorted:
+//!     length  u32
+//!     tag     length bytes of UTF-8
+//! rules     u32       number of prefix rules, then for each rule sorted by
+//!                     prefix, see `Consistant::add_prefix_rule`:
+//!   length  u32
+//!   prefix  length bytes of UTF-8
+//!   length  u32
+//!   member  length bytes of UTF-8
+//! factor    u32       the replication factor, see
+//!                     `Consistant::replication_factor`
+//! spacing   u32       the smallest distance between two virtual nodes of
+//!                     the same member, see `Consistant::set_min_self_spacing`
+//! ranges    u32       number of range assignments, then for each one sorted
+//!                     by start, see `Consistant::assign_range`:
+//!   start   u32
+//!   end     u32
+//!   length  u32
+//!   member  length bytes of UTF-8
+//! fractions u32       number of members of a fractional weight, then for
+//!                     each one sorted by name, see
+//!                     `Consistant::set_fractional_weight`:
+//!   length  u32
+//!   member  length bytes of UTF-8
+//!   fraction u32      the share of its keys it admits in millionths
+//! members   u64       the most members, u64::MAX for no limit, see
+//!                     `Consistant::set_limits`
+//! vnodes    u64       the most virtual nodes, u64::MAX for no limit
+//! groups    u32       number of colocation groups, then for each one sorted
+//!                     by group, see `Consistant::define_colocation_group`:
+//!   length  u32
+//!   group   length bytes of UTF-8
+//!   length  u32
+//!   key     length bytes of UTF-8, the representative key
+//! ```
+//!
+//! Older snapshots are still loaded, the fields they lack being defaulted:
+//! the keyed flag (from version 12) to whether a secret is given, the
+//! colocation groups (from version 11) to none, the limits (from version
+//! 10) to none, the fractional weights (from
+//! version 9) to none, the range assignments
+//! (from version 8) to none, the spacing (from version
+//! 7) to 0, the replication factor (from version 6)
+//! to 1, the prefix rules (from
+//! version 5) to none, the tags (from version 4) to
+//! none, the tokens (from version 3) to the derived ones, the ring version
+//! (from version 2) to 0 and the seed (from version 1) to 0.
+//! Snapshots of an unknown version are refused rather than loaded with
+//! possibly different mappings.
+//!
+//! The key normalizer and the fallback of `Consistant::set_fallback` are not
+//! written, a loaded hash ring having none until they are set again. Nor is
+//! the secret of a keyed hash ring, which `load` refuses, `load_keyed` being
+//! given the secret again.
+
+use std::collections::hash_map::HashMap;
+use std::io::{self, Read, Write};
+use consistant::Consistant;
+use error::SnapshotError;
+use fractional::MILLIONTHS;
+use limits::{loaded_vnodes, ResourceLimits};
+use placement::PlacementStrategy;
+use siphash::SipKey;
+
+/// The version of the binary snapshot format written by `save`.
+pub const SNAPSHOT_VERSION: u8 = 13;
+
+/// The id of the hash function used by the hash ring in snapshots.
+pub const HASHER_ID: u8 = 1;
+
+/// The vnode naming scheme of the default placement strategy, deriving
+/// virtual node names from the element name followed by the decimal replica
+/// index. Other schemes are identified by `PlacementStrategy::id`.
+pub const VNODE_NAMING_VERSION: u8 = 1;
+
+const MAGIC: &[u8; 4] = b"CSRG";
+
+impl Consistant {
+    /// Write a binary snapshot of the hash ring.
+    pub fn save<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let members = self.weighted_members();
+
+        w.write_all(MAGIC)?;
+        w.write_all(&[SNAPSHOT_VERSION, HASHER_ID, self.placement().id()])?;
+        w.write_all(&self.seed().to_le_bytes())?;
+        w.write_all(&[self.is_keyed() as u8])?;
+        w.write_all(&self.version().to_le_bytes())?;
+        write_u32(&mut w, self.replicas_num)?;
+        write_u32(&mut w, members.len())?;
+        for (element, weight) in members {
+            write_u32(&mut w, element.len())?;
+            w.write_all(element.as_bytes())?;
+            write_u32(&mut w, weight)?;
+            let tokens = self.explicit_tokens(&element).unwrap_or_default();
+            write_u32(&mut w, tokens.len())?;
+            for token in tokens {
+                w.write_all(&token.to_le_bytes())?;
+            }
+            let tags = self.tags_of(&element).unwrap_or_default();
+            write_u32(&mut w, tags.len())?;
+            for tag in tags {
+                write_u32(&mut w, tag.len())?;
+                w.write_all(tag.as_bytes())?;
+            }
+        }
+        let rules = self.prefix_rules();
+        write_u32(&mut w, rules.len())?;
+        for (prefix, member) in rules {
+            write_u32(&mut w, prefix.len())?;
+            w.write_all(prefix.as_bytes())?;
+            write_u32(&mut w, member.len())?;
+            w.write_all(member.as_bytes())?;
+        }
+        write_u32(&mut w, self.replication_factor())?;
+        w.write_all(&self.self_spacing().to_le_bytes())?;
+        let ranges = self.range_assignments();
+        write_u32(&mut w, ranges.len())?;
+        for (start, end, member) in ranges {
+            w.write_all(&start.to_le_bytes())?;
+            w.write_all(&end.to_le_bytes())?;
+            write_u32(&mut w, member.len())?;
+            w.write_all(member.as_bytes())?;
+        }
+        let fractions: Vec<(String, u32)> = self.members()
+            .into_iter()
+            .filter_map(|element| self.fraction(&element).map(|fraction| (element, fraction)))
+            .collect();
+        write_u32(&mut w, fractions.len())?;
+        for (element, fraction) in fractions {
+            write_u32(&mut w, element.len())?;
+            w.write_all(element.as_bytes())?;
+            w.write_all(&fraction.to_le_bytes())?;
+        }
+        let limits = self.limits();
+        for limit in &[limits.max_members, limits.max_vnodes] {
+            let limit = limit.map_or(u64::MAX, |limit| limit as u64);
+            w.write_all(&limit.to_le_bytes())?;
+        }
+        let groups = self.colocation_groups();
+        write_u32(&mut w, groups.len())?;
+        for (group, representative) in groups {
+            write_u32(&mut w, group.len())?;
+            w.write_all(group.as_bytes())?;
+            write_u32(&mut w, representative.len())?;
+            w.write_all(representative.as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Load a hash ring from a binary snapshot written by `save`. The
+    /// snapshot of a keyed hash ring is refused, see `load_keyed`.
+    pub fn load<R: Read>(r: R) -> Result<Consistant, SnapshotError> {
+        Consistant::load_with_secret(r, None)
+    }
+
+    /// Load a keyed hash ring from a binary snapshot written by `save`, its
+    /// lookup keys being hashed with the given secret, see `with_key`. The
+    /// snapshot of a hash ring which is not keyed is refused, unless it
+    /// predates the keyed flag.
+    pub fn load_keyed<R: Read>(r: R, key: [u8; 16]) -> Result<Consistant, SnapshotError> {
+        Consistant::load_with_secret(r, Some(SipKey::new(key)))
+    }
+
+    /// Load a hash ring from a binary snapshot, hashing its lookup keys with
+    /// the given secret, which is checked against the keyed flag.
+    pub(crate) fn load_with_secret<R: Read>(mut r: R,
+                                            secret: Option<SipKey>)
+                                            -> Result<Consistant, SnapshotError> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        let mut header = [0; 3];
+        r.read_exact(&mut header)?;
+        if header[0] == 0 || header[0] > SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(header[0]));
+        }
+        if header[1] != HASHER_ID {
+            return Err(SnapshotError::UnknownHasher(header[1]));
+        }
+        let placement = match PlacementStrategy::from_id(header[2]) {
+            Some(placement) => placement,
+            None => return Err(SnapshotError::UnknownVnodeNaming(header[2])),
+        };
+
+        let mut seed = 0;
+        if header[0] > 1 {
+            seed = read_u64(&mut r)?;
+        }
+        if header[0] > 12 {
+            let mut keyed = [0; 1];
+            r.read_exact(&mut keyed)?;
+            match (keyed[0] != 0, secret.is_some()) {
+                (true, false) => return Err(SnapshotError::MissingKey),
+                (false, true) => return Err(SnapshotError::UnexpectedKey),
+                _ => {}
+            }
+        }
+        let mut version = 0;
+        if header[0] > 2 {
+            version = read_u64(&mut r)?;
+        }
+
+        let mut consistant = Consistant::with_config(read_u32(&mut r)? as usize, placement, seed);
+        let count = read_u32(&mut r)?;
+        let mut seen = HashMap::new();
+        let mut vnodes = 0;
+        for _ in 0..count {
+            let name = String::from_utf8(read_bytes(&mut r)?)
+                .map_err(|_| SnapshotError::InvalidName)?;
+            let weight = read_u32(&mut r)? as usize;
+            if weight == 0 {
+                return Err(SnapshotError::ZeroWeight(name));
+            }
+            if seen.insert(name.clone(), ()).is_some() {
+                return Err(SnapshotError::DuplicateMember(name));
+            }
+            let expected = match loaded_vnodes(consistant.replicas_num, weight as u64, vnodes) {
+                Some(expected) => expected,
+                None => return Err(SnapshotError::TooManyVnodes(name)),
+            };
+            vnodes += expected;
+            let count = if header[0] > 3 { read_u32(&mut r)? as usize } else { 0 };
+            if count == 0 {
+                consistant.add_weighted(name.as_str(), weight);
+            } else if count != expected {
+                return Err(SnapshotError::InvalidTokens(name));
+            } else {
+                let mut tokens = Vec::with_capacity(count);
+                for _ in 0..count {
+                    tokens.push(read_u32(&mut r)?);
+                }
+                consistant.add_with_tokens(name.clone(), weight, tokens);
+            }
+            if header[0] > 4 {
+                let mut tags = Vec::new();
+                for _ in 0..read_u32(&mut r)? {
+                    match String::from_utf8(read_bytes(&mut r)?) {
+                        Ok(tag) => tags.push(tag),
+                        Err(_) => return Err(SnapshotError::InvalidTag(name)),
+                    }
+                }
+                consistant.insert_tags(&name, tags);
+            }
+        }
+        if header[0] > 5 {
+            for _ in 0..read_u32(&mut r)? {
+                let prefix = String::from_utf8(read_bytes(&mut r)?);
+                let member = String::from_utf8(read_bytes(&mut r)?);
+                match (prefix, member) {
+                    (Ok(prefix), Ok(member)) => consistant.insert_prefix_rule(prefix, member),
+                    _ => return Err(SnapshotError::InvalidRule),
+                }
+            }
+        }
+        if header[0] > 6 {
+            consistant.set_replication_factor(read_u32(&mut r)? as usize);
+        }
+        if header[0] > 7 {
+            // Derives the virtual nodes of the members again.
+            consistant.set_self_spacing(read_u32(&mut r)?);
+        }
+        if header[0] > 8 {
+            for _ in 0..read_u32(&mut r)? {
+                let (start, end) = (read_u32(&mut r)?, read_u32(&mut r)?);
+                let member = String::from_utf8(read_bytes(&mut r)?)
+                    .map_err(|_| SnapshotError::InvalidRange)?;
+                consistant.insert_range_assignment(start, end, member)
+                    .map_err(|_| SnapshotError::InvalidRange)?;
+            }
+        }
+        if header[0] > 9 {
+            for _ in 0..read_u32(&mut r)? {
+                let member = String::from_utf8(read_bytes(&mut r)?)
+                    .map_err(|_| SnapshotError::InvalidFraction)?;
+                let fraction = read_u32(&mut r)?;
+                if consistant.weight(&member) != Some(1) || fraction == 0 ||
+                   fraction >= MILLIONTHS {
+                    return Err(SnapshotError::InvalidFraction);
+                }
+                consistant.insert_fraction(&member, fraction);
+            }
+        }
+        if header[0] > 10 {
+            let limit = |limit: u64| if limit == u64::MAX { None } else { Some(limit as usize) };
+            consistant.set_limits(ResourceLimits {
+                max_members: limit(read_u64(&mut r)?),
+                max_vnodes: limit(read_u64(&mut r)?),
+            });
+        }
+        if header[0] > 11 {
+            for _ in 0..read_u32(&mut r)? {
+                let group = String::from_utf8(read_bytes(&mut r)?);
+                let representative = String::from_utf8(read_bytes(&mut r)?);
+                match (group, representative) {
+                    (Ok(group), Ok(representative)) => {
+                        consistant.insert_colocation_group(group, representative)
+                    }
+                    _ => return Err(SnapshotError::InvalidColocationGroup),
+                }
+            }
+        }
+        consistant.set_secret(secret);
+        consistant.set_version(version);
+
+        Ok(consistant)
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+    use consistant::Consistant;
+    use fractional::MILLIONTHS;
+    use json::{HASHER_NAME, JSON_FORMAT_VERSION};
+    use limits::{loaded_vnodes, ResourceLimits, MAX_LOADED_VNODES};
+    use placement::PlacementStrategy;
+    use super::VNODE_NAMING_VERSION;
+
+    /// The serde representation of the hash ring, which has the same fields as
+    /// its JSON representation.
+    #[derive(Serialize, Deserialize)]
+    struct Ring {
+        version: u64,
+        hasher: String,
+        #[serde(default)]
+        vnode_naming: Option<u8>,
+        #[serde(default)]
+        seed: Option<u64>,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        keyed: bool,
+        #[serde(default)]
+        ring_version: Option<u64>,
+        replicas: usize,
+        #[serde(default = "one", skip_serializing_if = "is_one")]
+        replication_factor: usize,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        min_self_spacing: u32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_members: Option<usize>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_vnodes: Option<usize>,
+        members: Vec<Member>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        prefix_rules: Vec<PrefixRule>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        range_assignments: Vec<RangeAssignment>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        colocation_groups: Vec<ColocationGroup>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Member {
+        name: String,
+        weight: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fraction: Option<u32>,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        incarnation: u64,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        tags: Vec<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tokens: Option<Vec<u32>>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct PrefixRule {
+        prefix: String,
+        member: String,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct RangeAssignment {
+        start: u32,
+        end: u32,
+        member: String,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct ColocationGroup {
+        group: String,
+        representative: String,
+    }
+
+    fn is_zero<T: Default + PartialEq>(n: &T) -> bool {
+        *n == T::default()
+    }
+
+    fn one() -> usize {
+        1
+    }
+
+    fn is_one(n: &usize) -> bool {
+        *n == 1
+    }
+
+    impl Serialize for Consistant {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            Ring {
+                    version: JSON_FORMAT_VERSION,
+                    hasher: String::from(HASHER_NAME),
+                    vnode_naming: Some(self.placement().id()),
+                    seed: Some(self.seed()),
+                    keyed: self.is_keyed(),
+                    ring_version: Some(self.version()),
+                    replicas: self.replicas_num,
+                    replication_factor: self.replication_factor(),
+                    min_self_spacing: self.self_spacing(),
+                    max_members: self.limits().max_members,
+                    max_vnodes: self.limits().max_vnodes,
+                    members: self.weighted_members()
+                        .into_iter()
+                        .map(|(name, weight)| {
+                            Member {
+                                tokens: self.explicit_tokens(&name),
+                                fraction: self.fraction(&name),
+                                incarnation: self.incarnation(&name).unwrap_or(0),
+                                tags: self.tags_of(&name).unwrap_or_default(),
+                                name: String::from(&*name),
+                                weight,
+                            }
+                        })
+                        .collect(),
+                    prefix_rules: self.prefix_rules()
+                        .into_iter()
+                        .map(|(prefix, member)| PrefixRule { prefix, member })
+                        .collect(),
+                    range_assignments: self.range_assignments()
+                        .into_iter()
+                        .map(|(start, end, member)| RangeAssignment { start, end, member })
+                        .collect(),
+                    colocation_groups: self.colocation_groups()
+                        .into_iter()
+                        .map(|(group, representative)| ColocationGroup { group, representative })
+                        .collect(),
+                }
+                .serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Consistant {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Consistant, D::Error> {
+            let ring = Ring::deserialize(deserializer)?;
+            if ring.version == 0 || ring.version > JSON_FORMAT_VERSION {
+                return Err(de::Error::custom(format!("unsupported version {}", ring.version)));
+            }
+            if ring.hasher != HASHER_NAME {
+                return Err(de::Error::custom(format!("unknown hasher {:?}", ring.hasher)));
+            }
+            // Version 1 predates the field, its mappings are those of scheme 1.
+            let naming = match (ring.version, ring.vnode_naming) {
+                (1, None) => VNODE_NAMING_VERSION,
+                (_, Some(naming)) => naming,
+                (_, None) => return Err(de::Error::missing_field("vnode_naming")),
+            };
+            let placement = match PlacementStrategy::from_id(naming) {
+                Some(placement) => placement,
+                None => {
+                    return Err(de::Error::custom(format!("unknown vnode naming scheme {}",
+                                                         naming)))
+                }
+            };
+
+            // Version 2 predates the field, its mappings are those of seed 0.
+            let seed = match (ring.version, ring.seed) {
+                (1, None) | (2, None) => 0,
+                (_, Some(seed)) => seed,
+                (_, None) => return Err(de::Error::missing_field("seed")),
+            };
+            // Version 14 predates the field. The secret is not written, see
+            // `Consistant::from_json_keyed`.
+            if ring.version > 14 && ring.keyed {
+                return Err(de::Error::custom("expected the secret of a keyed hash ring"));
+            }
+            let ring_version = match (ring.version, ring.ring_version) {
+                (1..=3, None) => 0,
+                (_, Some(ring_version)) => ring_version,
+                (_, None) => return Err(de::Error::missing_field("ring_version")),
+            };
+
+            let mut consistant = Consistant::with_config(ring.replicas, placement, seed);
+            // Version 9 predates the field, set before the members are added.
+            if ring.version > 9 {
+                consistant.set_self_spacing(ring.min_self_spacing);
+            }
+            let mut vnodes = 0;
+            for member in ring.members {
+                // Version 5 predates the field.
+                let incarnation = if ring.version > 5 { member.incarnation } else { 0 };
+                if member.weight == 0 {
+                    return Err(de::Error::custom(format!("member {:?} has a zero weight",
+                                                         member.name)));
+                }
+                if consistant.contains(&member.name) {
+                    return Err(de::Error::custom(format!("duplicate member {:?}",
+                                                         member.name)));
+                }
+                let count = loaded_vnodes(ring.replicas, member.weight as u64, vnodes)
+                    .ok_or_else(|| {
+                        de::Error::custom(format!("member {:?} takes the hash ring past {} \
+                                                   virtual nodes",
+                                                  member.name,
+                                                  MAX_LOADED_VNODES))
+                    })?;
+                vnodes += count;
+                match member.tokens {
+                    Some(tokens) if ring.version > 4 => {
+                        if tokens.len() != count {
+                            return Err(de::Error::custom(format!("member {:?} has {} tokens",
+                                                                 member.name,
+                                                                 tokens.len())));
+                        }
+                        consistant.add_with_tokens(member.name.clone(), member.weight, tokens);
+                    }
+                    _ => consistant.add_weighted(member.name.as_str(), member.weight),
+                }
+                consistant.set_incarnation(&member.name, incarnation);
+                // Version 6 predates the field.
+                if ring.version > 6 {
+                    consistant.insert_tags(&member.name, member.tags);
+                }
+                // Version 11 predates the field.
+                match member.fraction {
+                    Some(fraction) if ring.version > 11 => {
+                        if fraction == 0 || fraction >= MILLIONTHS || member.weight != 1 {
+                            return Err(de::Error::custom(format!("member {:?} has an invalid \
+                                                                  fraction",
+                                                                 member.name)));
+                        }
+                        consistant.insert_fraction(&member.name, fraction);
+                    }
+                    _ => {}
+                }
+            }
+            // Version 8 predates the field.
+            if ring.version > 8 {
+                consistant.set_replication_factor(ring.replication_factor);
+            }
+            // Version 7 predates the field.
+            if ring.version > 7 {
+                for rule in ring.prefix_rules {
+                    consistant.insert_prefix_rule(rule.prefix, rule.member);
+                }
+            }
+            // Version 10 predates the field.
+            if ring.version > 10 {
+                for range in ring.range_assignments {
+                    consistant.insert_range_assignment(range.start, range.end, range.member)
+                        .map_err(de::Error::custom)?;
+                }
+            }
+            // Version 12 predates the fields, set once the members are added.
+            if ring.version > 12 {
+                consistant.set_limits(ResourceLimits {
+                    max_members: ring.max_members,
+                    max_vnodes: ring.max_vnodes,
+                });
+            }
+            // Version 13 predates the field.
+            if ring.version > 13 {
+                for group in ring.colocation_groups {
+                    consistant.insert_colocation_group(group.group, group.representative);
+                }
+            }
+            consistant.set_version(ring_version);
+
+            Ok(consistant)
+        }
+    }
+}
+
+fn write_u32<W: Write>(w: &mut W, n: usize) -> io::Result<()> {
+    if n > u32::MAX as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "value exceeds u32"));
+    }
+    w.write_all(&(n as u32).to_le_bytes())
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// Read bytes prefixed by their length, the buffer growing as they are
+/// read rather than taking the length on trust.
+fn read_bytes<R: Read>(r: &mut R) -> io::Result<Vec<u8>> {
+    let length = read_u32(r)? as usize;
+    let mut buf = Vec::new();
+    if r.take(length as u64).read_to_end(&mut buf)? < length {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &[u8] = include_bytes!("../tests/fixtures/ring-v13.bin");
+    const FIXTURE_V12: &[u8] = include_bytes!("../tests/fixtures/ring-v12.bin");
+    const FIXTURE_V11: &[u8] = include_bytes!("../tests/fixtures/ring-v11.bin");
+    const FIXTURE_V10: &[u8] = include_bytes!("../tests/fixtures/ring-v10.bin");
+    const FIXTURE_V9: &[u8] = include_bytes!("../tests/fixtures/ring-v9.bin");
+    const FIXTURE_V8: &[u8] = include_bytes!("../tests/fixtures/ring-v8.bin");
+    const FIXTURE_V7: &[u8] = include_bytes!("../tests/fixtures/ring-v7.bin");
+    const FIXTURE_V6: &[u8] = include_bytes!("../tests/fixtures/ring-v6.bin");
+    const FIXTURE_V5: &[u8] = include_bytes!("../tests/fixtures/ring-v5.bin");
+    const FIXTURE_V4: &[u8] = include_bytes!("../tests/fixtures/ring-v4.bin");
+    const FIXTURE_V3: &[u8] = include_bytes!("../tests/fixtures/ring-v3.bin");
+    const FIXTURE_V2: &[u8] = include_bytes!("../tests/fixtures/ring-v2.bin");
+    const FIXTURE_V1: &[u8] = include_bytes!("../tests/fixtures/ring-v1.bin");
+
+    fn consistant() -> Consistant {
+        let mut consistant = Consistant::new(20);
+        consistant.add("cacheA");
+        consistant.add_weighted("cacheB", 2);
+        consistant.add("cacheC");
+        consistant
+    }
+
+    #[test]
+    fn test_save() {
+        let mut bytes = Vec::new();
+        consistant().save(&mut bytes).unwrap();
+        assert_eq!(bytes, FIXTURE);
+    }
+
+    #[test]
+    fn test_load() {
+        let consistant = consistant();
+        let fixtures = [FIXTURE,
+                        FIXTURE_V12,
+                        FIXTURE_V11,
+                        FIXTURE_V10,
+                        FIXTURE_V9,
+                        FIXTURE_V8,
+                        FIXTURE_V7,
+                        FIXTURE_V6,
+                        FIXTURE_V5,
+                        FIXTURE_V4,
+                        FIXTURE_V3,
+                        FIXTURE_V2,
+                        FIXTURE_V1];
+        for fixture in &fixtures {
+            let loaded = Consistant::load(*fixture).unwrap();
+
+            assert_eq!(loaded.replicas_num, 20);
+            assert_eq!(loaded.weight("cacheB"), Some(2));
+            for i in 0..1000 {
+                let key = format!("key{}", i);
+                assert_eq!(loaded.get(key.as_str()), consistant.get(key.as_str()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_seed() {
+        let mut seeded = Consistant::with_seed(20, 42);
+        seeded.add("cacheA");
+        let mut bytes = Vec::new();
+        seeded.save(&mut bytes).unwrap();
+        let loaded = Consistant::load(&bytes[..]).unwrap();
+        assert_eq!(loaded.seed(), 42);
+        assert_eq!(loaded.version(), 1);
+        assert_eq!(Consistant::load(FIXTURE).unwrap().version(), 3);
+        assert_eq!(Consistant::load(FIXTURE_V2).unwrap().version(), 0);
+        assert_eq!(loaded.vnodes(), seeded.vnodes());
+    }
+
+    #[test]
+    fn test_load_tokens() {
+        let mut replaced = consistant();
+        replaced.replace_node("cacheB", "cacheD").unwrap();
+        let mut bytes = Vec::new();
+        replaced.save(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), FIXTURE.len() + 40 * 4);
+        let loaded = Consistant::load(&bytes[..]).unwrap();
+        assert_eq!(loaded.vnodes(), replaced.vnodes());
+        assert_eq!(loaded.explicit_tokens("cacheD"), replaced.explicit_tokens("cacheD"));
+
+        // The count of tokens of cacheD, after its name and weight.
+        bytes[FIXTURE.len() - 48] = 3;
+        assert!(matches!(Consistant::load(&bytes[..]),
+                         Err(SnapshotError::InvalidTokens(ref name)) if name == "cacheD"));
+    }
+
+    #[test]
+    fn test_load_tags() {
+        let mut tagged = consistant();
+        tagged.set_tags("cacheC", &["ssd", "eu"]).unwrap();
+        let mut bytes = Vec::new();
+        tagged.save(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), FIXTURE.len() + 2 * 4 + 5);
+        let loaded = Consistant::load(&bytes[..]).unwrap();
+        assert_eq!(loaded.tags_of("cacheC"), tagged.tags_of("cacheC"));
+        let last = bytes.len() - 41;
+        bytes[last] = 0xff;
+        assert!(matches!(Consistant::load(&bytes[..]),
+                         Err(SnapshotError::InvalidTag(ref name)) if name == "cacheC"));
+    }
+
+    #[test]
+    fn test_load_prefix_rules() {
+        let mut ruled = consistant();
+        ruled.add_prefix_rule("tenant-42/", "cacheA").unwrap();
+        let mut bytes = Vec::new();
+        ruled.save(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), FIXTURE.len() + 2 * 4 + 10 + 6);
+        let loaded = Consistant::load(&bytes[..]).unwrap();
+        assert_eq!(loaded.prefix_rules(), ruled.prefix_rules());
+        let last = bytes.len() - 37;
+        bytes[last] = 0xff;
+        assert!(matches!(Consistant::load(&bytes[..]), Err(SnapshotError::InvalidRule)));
+    }
+
+    #[test]
+    fn test_load_replication_factor() {
+        let mut replicated = consistant();
+        replicated.set_replication_factor(3);
+        let mut bytes = Vec::new();
+        replicated.save(&mut bytes).unwrap();
+        assert_eq!(Consistant::load(&bytes[..]).unwrap().replication_factor(), 3);
+        assert_eq!(Consistant::load(FIXTURE_V6).unwrap().replication_factor(), 1);
+    }
+
+    #[test]
+    fn test_load_self_spacing() {
+        let mut spaced = consistant();
+        spaced.set_min_self_spacing(0.01);
+        let mut bytes = Vec::new();
+        spaced.save(&mut bytes).unwrap();
+        let loaded = Consistant::load(&bytes[..]).unwrap();
+        assert_eq!(loaded.self_spacing(), spaced.self_spacing());
+        assert_eq!(loaded.vnodes(), spaced.vnodes());
+        assert_eq!(Consistant::load(FIXTURE_V7).unwrap().self_spacing(), 0);
+    }
+
+    #[test]
+    fn test_load_range_assignments() {
+        let mut assigned = consistant();
+        assigned.assign_range(u32::MAX - 9, 1 << 30, "cacheC").unwrap();
+        let mut bytes = Vec::new();
+        assigned.save(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), FIXTURE.len() + 3 * 4 + 6);
+        let loaded = Consistant::load(&bytes[..]).unwrap();
+        assert_eq!(loaded.range_assignments(), assigned.range_assignments());
+        assert_eq!(loaded.get_by_hash(0), Some(String::from("cacheC")));
+        let last = bytes.len() - 25;
+        bytes[last] = 0xff;
+        assert!(matches!(Consistant::load(&bytes[..]), Err(SnapshotError::InvalidRange)));
+        assert!(Consistant::load(FIXTURE_V8).unwrap().range_assignments().is_empty());
+    }
+
+    #[test]
+    fn test_load_fractional_weight() {
+        let mut canary = consistant();
+        canary.set_fractional_weight("cacheC", 0.05).unwrap();
+        let mut bytes = Vec::new();
+        canary.save(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), FIXTURE.len() + 2 * 4 + 6);
+        let loaded = Consistant::load(&bytes[..]).unwrap();
+        assert_eq!(loaded.fractional_weight("cacheC"), Some(0.05));
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            assert_eq!(loaded.get(key.as_str()), canary.get(key.as_str()));
+        }
+        let last = bytes.len() - 21;
+        bytes[last] = 0xff;
+        assert!(matches!(Consistant::load(&bytes[..]), Err(SnapshotError::InvalidFraction)));
+        assert_eq!(Consistant::load(FIXTURE_V9).unwrap().fractional_weight("cacheC"), Some(1.0));
+    }
+
+    #[test]
+    fn test_load_limits() {
+        let mut limited = consistant();
+        limited.set_limits(ResourceLimits::new().max_members(3).max_vnodes(100));
+        let mut bytes = Vec::new();
+        limited.save(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), FIXTURE.len());
+        assert_eq!(Consistant::load(&bytes[..]).unwrap().limits(), limited.limits());
+        assert_eq!(Consistant::load(FIXTURE).unwrap().limits(), ResourceLimits::new());
+        assert_eq!(Consistant::load(FIXTURE_V10).unwrap().limits(), ResourceLimits::new());
+    }
+
+    #[test]
+    fn test_load_colocation_groups() {
+        let mut grouped = consistant();
+        grouped.define_colocation_group("session-42", "session");
+        let mut bytes = Vec::new();
+        grouped.save(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), FIXTURE.len() + 2 * 4 + 10 + 7);
+        let loaded = Consistant::load(&bytes[..]).unwrap();
+        assert_eq!(loaded.colocation_groups(), grouped.colocation_groups());
+        assert_eq!(loaded.get_grouped("session-42", "lock"), grouped.get("session"));
+        let last = bytes.len() - 1;
+        bytes[last] = 0xff;
+        assert!(matches!(Consistant::load(&bytes[..]),
+                         Err(SnapshotError::InvalidColocationGroup)));
+        assert!(Consistant::load(FIXTURE_V11).unwrap().colocation_groups().is_empty());
+    }
+
+    #[test]
+    fn test_load_keyed() {
+        let mut keyed = consistant();
+        keyed.set_hash_key(Some([7; 16]));
+        let mut bytes = Vec::new();
+        keyed.save(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), FIXTURE.len());
+        assert_eq!(bytes[15], 1);
+        assert!(matches!(Consistant::load(&bytes[..]), Err(SnapshotError::MissingKey)));
+        let loaded = Consistant::load_keyed(&bytes[..], [7; 16]).unwrap();
+        assert!(loaded.is_keyed());
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            assert_eq!(loaded.get(key.as_str()), keyed.get(key.as_str()));
+        }
+
+        assert!(matches!(Consistant::load_keyed(FIXTURE, [7; 16]),
+                         Err(SnapshotError::UnexpectedKey)));
+        // Older snapshots do not tell, and are loaded either way.
+        assert!(Consistant::load_keyed(FIXTURE_V12, [7; 16]).unwrap().is_keyed());
+        assert!(!Consistant::load(FIXTURE_V12).unwrap().is_keyed());
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let mut bytes = FIXTURE.to_vec();
+        bytes[4] = SNAPSHOT_VERSION + 1;
+        match Consistant::load(&bytes[..]) {
+            Err(SnapshotError::UnsupportedVersion(version)) => {
+                assert_eq!(version, SNAPSHOT_VERSION + 1)
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        let mut bytes = FIXTURE.to_vec();
+        bytes[5] = 7;
+        assert!(matches!(Consistant::load(&bytes[..]), Err(SnapshotError::UnknownHasher(7))));
+
+        let mut bytes = FIXTURE.to_vec();
+        bytes[6] = 2;
+        assert_eq!(Consistant::load(&bytes[..]).unwrap().placement(),
+                   PlacementStrategy::DoubleHash);
+
+        let mut bytes = FIXTURE.to_vec();
+        bytes[6] = 7;
+        assert!(matches!(Consistant::load(&bytes[..]),
+                         Err(SnapshotError::UnknownVnodeNaming(7))));
+
+        assert!(matches!(Consistant::load(&b"CSR"[..]), Err(SnapshotError::Truncated)));
+        assert!(matches!(Consistant::load(&b"JSON{}"[..]), Err(SnapshotError::BadMagic)));
+        assert!(matches!(Consistant::load(&FIXTURE[..FIXTURE.len() - 1]),
+                         Err(SnapshotError::Truncated)));
+    }
+
+    #[test]
+    fn test_load_oversized() {
+        // The header down to the count of members, of the given replicas.
+        let header = |replicas: u32| {
+            let mut bytes = FIXTURE[..7 + 8 + 1 + 8].to_vec();
+            bytes.extend_from_slice(&replicas.to_le_bytes());
+            bytes.extend_from_slice(&1u32.to_le_bytes());
+            bytes
+        };
+        let member = |weight: u32| {
+            let mut bytes = 1u32.to_le_bytes().to_vec();
+            bytes.push(b'a');
+            bytes.extend_from_slice(&weight.to_le_bytes());
+            bytes
+        };
+
+        // A name longer than the snapshot is not allocated.
+        let mut bytes = header(20);
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.push(b'a');
+        assert!(matches!(Consistant::load(&bytes[..]), Err(SnapshotError::Truncated)));
+
+        for &(replicas, weight) in &[(u32::MAX, 2), (1, u32::MAX), (1 << 12, (1 << 12) + 1)] {
+            let mut bytes = header(replicas);
+            bytes.extend(member(weight));
+            assert!(matches!(Consistant::load(&bytes[..]),
+                             Err(SnapshotError::TooManyVnodes(ref name)) if name == "a"),
+                    "{} replicas of weight {}",
+                    replicas,
+                    weight);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let consistant = consistant();
+        let json = ::serde_json::to_string(&consistant).unwrap();
+        assert_eq!(json, consistant.to_json());
+
+        let loaded: Consistant = ::serde_json::from_str(include_str!("../tests/fixtures/ring-v1.json"))
+            .unwrap();
+        assert_eq!(loaded.to_json(),
+                   json.replace("\"ring_version\":3", "\"ring_version\":0"));
+
+        let bumped = json.replace("\"version\":15", "\"version\":16");
+        let err = ::serde_json::from_str::<Consistant>(&bumped).unwrap_err();
+        assert!(err.to_string().contains("unsupported version 16"));
+
+        let loaded: Consistant = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.version(), 3);
+        let unversioned = json.replace("\"ring_version\":3,", "");
+        let err = ::serde_json::from_str::<Consistant>(&unversioned).unwrap_err();
+        assert!(err.to_string().contains("missing field `ring_version`"));
+
+        let unseeded = json.replace("\"version\":15", "\"version\":3")
+            .replace("\"seed\":0,", "");
+        let err = ::serde_json::from_str::<Consistant>(&unseeded).unwrap_err();
+        assert!(err.to_string().contains("missing field `seed`"));
+
+        let mut replaced = consistant.clone();
+        replaced.replace_node("cacheA", "cacheD").unwrap();
+        let json = ::serde_json::to_string(&replaced).unwrap();
+        assert_eq!(json, replaced.to_json());
+        let loaded: Consistant = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.vnodes(), replaced.vnodes());
+        let truncated = json.replace(",3579646308]", "]");
+        let err = ::serde_json::from_str::<Consistant>(&truncated).unwrap_err();
+        assert!(err.to_string().contains("member \"cacheD\" has 19 tokens"));
+
+        replaced.remove("cacheD");
+        replaced.add("cacheD");
+        let json = ::serde_json::to_string(&replaced).unwrap();
+        assert_eq!(json, replaced.to_json());
+        let loaded: Consistant = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.incarnation("cacheD"), Some(1));
+
+        replaced.set_tags("cacheC", &["ssd"]).unwrap();
+        let json = ::serde_json::to_string(&replaced).unwrap();
+        assert_eq!(json, replaced.to_json());
+        let loaded: Consistant = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.tags_of("cacheC"), Some(vec![String::from("ssd")]));
+
+        replaced.add_prefix_rule("tenant-42/", "cacheC").unwrap();
+        let json = ::serde_json::to_string(&replaced).unwrap();
+        assert_eq!(json, replaced.to_json());
+        let loaded: Consistant = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.prefix_rules(), replaced.prefix_rules());
+
+        replaced.assign_range(7, 3, "cacheB").unwrap();
+        let json = ::serde_json::to_string(&replaced).unwrap();
+        assert_eq!(json, replaced.to_json());
+        let loaded: Consistant = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.range_assignments(), replaced.range_assignments());
+        let overlapping = json.replace("\"start\":7,\"end\":3,\"member\":\"cacheB\"}",
+                                       "\"start\":7,\"end\":3,\"member\":\"cacheB\"},\
+                                        {\"start\":1,\"end\":2,\"member\":\"cacheC\"}");
+        let err = ::serde_json::from_str::<Consistant>(&overlapping).unwrap_err();
+        assert!(err.to_string().contains("range overlaps the assigned range (7, 3]"));
+        replaced.clear_range_assignment(7, 3);
+
+        replaced.set_fractional_weight("cacheC", 0.25).unwrap();
+        let json = ::serde_json::to_string(&replaced).unwrap();
+        assert_eq!(json, replaced.to_json());
+        let loaded: Consistant = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.fractional_weight("cacheC"), Some(0.25));
+        let whole = json.replace("\"fraction\":250000", "\"fraction\":1000000");
+        let err = ::serde_json::from_str::<Consistant>(&whole).unwrap_err();
+        assert!(err.to_string().contains("member \"cacheC\" has an invalid fraction"));
+        replaced.set_fractional_weight("cacheC", 1.0).unwrap();
+
+        replaced.set_replication_factor(2);
+        let json = ::serde_json::to_string(&replaced).unwrap();
+        assert_eq!(json, replaced.to_json());
+        let loaded: Consistant = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.replication_factor(), 2);
+
+        replaced.set_min_self_spacing(0.01);
+        let json = ::serde_json::to_string(&replaced).unwrap();
+        assert_eq!(json, replaced.to_json());
+        let loaded: Consistant = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.vnodes(), replaced.vnodes());
+
+        // The members of a hash ring over its limits are all loaded.
+        replaced.set_limits(ResourceLimits::new().max_members(3).max_vnodes(60));
+        let json = ::serde_json::to_string(&replaced).unwrap();
+        assert_eq!(json, replaced.to_json());
+        let loaded: Consistant = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.limits(), replaced.limits());
+        assert_eq!(loaded.vnodes(), replaced.vnodes());
+
+        // Keyed hash rings are refused, their secret not being written.
+        replaced.set_hash_key(Some([7; 16]));
+        let json = ::serde_json::to_string(&replaced).unwrap();
+        assert_eq!(json, replaced.to_json());
+        let err = ::serde_json::from_str::<Consistant>(&json).unwrap_err();
+        assert!(err.to_string().contains("expected the secret of a keyed hash ring"));
+        let older = json.replace("\"version\":15", "\"version\":14");
+        assert!(!::serde_json::from_str::<Consistant>(&older).unwrap().is_keyed());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_oversized() {
+        let header = "{\"version\":9,\"hasher\":\"crc32-ieee\",\"vnode_naming\":1,\"seed\":0,\
+                      \"ring_version\":0,";
+        for members in &["\"replicas\":2,\"members\":[\
+                          {\"name\":\"a\",\"weight\":9223372036854775808,\"tokens\":[]}]}",
+                         "\"replicas\":1000000000000,\"members\":[\
+                          {\"name\":\"a\",\"weight\":1}]}"] {
+            let json = format!("{}{}", header, members);
+            let err = ::serde_json::from_str::<Consistant>(&json).unwrap_err();
+            assert!(err.to_string().contains("member \"a\" takes the hash ring past 16777216"),
+                    "{}",
+                    err);
+        }
+    }
+}
@@ -0,0 +1,290 @@
+//! A plain representation of the hash ring, for persisting it without any
+//! serialization dependency.
+//!
+//! `RingState` only holds std types, so that it may be written in any
+//! format. It has the fields of the JSON representation, see the `json`
+//! module, and `from_state` checks them as `from_json` does.
+
+use std::collections::hash_set::HashSet;
+use consistant::Consistant;
+use error::StateError;
+use fractional::MILLIONTHS;
+use limits::{loaded_vnodes, ResourceLimits};
+use placement::PlacementStrategy;
+use snapshot::HASHER_ID;
+
+/// The version of `RingState` written by `export_state`.
+pub const STATE_FORMAT_VERSION: u32 = 2;
+
+/// RingState is the configuration of a hash ring, see `export_state`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RingState {
+    /// `STATE_FORMAT_VERSION` when exported. Later versions are refused.
+    pub format_version: u32,
+    /// The id of the hash function, `HASHER_ID`.
+    pub hasher_id: u8,
+    /// The id of the placement strategy, see `PlacementStrategy::id`.
+    pub placement_id: u8,
+    pub seed: u64,
+    /// The version of the hash ring, see `Consistant::version`.
+    pub ring_version: u64,
+    /// The count of virtual nodes per weight unit.
+    pub replicas: usize,
+    /// See `Consistant::replication_factor`.
+    pub replication_factor: usize,
+    /// The smallest distance between two virtual nodes of the same member
+    /// in checksums, see `Consistant::set_min_self_spacing`.
+    pub min_self_spacing: u32,
+    /// See `Consistant::set_limits`.
+    pub limits: ResourceLimits,
+    /// Sorted by name when exported.
+    pub members: Vec<MemberState>,
+    /// The `(prefix, member)` rules of `Consistant::add_prefix_rule`, sorted
+    /// by prefix when exported.
+    pub prefix_rules: Vec<(String, String)>,
+    /// The `(start, end, member)` assignments of `Consistant::assign_range`,
+    /// sorted by start when exported.
+    pub range_assignments: Vec<(u32, u32, String)>,
+    /// The `(group, representative)` groups of
+    /// `Consistant::define_colocation_group`, sorted by group when exported.
+    /// Ignored in states of version 1, which predate them.
+    pub colocation_groups: Vec<(String, String)>,
+}
+
+/// MemberState is an element of a `RingState`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemberState {
+    pub name: String,
+    pub weight: usize,
+    /// See `Consistant::incarnation`.
+    pub incarnation: u64,
+    /// Sorted when exported.
+    pub tags: Vec<String>,
+    /// The virtual node hashes of the member if it does not derive them from
+    /// its name, see `Consistant::replace_node`, `None` otherwise.
+    pub tokens: Option<Vec<u32>>,
+    /// The fraction of the keys the member admits in millionths, below one
+    /// million, if it is of a fractional weight, see
+    /// `Consistant::set_fractional_weight`, `None` otherwise.
+    pub fraction: Option<u32>,
+}
+
+impl Consistant {
+    /// Get the configuration of the hash ring as a plain struct, which
+    /// `from_state` builds the same hash ring from.
+    pub fn export_state(&self) -> RingState {
+        RingState {
+            format_version: STATE_FORMAT_VERSION,
+            hasher_id: HASHER_ID,
+            placement_id: self.placement().id(),
+            seed: self.seed(),
+            ring_version: self.version(),
+            replicas: self.replicas_num,
+            replication_factor: self.replication_factor(),
+            min_self_spacing: self.self_spacing(),
+            limits: self.limits(),
+            members: self.weighted_members()
+                .into_iter()
+                .map(|(element, weight)| {
+                    MemberState {
+                        incarnation: self.incarnation(&element).unwrap_or(0),
+                        tags: self.tags_of(&element).unwrap_or_default(),
+                        tokens: self.explicit_tokens(&element),
+                        fraction: self.fraction(&element),
+                        name: String::from(&*element),
+                        weight,
+                    }
+                })
+                .collect(),
+            prefix_rules: self.prefix_rules(),
+            range_assignments: self.range_assignments(),
+            colocation_groups: self.colocation_groups(),
+        }
+    }
+
+    /// Build a hash ring from its configuration, see `export_state`.
+    pub fn from_state(state: RingState) -> Result<Consistant, StateError> {
+        if state.format_version == 0 || state.format_version > STATE_FORMAT_VERSION {
+            return Err(StateError::UnsupportedVersion(state.format_version));
+        }
+        if state.hasher_id != HASHER_ID {
+            return Err(StateError::UnknownHasher(state.hasher_id));
+        }
+        let placement = match PlacementStrategy::from_id(state.placement_id) {
+            Some(placement) => placement,
+            None => return Err(StateError::UnknownPlacement(state.placement_id)),
+        };
+
+        let mut consistant = Consistant::with_config(state.replicas, placement, state.seed);
+        consistant.set_replication_factor(state.replication_factor);
+        // Set before the members are added, for their virtual nodes.
+        consistant.set_self_spacing(state.min_self_spacing);
+        let mut seen = HashSet::new();
+        let mut vnodes = 0;
+        for member in state.members {
+            if member.weight == 0 {
+                return Err(StateError::ZeroWeight(member.name));
+            }
+            if !seen.insert(member.name.clone()) {
+                return Err(StateError::DuplicateMember(member.name));
+            }
+            let count = match loaded_vnodes(state.replicas, member.weight as u64, vnodes) {
+                Some(count) => count,
+                None => return Err(StateError::TooManyVnodes(member.name)),
+            };
+            vnodes += count;
+            if member.fraction.is_some_and(|fraction| {
+                fraction == 0 || fraction >= MILLIONTHS || member.weight != 1
+            }) {
+                return Err(StateError::InvalidFraction(member.name));
+            }
+            match member.tokens {
+                Some(tokens) => {
+                    if tokens.len() != count {
+                        return Err(StateError::InvalidTokens(member.name));
+                    }
+                    consistant.add_with_tokens(member.name.clone(), member.weight, tokens);
+                }
+                None => consistant.add_weighted(member.name.as_str(), member.weight),
+            }
+            consistant.set_incarnation(&member.name, member.incarnation);
+            consistant.insert_tags(&member.name, member.tags);
+            if let Some(fraction) = member.fraction {
+                consistant.insert_fraction(&member.name, fraction);
+            }
+        }
+        for (prefix, member) in state.prefix_rules {
+            consistant.insert_prefix_rule(prefix, member);
+        }
+        for (start, end, member) in state.range_assignments {
+            if consistant.insert_range_assignment(start, end, member).is_err() {
+                return Err(StateError::OverlappingRange { start, end });
+            }
+        }
+        // Set once the members are added, for their owners.
+        if state.format_version > 1 {
+            for (group, representative) in state.colocation_groups {
+                consistant.insert_colocation_group(group, representative);
+            }
+        }
+        // Set once the members are added, which they do not limit.
+        consistant.set_limits(state.limits);
+        consistant.set_version(state.ring_version);
+
+        Ok(consistant)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn consistant() -> Consistant {
+        let mut consistant = Consistant::with_seed(20, 42);
+        consistant.add_with_tags("cacheA", &["ssd"]);
+        consistant.add_weighted("cacheB", 2);
+        consistant.add("cacheC");
+        consistant.remove("cacheB");
+        consistant.add_weighted("cacheB", 2);
+        consistant.replace_node("cacheC", "cacheD").unwrap();
+        consistant.add_prefix_rule("tenant-42/", "cacheD").unwrap();
+        consistant.assign_range(u32::MAX - 100, 1 << 30, "cacheA").unwrap();
+        consistant.add_fractional("cacheE", 0.25);
+        consistant.set_replication_factor(2);
+        consistant.set_limits(ResourceLimits::new().max_members(4));
+        consistant
+    }
+
+    #[test]
+    fn test_export_state() {
+        let consistant = consistant();
+        let state = consistant.export_state();
+        assert_eq!(state.format_version, STATE_FORMAT_VERSION);
+        assert_eq!((state.seed, state.ring_version, state.replicas), (42, 7, 20));
+        assert_eq!(state.members.len(), 4);
+        assert_eq!(state.members[0].tags, ["ssd"]);
+        assert_eq!(state.members[1].incarnation, 1);
+        assert!(state.members[1].tokens.is_none());
+        assert_eq!(state.members[2].tokens.as_ref().map(Vec::len), Some(20));
+        assert_eq!((state.members[2].fraction, state.members[3].fraction), (None, Some(250000)));
+        assert_eq!(state.limits, ResourceLimits::new().max_members(4));
+        assert_eq!(state.prefix_rules,
+                   [(String::from("tenant-42/"), String::from("cacheD"))]);
+        assert_eq!(state.range_assignments,
+                   [(u32::MAX - 100, 1 << 30, String::from("cacheA"))]);
+
+        let loaded = Consistant::from_state(state.clone()).unwrap();
+        assert_eq!(loaded.export_state(), state);
+        assert_eq!(loaded.vnodes(), consistant.vnodes());
+        assert_eq!(loaded.to_json(), consistant.to_json());
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            assert_eq!(loaded.owners(&key), consistant.owners(&key));
+        }
+        assert_eq!(loaded.get("tenant-42/david"), Some(String::from("cacheD")));
+        assert_eq!(loaded.get_by_hash(0), Some(String::from("cacheA")));
+
+        let mut spaced = consistant.clone();
+        spaced.set_min_self_spacing(0.01);
+        let loaded = Consistant::from_state(spaced.export_state()).unwrap();
+        assert_eq!(loaded.vnodes(), spaced.vnodes());
+    }
+
+    #[test]
+    fn test_state_colocation_groups() {
+        let mut grouped = consistant();
+        grouped.define_colocation_group("session-42", "tenant-42/session");
+        grouped.define_colocation_group("batch-7", "batch");
+        let state = grouped.export_state();
+        assert_eq!(state.colocation_groups,
+                   [(String::from("batch-7"), String::from("batch")),
+                    (String::from("session-42"), String::from("tenant-42/session"))]);
+
+        let loaded = Consistant::from_state(state.clone()).unwrap();
+        assert_eq!(loaded.export_state(), state);
+        assert_eq!(loaded.colocation_groups(), grouped.colocation_groups());
+        assert_eq!(loaded.get_grouped("session-42", "david"), Some(String::from("cacheD")));
+        assert_eq!(loaded.get_grouped("batch-7", "david"), grouped.get("batch"));
+
+        // States of version 1 predate the groups.
+        let mut older = state;
+        older.format_version = 1;
+        assert!(Consistant::from_state(older).unwrap().colocation_groups().is_empty());
+    }
+
+    #[test]
+    fn test_from_state_errors() {
+        let state = consistant().export_state();
+        let invalid = |f: &dyn Fn(&mut RingState)| {
+            let mut state = state.clone();
+            f(&mut state);
+            Consistant::from_state(state).unwrap_err()
+        };
+        assert_eq!(invalid(&|state| state.format_version = 3),
+                   StateError::UnsupportedVersion(3));
+        assert_eq!(invalid(&|state| state.hasher_id = 7), StateError::UnknownHasher(7));
+        assert_eq!(invalid(&|state| state.placement_id = 7),
+                   StateError::UnknownPlacement(7));
+        assert_eq!(invalid(&|state| state.members[1].weight = 0),
+                   StateError::ZeroWeight(String::from("cacheB")));
+        assert_eq!(invalid(&|state| state.members[1].name = String::from("cacheA")),
+                   StateError::DuplicateMember(String::from("cacheA")));
+        assert_eq!(invalid(&|state| state.members[0].tokens = Some(vec![1, 2])),
+                   StateError::InvalidTokens(String::from("cacheA")));
+        assert_eq!(invalid(&|state| state.replicas = usize::MAX),
+                   StateError::TooManyVnodes(String::from("cacheA")));
+        assert_eq!(invalid(&|state| state.members[1].weight = usize::MAX),
+                   StateError::TooManyVnodes(String::from("cacheB")));
+        assert_eq!(invalid(&|state| state.members[3].fraction = Some(1_000_000)),
+                   StateError::InvalidFraction(String::from("cacheE")));
+        assert_eq!(invalid(&|state| state.members[1].fraction = Some(10)),
+                   StateError::InvalidFraction(String::from("cacheB")));
+        assert_eq!(invalid(&|state| {
+                       state.range_assignments.push((1 << 29, 1 << 31, String::from("cacheB")))
+                   }),
+                   StateError::OverlappingRange {
+                       start: 1 << 29,
+                       end: 1 << 31,
+                   });
+    }
+}
@@ -0,0 +1,236 @@
+use placement::checksum;
+
+/// StaticRing is a hash ring computed at compile time by `static_ring!`,
+/// for fixed topologies. Its lookups neither allocate nor lock, and map keys
+/// as a `Consistant` of the same replicas with the members added in order.
+#[derive(Debug, Clone, Copy)]
+pub struct StaticRing {
+    members: &'static [&'static str],
+    tokens: &'static [(u32, u32)],
+}
+
+impl StaticRing {
+    /// Crete a new instance of StaticRing, see `static_ring!`.
+    #[doc(hidden)]
+    pub const fn new(members: &'static [&'static str],
+                     tokens: &'static [(u32, u32)])
+                     -> StaticRing {
+        StaticRing { members, tokens }
+    }
+
+    /// Compute the sorted `(hash, member)` tokens of the given members,
+    /// `N` being `replicas` times their count. A virtual node colliding with
+    /// the one of an earlier member is sorted before it, so that lookups find
+    /// the later member as the one which took it over.
+    #[doc(hidden)]
+    pub const fn tokens<const N: usize>(replicas: usize,
+                                        members: &[&str])
+                                        -> [(u32, u32); N] {
+        let mut i = 0;
+        while i < members.len() {
+            let mut j = 0;
+            while j < i {
+                if str_eq(members[i], members[j]) {
+                    panic!("duplicate member in static_ring!");
+                }
+                j += 1;
+            }
+            i += 1;
+        }
+
+        let mut tokens = [(0, 0); N];
+        let mut index = 0;
+        while index < N {
+            let member = index / replicas;
+            tokens[index] = (vnode_checksum(members[member], index % replicas), member as u32);
+            index += 1;
+        }
+
+        // A heapsort, as there is no sort in const fn.
+        let mut end = N;
+        let mut start = N / 2;
+        while end > 1 {
+            if start > 0 {
+                start -= 1;
+            } else {
+                end -= 1;
+                let tmp = tokens[0];
+                tokens[0] = tokens[end];
+                tokens[end] = tmp;
+            }
+            let mut root = start;
+            loop {
+                let mut child = 2 * root + 1;
+                if child >= end {
+                    break;
+                }
+                if child + 1 < end && token_lt(tokens[child], tokens[child + 1]) {
+                    child += 1;
+                }
+                if !token_lt(tokens[root], tokens[child]) {
+                    break;
+                }
+                let tmp = tokens[root];
+                tokens[root] = tokens[child];
+                tokens[child] = tmp;
+                root = child;
+            }
+        }
+
+        tokens
+    }
+
+    /// Get the cloeset element's name to the given key.
+    pub fn get<S: AsRef<str>>(&self, key: S) -> Option<&'static str> {
+        if self.tokens.is_empty() {
+            return None;
+        }
+        Some(self.owner_of(checksum(0, key.as_ref().as_bytes())))
+    }
+
+    /// Get the names of the members, in order.
+    pub fn members(&self) -> &'static [&'static str] {
+        self.members
+    }
+
+    #[inline]
+    fn owner_of(&self, sum: u32) -> &'static str {
+        let index = match self.tokens.partition_point(|token| token.0 <= sum) {
+            index if index == self.tokens.len() => 0,
+            index => index,
+        };
+        self.members[self.tokens[index].1 as usize]
+    }
+}
+
+/// Build a `StaticRing` at compile time, with the given replicas and members.
+/// A duplicate member fails the compilation.
+///
+/// ```
+/// # #[macro_use] extern crate consistent_rs;
+/// # use consistent_rs::StaticRing;
+/// static RING: StaticRing = static_ring!(replicas = 20, members = ["a", "b", "c"]);
+///
+/// # fn main() {
+/// assert!(RING.get("david").is_some());
+/// # }
+/// ```
+///
+/// ```compile_fail
+/// # #[macro_use] extern crate consistent_rs;
+/// # use consistent_rs::StaticRing;
+/// static RING: StaticRing = static_ring!(replicas = 20, members = ["a", "b", "a"]);
+/// # fn main() {}
+/// ```
+#[macro_export]
+macro_rules! static_ring {
+    (replicas = $replicas:expr, members = [$($member:expr),* $(,)*]) => {{
+        const MEMBERS: &[&str] = &[$($member),*];
+        const REPLICAS: usize = $replicas;
+        static TOKENS: [(u32, u32); REPLICAS * MEMBERS.len()] =
+            $crate::StaticRing::tokens(REPLICAS, MEMBERS);
+        $crate::StaticRing::new(MEMBERS, &TOKENS)
+    }};
+}
+
+/// Order tokens by hash, and the later members first.
+const fn token_lt(a: (u32, u32), b: (u32, u32)) -> bool {
+    a.0 < b.0 || (a.0 == b.0 && a.1 > b.1)
+}
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// Compute the CRC32 (IEEE) of the name of the i-th virtual node of the
+/// given member, as `PlacementStrategy::Classic` does.
+pub(crate) const fn vnode_checksum(member: &str, i: usize) -> u32 {
+    let mut crc = !0u32;
+    let bytes = member.as_bytes();
+    let mut index = 0;
+    while index < bytes.len() {
+        crc = crc_byte(crc, bytes[index]);
+        index += 1;
+    }
+
+    let mut digits = 1;
+    while i / digits >= 10 {
+        digits *= 10;
+    }
+    while digits > 0 {
+        crc = crc_byte(crc, b'0' + (i / digits % 10) as u8);
+        digits /= 10;
+    }
+    !crc
+}
+
+const fn crc_byte(crc: u32, byte: u8) -> u32 {
+    let mut crc = crc ^ byte as u32;
+    let mut bit = 0;
+    while bit < 8 {
+        crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        bit += 1;
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consistant::Consistant;
+
+    static RING: StaticRing =
+        static_ring!(replicas = 20, members = ["shard-0", "shard-1", "shard-2", "shard-3"]);
+
+    #[test]
+    fn test_static_ring() {
+        let mut consistant = Consistant::new(20);
+        for member in RING.members() {
+            consistant.add(*member);
+        }
+
+        for i in 0..10000 {
+            let key = format!("key{}", i);
+            assert_eq!(RING.get(&key).map(String::from), consistant.get(key.as_str()));
+        }
+    }
+
+    #[test]
+    fn test_static_ring_collisions() {
+        // "node-1" followed by 1x is also "node-11" followed by x.
+        let ring = static_ring!(replicas = 20, members = ["node-1", "node-11", "node-2"]);
+        let mut consistant = Consistant::new(20);
+        for member in ring.members() {
+            consistant.add(*member);
+        }
+
+        for i in 0..10000 {
+            let key = format!("key{}", i);
+            assert_eq!(ring.get(&key).map(String::from), consistant.get(key.as_str()));
+        }
+        // Around every virtual node, some of them taken over.
+        for (hash, _) in consistant.vnodes() {
+            for sum in [hash.wrapping_sub(1), hash].iter() {
+                assert_eq!(Some(ring.owner_of(*sum)), consistant.owner_of(*sum).as_deref());
+            }
+        }
+    }
+
+    #[test]
+    fn test_static_ring_empty() {
+        let ring = static_ring!(replicas = 20, members = []);
+        assert_eq!(ring.get("david"), None);
+        assert!(ring.members().is_empty());
+    }
+}
@@ -0,0 +1,11 @@
+//! The synchronization primitives of the crate, which are the ones of loom
+//! when model checking the tests with:
+//!
+//! ```text
+//! RUSTFLAGS="--cfg loom" cargo test --release --lib loom
+//! ```
+
+#[cfg(all(test, loom))]
+pub(crate) use loom::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(not(all(test, loom)))]
+pub(crate) use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
@@ -0,0 +1,194 @@
+//! Helpers for the tests of code built on the hash ring, with the `test-util`
+//! feature.
+//!
+//! `assert_assignments` checks that keys are still mapped to the members a
+//! file recorded, so that a change of the members or the replicas moving
+//! keys fails the test, listing them. The file is made of the sorted
+//! `key\tmember` lines, for its changes to be reviewed as any other, and is
+//! rewritten instead when `UPDATE_ASSIGNMENTS=1` is set:
+//!
+//! ```text
+//! UPDATE_ASSIGNMENTS=1 cargo test
+//! ```
+
+use std::collections::btree_map::BTreeMap;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+use consistant::Consistant;
+
+/// Assert that the given keys are mapped by the hash ring to the members the
+/// file at the given path recorded, panicking with the keys moved, added or
+/// no longer given otherwise. With `UPDATE_ASSIGNMENTS=1`, the file is
+/// written with the current members of the keys instead. A key of the empty
+/// hash ring is of the empty member.
+pub fn assert_assignments(ring: &Consistant, keys: &[&str], path: &Path) {
+    let update = env::var("UPDATE_ASSIGNMENTS").is_ok_and(|update| update == "1");
+    if let Err(message) = check_assignments(ring, keys, path, update) {
+        panic!("{}", message);
+    }
+}
+
+fn check_assignments(ring: &Consistant,
+                     keys: &[&str],
+                     path: &Path,
+                     update: bool)
+                     -> Result<(), String> {
+    let mut current = BTreeMap::new();
+    for key in keys {
+        if key.contains(['\t', '\n', '\r']) {
+            return Err(format!("key {:?} can not be recorded", key));
+        }
+        current.insert(String::from(*key), ring.get(key).unwrap_or_default());
+    }
+
+    if update {
+        let mut contents = String::new();
+        for (key, member) in &current {
+            let _ = writeln!(contents, "{}\t{}", key, member);
+        }
+        return fs::write(path, contents)
+            .map_err(|err| format!("can not write {}: {}", path.display(), err));
+    }
+
+    let recorded = match fs::read_to_string(path) {
+        Ok(recorded) => recorded,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+            return Err(format!("no assignments recorded at {}, run with UPDATE_ASSIGNMENTS=1 \
+                                to record them",
+                               path.display()));
+        }
+        Err(err) => return Err(format!("can not read {}: {}", path.display(), err)),
+    };
+    let mut recorded_members = BTreeMap::new();
+    for (i, line) in recorded.lines().enumerate() {
+        match line.split_once('\t') {
+            Some((key, member)) => recorded_members.insert(key, member),
+            None => return Err(format!("{}:{}: not a key and member", path.display(), i + 1)),
+        };
+    }
+
+    let mut diff = String::new();
+    let mut changes = 0;
+    for (key, member) in &current {
+        match recorded_members.get(key.as_str()) {
+            Some(recorded) if recorded == member => continue,
+            Some(recorded) => {
+                let _ = writeln!(diff, "  {}: {} -> {}", key, recorded, member);
+            }
+            None => {
+                let _ = writeln!(diff, "  {}: not recorded, now {}", key, member);
+            }
+        }
+        changes += 1;
+    }
+    for (key, recorded) in &recorded_members {
+        if !current.contains_key(*key) {
+            let _ = writeln!(diff, "  {}: recorded {}, no longer given", key, recorded);
+            changes += 1;
+        }
+    }
+    if changes == 0 {
+        return Ok(());
+    }
+    Err(format!("{} of the assignments recorded at {} changed, run with UPDATE_ASSIGNMENTS=1 \
+                 to record them:\n{}",
+                changes,
+                path.display(),
+                diff))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::process;
+
+    fn assignments_path(name: &str) -> PathBuf {
+        let path = env::temp_dir()
+            .join(format!("consistent-assignments-{}-{}", process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn consistant() -> Consistant {
+        let mut consistant = Consistant::new(20);
+        for i in 0..5 {
+            consistant.add(format!("cache{}", i));
+        }
+        consistant
+    }
+
+    #[test]
+    fn test_assert_assignments() {
+        let path = assignments_path("matching");
+        let consistant = consistant();
+        let keys = ["kelly", "david", "james"];
+        assert!(check_assignments(&consistant, &keys, &path, false)
+            .unwrap_err()
+            .contains("run with UPDATE_ASSIGNMENTS=1"));
+        check_assignments(&consistant, &keys, &path, true).unwrap();
+
+        let recorded = fs::read_to_string(&path).unwrap();
+        let lines: Vec<String> = ["david", "james", "kelly"]
+            .iter()
+            .map(|key| format!("{}\t{}", key, consistant.get(key).unwrap()))
+            .collect();
+        assert_eq!(recorded, lines.join("\n") + "\n");
+        assert_assignments(&consistant, &keys, &path);
+
+        // Updating again, the same ring writes the same file.
+        let reversed = ["james", "david", "kelly", "david"];
+        check_assignments(&consistant, &reversed, &path, true).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), recorded);
+        assert!(check_assignments(&consistant, &["a\tb"], &path, true).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_assert_assignments_moved() {
+        let path = assignments_path("moved");
+        let mut consistant = consistant();
+        let keys: Vec<String> = (0..100).map(|i| format!("key{}", i)).collect();
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+        check_assignments(&consistant, &keys, &path, true).unwrap();
+
+        let before: Vec<String> = keys.iter().map(|key| consistant.get(key).unwrap()).collect();
+        consistant.remove("cache2");
+        let moved: Vec<(&str, &String)> = keys.iter()
+            .cloned()
+            .zip(&before)
+            .filter(|&(_, member)| member == "cache2")
+            .collect();
+        assert!(!moved.is_empty());
+        let message = check_assignments(&consistant, &keys, &path, false).unwrap_err();
+        assert!(message.starts_with(&format!("{} of the assignments", moved.len())),
+                "{}",
+                message);
+        for &(key, _) in &moved {
+            let line = format!("  {}: cache2 -> {}\n", key, consistant.get(key).unwrap());
+            assert!(message.contains(&line), "{}", message);
+        }
+        assert_eq!(message.lines().count(), moved.len() + 1);
+
+        let message = check_assignments(&consistant, &["key0", "david"], &path, false)
+            .unwrap_err();
+        assert!(message.contains("  david: not recorded, now "), "{}", message);
+        assert!(message.contains("  key99: recorded "), "{}", message);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "assignments recorded at")]
+    fn test_assert_assignments_panics() {
+        let path = assignments_path("panics");
+        let mut consistant = consistant();
+        let keys: Vec<String> = (0..100).map(|i| format!("key{}", i)).collect();
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+        check_assignments(&consistant, &keys, &path, true).unwrap();
+        consistant.add("cache5");
+        assert_assignments(&consistant, &keys, &path);
+    }
+}
@@ -0,0 +1,212 @@
+//! A reproduction of the ketama distribution of twemproxy (nutcracker), for
+//! pools of `distribution: ketama` with `hash: fnv1a_64` or `hash: md5`.
+//!
+//! The continuum is built as by twemproxy's `nc_ketama.c`:
+//!
+//! - each server gets `floor(weight / total * 160 / 4 * servers) * 4` points,
+//!   computed in single precision;
+//! - its points are named `"{name}-{i}"`, truncated to 85 bytes, where the name
+//!   is the one given after the weight, else the host, followed by `:port`
+//!   unless the port is 11211;
+//! - each name is hashed with MD5, giving 4 points of its 16 bytes;
+//! - a key belongs to the first point at or after its hash, wrapping around.
+//!
+//! Twemproxy sorts the points with `qsort`, which leaves the order of points
+//! of the same value to the C library; here the server listed first wins.
+//! Servers ejected by `auto_eject_hosts` are left out of the list, the way
+//! twemproxy rebuilds the continuum of the live servers only. Hash tags are
+//! not applied: the key given is hashed as it is.
+
+use md5;
+use error::TwemproxyError;
+
+const POINTS_PER_SERVER: f32 = 160.0;
+const POINTS_PER_HASH: u32 = 4;
+const MAX_HOSTLEN: usize = 85;
+const DEFAULT_PORT: &str = "11211";
+
+/// Hash is the `hash` of a twemproxy pool, used for the keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hash {
+    Fnv1a64,
+    Md5,
+}
+
+/// KetamaRing maps keys to servers of a twemproxy pool as twemproxy does.
+#[derive(Debug, Clone)]
+pub struct KetamaRing {
+    hash: Hash,
+    servers: Vec<String>,
+    // (value, server index), sorted by value.
+    continuum: Vec<(u32, usize)>,
+}
+
+impl KetamaRing {
+    /// Crete a new instance of KetamaRing of the given servers, each written
+    /// as in twemproxy's configuration: `host:port:weight [name]`.
+    pub fn new<S: AsRef<str>>(servers: &[S], hash: Hash) -> Result<Self, TwemproxyError> {
+        let mut parsed = Vec::with_capacity(servers.len());
+        for server in servers {
+            parsed.push(parse(server.as_ref().trim())?);
+        }
+        let total_weight: u32 = parsed.iter().map(|&(_, weight)| weight).sum();
+        if total_weight == 0 && !parsed.is_empty() {
+            return Err(TwemproxyError::ZeroWeight);
+        }
+
+        let mut continuum = Vec::new();
+        for (index, &(ref name, weight)) in parsed.iter().enumerate() {
+            let pct = weight as f32 / total_weight as f32;
+            let points = pct * POINTS_PER_SERVER / 4.0 * parsed.len() as f32;
+            let points = ((points as f64 + 0.0000000001) as f32).floor() as u32 * 4;
+            for i in 0..points / POINTS_PER_HASH {
+                let mut host = format!("{}-{}", name, i).into_bytes();
+                host.truncate(MAX_HOSTLEN);
+                let digest = md5::compute(&host).0;
+                for x in 0..POINTS_PER_HASH as usize {
+                    continuum.push((le_u32(&digest[x * 4..]), index));
+                }
+            }
+        }
+        continuum.sort_by_key(|&(value, _)| value);
+
+        Ok(KetamaRing {
+            hash,
+            servers: servers.iter().map(|server| String::from(server.as_ref().trim())).collect(),
+            continuum,
+        })
+    }
+
+    /// Get the hash of the pool.
+    pub fn hash(&self) -> Hash {
+        self.hash
+    }
+
+    /// Get the servers, as they were given.
+    pub fn servers(&self) -> &[String] {
+        &self.servers
+    }
+
+    /// Get the count of points of the continuum.
+    pub fn points(&self) -> usize {
+        self.continuum.len()
+    }
+
+    /// Get the index of the server of the given key, among `servers()`.
+    pub fn get_index<K: AsRef<[u8]>>(&self, key: K) -> Option<usize> {
+        if self.continuum.is_empty() {
+            return None;
+        }
+        let hash = match self.hash {
+            Hash::Fnv1a64 => fnv1a_64(key.as_ref()),
+            Hash::Md5 => le_u32(&md5::compute(key.as_ref()).0),
+        };
+        let index = self.continuum.partition_point(|&(value, _)| value < hash);
+        let point = self.continuum.get(index).unwrap_or(&self.continuum[0]);
+        Some(point.1)
+    }
+
+    /// Get the server of the given key, as it was given.
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Option<&str> {
+        self.get_index(key).map(|index| self.servers[index].as_str())
+    }
+}
+
+/// Parse a `host:port:weight [name]` server into the name its points are
+/// named after, and its weight.
+fn parse(server: &str) -> Result<(String, u32), TwemproxyError> {
+    let invalid = || TwemproxyError::InvalidServer(String::from(server));
+    let (address, name) = match server.find(' ') {
+        Some(space) => (&server[..space], Some(server[space + 1..].trim())),
+        None => (server, None),
+    };
+    let mut fields = address.rsplitn(3, ':');
+    let weight = fields.next().and_then(|weight| weight.parse().ok()).ok_or_else(invalid)?;
+    let port = fields.next().filter(|port| port.parse::<u16>().is_ok()).ok_or_else(invalid)?;
+    let host = fields.next().filter(|host| !host.is_empty()).ok_or_else(invalid)?;
+    let name = match name {
+        Some(name) if !name.is_empty() => String::from(name),
+        Some(_) => return Err(invalid()),
+        None if port == DEFAULT_PORT => String::from(host),
+        None => format!("{}:{}", host, port),
+    };
+    Ok((name, weight))
+}
+
+/// The `fnv1a_64` hash of twemproxy, truncated to 32 bits, over bytes taken
+/// as a signed `char`.
+fn fnv1a_64(key: &[u8]) -> u32 {
+    let mut hash = 0xcbf29ce484222325u64 as u32;
+    for &byte in key {
+        hash ^= byte as i8 as u32;
+        hash = hash.wrapping_mul(0x100000001b3u64 as u32);
+    }
+    hash
+}
+
+fn le_u32(bytes: &[u8]) -> u32 {
+    u32::from(bytes[0]) | u32::from(bytes[1]) << 8 | u32::from(bytes[2]) << 16 |
+    u32::from(bytes[3]) << 24
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("127.0.0.1:11211:1"), Ok((String::from("127.0.0.1"), 1)));
+        assert_eq!(parse("127.0.0.1:22122:3"), Ok((String::from("127.0.0.1:22122"), 3)));
+        assert_eq!(parse("10.0.0.3:11211:2 cache-c"), Ok((String::from("cache-c"), 2)));
+        for server in &["127.0.0.1:11211", "127.0.0.1:port:1", ":11211:1", "127.0.0.1:11211:1 "] {
+            assert_eq!(parse(server), Err(TwemproxyError::InvalidServer(String::from(*server))));
+        }
+        assert_eq!(KetamaRing::new(&["a:1:0", "b:1:0"], Hash::Md5).unwrap_err(),
+                   TwemproxyError::ZeroWeight);
+
+        let empty = KetamaRing::new::<&str>(&[], Hash::Md5).unwrap();
+        assert_eq!(empty.get("david"), None);
+    }
+
+    #[test]
+    fn test_points() {
+        // 160 points for each server of equal weights, rounded down to a
+        // multiple of 4 otherwise.
+        let ring = KetamaRing::new(&["a:1:1", "b:1:1", "c:1:1"], Hash::Md5).unwrap();
+        assert_eq!(ring.points(), 480);
+        let ring = KetamaRing::new(&["a:1:1", "b:1:2"], Hash::Md5).unwrap();
+        assert_eq!(ring.points(), 104 + 212);
+        let ring = KetamaRing::new(&["a:1:1", "b:1:0"], Hash::Md5).unwrap();
+        assert_eq!(ring.points(), 320);
+        assert_eq!(ring.get("david"), Some("a:1:1"));
+    }
+
+    #[test]
+    fn test_twemproxy_vectors() {
+        let fixture = include_str!("../tests/fixtures/twemproxy-ketama.tsv");
+        let mut pools: BTreeMap<&str, (Vec<&str>, Hash)> = BTreeMap::new();
+        let mut rings = BTreeMap::new();
+        let mut checked = 0;
+
+        for line in fixture.lines().filter(|line| !line.starts_with('#')) {
+            let fields: Vec<&str> = line.split('\t').collect();
+            match fields[0] {
+                "server" => {
+                    let hash = if fields[2] == "md5" { Hash::Md5 } else { Hash::Fnv1a64 };
+                    pools.entry(fields[1]).or_insert((Vec::new(), hash)).0.push(fields[3]);
+                }
+                _ => {
+                    let ring = rings.entry(fields[1]).or_insert_with(|| {
+                        let (ref servers, hash) = pools[fields[1]];
+                        KetamaRing::new(servers, hash).unwrap()
+                    });
+                    assert_eq!(ring.get(fields[2]), Some(fields[3]), "{}", line);
+                    checked += 1;
+                }
+            }
+        }
+        assert_eq!(rings.len(), 4);
+        assert_eq!(checked, 432);
+    }
+}
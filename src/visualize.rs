@@ -0,0 +1,141 @@
+use std::fmt::Write;
+use consistant::Consistant;
+
+/// The maximum number of virtual nodes drawn by `to_dot`. Larger hash rings
+/// are sampled evenly.
+pub const MAX_DOT_VNODES: usize = 512;
+
+/// The maximum width of the bar drawn by `to_ascii`.
+pub const MAX_ASCII_WIDTH: usize = 4096;
+
+const COLORS: [&str; 8] = ["#8dd3c7", "#ffffb3", "#bebada", "#fb8072", "#80b1d3", "#fdb462",
+                           "#b3de69", "#fccde5"];
+
+const LETTERS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+impl Consistant {
+    /// Render the hash ring as a Graphviz graph in a circular layout, each
+    /// virtual node being labeled and colored by its element. At most
+    /// `MAX_DOT_VNODES` virtual nodes are drawn.
+    pub fn to_dot(&self) -> String {
+        let members = self.sorted_members();
+        let vnodes = self.vnodes();
+        let step = vnodes.len().div_ceil(MAX_DOT_VNODES);
+        let vnodes: Vec<_> = vnodes.into_iter().step_by(step.max(1)).collect();
+
+        let mut dot = String::from("digraph ring {\n    layout=circo;\n    node [style=filled];\n");
+        for &(hash, ref element) in &vnodes {
+            let color = match members.binary_search(element) {
+                Ok(i) => COLORS[i % COLORS.len()],
+                Err(_) => unreachable!(),
+            };
+            writeln!(dot,
+                     "    \"{:08x}\" [label=\"{}\\n{:08x}\", fillcolor=\"{}\"];",
+                     hash,
+                     element.escape_default(),
+                     hash,
+                     color)
+                .unwrap();
+        }
+        for (i, &(hash, _)) in vnodes.iter().enumerate() {
+            let next = vnodes[(i + 1) % vnodes.len()].0;
+            writeln!(dot, "    \"{:08x}\" -> \"{:08x}\";", hash, next).unwrap();
+        }
+        dot.push_str("}\n");
+
+        dot
+    }
+
+    /// Render the keyspace as a bar of `width` characters, each character
+    /// showing the element owning the start of its slice, followed by a
+    /// legend. Elements are lettered by name, the ones beyond the 52nd are all
+    /// shown as `?`. The width is capped at `MAX_ASCII_WIDTH`.
+    pub fn to_ascii(&self, width: usize) -> String {
+        let members = self.sorted_members();
+        let width = width.min(MAX_ASCII_WIDTH);
+        let letter = |i: usize| if i < LETTERS.len() {
+            LETTERS[i] as char
+        } else {
+            '?'
+        };
+
+        let mut ascii = String::with_capacity(width + 1);
+        for i in 0..width as u64 {
+            let sum = (i * (1 << 32) / width as u64) as u32;
+            match self.owner_of(sum) {
+                Some(element) => {
+                    match members.binary_search(&element) {
+                        Ok(i) => ascii.push(letter(i)),
+                        Err(_) => unreachable!(),
+                    }
+                }
+                None => ascii.push(' '),
+            }
+        }
+        ascii.push('\n');
+        for (i, element) in members.iter().enumerate().take(LETTERS.len()) {
+            writeln!(ascii, "{}: {}", letter(i), element).unwrap();
+        }
+        if members.len() > LETTERS.len() {
+            writeln!(ascii, "?: {} more", members.len() - LETTERS.len()).unwrap();
+        }
+
+        ascii
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn consistant() -> Consistant {
+        let mut consistant = Consistant::new(2);
+        consistant.add("cacheC");
+        consistant.add("cacheA");
+        consistant.add("cacheB");
+        consistant
+    }
+
+    #[test]
+    fn test_to_dot() {
+        assert_eq!(consistant().to_dot(),
+                   r##"digraph ring {
+    layout=circo;
+    node [style=filled];
+    "000c92bf" [label="cacheC\n000c92bf", fillcolor="#bebada"];
+    "1917a3fe" [label="cacheB\n1917a3fe", fillcolor="#ffffb3"];
+    "323af03d" [label="cacheA\n323af03d", fillcolor="#8dd3c7"];
+    "453dc0ab" [label="cacheA\n453dc0ab", fillcolor="#8dd3c7"];
+    "6e109368" [label="cacheB\n6e109368", fillcolor="#ffffb3"];
+    "770ba229" [label="cacheC\n770ba229", fillcolor="#bebada"];
+    "000c92bf" -> "1917a3fe";
+    "1917a3fe" -> "323af03d";
+    "323af03d" -> "453dc0ab";
+    "453dc0ab" -> "6e109368";
+    "6e109368" -> "770ba229";
+    "770ba229" -> "000c92bf";
+}
+"##);
+    }
+
+    #[test]
+    fn test_to_dot_capped() {
+        let mut consistant = Consistant::new(100);
+        for i in 0..20 {
+            consistant.add(format!("cache{}", i));
+        }
+
+        let dot = consistant.to_dot();
+        assert!(dot.matches("label=").count() <= MAX_DOT_VNODES);
+        assert_eq!(dot, consistant.to_dot());
+    }
+
+    #[test]
+    fn test_to_ascii() {
+        assert_eq!(consistant().to_ascii(32),
+                   "CBBBAAAAABBBBBCCCCCCCCCCCCCCCCCC\nA: cacheA\nB: cacheB\nC: cacheC\n");
+        assert_eq!(Consistant::default().to_ascii(4), "    \n");
+        assert_eq!(consistant().to_ascii(1 << 20).lines().next().unwrap().len(),
+                   MAX_ASCII_WIDTH);
+    }
+}
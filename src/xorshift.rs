@@ -0,0 +1,24 @@
+//! A xorshift generator shared by the tests, for reproducible random
+//! operations.
+
+/// Xorshift is a xorshift64 generator of the given seed, which must not be 0.
+pub(crate) struct Xorshift(u64);
+
+impl Xorshift {
+    pub(crate) fn new(seed: u64) -> Xorshift {
+        Xorshift(seed)
+    }
+
+    /// Get the next number of the sequence.
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Get the low 32 bits of the next number of the sequence.
+    pub(crate) fn next_u32(&mut self) -> u32 {
+        self.next_u64() as u32
+    }
+}
@@ -0,0 +1,88 @@
+extern crate consistent_rs;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use consistent_rs::{AnchorRing, Consistant, KeyNormalizer};
+
+/// Counts the allocations, the only test of this binary running alone.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn allocations<T, F: FnOnce() -> T>(f: F) -> usize {
+    let before = ALLOCATIONS.load(Ordering::SeqCst);
+    let result = f();
+    let count = ALLOCATIONS.load(Ordering::SeqCst) - before;
+    drop(result);
+    count
+}
+
+#[test]
+fn test_lookup_allocations() {
+    let mut consistant = Consistant::new(20);
+    for i in 0..10 {
+        consistant.add(format!("cache{}", i));
+    }
+    // The first lookup sorts the virtual nodes.
+    let owner = consistant.get("david");
+    let key = String::from("david");
+
+    // Only the returned names are allocated, not copies of the keys.
+    assert_eq!(allocations(|| consistant.get("david")), 1);
+    assert_eq!(consistant.get(&key), owner);
+    assert_eq!(consistant.get(key.clone()), owner);
+    assert_eq!(allocations(|| consistant.get_nth_owner("david", 2)), 1);
+    assert_eq!(allocations(|| consistant.is_owner("cache0", "david", 3)), 0);
+    assert_eq!(allocations(|| consistant.get_n("david", 3)), 5);
+    assert_eq!(consistant.get_n(&key, 3), consistant.get_n("david", 3));
+    assert_eq!(allocations(|| consistant.get_primary_and_backup("david")), 2);
+    assert_eq!(allocations(|| consistant.get_composite(&[b"users", b"david"])), 1);
+
+    // The walk of an anchor ring does not copy its buckets.
+    let mut anchor = AnchorRing::new(100000).unwrap();
+    for i in 0..10 {
+        anchor.add(format!("cache{}", i));
+    }
+    assert_eq!(allocations(|| anchor.get_n("david", 3)), 5);
+
+    // A normalizer borrowing the keys it leaves alone allocates nothing.
+    let lowercase = KeyNormalizer::new("lowercase", |key: &str| {
+        if key.bytes().any(|byte| byte.is_ascii_uppercase()) {
+            Cow::Owned(key.to_ascii_lowercase())
+        } else {
+            Cow::Borrowed(key)
+        }
+    });
+    consistant.set_key_normalizer(Some(lowercase));
+    assert_eq!(allocations(|| consistant.get("david")), 1);
+    assert_eq!(allocations(|| consistant.is_owner("cache0", "david", 3)), 0);
+    assert_eq!(allocations(|| consistant.is_owner("cache0", "David", 3)), 1);
+
+    // Nor does a ring of fixed capacity, for any lookup.
+    #[cfg(feature = "heapless")]
+    {
+        let mut ring: consistent_rs::StaticConsistant<10, 20> = Default::default();
+        for name in &["cache0", "cache1", "cache2", "cache3"] {
+            ring.add(name).unwrap();
+        }
+        assert_eq!(allocations(|| ring.get("david")), 0);
+        assert_eq!(allocations(|| ring.get_n("david", 3)), 0);
+        assert_eq!(allocations(|| ring.add("cache4")), 0);
+        assert_eq!(allocations(|| ring.remove("cache0")), 0);
+    }
+}
@@ -0,0 +1,73 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn consistent(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_consistent")).args(args).output().unwrap()
+}
+
+fn stdout(args: &[&str]) -> String {
+    let output = consistent(args);
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn write_file(name: &str, content: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("consistent-cli-{}-{}", std::process::id(), name));
+    fs::write(&path, content).unwrap();
+    path
+}
+
+#[test]
+fn test_get() {
+    assert_eq!(stdout(&["get", "--members", "cacheA,cacheB,cacheC", "--replicas", "20", "david"]),
+               "cacheC\n");
+}
+
+#[test]
+fn test_get_n() {
+    assert_eq!(stdout(&["get-n", "--members", "cacheA,cacheB,cacheC", "-n", "2", "david"]),
+               "cacheC\ncacheB\n");
+}
+
+#[test]
+fn test_map() {
+    let members = write_file("nodes.txt", "cacheA\ncacheB=2\n\ncacheC\n");
+    let keys = write_file("keys.txt", "david\nkally\njason\n");
+
+    assert_eq!(stdout(&["map",
+                        "--members-file",
+                        members.to_str().unwrap(),
+                        "--keys-file",
+                        keys.to_str().unwrap()]),
+               "david\tcacheB\nkally\tcacheC\njason\tcacheB\n");
+}
+
+#[test]
+fn test_stats() {
+    assert_eq!(stdout(&["stats", "--members", "cacheA,cacheB=2,cacheC", "--replicas", "10"]),
+               "cacheA\t0.0924\ncacheB\t0.6195\ncacheC\t0.2882\n");
+}
+
+#[test]
+fn test_host_port() {
+    let members = "10.0.0.1:11211,10.0.0.2:11211=2,10.0.0.3:11211";
+    assert_eq!(stdout(&["get", "--members", members, "david"]), "10.0.0.3:11211\n");
+    assert_eq!(stdout(&["stats", "--members", members, "--replicas", "10"]),
+               "10.0.0.1:11211\t0.2212\n10.0.0.2:11211\t0.5455\n10.0.0.3:11211\t0.2333\n");
+}
+
+#[test]
+fn test_errors() {
+    let output = consistent(&["get", "--members", "", "david"]);
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8(output.stderr).unwrap(),
+               "consistent: no members given\n");
+
+    let output = consistent(&["get", "--members", "cacheA=0", "david"]);
+    assert!(!output.status.success());
+
+    let output = consistent(&["frobnicate", "--members", "cacheA"]);
+    assert!(!output.status.success());
+}
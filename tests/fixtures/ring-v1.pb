@@ -0,0 +1,3 @@
+ *(0:
+cacheA"_H,oO:
+cacheB"ye<bUmk%]lR
\ No newline at end of file
@@ -0,0 +1,51 @@
+extern crate consistent_rs;
+
+use consistent_rs::{Consistant, PlacementStrategy, MAPPING_VERSION};
+
+const FIXTURE: &str = include_str!("fixtures/mapping-v1.tsv");
+
+fn consistant(ring: &str) -> Consistant {
+    let mut consistant = match ring {
+        "classic" => Consistant::new(20),
+        "double-hash" => Consistant::with_placement(20, PlacementStrategy::DoubleHash),
+        "seed-42" => Consistant::with_seed(20, 42),
+        _ => panic!("unknown ring {:?}", ring),
+    };
+    for &(member, weight) in &[("cacheA", 1), ("cacheB", 2), ("cacheC", 1), ("cacheD", 1),
+                               ("cacheE", 3)] {
+        consistant.add_weighted(member, weight);
+    }
+    consistant
+}
+
+// Failing means the mappings changed: if deliberate, bump MAPPING_VERSION and
+// check in a new fixture for it.
+#[test]
+fn test_golden_mappings() {
+    assert_eq!(MAPPING_VERSION, 1);
+    let rings: Vec<(&str, Consistant)> = ["classic", "double-hash", "seed-42"]
+        .iter()
+        .map(|ring| (*ring, consistant(ring)))
+        .collect();
+    let (mut checked, mut wrapped) = (0, 0);
+
+    for line in FIXTURE.lines().filter(|line| !line.starts_with('#')) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let consistant = &rings.iter().find(|ring| ring.0 == fields[0]).unwrap().1;
+        let expected: Vec<String> = fields[3].split(',').map(String::from).collect();
+        let actual = match fields[1] {
+            "get" => consistant.get(fields[2]).into_iter().collect(),
+            "get_n" => consistant.get_n(fields[2], expected.len()).unwrap(),
+            method => panic!("unknown method {:?}", method),
+        };
+        assert_eq!(actual, expected, "{}", line);
+
+        if consistant.explain(fields[2]).unwrap().wrapped {
+            wrapped += 1;
+        }
+        checked += 1;
+    }
+
+    assert_eq!(checked, 739);
+    assert!(wrapped >= 9, "{}", wrapped);
+}
@@ -0,0 +1,153 @@
+extern crate proptest;
+extern crate consistent_rs;
+
+use std::collections::HashSet;
+use consistent_rs::{BTreeRing, Consistant, Rendezvous};
+use proptest::prelude::*;
+
+/// Build a hash ring of the given members, added in the given order.
+fn ring(replicas: usize, members: &[String]) -> Consistant {
+    let mut consistant = Consistant::new(replicas);
+    for member in members {
+        consistant.add(member.as_str());
+    }
+    consistant
+}
+
+/// Between 1 and 12 distinct member names.
+fn members() -> impl Strategy<Value = Vec<String>> {
+    proptest::collection::hash_set("[a-z]{1,8}", 1..12)
+        .prop_map(|members| members.into_iter().collect())
+}
+
+fn keys() -> impl Strategy<Value = Vec<String>> {
+    proptest::collection::vec(".{0,16}", 1..64)
+}
+
+/// Adds of a member of the given weight, or removes of it for a weight of 0,
+/// among a few names for the removes to hit.
+fn operations() -> impl Strategy<Value = Vec<(String, usize)>> {
+    proptest::collection::vec(("[a-f]{1,2}", 0usize..4), 1..48)
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn prop_permutation_determinism(replicas in 1usize..40,
+                                    (members, permuted) in members().prop_flat_map(|members| {
+                                        (Just(members.clone()), Just(members).prop_shuffle())
+                                    }),
+                                    keys in keys()) {
+        let consistant = ring(replicas, &members);
+        let other = ring(replicas, &permuted);
+
+        prop_assert_eq!(consistant.fingerprint(), other.fingerprint());
+        for key in &keys {
+            prop_assert_eq!(consistant.get_n(key.as_str(), 3), other.get_n(key.as_str(), 3));
+        }
+    }
+
+    #[test]
+    fn prop_minimal_disruption(replicas in 1usize..40,
+                               members in members(),
+                               added in "[A-Z]{1,8}",
+                               keys in keys()) {
+        let before = ring(replicas, &members);
+        let mut after = before.clone();
+        after.add(added.as_str());
+
+        for key in &keys {
+            let owner = after.get(key.as_str()).unwrap();
+            prop_assert!(owner == added || Some(owner) == before.get(key.as_str()));
+        }
+    }
+
+    #[test]
+    fn prop_remove_inverts_add(replicas in 1usize..40,
+                               members in members(),
+                               added in "[A-Z]{1,8}",
+                               keys in keys()) {
+        let before = ring(replicas, &members);
+        let mut after = before.clone();
+        after.add(added.as_str());
+        after.remove(added.as_str());
+
+        prop_assert_eq!(after.fingerprint(), before.fingerprint());
+        for key in &keys {
+            prop_assert_eq!(after.get(key.as_str()), before.get(key.as_str()));
+        }
+    }
+
+    #[test]
+    fn prop_get_n_distinct_members(replicas in 1usize..40,
+                                   members in members(),
+                                   kept in any::<prop::sample::Index>(),
+                                   n in 1usize..16,
+                                   keys in keys()) {
+        // Some of the members, whose count may be lower than n.
+        let members = &members[..1 + kept.index(members.len())];
+        let consistant = ring(replicas, members);
+
+        for key in &keys {
+            let owners = consistant.get_n(key.as_str(), n).unwrap();
+            prop_assert_eq!(owners.len(), n.min(members.len()));
+            let distinct: HashSet<&String> = owners.iter().collect();
+            prop_assert_eq!(distinct.len(), owners.len());
+            prop_assert!(owners.iter().all(|owner| members.contains(owner)));
+            prop_assert_eq!(owners.first().cloned(), consistant.get(key.as_str()));
+        }
+    }
+
+    #[test]
+    fn prop_btree_ring_equivalence(replicas in 1usize..40,
+                                   operations in operations(),
+                                   n in 1usize..4,
+                                   keys in keys()) {
+        let mut consistant = Consistant::new(replicas);
+        let mut btree = BTreeRing::new(replicas);
+        for (name, weight) in &operations {
+            if *weight == 0 {
+                consistant.remove(name.as_str());
+                btree.remove(name.as_str());
+            } else {
+                consistant.add_weighted(name.as_str(), *weight);
+                btree.add_weighted(name.as_str(), *weight);
+            }
+            // Lookups in between, for the sorted keys of Consistant to drift
+            // if they can.
+            let key = keys[0].as_str();
+            prop_assert_eq!(btree.get(key), consistant.get(key));
+        }
+
+        prop_assert_eq!(btree.members(), consistant.members());
+        for key in &keys {
+            prop_assert_eq!(btree.get(key.as_str()), consistant.get(key.as_str()));
+            prop_assert_eq!(btree.get_n(key.as_str(), n), consistant.get_n(key.as_str(), n));
+        }
+    }
+
+    #[test]
+    fn prop_rendezvous_weight_change(members in members(),
+                                     weights in proptest::collection::vec(0.1f64..10.0, 12),
+                                     changed in any::<prop::sample::Index>(),
+                                     weight in 0.1f64..10.0,
+                                     keys in keys()) {
+        let mut rendezvous = Rendezvous::new();
+        for (member, weight) in members.iter().zip(&weights) {
+            rendezvous.add_weighted(member.as_str(), *weight);
+        }
+        let before: Vec<String> = keys.iter().map(|key| rendezvous.get(key).unwrap()).collect();
+        let changed = &members[changed.index(members.len())];
+        let grown = weight > rendezvous.weight(changed).unwrap();
+        rendezvous.update_weight(changed, weight).unwrap();
+
+        for (key, before) in keys.iter().zip(&before) {
+            let after = rendezvous.get(key).unwrap();
+            if after != *before {
+                // Keys only move into a grown member, or out of a shrunk one.
+                prop_assert_eq!(if grown { &after } else { before }, changed);
+            }
+        }
+    }
+}